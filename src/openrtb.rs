@@ -10,12 +10,57 @@
 //    1000-1999: Reserved for Google.
 //    2000-9999: Free for use with other exchanges or projects.
 
+//! OpenRTB 2.5 messages and the tools built on them.
+//!
+//! Besides `serde` (with `derive`) and `serde_repr`, the modules here need
+//! serde_json's `raw_value` feature, for the lazily decoded views in `lazy`,
+//! and an optional `simd-json` behind the `simd` feature, for the alternative
+//! backend of `json`:
+//!
+//! ```toml
+//! [dependencies]
+//! serde_json = { version = "1", features = ["raw_value"] }
+//! simd-json = { version = "0.14", optional = true }
+//!
+//! [features]
+//! simd = ["dep:simd-json"]
+//! ```
+
 use self::bool::Bool;
 use bid_request::{App, Site};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value;
 use serde_repr::*;
 
+pub mod ads_txt;
+pub mod canonical;
+pub mod codes;
+pub mod decode;
+pub mod device_id;
+pub mod diff;
+pub mod eid;
+pub mod fields;
+pub mod geo;
+pub mod gpp;
+pub mod ip;
+pub mod json;
+pub mod lazy;
+pub mod limits;
+pub mod mccmnc;
+pub mod pchain;
+pub mod price;
+pub mod privacy;
+mod scan;
+pub mod schain;
+pub mod segments;
+pub mod split;
+pub mod sua;
+pub mod targeting;
+pub mod tcf;
+mod tzif;
+pub mod ua;
+pub mod usp;
+
 /// OpenRTB 2.0: The top-level bid request object contains a globally unique
 /// bid request or auction ID. This id attribute is required as is at least one
 /// impression object (Section 3.2.2). Other attributes in this top-level object
@@ -142,6 +187,8 @@ pub struct BidRequest {
 /// Nested message and enum types in `BidRequest`.
 pub mod bid_request {
     use super::bool::Bool;
+    use super::eid::Eid;
+    use super::schain::SupplyChain;
     use super::{
         ConnectionType, ContentContext, DeviceType, LocationService, LocationType,
         ProductionQuality, QagMediaRating,
@@ -177,6 +224,12 @@ pub mod bid_request {
         #[serde(skip_serializing_if = "Option::is_none")]
         pub pchain: Option<String>,
 
+        /// OpenRTB 2.6: The supply chain of the request: every party that
+        /// sold or resold it and whether the chain is complete. OpenRTB 2.5
+        /// carries the same object in `ext.schain`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub schain: Option<SupplyChain>,
+
         /// Extensions.
         #[serde(skip_serializing_if = "Option::is_none")]
         pub ext: Option<Value>,
@@ -1500,6 +1553,11 @@ pub mod bid_request {
         #[serde(skip_serializing_if = "Option::is_none")]
         pub data: Option<Vec<Data>>,
 
+        /// OpenRTB 2.6: Data from identity providers, one Eid object per
+        /// source. OpenRTB 2.5 carries the same array in `ext.eids`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub eids: Option<Vec<Eid>>,
+
         /// Extensions.
         #[serde(skip_serializing_if = "Option::is_none")]
         pub ext: Option<Value>,
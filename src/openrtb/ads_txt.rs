@@ -0,0 +1,620 @@
+//! ads.txt, app-ads.txt and sellers.json authorization.
+//!
+//! A publisher lists the ad systems and accounts allowed to sell its
+//! inventory in ads.txt (sites) or app-ads.txt (apps); each ad system lists
+//! the accounts it pays in sellers.json. `Authorizer` holds these files and
+//! checks every seller a `BidRequest` passed through: the nodes of the
+//! supply chain, or our own account for `Publisher.id` when there is no
+//! chain. The first seller must be listed as DIRECT and every later one as
+//! RESELLER. Files are parsed from strings or local paths; fetching them is
+//! up to the caller.
+
+use super::bid_request::{Publisher, Source};
+use super::bool::Bool;
+use super::schain::SupplyChainNode;
+use super::BidRequest;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+/// Account relationship declared in ads.txt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Relationship {
+    /// The publisher directly controls the account.
+    Direct,
+    /// The publisher authorized the ad system to resell its inventory.
+    Reseller,
+}
+
+/// A data record of an ads.txt file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AdsTxtRecord {
+    /// Domain of the advertising system, lowercased.
+    pub domain: String,
+    /// The publisher's account ID within the advertising system.
+    pub account_id: String,
+    /// Whether the publisher controls the account or resells through it.
+    pub relationship: Relationship,
+    /// Certification authority ID of the advertising system.
+    pub cert_authority_id: Option<String>,
+}
+
+/// A parsed ads.txt or app-ads.txt file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AdsTxt {
+    /// The data records, in file order.
+    pub records: Vec<AdsTxtRecord>,
+    /// Variable declarations such as CONTACT or OWNERDOMAIN, in file order.
+    pub variables: Vec<(String, String)>,
+    /// Line numbers (1-based) of lines that could not be parsed.
+    pub invalid_lines: Vec<usize>,
+}
+
+impl AdsTxt {
+    /// Parses a file. Invalid lines are skipped and recorded in
+    /// `invalid_lines`, as crawlers are expected to tolerate them.
+    pub fn parse(text: &str) -> AdsTxt {
+        let mut ads_txt = AdsTxt::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some((name, value)) = line.split_once('=') {
+                if !name.contains(',') {
+                    ads_txt
+                        .variables
+                        .push((name.trim().to_ascii_uppercase(), value.trim().to_string()));
+                    continue;
+                }
+            }
+            let record = line.split(';').next().unwrap_or_default();
+            let fields: Vec<&str> = record.split(',').map(str::trim).collect();
+            let relationship = match fields.get(2) {
+                Some(r) if r.eq_ignore_ascii_case("DIRECT") => Relationship::Direct,
+                Some(r) if r.eq_ignore_ascii_case("RESELLER") => Relationship::Reseller,
+                _ => {
+                    ads_txt.invalid_lines.push(i + 1);
+                    continue;
+                }
+            };
+            if fields[0].is_empty() || fields[1].is_empty() || fields.len() > 4 {
+                ads_txt.invalid_lines.push(i + 1);
+                continue;
+            }
+            ads_txt.records.push(AdsTxtRecord {
+                domain: fields[0].to_ascii_lowercase(),
+                account_id: fields[1].to_string(),
+                relationship,
+                cert_authority_id: fields
+                    .get(3)
+                    .filter(|c| !c.is_empty())
+                    .map(|c| c.to_string()),
+            });
+        }
+        ads_txt
+    }
+
+    /// Reads and parses a file.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> std::io::Result<AdsTxt> {
+        Ok(AdsTxt::parse(&std::fs::read_to_string(path)?))
+    }
+
+    /// The relationships under which `account_id` of ad system `domain` is
+    /// listed. The domain is compared case-insensitively.
+    pub fn relationships(&self, domain: &str, account_id: &str) -> Vec<Relationship> {
+        self.records
+            .iter()
+            .filter(|r| r.domain.eq_ignore_ascii_case(domain) && r.account_id == account_id)
+            .map(|r| r.relationship)
+            .collect()
+    }
+}
+
+/// Type of a seller in sellers.json. Lowercase values, which some files
+/// use, are accepted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SellerType {
+    /// The inventory is owned by the seller, who is paid directly.
+    #[serde(alias = "publisher")]
+    Publisher,
+    /// The seller resells inventory it does not own.
+    #[serde(alias = "intermediary")]
+    Intermediary,
+    /// The seller both owns and resells inventory.
+    #[serde(alias = "both")]
+    Both,
+}
+
+/// A seller entry of sellers.json.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Seller {
+    /// The seller's account ID in the ad system, as in ads.txt and
+    /// `SupplyChainNode.sid`.
+    pub seller_id: String,
+
+    /// Whether the seller owns the inventory, resells it, or both.
+    pub seller_type: SellerType,
+
+    /// Business name of the seller, unless confidential.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Business domain of the seller, unless confidential.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+
+    /// 1 if the seller's identity is withheld.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_confidential: Option<u8>,
+
+    /// 1 if the ad system passes the inventory through without acting as
+    /// the seller.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_passthrough: Option<u8>,
+
+    /// Free-form description of the seller.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+
+    /// Extensions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ext: Option<Value>,
+}
+
+/// A parsed sellers.json file.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SellersJson {
+    /// Contact address for questions about the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contact_email: Option<String>,
+
+    /// Postal address of the ad system.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contact_address: Option<String>,
+
+    /// Version of the sellers.json specification, e.g. "1.0".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+
+    /// The accounts the ad system pays.
+    #[serde(default)]
+    pub sellers: Vec<Seller>,
+
+    /// Extensions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ext: Option<Value>,
+}
+
+impl SellersJson {
+    /// Parses a sellers.json document.
+    pub fn from_json(json: &str) -> serde_json::Result<SellersJson> {
+        serde_json::from_str(json)
+    }
+
+    /// Reads and parses a sellers.json file.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> serde_json::Result<SellersJson> {
+        let json = std::fs::read_to_string(path).map_err(serde_json::Error::io)?;
+        SellersJson::from_json(&json)
+    }
+
+    /// The seller with ID `seller_id`.
+    pub fn seller(&self, seller_id: &str) -> Option<&Seller> {
+        self.sellers.iter().find(|s| s.seller_id == seller_id)
+    }
+}
+
+/// Why a request is not authorized.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// The request has neither a site domain nor an app bundle or store URL.
+    NoInventory,
+    /// No ads.txt or app-ads.txt is loaded for the inventory.
+    NoAdsTxt,
+    /// The request has no supply chain and no `Publisher.id`.
+    NoSeller,
+    /// The supply chain could not be decoded.
+    MalformedSchain,
+    /// The supply chain is incomplete and `require_complete` is set.
+    IncompleteSchain,
+    /// The account is not listed in ads.txt for the ad system.
+    NotListed,
+    /// The account is listed in ads.txt, but not with the relationship its
+    /// position requires: DIRECT for the first seller, RESELLER for the
+    /// later nodes of the supply chain.
+    RelationshipMismatch(Relationship),
+    /// `Publisher.id` differs from the seller ID of the last supply chain
+    /// node, the seller that issued the request to us.
+    PublisherMismatch,
+    /// The ad system's sellers.json has no such seller.
+    UnknownSeller,
+    /// The sellers.json type contradicts the required relationship, e.g.
+    /// INTERMEDIARY for the first seller.
+    SellerTypeMismatch(SellerType),
+}
+
+/// The hop that failed authorization.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Unauthorized {
+    /// Index of the supply chain node, or `None` for the request itself or
+    /// our own account when there is no chain.
+    pub node: Option<usize>,
+    /// Ad system of the hop, if known.
+    pub asi: Option<String>,
+    /// Seller account of the hop, if known.
+    pub sid: Option<String>,
+    /// Why the hop is not authorized.
+    pub reason: Reason,
+}
+
+impl fmt::Display for Unauthorized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unauthorized supply ({:?})", self.reason)?;
+        if let Some(node) = self.node {
+            write!(f, " at schain node {}", node)?;
+        }
+        if let (Some(asi), Some(sid)) = (&self.asi, &self.sid) {
+            write!(f, " ({}, {})", asi, sid)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Unauthorized {}
+
+/// Authorization checker over loaded ads.txt, app-ads.txt and sellers.json
+/// files.
+#[derive(Clone, Debug, Default)]
+pub struct Authorizer {
+    /// Our own ad system domain, used with `Publisher.id` for requests
+    /// without a supply chain.
+    pub own_asi: String,
+    /// Reject requests whose supply chain is incomplete.
+    pub require_complete: bool,
+    ads_txt: HashMap<String, AdsTxt>,
+    app_ads_txt: HashMap<String, AdsTxt>,
+    sellers_json: HashMap<String, SellersJson>,
+}
+
+impl Authorizer {
+    /// An authorizer for requests sold through `own_asi`.
+    pub fn new(own_asi: &str) -> Authorizer {
+        Authorizer {
+            own_asi: own_asi.to_ascii_lowercase(),
+            ..Authorizer::default()
+        }
+    }
+
+    /// Registers the ads.txt of site `domain`.
+    pub fn add_ads_txt(&mut self, domain: &str, ads_txt: AdsTxt) {
+        self.ads_txt.insert(domain.to_ascii_lowercase(), ads_txt);
+    }
+
+    /// Registers an app-ads.txt under an app's bundle or store URL, matched
+    /// case-insensitively.
+    pub fn add_app_ads_txt(&mut self, bundle_or_storeurl: &str, ads_txt: AdsTxt) {
+        self.app_ads_txt
+            .insert(bundle_or_storeurl.to_ascii_lowercase(), ads_txt);
+    }
+
+    /// Registers the sellers.json of ad system `asi`.
+    pub fn add_sellers_json(&mut self, asi: &str, sellers_json: SellersJson) {
+        self.sellers_json
+            .insert(asi.to_ascii_lowercase(), sellers_json);
+    }
+
+    /// The ads.txt or app-ads.txt governing the request's inventory.
+    fn inventory_ads_txt(&self, req: &BidRequest) -> Result<&AdsTxt, Reason> {
+        if let Some(site) = &req.site {
+            let domain = site
+                .domain
+                .clone()
+                .or_else(|| site.page.as_deref().and_then(host))
+                .ok_or(Reason::NoInventory)?
+                .to_ascii_lowercase();
+            let bare = domain.strip_prefix("www.").unwrap_or(&domain);
+            return self
+                .ads_txt
+                .get(&domain)
+                .or_else(|| self.ads_txt.get(bare))
+                .ok_or(Reason::NoAdsTxt);
+        }
+        if let Some(app) = &req.app {
+            let keys = [&app.bundle, &app.storeurl];
+            if keys.iter().all(|k| k.is_none()) {
+                return Err(Reason::NoInventory);
+            }
+            return keys
+                .into_iter()
+                .flatten()
+                .find_map(|k| self.app_ads_txt.get(&k.to_ascii_lowercase()))
+                .ok_or(Reason::NoAdsTxt);
+        }
+        Err(Reason::NoInventory)
+    }
+
+    /// Checks one seller hop against ads.txt, where it must be listed with
+    /// `required`, and, when loaded and the hop is paid (`hp` is 1), the ad
+    /// system's sellers.json.
+    fn check_hop(
+        &self,
+        ads_txt: &AdsTxt,
+        asi: &str,
+        sid: &str,
+        paid: bool,
+        required: Relationship,
+    ) -> Result<(), Reason> {
+        let relationships = ads_txt.relationships(asi, sid);
+        if relationships.is_empty() {
+            return Err(Reason::NotListed);
+        }
+        if !relationships.contains(&required) {
+            return Err(Reason::RelationshipMismatch(required));
+        }
+        if !paid {
+            return Ok(());
+        }
+        let Some(sellers) = self.sellers_json.get(&asi.to_ascii_lowercase()) else {
+            return Ok(());
+        };
+        let seller = sellers.seller(sid).ok_or(Reason::UnknownSeller)?;
+        let consistent = matches!(
+            (required, seller.seller_type),
+            (_, SellerType::Both)
+                | (Relationship::Direct, SellerType::Publisher)
+                | (Relationship::Reseller, SellerType::Intermediary)
+        );
+        if consistent {
+            Ok(())
+        } else {
+            Err(Reason::SellerTypeMismatch(seller.seller_type))
+        }
+    }
+
+    /// Checks that every seller of `req` is authorized, returning the first
+    /// hop that is not.
+    pub fn authorize(&self, req: &BidRequest) -> Result<(), Unauthorized> {
+        let fail = |node, asi: Option<&str>, sid: Option<&str>, reason| Unauthorized {
+            node,
+            asi: asi.map(str::to_string),
+            sid: sid.map(str::to_string),
+            reason,
+        };
+        let ads_txt = self
+            .inventory_ads_txt(req)
+            .map_err(|reason| fail(None, None, None, reason))?;
+        let schain = match req.source.as_ref().and_then(Source::schain_or_ext) {
+            Some(Ok(schain)) => Some(schain),
+            Some(Err(_)) => return Err(fail(None, None, None, Reason::MalformedSchain)),
+            None => None,
+        };
+        let publisher_id = req
+            .site
+            .as_ref()
+            .and_then(|s| s.publisher.as_ref())
+            .or_else(|| req.app.as_ref().and_then(|a| a.publisher.as_ref()))
+            .and_then(|p: &Publisher| p.id.as_deref());
+        match schain {
+            Some(schain) if !schain.nodes.is_empty() => {
+                if self.require_complete && !schain.is_complete() {
+                    return Err(fail(None, None, None, Reason::IncompleteSchain));
+                }
+                let last = schain.nodes.len() - 1;
+                let issuer = &schain.nodes[last];
+                if publisher_id.is_some_and(|id| id != issuer.sid) {
+                    let reason = Reason::PublisherMismatch;
+                    return Err(fail(
+                        Some(last),
+                        Some(&issuer.asi),
+                        Some(&issuer.sid),
+                        reason,
+                    ));
+                }
+                for (i, SupplyChainNode { asi, sid, hp, .. }) in schain.nodes.iter().enumerate() {
+                    let required = if i == 0 {
+                        Relationship::Direct
+                    } else {
+                        Relationship::Reseller
+                    };
+                    self.check_hop(ads_txt, asi, sid, *hp != Some(Bool::False), required)
+                        .map_err(|reason| fail(Some(i), Some(asi), Some(sid), reason))?;
+                }
+                Ok(())
+            }
+            _ => {
+                if self.require_complete {
+                    return Err(fail(None, None, None, Reason::IncompleteSchain));
+                }
+                let sid = publisher_id
+                    .ok_or_else(|| fail(None, Some(&self.own_asi), None, Reason::NoSeller))?;
+                self.check_hop(ads_txt, &self.own_asi, sid, true, Relationship::Direct)
+                    .map_err(|reason| fail(None, Some(&self.own_asi), Some(sid), reason))
+            }
+        }
+    }
+}
+
+/// The lowercase host of a URL such as "https://www.example.com:8080/path".
+fn host(url: &str) -> Option<String> {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let host = rest
+        .split(['/', '?', '#'])
+        .next()?
+        .rsplit('@')
+        .next()?
+        .split(':')
+        .next()?;
+    if host.is_empty() {
+        None
+    } else {
+        Some(host.to_ascii_lowercase())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHAIN_REQUEST: &str = r#"{"id":"1","imp":[],
+        "site":{"page":"https://www.pub.com/a","publisher":{"id":"r-1"}},
+        "source":{"ext":{"schain":{"complete":1,"ver":"1.0","nodes":[
+            {"asi":"ssp.com","sid":"42","hp":1},
+            {"asi":"reseller.com","sid":"r-1","hp":1}]}}}}"#;
+
+    fn sellers(json: &str) -> SellersJson {
+        SellersJson::from_json(json).unwrap()
+    }
+
+    fn authorizer() -> Authorizer {
+        let mut authorizer = Authorizer::new("us.com");
+        authorizer.add_ads_txt(
+            "pub.com",
+            AdsTxt::parse("ssp.com, 42, DIRECT\nreseller.com, r-1, RESELLER\n"),
+        );
+        authorizer
+    }
+
+    #[test]
+    fn parses_ads_txt() {
+        let ads_txt = AdsTxt::parse(
+            "# comment\nCONTACT=ops@pub.com\nssp.com, 42, DIRECT, f08c47fec0942fa0\n\
+             Reseller.com,r-1,RESELLER # x\nbroken line\n",
+        );
+        assert_eq!(ads_txt.records.len(), 2);
+        assert_eq!(ads_txt.invalid_lines, vec![5]);
+        assert_eq!(
+            ads_txt.variables,
+            vec![("CONTACT".to_string(), "ops@pub.com".to_string())]
+        );
+        assert_eq!(
+            ads_txt.relationships("RESELLER.COM", "r-1"),
+            vec![Relationship::Reseller]
+        );
+    }
+
+    #[test]
+    fn checks_every_schain_node() {
+        let request: BidRequest = serde_json::from_str(SCHAIN_REQUEST).unwrap();
+        let mut authorizer = authorizer();
+        authorizer.add_sellers_json(
+            "Reseller.com",
+            sellers(r#"{"sellers":[{"seller_id":"r-1","seller_type":"PUBLISHER"}]}"#),
+        );
+        let err = authorizer.authorize(&request).unwrap_err();
+        assert_eq!(err.node, Some(1));
+        assert_eq!(
+            err.reason,
+            Reason::SellerTypeMismatch(SellerType::Publisher)
+        );
+
+        authorizer.add_sellers_json(
+            "reseller.com",
+            sellers(r#"{"sellers":[{"seller_id":"r-1","seller_type":"intermediary"}]}"#),
+        );
+        assert!(authorizer.authorize(&request).is_ok());
+
+        authorizer.add_sellers_json("ssp.com", sellers(r#"{"sellers":[]}"#));
+        let err = authorizer.authorize(&request).unwrap_err();
+        assert_eq!((err.node, err.reason), (Some(0), Reason::UnknownSeller));
+    }
+
+    #[test]
+    fn requires_direct_first_and_resellers_after() {
+        let request: BidRequest = serde_json::from_str(SCHAIN_REQUEST).unwrap();
+        let mut authorizer = Authorizer::new("us.com");
+        authorizer.add_ads_txt(
+            "pub.com",
+            AdsTxt::parse("ssp.com, 42, RESELLER\nreseller.com, r-1, RESELLER\n"),
+        );
+        let err = authorizer.authorize(&request).unwrap_err();
+        assert_eq!(err.node, Some(0));
+        assert_eq!(
+            err.reason,
+            Reason::RelationshipMismatch(Relationship::Direct)
+        );
+
+        authorizer.add_ads_txt(
+            "pub.com",
+            AdsTxt::parse("ssp.com, 42, DIRECT\nreseller.com, r-1, DIRECT\n"),
+        );
+        let err = authorizer.authorize(&request).unwrap_err();
+        assert_eq!(err.node, Some(1));
+        assert_eq!(
+            err.reason,
+            Reason::RelationshipMismatch(Relationship::Reseller)
+        );
+
+        // Our own account is the first seller when there is no chain.
+        let mut authorizer = Authorizer::new("us.com");
+        authorizer.add_ads_txt("pub.com", AdsTxt::parse("us.com, 42, RESELLER"));
+        let request: BidRequest = serde_json::from_str(
+            r#"{"id":"1","imp":[],"site":{"domain":"pub.com","publisher":{"id":"42"}}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            authorizer.authorize(&request).unwrap_err().reason,
+            Reason::RelationshipMismatch(Relationship::Direct)
+        );
+    }
+
+    #[test]
+    fn checks_publisher_id_against_schain() {
+        let authorizer = authorizer();
+        assert!(authorizer
+            .authorize(&serde_json::from_str(SCHAIN_REQUEST).unwrap())
+            .is_ok());
+
+        // The publisher's own account, the first node, did not issue it.
+        let request: BidRequest =
+            serde_json::from_str(&SCHAIN_REQUEST.replace(r#"{"id":"r-1"}"#, r#"{"id":"42"}"#))
+                .unwrap();
+        let err = authorizer.authorize(&request).unwrap_err();
+        assert_eq!((err.node, err.reason), (Some(1), Reason::PublisherMismatch));
+        assert_eq!(err.sid.as_deref(), Some("r-1"));
+
+        let request: BidRequest = serde_json::from_str(
+            &SCHAIN_REQUEST.replace(r#""publisher":{"id":"r-1"}"#, r#""publisher":{}"#),
+        )
+        .unwrap();
+        assert!(authorizer.authorize(&request).is_ok());
+    }
+
+    #[test]
+    fn unpaid_nodes_need_no_sellers_json_entry() {
+        let request: BidRequest =
+            serde_json::from_str(&SCHAIN_REQUEST.replacen(r#""hp":1"#, r#""hp":0"#, 1)).unwrap();
+        let mut authorizer = authorizer();
+        authorizer.add_sellers_json("ssp.com", sellers(r#"{"sellers":[]}"#));
+        assert!(authorizer.authorize(&request).is_ok());
+    }
+
+    #[test]
+    fn checks_own_account_without_schain() {
+        let authorizer = authorizer();
+        let request: BidRequest = serde_json::from_str(
+            r#"{"id":"1","imp":[],"site":{"domain":"pub.com","publisher":{"id":"42"}}}"#,
+        )
+        .unwrap();
+        let err = authorizer.authorize(&request).unwrap_err();
+        assert_eq!(err.reason, Reason::NotListed);
+        assert_eq!(err.asi.as_deref(), Some("us.com"));
+    }
+
+    #[test]
+    fn matches_app_ads_txt_case_insensitively() {
+        let mut authorizer = authorizer();
+        let request: BidRequest = serde_json::from_str(
+            r#"{"id":"1","imp":[],"app":{"bundle":"com.Example.App","publisher":{"id":"42"}}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            authorizer.authorize(&request).unwrap_err().reason,
+            Reason::NoAdsTxt
+        );
+        authorizer.add_app_ads_txt("COM.example.app", AdsTxt::parse("us.com, 42, DIRECT"));
+        assert!(authorizer.authorize(&request).is_ok());
+    }
+}
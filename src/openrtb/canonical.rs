@@ -0,0 +1,364 @@
+//! Canonical form and content hashing.
+//!
+//! The same auction often reaches a bidder through several exchanges with
+//! differences that do not change its meaning: field order, `[]` or `{}`
+//! instead of an absent field, `0` instead of an absent `Bool`. The
+//! canonical form drops such defaults and sorts object keys, including in
+//! `ext`, so two requests for the same auction serialize to the same
+//! canonical JSON. `ContentHash` is a 128-bit FNV-1a hash of that JSON; it
+//! is stable across processes and releases and leaves out the fields that
+//! differ per hop (`id`, `tmax`, `source.tid`) unless configured otherwise.
+
+use super::fields::{Field, FieldPath, Fields, PathError};
+use super::BidRequest;
+use std::fmt;
+
+/// `Bool` fields whose default, when absent, is true.
+const DEFAULT_TRUE: &[&str] = &["boxingallowed"];
+
+/// `Bool` fields that the spec requires, whose absence is not a default.
+const REQUIRED_BOOL: &[&str] = &["complete", "hp"];
+
+/// Fields that differ between exchanges for the same auction.
+pub const VOLATILE: &[&str] = &["id", "tmax", "source.tid"];
+
+/// Whether a field holds the value absence stands for.
+fn is_default(key: &str, node: &dyn Fields) -> bool {
+    match node.field() {
+        _ if !node.is_present() => true,
+        Field::Bool(b) if !REQUIRED_BOOL.contains(&key) => b == DEFAULT_TRUE.contains(&key),
+        Field::Object(_) | Field::Array(_) => node.keys().is_empty(),
+        _ => false,
+    }
+}
+
+/// Drops defaults below `node`, children first so that objects emptied on
+/// the way are dropped too. `ext` values are left as they are apart from
+/// being dropped when empty.
+fn normalize(node: &mut dyn Fields) {
+    for key in node.keys() {
+        let child = match node.child_mut(&key) {
+            Some(child) => child,
+            None => continue,
+        };
+        if key != "ext" {
+            normalize(child);
+        }
+        if is_default(&key, child) {
+            // Required fields and array elements stay.
+            let _ = child.clear();
+        }
+    }
+}
+
+/// Which fields the canonical JSON and the hash cover. Paths may use `*`
+/// for any array index or key, as in `imp.*.id`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HashConfig {
+    /// Fields to cover; empty for the whole request.
+    pub include: Vec<FieldPath>,
+    /// Fields to leave out.
+    pub exclude: Vec<FieldPath>,
+}
+
+/// The whole request without the `VOLATILE` fields.
+impl Default for HashConfig {
+    fn default() -> Self {
+        HashConfig {
+            include: Vec::new(),
+            exclude: VOLATILE.iter().map(|p| path(p)).collect(),
+        }
+    }
+}
+
+fn path(s: &str) -> FieldPath {
+    FieldPath::from_dotted(s).unwrap_or_default()
+}
+
+impl HashConfig {
+    /// The whole request, volatile fields included.
+    pub fn everything() -> HashConfig {
+        HashConfig {
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+
+    /// Only `fields` (dotted paths or JSON Pointers), without the
+    /// `VOLATILE` fields.
+    pub fn only(fields: &[&str]) -> Result<HashConfig, PathError> {
+        Ok(HashConfig {
+            include: fields
+                .iter()
+                .map(|f| FieldPath::parse(f))
+                .collect::<Result<_, _>>()?,
+            ..HashConfig::default()
+        })
+    }
+
+    /// Whether to write the field at `path`: it is not excluded and is
+    /// included, inside an included field or on the way to one.
+    fn covers(&self, path: &[String]) -> bool {
+        if self.exclude.iter().any(|p| matches(p.tokens(), path)) {
+            return false;
+        }
+        self.include.is_empty()
+            || self.include.iter().any(|p| {
+                let n = p.tokens().len().min(path.len());
+                matches(&p.tokens()[..n], &path[..n])
+            })
+    }
+}
+
+fn matches(pattern: &[String], path: &[String]) -> bool {
+    pattern.len() == path.len() && pattern.iter().zip(path).all(|(p, k)| p == "*" || p == k)
+}
+
+/// Canonical JSON writer.
+struct Writer<'c> {
+    config: &'c HashConfig,
+    path: Vec<String>,
+    out: String,
+}
+
+impl Writer<'_> {
+    /// Writes `node`. `in_ext` keeps defaults, which only typed fields
+    /// have.
+    fn value(&mut self, node: &dyn Fields, in_ext: bool) {
+        match node.field() {
+            Field::Null => self.out.push_str("null"),
+            Field::Bool(b) => self.out.push_str(if b { "true" } else { "false" }),
+            Field::Int(n) | Field::Enum(n, _) => self.out.push_str(&n.to_string()),
+            Field::Float(x) => self.float(x),
+            Field::Str(s) => self.string(s),
+            Field::Array(_) => {
+                self.out.push('[');
+                for (i, key) in node.keys().iter().enumerate() {
+                    if i > 0 {
+                        self.out.push(',');
+                    }
+                    self.path.push(key.clone());
+                    match node.child(key).filter(|_| self.config.covers(&self.path)) {
+                        Some(child) => self.value(child, in_ext),
+                        None => self.out.push_str("null"),
+                    }
+                    self.path.pop();
+                }
+                self.out.push(']');
+            }
+            Field::Object(_) => {
+                let mut keys = node.keys();
+                keys.sort_unstable();
+                self.out.push('{');
+                for key in &keys {
+                    let child = match node.child(key) {
+                        Some(child) if in_ext || !is_default(key, child) => child,
+                        _ => continue,
+                    };
+                    self.path.push(key.clone());
+                    if self.config.covers(&self.path) {
+                        let mark = self.out.len();
+                        if !self.out.ends_with('{') {
+                            self.out.push(',');
+                        }
+                        self.string(key);
+                        self.out.push(':');
+                        let start = self.out.len();
+                        self.value(child, in_ext || key == "ext");
+                        // Objects left empty by dropped defaults, or on the
+                        // way to an included field that hold none of it.
+                        if !in_ext && &self.out[start..] == "{}" {
+                            self.out.truncate(mark);
+                        }
+                    }
+                    self.path.pop();
+                }
+                self.out.push('}');
+            }
+        }
+    }
+
+    /// Integral floats are written as integers, so `1.0` and `1` agree.
+    fn float(&mut self, x: f64) {
+        if x.fract() == 0.0 && x.abs() < 1e15 {
+            self.out.push_str(&(x as i64).to_string());
+        } else {
+            self.out.push_str(&x.to_string());
+        }
+    }
+
+    fn string(&mut self, s: &str) {
+        self.out
+            .push_str(&serde_json::to_string(s).unwrap_or_default());
+    }
+}
+
+/// The canonical JSON of `node` covering the fields of `config`.
+pub fn canonical_json(node: &dyn Fields, config: &HashConfig) -> String {
+    let mut writer = Writer {
+        config,
+        path: Vec::new(),
+        out: String::new(),
+    };
+    writer.value(node, false);
+    writer.out
+}
+
+/// A stable 128-bit content hash.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ContentHash(pub u128);
+
+impl ContentHash {
+    /// FNV-1a over `bytes`.
+    pub fn of(bytes: &[u8]) -> ContentHash {
+        const OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+        const PRIME: u128 = 0x0000000001000000000000000000013b;
+        let hash = bytes.iter().fold(OFFSET, |hash, &b| {
+            (hash ^ u128::from(b)).wrapping_mul(PRIME)
+        });
+        ContentHash(hash)
+    }
+}
+
+/// 32 lowercase hex digits.
+impl fmt::Display for ContentHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl BidRequest {
+    /// Drops defaults in place: empty arrays and objects, and `Bool`s at
+    /// their default value (false, or true for `video.boxingallowed`). The
+    /// required `schain.complete` and node `hp` are kept.
+    pub fn canonicalize(&mut self) {
+        normalize(self);
+    }
+
+    /// The canonical JSON of the request, volatile fields left out.
+    pub fn canonical_json(&self) -> String {
+        canonical_json(self, &HashConfig::default())
+    }
+
+    /// The content hash of the request, volatile fields left out.
+    pub fn content_hash(&self) -> ContentHash {
+        self.content_hash_with(&HashConfig::default())
+    }
+
+    /// The content hash of the fields `config` covers.
+    pub fn content_hash_with(&self, config: &HashConfig) -> ContentHash {
+        ContentHash::of(canonical_json(self, config).as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(json: &str) -> BidRequest {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn verbose() -> BidRequest {
+        request(
+            r#"{"id":"x1","tmax":100,"test":0,"bcat":[],
+            "imp":[{"id":"1","secure":0,"banner":{"w":300,"h":250,"btype":[]},
+                "video":{"boxingallowed":1,"mimes":["a"]},"ext":{"b":2,"a":{"z":false,"y":[]}}}],
+            "source":{"tid":"t1","schain":{"complete":0,"ver":"1.0","nodes":[{"asi":"a","sid":"s","hp":1}]}},
+            "device":{"ua":"u","geo":{}},"regs":{"coppa":0},"user":{"ext":{}}}"#,
+        )
+    }
+
+    fn terse() -> BidRequest {
+        request(
+            r#"{"regs":{},"source":{"schain":{"nodes":[{"hp":1,"sid":"s","asi":"a"}],"ver":"1.0","complete":0},"tid":"t2"},
+            "device":{"ua":"u"},"tmax":250,"id":"y2",
+            "imp":[{"ext":{"a":{"y":[],"z":false},"b":2.0},"video":{"mimes":["a"]},"banner":{"h":250,"w":300},"id":"1"}]}"#,
+        )
+    }
+
+    #[test]
+    fn equivalent_requests_hash_alike() {
+        let (a, b) = (verbose(), terse());
+        assert_eq!(a.canonical_json(), b.canonical_json());
+        assert_eq!(a.content_hash(), b.content_hash());
+        assert_eq!(a.content_hash().to_string().len(), 32);
+        assert_ne!(
+            a.content_hash_with(&HashConfig::everything()),
+            b.content_hash_with(&HashConfig::everything())
+        );
+
+        let mut c = b.clone();
+        c.device.as_mut().unwrap().ua = Some("other".to_string());
+        assert_ne!(c.content_hash(), b.content_hash());
+    }
+
+    #[test]
+    fn defaults_equal_absent_fields() {
+        let absent = request(r#"{"id":"1","imp":[{"id":"1"}]}"#);
+        for json in [
+            r#"{"id":"1","imp":[{"id":"1"}],"bcat":[]}"#,
+            r#"{"id":"1","imp":[{"id":"1","secure":0}],"test":0}"#,
+            r#"{"imp":[{"id":"1","ext":{}}],"id":"1","user":{"ext":{}}}"#,
+        ] {
+            assert_eq!(
+                request(json).canonical_json(),
+                absent.canonical_json(),
+                "{}",
+                json
+            );
+        }
+        let secure = request(r#"{"id":"1","imp":[{"id":"1","secure":1}]}"#);
+        assert_ne!(secure.canonical_json(), absent.canonical_json());
+    }
+
+    #[test]
+    fn canonicalize_drops_defaults() {
+        let mut request = verbose();
+        request.canonicalize();
+        let json = serde_json::to_value(&request).unwrap();
+        for field in ["bcat", "test", "regs", "user"] {
+            assert!(json.get(field).is_none(), "{}", field);
+        }
+        assert!(json["device"].get("geo").is_none());
+        assert!(json["imp"][0].get("secure").is_none());
+        assert!(json["imp"][0]["video"].get("boxingallowed").is_none());
+        assert_eq!(json["source"]["schain"]["complete"], 0);
+        assert_eq!(json["source"]["schain"]["nodes"][0]["hp"], 1);
+        assert_eq!(request.canonical_json(), verbose().canonical_json());
+    }
+
+    #[test]
+    fn hashes_selected_fields() {
+        let mut request = terse();
+        request.device.as_mut().unwrap().ua = Some("other".to_string());
+        let only = HashConfig::only(&["imp.*.banner", "device.geo"]).unwrap();
+        assert_eq!(
+            canonical_json(&request, &only),
+            r#"{"imp":[{"banner":{"h":250,"w":300}}]}"#
+        );
+        assert_eq!(
+            request.content_hash_with(&only),
+            verbose().content_hash_with(&only)
+        );
+
+        let mut only = HashConfig::only(&["imp"]).unwrap();
+        only.exclude.push("imp.*.ext".parse().unwrap());
+        assert_eq!(
+            canonical_json(&request, &only),
+            r#"{"imp":[{"banner":{"h":250,"w":300},"id":"1","video":{"mimes":["a"]}}]}"#
+        );
+    }
+
+    #[test]
+    fn hash_is_fnv1a() {
+        assert_eq!(
+            ContentHash::of(b"").to_string(),
+            "6c62272e07bb014262b821756295c58d"
+        );
+        assert_eq!(
+            ContentHash::of(b"a").to_string(),
+            "d228cb696f1a8caf78912b704e4a8964"
+        );
+    }
+}
@@ -0,0 +1,733 @@
+//! Typed country, region and language codes.
+//!
+//! OpenRTB 2.5 requires ISO-3166-1 alpha-3 country codes in `Geo.country`,
+//! ISO-3166-2 region codes in `Geo.region` and ISO-639-1 language codes in
+//! `language` fields and `wlang`. Partners often send alpha-2 countries or
+//! locale tags such as "en-US" instead; the types here accept both and
+//! normalize to the form the specification requires.
+
+use super::bid_request::{Content, Device, Geo};
+use super::bid_response::seat_bid::Bid;
+use super::BidRequest;
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// ISO 3166-1 countries as (alpha-2, alpha-3), sorted by alpha-2.
+pub(crate) const COUNTRIES: &[(&str, &str)] = &[
+    ("AD", "AND"),
+    ("AE", "ARE"),
+    ("AF", "AFG"),
+    ("AG", "ATG"),
+    ("AI", "AIA"),
+    ("AL", "ALB"),
+    ("AM", "ARM"),
+    ("AO", "AGO"),
+    ("AQ", "ATA"),
+    ("AR", "ARG"),
+    ("AS", "ASM"),
+    ("AT", "AUT"),
+    ("AU", "AUS"),
+    ("AW", "ABW"),
+    ("AX", "ALA"),
+    ("AZ", "AZE"),
+    ("BA", "BIH"),
+    ("BB", "BRB"),
+    ("BD", "BGD"),
+    ("BE", "BEL"),
+    ("BF", "BFA"),
+    ("BG", "BGR"),
+    ("BH", "BHR"),
+    ("BI", "BDI"),
+    ("BJ", "BEN"),
+    ("BL", "BLM"),
+    ("BM", "BMU"),
+    ("BN", "BRN"),
+    ("BO", "BOL"),
+    ("BQ", "BES"),
+    ("BR", "BRA"),
+    ("BS", "BHS"),
+    ("BT", "BTN"),
+    ("BV", "BVT"),
+    ("BW", "BWA"),
+    ("BY", "BLR"),
+    ("BZ", "BLZ"),
+    ("CA", "CAN"),
+    ("CC", "CCK"),
+    ("CD", "COD"),
+    ("CF", "CAF"),
+    ("CG", "COG"),
+    ("CH", "CHE"),
+    ("CI", "CIV"),
+    ("CK", "COK"),
+    ("CL", "CHL"),
+    ("CM", "CMR"),
+    ("CN", "CHN"),
+    ("CO", "COL"),
+    ("CR", "CRI"),
+    ("CU", "CUB"),
+    ("CV", "CPV"),
+    ("CW", "CUW"),
+    ("CX", "CXR"),
+    ("CY", "CYP"),
+    ("CZ", "CZE"),
+    ("DE", "DEU"),
+    ("DJ", "DJI"),
+    ("DK", "DNK"),
+    ("DM", "DMA"),
+    ("DO", "DOM"),
+    ("DZ", "DZA"),
+    ("EC", "ECU"),
+    ("EE", "EST"),
+    ("EG", "EGY"),
+    ("EH", "ESH"),
+    ("ER", "ERI"),
+    ("ES", "ESP"),
+    ("ET", "ETH"),
+    ("FI", "FIN"),
+    ("FJ", "FJI"),
+    ("FK", "FLK"),
+    ("FM", "FSM"),
+    ("FO", "FRO"),
+    ("FR", "FRA"),
+    ("GA", "GAB"),
+    ("GB", "GBR"),
+    ("GD", "GRD"),
+    ("GE", "GEO"),
+    ("GF", "GUF"),
+    ("GG", "GGY"),
+    ("GH", "GHA"),
+    ("GI", "GIB"),
+    ("GL", "GRL"),
+    ("GM", "GMB"),
+    ("GN", "GIN"),
+    ("GP", "GLP"),
+    ("GQ", "GNQ"),
+    ("GR", "GRC"),
+    ("GS", "SGS"),
+    ("GT", "GTM"),
+    ("GU", "GUM"),
+    ("GW", "GNB"),
+    ("GY", "GUY"),
+    ("HK", "HKG"),
+    ("HM", "HMD"),
+    ("HN", "HND"),
+    ("HR", "HRV"),
+    ("HT", "HTI"),
+    ("HU", "HUN"),
+    ("ID", "IDN"),
+    ("IE", "IRL"),
+    ("IL", "ISR"),
+    ("IM", "IMN"),
+    ("IN", "IND"),
+    ("IO", "IOT"),
+    ("IQ", "IRQ"),
+    ("IR", "IRN"),
+    ("IS", "ISL"),
+    ("IT", "ITA"),
+    ("JE", "JEY"),
+    ("JM", "JAM"),
+    ("JO", "JOR"),
+    ("JP", "JPN"),
+    ("KE", "KEN"),
+    ("KG", "KGZ"),
+    ("KH", "KHM"),
+    ("KI", "KIR"),
+    ("KM", "COM"),
+    ("KN", "KNA"),
+    ("KP", "PRK"),
+    ("KR", "KOR"),
+    ("KW", "KWT"),
+    ("KY", "CYM"),
+    ("KZ", "KAZ"),
+    ("LA", "LAO"),
+    ("LB", "LBN"),
+    ("LC", "LCA"),
+    ("LI", "LIE"),
+    ("LK", "LKA"),
+    ("LR", "LBR"),
+    ("LS", "LSO"),
+    ("LT", "LTU"),
+    ("LU", "LUX"),
+    ("LV", "LVA"),
+    ("LY", "LBY"),
+    ("MA", "MAR"),
+    ("MC", "MCO"),
+    ("MD", "MDA"),
+    ("ME", "MNE"),
+    ("MF", "MAF"),
+    ("MG", "MDG"),
+    ("MH", "MHL"),
+    ("MK", "MKD"),
+    ("ML", "MLI"),
+    ("MM", "MMR"),
+    ("MN", "MNG"),
+    ("MO", "MAC"),
+    ("MP", "MNP"),
+    ("MQ", "MTQ"),
+    ("MR", "MRT"),
+    ("MS", "MSR"),
+    ("MT", "MLT"),
+    ("MU", "MUS"),
+    ("MV", "MDV"),
+    ("MW", "MWI"),
+    ("MX", "MEX"),
+    ("MY", "MYS"),
+    ("MZ", "MOZ"),
+    ("NA", "NAM"),
+    ("NC", "NCL"),
+    ("NE", "NER"),
+    ("NF", "NFK"),
+    ("NG", "NGA"),
+    ("NI", "NIC"),
+    ("NL", "NLD"),
+    ("NO", "NOR"),
+    ("NP", "NPL"),
+    ("NR", "NRU"),
+    ("NU", "NIU"),
+    ("NZ", "NZL"),
+    ("OM", "OMN"),
+    ("PA", "PAN"),
+    ("PE", "PER"),
+    ("PF", "PYF"),
+    ("PG", "PNG"),
+    ("PH", "PHL"),
+    ("PK", "PAK"),
+    ("PL", "POL"),
+    ("PM", "SPM"),
+    ("PN", "PCN"),
+    ("PR", "PRI"),
+    ("PS", "PSE"),
+    ("PT", "PRT"),
+    ("PW", "PLW"),
+    ("PY", "PRY"),
+    ("QA", "QAT"),
+    ("RE", "REU"),
+    ("RO", "ROU"),
+    ("RS", "SRB"),
+    ("RU", "RUS"),
+    ("RW", "RWA"),
+    ("SA", "SAU"),
+    ("SB", "SLB"),
+    ("SC", "SYC"),
+    ("SD", "SDN"),
+    ("SE", "SWE"),
+    ("SG", "SGP"),
+    ("SH", "SHN"),
+    ("SI", "SVN"),
+    ("SJ", "SJM"),
+    ("SK", "SVK"),
+    ("SL", "SLE"),
+    ("SM", "SMR"),
+    ("SN", "SEN"),
+    ("SO", "SOM"),
+    ("SR", "SUR"),
+    ("SS", "SSD"),
+    ("ST", "STP"),
+    ("SV", "SLV"),
+    ("SX", "SXM"),
+    ("SY", "SYR"),
+    ("SZ", "SWZ"),
+    ("TC", "TCA"),
+    ("TD", "TCD"),
+    ("TF", "ATF"),
+    ("TG", "TGO"),
+    ("TH", "THA"),
+    ("TJ", "TJK"),
+    ("TK", "TKL"),
+    ("TL", "TLS"),
+    ("TM", "TKM"),
+    ("TN", "TUN"),
+    ("TO", "TON"),
+    ("TR", "TUR"),
+    ("TT", "TTO"),
+    ("TV", "TUV"),
+    ("TW", "TWN"),
+    ("TZ", "TZA"),
+    ("UA", "UKR"),
+    ("UG", "UGA"),
+    ("UM", "UMI"),
+    ("US", "USA"),
+    ("UY", "URY"),
+    ("UZ", "UZB"),
+    ("VA", "VAT"),
+    ("VC", "VCT"),
+    ("VE", "VEN"),
+    ("VG", "VGB"),
+    ("VI", "VIR"),
+    ("VN", "VNM"),
+    ("VU", "VUT"),
+    ("WF", "WLF"),
+    ("WS", "WSM"),
+    ("YE", "YEM"),
+    ("YT", "MYT"),
+    ("ZA", "ZAF"),
+    ("ZM", "ZMB"),
+    ("ZW", "ZWE"),
+];
+
+/// The alpha-3 code of an alpha-2 country code (case-insensitive).
+pub(crate) fn alpha3_of(alpha2: &str) -> Option<&'static str> {
+    let alpha2 = alpha2.to_ascii_uppercase();
+    COUNTRIES
+        .binary_search_by(|(a2, _)| (*a2).cmp(alpha2.as_str()))
+        .ok()
+        .map(|i| COUNTRIES[i].1)
+}
+
+/// ISO 639-1 language codes, sorted.
+const LANGUAGES: &[&str] = &[
+    "aa", "ab", "ae", "af", "ak", "am", "an", "ar", "as", "av", "ay", "az", "ba", "be", "bg", "bh",
+    "bi", "bm", "bn", "bo", "br", "bs", "ca", "ce", "ch", "co", "cr", "cs", "cu", "cv", "cy", "da",
+    "de", "dv", "dz", "ee", "el", "en", "eo", "es", "et", "eu", "fa", "ff", "fi", "fj", "fo", "fr",
+    "fy", "ga", "gd", "gl", "gn", "gu", "gv", "ha", "he", "hi", "ho", "hr", "ht", "hu", "hy", "hz",
+    "ia", "id", "ie", "ig", "ii", "ik", "io", "is", "it", "iu", "ja", "jv", "ka", "kg", "ki", "kj",
+    "kk", "kl", "km", "kn", "ko", "kr", "ks", "ku", "kv", "kw", "ky", "la", "lb", "lg", "li", "ln",
+    "lo", "lt", "lu", "lv", "mg", "mh", "mi", "mk", "ml", "mn", "mr", "ms", "mt", "my", "na", "nb",
+    "nd", "ne", "ng", "nl", "nn", "no", "nr", "nv", "ny", "oc", "oj", "om", "or", "os", "pa", "pi",
+    "pl", "ps", "pt", "qu", "rm", "rn", "ro", "ru", "rw", "sa", "sc", "sd", "se", "sg", "si", "sk",
+    "sl", "sm", "sn", "so", "sq", "sr", "ss", "st", "su", "sv", "sw", "ta", "te", "tg", "th", "ti",
+    "tk", "tl", "tn", "to", "tr", "ts", "tt", "tw", "ty", "ug", "uk", "ur", "uz", "ve", "vi", "vo",
+    "wa", "wo", "xh", "yi", "yo", "za", "zh", "zu",
+];
+
+/// Kind of code that failed to parse.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CodeKind {
+    /// An ISO-3166-1 country code.
+    Country,
+    /// An ISO-3166-2 region code.
+    Region,
+    /// An ISO-639-1 language code.
+    Language,
+}
+
+/// Error returned when a string is not a valid code of the given kind.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidCode(pub CodeKind);
+
+impl fmt::Display for InvalidCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid code ({:?})", self.0)
+    }
+}
+
+impl std::error::Error for InvalidCode {}
+
+/// An ISO-3166-1 country. Parses from alpha-2 or alpha-3 in any case and
+/// displays as alpha-3, the form OpenRTB uses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Country(u8);
+
+impl Country {
+    /// The country with alpha-2 code `code`, e.g. "US" or "us".
+    pub fn from_alpha2(code: &str) -> Result<Country, InvalidCode> {
+        let code = code.to_ascii_uppercase();
+        COUNTRIES
+            .binary_search_by(|(a2, _)| (*a2).cmp(code.as_str()))
+            .map(|i| Country(i as u8))
+            .map_err(|_| InvalidCode(CodeKind::Country))
+    }
+
+    /// The country with alpha-3 code `code`, e.g. "USA" or "usa".
+    pub fn from_alpha3(code: &str) -> Result<Country, InvalidCode> {
+        COUNTRIES
+            .iter()
+            .position(|(_, a3)| a3.eq_ignore_ascii_case(code))
+            .map(|i| Country(i as u8))
+            .ok_or(InvalidCode(CodeKind::Country))
+    }
+
+    /// Parses an alpha-2 or alpha-3 code, ignoring case and surrounding
+    /// whitespace.
+    pub fn parse(code: &str) -> Result<Country, InvalidCode> {
+        let code = code.trim();
+        match code.len() {
+            2 => Country::from_alpha2(code),
+            3 => Country::from_alpha3(code),
+            _ => Err(InvalidCode(CodeKind::Country)),
+        }
+    }
+
+    /// The alpha-2 code, e.g. "US".
+    pub fn alpha2(self) -> &'static str {
+        COUNTRIES[self.0 as usize].0
+    }
+
+    /// The alpha-3 code, e.g. "USA".
+    pub fn alpha3(self) -> &'static str {
+        COUNTRIES[self.0 as usize].1
+    }
+}
+
+impl fmt::Display for Country {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.alpha3())
+    }
+}
+
+impl FromStr for Country {
+    type Err = InvalidCode;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Country::parse(s)
+    }
+}
+
+impl Serialize for Country {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.alpha3())
+    }
+}
+
+impl<'de> Deserialize<'de> for Country {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        Country::parse(&s).map_err(de::Error::custom)
+    }
+}
+
+/// An ISO-3166-2 region such as "US-CA". The country prefix is optional,
+/// since OpenRTB allows the bare 2-letter state code for the USA.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Region {
+    country: Option<Country>,
+    subdivision: String,
+}
+
+impl Region {
+    /// Parses "US-CA", "us-ca" or "CA". The subdivision is 1 to 3 letters
+    /// or digits and the prefix, if any, an alpha-2 country.
+    pub fn parse(code: &str) -> Result<Region, InvalidCode> {
+        let code = code.trim();
+        let (country, subdivision) = match code.split_once('-') {
+            Some((country, sub)) => (
+                Some(Country::from_alpha2(country).map_err(|_| InvalidCode(CodeKind::Region))?),
+                sub,
+            ),
+            None => (None, code),
+        };
+        if !(1..=3).contains(&subdivision.len())
+            || !subdivision.bytes().all(|b| b.is_ascii_alphanumeric())
+        {
+            return Err(InvalidCode(CodeKind::Region));
+        }
+        Ok(Region {
+            country,
+            subdivision: subdivision.to_ascii_uppercase(),
+        })
+    }
+
+    /// The country prefix, if present.
+    pub fn country(&self) -> Option<Country> {
+        self.country
+    }
+
+    /// The subdivision part, e.g. "CA".
+    pub fn subdivision(&self) -> &str {
+        &self.subdivision
+    }
+
+    /// Sets the country prefix if it is missing.
+    pub fn with_country(mut self, country: Country) -> Region {
+        self.country.get_or_insert(country);
+        self
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.country {
+            Some(country) => write!(f, "{}-{}", country.alpha2(), self.subdivision),
+            None => f.write_str(&self.subdivision),
+        }
+    }
+}
+
+impl FromStr for Region {
+    type Err = InvalidCode;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Region::parse(s)
+    }
+}
+
+impl Serialize for Region {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Region {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        Region::parse(&s).map_err(de::Error::custom)
+    }
+}
+
+/// An ISO-639-1 language. Parses from a bare code or a locale such as
+/// "en-US" or "pt_BR" in any case, keeping only the language.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Language([u8; 2]);
+
+impl Language {
+    /// Parses a language code or locale tag. The withdrawn codes "iw", "in"
+    /// and "ji", which Java still emits, map to "he", "id" and "yi".
+    pub fn parse(code: &str) -> Result<Language, InvalidCode> {
+        let primary = code.trim().split(['-', '_']).next().unwrap_or_default();
+        let primary = match primary.to_ascii_lowercase().as_str() {
+            "iw" => "he".to_string(),
+            "in" => "id".to_string(),
+            "ji" => "yi".to_string(),
+            other => other.to_string(),
+        };
+        match LANGUAGES.binary_search(&primary.as_str()) {
+            Ok(_) => {
+                let b = primary.as_bytes();
+                Ok(Language([b[0], b[1]]))
+            }
+            Err(_) => Err(InvalidCode(CodeKind::Language)),
+        }
+    }
+
+    /// The lowercase code, e.g. "en".
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.0).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Language {
+    type Err = InvalidCode;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Language::parse(s)
+    }
+}
+
+impl Serialize for Language {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Language {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        Language::parse(&s).map_err(de::Error::custom)
+    }
+}
+
+/// Rewrites `field` in canonical form if it parses; returns false if it is
+/// present but invalid.
+fn normalize<T: fmt::Display>(
+    field: &mut Option<String>,
+    parse: impl Fn(&str) -> Result<T, InvalidCode>,
+) -> bool {
+    match field.as_deref().map(parse) {
+        Some(Ok(code)) => {
+            *field = Some(code.to_string());
+            true
+        }
+        Some(Err(_)) => false,
+        None => true,
+    }
+}
+
+impl Geo {
+    /// The country, from `country` in either alpha-2 or alpha-3 form.
+    pub fn country_code(&self) -> Option<Result<Country, InvalidCode>> {
+        self.country.as_deref().map(Country::parse)
+    }
+
+    /// The region, with the country prefix taken from `country` when
+    /// `region` is a bare subdivision code.
+    pub fn region_code(&self) -> Option<Result<Region, InvalidCode>> {
+        let region = Region::parse(self.region.as_deref()?);
+        Some(match self.country_code() {
+            Some(Ok(country)) => region.map(|r| r.with_country(country)),
+            _ => region,
+        })
+    }
+
+    /// Rewrites `country` as alpha-3 and `region` in upper case. Returns the
+    /// names of the fields left unchanged because they are invalid.
+    pub fn normalize_codes(&mut self) -> Vec<&'static str> {
+        let mut invalid = Vec::new();
+        if !normalize(&mut self.country, Country::parse) {
+            invalid.push("country");
+        }
+        if !normalize(&mut self.region, Region::parse) {
+            invalid.push("region");
+        }
+        invalid
+    }
+}
+
+impl Device {
+    /// The browser language, reduced to ISO-639-1.
+    pub fn language_code(&self) -> Option<Result<Language, InvalidCode>> {
+        self.language.as_deref().map(Language::parse)
+    }
+}
+
+impl Content {
+    /// The content language, reduced to ISO-639-1.
+    pub fn language_code(&self) -> Option<Result<Language, InvalidCode>> {
+        self.language.as_deref().map(Language::parse)
+    }
+}
+
+impl Bid {
+    /// The creative language, reduced to ISO-639-1.
+    pub fn language_code(&self) -> Option<Result<Language, InvalidCode>> {
+        self.language.as_deref().map(Language::parse)
+    }
+}
+
+impl BidRequest {
+    /// The `wlang` allowlist, reduced to ISO-639-1.
+    pub fn wlang_codes(&self) -> Option<Result<Vec<Language>, InvalidCode>> {
+        let wlang = self.wlang.as_ref()?;
+        Some(wlang.iter().map(|l| Language::parse(l)).collect())
+    }
+
+    /// Whether `wlang` allows creatives in `language`. Absent `wlang` and
+    /// entries that are not valid codes impose no restriction.
+    pub fn allows_language(&self, language: Language) -> bool {
+        match &self.wlang {
+            Some(wlang) => {
+                let allowed: Vec<Language> = wlang
+                    .iter()
+                    .filter_map(|l| Language::parse(l).ok())
+                    .collect();
+                allowed.is_empty() || allowed.contains(&language)
+            }
+            None => true,
+        }
+    }
+
+    /// Rewrites the country, region and language codes of the device, user
+    /// and content objects and of `wlang` in the form OpenRTB requires.
+    /// Returns the paths of the fields left unchanged because they are
+    /// invalid.
+    pub fn normalize_codes(&mut self) -> Vec<String> {
+        let mut invalid = Vec::new();
+        let geos = [
+            (
+                "device.geo",
+                self.device.as_mut().and_then(|d| d.geo.as_mut()),
+            ),
+            ("user.geo", self.user.as_mut().and_then(|u| u.geo.as_mut())),
+        ];
+        for (path, geo) in geos {
+            if let Some(geo) = geo {
+                for field in geo.normalize_codes() {
+                    invalid.push(format!("{}.{}", path, field));
+                }
+            }
+        }
+        let languages = [
+            (
+                "device.language",
+                self.device.as_mut().map(|d| &mut d.language),
+            ),
+            (
+                "site.content.language",
+                self.site
+                    .as_mut()
+                    .and_then(|s| s.content.as_mut())
+                    .map(|c| &mut c.language),
+            ),
+            (
+                "app.content.language",
+                self.app
+                    .as_mut()
+                    .and_then(|a| a.content.as_mut())
+                    .map(|c| &mut c.language),
+            ),
+        ];
+        for (path, field) in languages {
+            if let Some(field) = field {
+                if !normalize(field, Language::parse) {
+                    invalid.push(path.to_string());
+                }
+            }
+        }
+        for (i, lang) in self.wlang.iter_mut().flatten().enumerate() {
+            match Language::parse(lang) {
+                Ok(code) => *lang = code.to_string(),
+                Err(_) => invalid.push(format!("wlang[{}]", i)),
+            }
+        }
+        invalid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::BidRequest;
+    use super::*;
+
+    #[test]
+    fn converts_countries() {
+        let us = Country::parse("us").unwrap();
+        assert_eq!(us, Country::parse(" USA ").unwrap());
+        assert_eq!((us.alpha2(), us.alpha3()), ("US", "USA"));
+        assert!(Country::parse("XX").is_err());
+        assert!(Country::from_alpha3("US").is_err());
+        assert_eq!(alpha3_of("de"), Some("DEU"));
+        assert!(COUNTRIES.windows(2).all(|w| w[0].0 < w[1].0));
+
+        assert_eq!(serde_json::to_string(&us).unwrap(), "\"USA\"");
+        let gb: Country = serde_json::from_str("\"gb\"").unwrap();
+        assert_eq!(gb.alpha3(), "GBR");
+    }
+
+    #[test]
+    fn parses_regions_and_languages() {
+        assert_eq!(Region::parse("us-ca").unwrap().to_string(), "US-CA");
+        assert!(Region::parse("XX-CA").is_err());
+        let region: Region = serde_json::from_str("\"gb-eng\"").unwrap();
+        assert_eq!(serde_json::to_string(&region).unwrap(), "\"GB-ENG\"");
+        assert!(serde_json::from_str::<Region>("\"GB-ENGL\"").is_err());
+        assert_eq!(Language::parse("en_US").unwrap().as_str(), "en");
+        assert_eq!(Language::parse("iw-IL").unwrap().as_str(), "he");
+        assert!(Language::parse("xx").is_err());
+    }
+
+    #[test]
+    fn normalizes_request_codes() {
+        let mut request: BidRequest = serde_json::from_str(
+            r#"{"id":"1","imp":[],"wlang":["EN-gb","zz"],
+                "device":{"language":"pt_BR","geo":{"country":"us","region":"ca"}},
+                "user":{"geo":{"country":"Nowhere"}},
+                "site":{"content":{"language":"FR"}}}"#,
+        )
+        .unwrap();
+        let geo = request.device.as_ref().unwrap().geo.as_ref().unwrap();
+        assert_eq!(geo.region_code().unwrap().unwrap().to_string(), "US-CA");
+
+        assert_eq!(
+            request.normalize_codes(),
+            vec!["user.geo.country".to_string(), "wlang[1]".to_string()]
+        );
+        let device = request.device.as_ref().unwrap();
+        let geo = device.geo.as_ref().unwrap();
+        assert_eq!(geo.country.as_deref(), Some("USA"));
+        assert_eq!(geo.region.as_deref(), Some("CA"));
+        assert_eq!(device.language.as_deref(), Some("pt"));
+        let content = request.site.as_ref().unwrap().content.as_ref().unwrap();
+        assert_eq!(content.language.as_deref(), Some("fr"));
+        assert_eq!(request.wlang.as_ref().unwrap()[0], "en");
+
+        assert!(request.allows_language(Language::parse("en").unwrap()));
+        assert!(!request.allows_language(Language::parse("de").unwrap()));
+    }
+}
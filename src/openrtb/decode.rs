@@ -0,0 +1,445 @@
+//! Decoding with diagnostics that point at the offending field.
+//!
+//! serde only reports a line and column on failure. On error, the functions
+//! here re-scan the input to find the JSON path of the value at that
+//! position, and attach the offending value, what was expected, and the
+//! object and section of the OpenRTB 2.5 specification it belongs to.
+
+use super::scan::{self, Event};
+use super::{BidRequest, BidResponse, NoBidReason};
+use serde::de::DeserializeOwned;
+use serde_json::error::Category;
+use std::fmt;
+
+/// Longest offending value (in bytes) kept in a `DecodeError`.
+const MAX_VALUE_LEN: usize = 64;
+
+/// A decoding failure with its location in OpenRTB terms.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    /// Path of the offending value, e.g. `imp[2].video.protocols[1]`.
+    /// Empty for the root object.
+    pub path: String,
+
+    /// OpenRTB object the value belongs to, e.g. `Video`.
+    pub object: Object,
+
+    /// What the decoder expected, e.g. `u8` or `one of 1, 2, 3`.
+    pub expected: Option<String>,
+
+    /// The offending value as it appears in the input, possibly truncated.
+    pub value: Option<String>,
+
+    /// The decoder's message, without position.
+    pub message: String,
+
+    /// Kind of failure.
+    pub category: Category,
+
+    /// 1-based line of the error.
+    pub line: usize,
+
+    /// 1-based column of the error.
+    pub column: usize,
+}
+
+impl DecodeError {
+    /// The no-bid reason to answer with.
+    pub fn no_bid_reason(&self) -> NoBidReason {
+        NoBidReason::InvalidRequest
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = if self.path.is_empty() {
+            "$"
+        } else {
+            &self.path
+        };
+        write!(
+            f,
+            "{} ({}, section {}): {}",
+            path,
+            self.object.name(),
+            self.object.section(),
+            self.message
+        )?;
+        if let Some(value) = &self.value {
+            write!(f, "; got {}", value)?;
+        }
+        write!(f, " (line {} column {})", self.line, self.column)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<DecodeError> for NoBidReason {
+    fn from(e: DecodeError) -> NoBidReason {
+        e.no_bid_reason()
+    }
+}
+
+/// Decodes a `BidRequest`, reporting failures with their JSON path.
+pub fn bid_request_from_str(input: &str) -> Result<BidRequest, DecodeError> {
+    from_str(input, Object::BidRequest)
+}
+
+/// Decodes a `BidRequest` from bytes, reporting failures with their JSON path.
+pub fn bid_request_from_slice(input: &[u8]) -> Result<BidRequest, DecodeError> {
+    from_slice(input, Object::BidRequest)
+}
+
+/// Decodes a `BidResponse`, reporting failures with their JSON path.
+pub fn bid_response_from_str(input: &str) -> Result<BidResponse, DecodeError> {
+    from_str(input, Object::BidResponse)
+}
+
+/// Decodes a `BidResponse` from bytes, reporting failures with their JSON path.
+pub fn bid_response_from_slice(input: &[u8]) -> Result<BidResponse, DecodeError> {
+    from_slice(input, Object::BidResponse)
+}
+
+fn from_str<T: DeserializeOwned>(input: &str, root: Object) -> Result<T, DecodeError> {
+    serde_json::from_str(input).map_err(|e| locate(input, root, e))
+}
+
+fn from_slice<T: DeserializeOwned>(input: &[u8], root: Object) -> Result<T, DecodeError> {
+    serde_json::from_slice(input).map_err(|e| match std::str::from_utf8(input) {
+        Ok(input) => locate(input, root, e),
+        Err(_) => locate("", root, e),
+    })
+}
+
+/// Builds a `DecodeError` from a serde_json error on `input`.
+fn locate(input: &str, root: Object, e: serde_json::Error) -> DecodeError {
+    let suffix = format!(" at line {} column {}", e.line(), e.column());
+    let full = e.to_string();
+    let message = full.strip_suffix(&suffix).unwrap_or(&full).to_string();
+    let expected = message
+        .split_once(", expected ")
+        .map(|(_, expected)| expected.to_string());
+
+    // A missing field is reported at the end of its parent object.
+    let missing = message
+        .strip_prefix("missing field `")
+        .and_then(|m| m.strip_suffix('`'));
+
+    // Find the last event before the error. Errors raised by a value without
+    // a position get the position of its enclosing array or object once that
+    // is closed, so a close is attributed to the last value inside it.
+    let offset = offset_of(input, e.line(), e.column());
+    let mut path = Vec::new();
+    let mut value_path = Vec::new();
+    let mut value = None;
+    let mut closed = false;
+    let _ = scan::scan(input, |p, start, event| {
+        if start >= offset {
+            return Err(());
+        }
+        path.clear();
+        path.extend_from_slice(p);
+        closed = matches!(event, Event::Close { .. });
+        let v = match event {
+            Event::Open { array: true } => "[…]".to_string(),
+            Event::Open { array: false } => "{…}".to_string(),
+            Event::Str { len } => truncate(&input[start..start + len + 2]),
+            Event::Scalar => truncate(&input[start..scan::scalar_end(input.as_bytes(), start)]),
+            Event::Key { .. } => {
+                value = None;
+                return Ok(());
+            }
+            Event::Close { .. } => return Ok(()),
+        };
+        value_path.clear();
+        value_path.extend_from_slice(p);
+        value = Some(v);
+        Ok(())
+    });
+    if missing.is_some() {
+        value = None;
+    } else if closed && value.is_some() {
+        path = value_path;
+    }
+
+    let mut object = root;
+    for key in scan::keys(&path).chain(missing) {
+        if key == "ext" {
+            break;
+        }
+        if let Some(child) = object.child(key) {
+            object = child;
+        }
+    }
+    let mut path = scan::format_path(&path);
+    if let Some(field) = missing {
+        if !path.is_empty() {
+            path.push('.');
+        }
+        path.push_str(field);
+    }
+
+    DecodeError {
+        path,
+        object,
+        expected,
+        value,
+        message,
+        category: e.classify(),
+        line: e.line(),
+        column: e.column(),
+    }
+}
+
+/// Byte offset of a serde_json position, whose column counts the bytes
+/// before the error on its line.
+fn offset_of(input: &str, line: usize, column: usize) -> usize {
+    let line_start = match line {
+        0 | 1 => 0,
+        _ => input
+            .match_indices('\n')
+            .nth(line - 2)
+            .map_or(input.len(), |(i, _)| i + 1),
+    };
+    (line_start + column).min(input.len())
+}
+
+fn truncate(s: &str) -> String {
+    if s.len() <= MAX_VALUE_LEN {
+        return s.to_string();
+    }
+    let mut end = MAX_VALUE_LEN;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}…", &s[..end])
+}
+
+/// OpenRTB 2.5 objects that a decoding error can be attributed to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Object {
+    /// The top-level bid request object.
+    BidRequest,
+    /// The Source object: the entity responsible for the final sale decision.
+    Source,
+    /// The Regs object: regulations in force for the request.
+    Regs,
+    /// The Imp object: an impression being auctioned.
+    Imp,
+    /// The Metric object: a metric of an impression, e.g. viewability.
+    Metric,
+    /// The Banner object: a banner or HTML5 impression.
+    Banner,
+    /// The Video object: an in-stream video impression.
+    Video,
+    /// The Audio object: an audio impression.
+    Audio,
+    /// The Native object: a native ad impression.
+    Native,
+    /// The Format object: an allowed banner size.
+    Format,
+    /// The Pmp object: the private marketplace of an impression.
+    Pmp,
+    /// The Deal object: a direct deal between buyer and seller.
+    Deal,
+    /// The Site object: the website of the impression.
+    Site,
+    /// The App object: the application of the impression.
+    App,
+    /// The Publisher object: the publisher of the site or app.
+    Publisher,
+    /// The Content object: the content the impression appears in.
+    Content,
+    /// The Producer object: the producer of the content.
+    Producer,
+    /// The Device object: the device the impression is delivered to.
+    Device,
+    /// The Geo object: a device or user location.
+    Geo,
+    /// The User object: the human user of the device.
+    User,
+    /// The Data object: additional user data from a data provider.
+    Data,
+    /// The Segment object: a data segment of a Data object.
+    Segment,
+    /// The top-level bid response object.
+    BidResponse,
+    /// The SeatBid object: the bids of one buyer seat.
+    SeatBid,
+    /// The Bid object: an offer to buy an impression.
+    Bid,
+}
+
+impl Object {
+    /// Name of the object in the specification.
+    pub fn name(self) -> &'static str {
+        match self {
+            Object::BidRequest => "BidRequest",
+            Object::Source => "Source",
+            Object::Regs => "Regs",
+            Object::Imp => "Imp",
+            Object::Metric => "Metric",
+            Object::Banner => "Banner",
+            Object::Video => "Video",
+            Object::Audio => "Audio",
+            Object::Native => "Native",
+            Object::Format => "Format",
+            Object::Pmp => "Pmp",
+            Object::Deal => "Deal",
+            Object::Site => "Site",
+            Object::App => "App",
+            Object::Publisher => "Publisher",
+            Object::Content => "Content",
+            Object::Producer => "Producer",
+            Object::Device => "Device",
+            Object::Geo => "Geo",
+            Object::User => "User",
+            Object::Data => "Data",
+            Object::Segment => "Segment",
+            Object::BidResponse => "BidResponse",
+            Object::SeatBid => "SeatBid",
+            Object::Bid => "Bid",
+        }
+    }
+
+    /// Section of the OpenRTB 2.5 specification describing the object.
+    pub fn section(self) -> &'static str {
+        match self {
+            Object::BidRequest => "3.2.1",
+            Object::Source => "3.2.2",
+            Object::Regs => "3.2.3",
+            Object::Imp => "3.2.4",
+            Object::Metric => "3.2.5",
+            Object::Banner => "3.2.6",
+            Object::Video => "3.2.7",
+            Object::Audio => "3.2.8",
+            Object::Native => "3.2.9",
+            Object::Format => "3.2.10",
+            Object::Pmp => "3.2.11",
+            Object::Deal => "3.2.12",
+            Object::Site => "3.2.13",
+            Object::App => "3.2.14",
+            Object::Publisher => "3.2.15",
+            Object::Content => "3.2.16",
+            Object::Producer => "3.2.17",
+            Object::Device => "3.2.18",
+            Object::Geo => "3.2.19",
+            Object::User => "3.2.20",
+            Object::Data => "3.2.21",
+            Object::Segment => "3.2.22",
+            Object::BidResponse => "4.2.1",
+            Object::SeatBid => "4.2.2",
+            Object::Bid => "4.2.3",
+        }
+    }
+
+    /// The object held by field `key`, if it is an object field.
+    fn child(self, key: &str) -> Option<Object> {
+        Some(match (self, key) {
+            (Object::BidRequest, "source") => Object::Source,
+            (Object::BidRequest, "regs") => Object::Regs,
+            (Object::BidRequest, "imp") => Object::Imp,
+            (Object::BidRequest, "site") => Object::Site,
+            (Object::BidRequest, "app") => Object::App,
+            (Object::BidRequest, "device") => Object::Device,
+            (Object::BidRequest, "user") => Object::User,
+            (Object::Imp, "metric") => Object::Metric,
+            (Object::Imp, "banner") => Object::Banner,
+            (Object::Imp, "video") => Object::Video,
+            (Object::Imp, "audio") => Object::Audio,
+            (Object::Imp, "native") => Object::Native,
+            (Object::Imp, "pmp") => Object::Pmp,
+            (Object::Banner, "format") => Object::Format,
+            (Object::Video | Object::Audio, "companionad") => Object::Banner,
+            (Object::Pmp, "deals") => Object::Deal,
+            (Object::Site | Object::App, "publisher") => Object::Publisher,
+            (Object::Site | Object::App, "content") => Object::Content,
+            (Object::Content, "producer") => Object::Producer,
+            (Object::Content | Object::User, "data") => Object::Data,
+            (Object::Device | Object::User, "geo") => Object::Geo,
+            (Object::Data, "segment") => Object::Segment,
+            (Object::BidResponse, "seatbid") => Object::SeatBid,
+            (Object::SeatBid, "bid") => Object::Bid,
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request_error(input: &str) -> DecodeError {
+        bid_request_from_str(input).err().unwrap()
+    }
+
+    #[test]
+    fn locates_invalid_enum_value() {
+        let input = "{\"id\":\"x\",\n\"imp\":[{\"id\":\"1\"},{\"id\":\"2\"},{\"id\":\"3\",\"video\":{\"protocols\":[2,\n99]}}]}";
+        let e = request_error(input);
+        assert_eq!(e.path, "imp[2].video.protocols[1]");
+        assert_eq!(e.object, Object::Video);
+        assert_eq!(e.value.as_deref(), Some("99"));
+        assert_eq!(
+            e.expected.as_deref(),
+            Some("one of: 1, 2, 3, 4, 5, 6, 7, 8, 9, 10")
+        );
+        assert_eq!(e.category, Category::Data);
+        assert_eq!((e.line, e.column), (3, 3));
+        assert_eq!(
+            e.to_string(),
+            "imp[2].video.protocols[1] (Video, section 3.2.7): invalid value: 99, \
+             expected one of: 1, 2, 3, 4, 5, 6, 7, 8, 9, 10; got 99 (line 3 column 3)"
+        );
+    }
+
+    #[test]
+    fn locates_invalid_type() {
+        let e = request_error(r#"{"id":"x","imp":[{"banner":{"w":"a"}}]}"#);
+        assert_eq!(e.path, "imp[0].banner.w");
+        assert_eq!(e.object, Object::Banner);
+        assert_eq!(e.value.as_deref(), Some("\"a\""));
+        assert_eq!(e.expected.as_deref(), Some("i32"));
+        let e = request_error(r#"{"id":"x","imp":[],"device":{"geo":{"lat":"n"}}}"#);
+        assert_eq!(e.path, "device.geo.lat");
+        assert_eq!(e.object, Object::Geo);
+        assert_eq!(e.object.section(), "3.2.19");
+    }
+
+    #[test]
+    fn locates_missing_field() {
+        let e = request_error(r#"{"id":"x","imp":[{"banner":{"w":1}}]}"#);
+        assert_eq!(e.path, "imp[0].id");
+        assert_eq!(e.object, Object::Imp);
+        assert_eq!(e.value, None);
+        let e = request_error(r#"{"imp":[]}"#);
+        assert_eq!(e.path, "id");
+        assert_eq!(e.object, Object::BidRequest);
+        assert_eq!(NoBidReason::from(e), NoBidReason::InvalidRequest);
+    }
+
+    #[test]
+    fn locates_syntax_error() {
+        let e = request_error(r#"{"id":"x","imp":[],"device":{"geo":{"lat":1"#);
+        assert_eq!(e.category, Category::Eof);
+        assert_eq!(e.path, "device.geo.lat");
+    }
+
+    #[test]
+    fn locates_response_errors() {
+        let input = r#"{"id":"x","seatbid":[{"bid":[{"id":"1","impid":"1","price":"x"}]}]}"#;
+        let e = bid_response_from_slice(input.as_bytes()).err().unwrap();
+        assert_eq!(e.path, "seatbid[0].bid[0].price");
+        assert_eq!(e.object, Object::Bid);
+        assert_eq!(e.object.section(), "4.2.3");
+    }
+
+    #[test]
+    fn truncates_long_values() {
+        let long = "é".repeat(40);
+        let e = request_error(&format!(r#"{{"id":"x","imp":[],"tmax":"{}"}}"#, long));
+        let value = e.value.unwrap();
+        assert!(value.ends_with('…'));
+        assert!(value.len() <= MAX_VALUE_LEN + '…'.len_utf8());
+    }
+}
@@ -0,0 +1,423 @@
+//! Device identifier normalization and hashing.
+//!
+//! `Device` carries hashed hardware IDs (`didsha1`, `didmd5`), platform IDs
+//! (`dpidsha1`, `dpidmd5`) and MAC addresses (`macsha1`, `macmd5`). Hashes
+//! only match across partners if the raw ID is normalized the same way
+//! before hashing, so the setters here normalize first:
+//!
+//! * IMEI: digits only, e.g. `490154203237518`.
+//! * Android ID: lowercase hex, e.g. `9774d56d682e549c`.
+//! * MAC: lowercase hex pairs separated by colons, e.g. `00:1a:2b:3c:4d:5e`.
+//!
+//! Hashes are lowercase hex.
+
+use super::bid_request::Device;
+use std::fmt;
+
+/// Kind of raw device identifier.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DeviceIdKind {
+    /// An IMEI or IMEISV, the hardware ID of a phone.
+    Imei,
+    /// An Android ID, a 64-bit hex platform ID.
+    AndroidId,
+    /// A MAC address.
+    Mac,
+    /// An advertising ID (IDFA, GAID), a UUID.
+    Ifa,
+}
+
+/// Error returned when a raw identifier cannot be normalized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidDeviceId(pub DeviceIdKind);
+
+impl fmt::Display for InvalidDeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid device identifier ({:?})", self.0)
+    }
+}
+
+impl std::error::Error for InvalidDeviceId {}
+
+/// State of `Device.ifa`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IfaStatus {
+    /// A well-formed, non-zero UUID.
+    Valid,
+    /// `00000000-0000-0000-0000-000000000000`, sent when tracking is limited
+    /// (e.g. iOS App Tracking Transparency or Android opt-out).
+    Zero,
+    /// Not a UUID.
+    Malformed,
+}
+
+/// The all-zero advertising ID.
+pub const ZERO_IFA: &str = "00000000-0000-0000-0000-000000000000";
+
+/// Normalizes an IMEI (or IMEISV): strips spaces, dashes, dots and slashes
+/// and checks that 14 to 16 digits remain.
+pub fn normalize_imei(raw: &str) -> Result<String, InvalidDeviceId> {
+    let digits: String = raw
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '.' | '/'))
+        .collect();
+    if (14..=16).contains(&digits.len()) && digits.bytes().all(|b| b.is_ascii_digit()) {
+        Ok(digits)
+    } else {
+        Err(InvalidDeviceId(DeviceIdKind::Imei))
+    }
+}
+
+/// Normalizes an Android ID: trims it, lowercases it and checks that it is
+/// at most 16 hex digits.
+pub fn normalize_android_id(raw: &str) -> Result<String, InvalidDeviceId> {
+    let id = raw.trim().to_ascii_lowercase();
+    if (1..=16).contains(&id.len()) && id.bytes().all(|b| b.is_ascii_hexdigit()) {
+        Ok(id)
+    } else {
+        Err(InvalidDeviceId(DeviceIdKind::AndroidId))
+    }
+}
+
+/// Normalizes a MAC address written with `:`, `-` or `.` separators (or
+/// none) to lowercase colon-separated pairs.
+pub fn normalize_mac(raw: &str) -> Result<String, InvalidDeviceId> {
+    let hex: Vec<u8> = raw
+        .trim()
+        .bytes()
+        .filter(|b| !matches!(b, b':' | b'-' | b'.'))
+        .map(|b| b.to_ascii_lowercase())
+        .collect();
+    if hex.len() != 12 || !hex.iter().all(u8::is_ascii_hexdigit) {
+        return Err(InvalidDeviceId(DeviceIdKind::Mac));
+    }
+    let pairs: Vec<&str> = hex
+        .chunks(2)
+        .map(|pair| std::str::from_utf8(pair).unwrap_or_default())
+        .collect();
+    Ok(pairs.join(":"))
+}
+
+/// Normalizes an advertising ID to a lowercase hyphenated UUID. A UUID
+/// without hyphens is accepted.
+pub fn normalize_ifa(raw: &str) -> Result<String, InvalidDeviceId> {
+    let raw = raw.trim();
+    let hex: String = match raw.len() {
+        36 if [8, 13, 18, 23].iter().all(|&i| raw.as_bytes()[i] == b'-') => {
+            raw.split('-').collect()
+        }
+        32 => raw.to_string(),
+        _ => return Err(InvalidDeviceId(DeviceIdKind::Ifa)),
+    };
+    if hex.len() != 32 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(InvalidDeviceId(DeviceIdKind::Ifa));
+    }
+    let hex = hex.to_ascii_lowercase();
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    ))
+}
+
+/// SHA-1 of `s` as lowercase hex.
+pub fn sha1_hex(s: &str) -> String {
+    to_hex(&sha1(s.as_bytes()))
+}
+
+/// MD5 of `s` as lowercase hex.
+pub fn md5_hex(s: &str) -> String {
+    to_hex(&md5(s.as_bytes()))
+}
+
+impl Device {
+    /// Normalizes a raw IMEI and sets `didsha1` and `didmd5`.
+    pub fn set_imei(&mut self, raw: &str) -> Result<(), InvalidDeviceId> {
+        let id = normalize_imei(raw)?;
+        self.didsha1 = Some(sha1_hex(&id));
+        self.didmd5 = Some(md5_hex(&id));
+        Ok(())
+    }
+
+    /// Normalizes a raw Android ID and sets `dpidsha1` and `dpidmd5`.
+    pub fn set_android_id(&mut self, raw: &str) -> Result<(), InvalidDeviceId> {
+        let id = normalize_android_id(raw)?;
+        self.dpidsha1 = Some(sha1_hex(&id));
+        self.dpidmd5 = Some(md5_hex(&id));
+        Ok(())
+    }
+
+    /// Normalizes a raw MAC address and sets `macsha1` and `macmd5`.
+    pub fn set_mac(&mut self, raw: &str) -> Result<(), InvalidDeviceId> {
+        let id = normalize_mac(raw)?;
+        self.macsha1 = Some(sha1_hex(&id));
+        self.macmd5 = Some(md5_hex(&id));
+        Ok(())
+    }
+
+    /// The state of `ifa`, if present.
+    pub fn ifa_status(&self) -> Option<IfaStatus> {
+        let ifa = self.ifa.as_deref()?;
+        Some(match normalize_ifa(ifa) {
+            Ok(ifa) if ifa == ZERO_IFA => IfaStatus::Zero,
+            Ok(_) => IfaStatus::Valid,
+            Err(_) => IfaStatus::Malformed,
+        })
+    }
+
+    /// Names of the hashed ID fields that are present but are not hex
+    /// digests of the right length.
+    pub fn malformed_hashed_ids(&self) -> Vec<&'static str> {
+        let fields = [
+            ("didsha1", &self.didsha1, 40),
+            ("didmd5", &self.didmd5, 32),
+            ("dpidsha1", &self.dpidsha1, 40),
+            ("dpidmd5", &self.dpidmd5, 32),
+            ("macsha1", &self.macsha1, 40),
+            ("macmd5", &self.macmd5, 32),
+        ];
+        fields
+            .into_iter()
+            .filter(|(_, value, len)| match value {
+                Some(v) => v.len() != *len || !v.bytes().all(|b| b.is_ascii_hexdigit()),
+                None => false,
+            })
+            .map(|(name, _, _)| name)
+            .collect()
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Pads a message as MD5 and SHA-1 do: a 1 bit, zeros, and the bit length
+/// (little-endian for MD5, big-endian for SHA-1).
+fn pad(input: &[u8], big_endian: bool) -> Vec<u8> {
+    let bits = (input.len() as u64).wrapping_mul(8);
+    let mut msg = input.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    if big_endian {
+        msg.extend_from_slice(&bits.to_be_bytes());
+    } else {
+        msg.extend_from_slice(&bits.to_le_bytes());
+    }
+    msg
+}
+
+fn sha1(input: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    for block in pad(input, true).chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &wi) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let t = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(wi);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+    let mut out = [0u8; 20];
+    for (chunk, v) in out.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&v.to_be_bytes());
+    }
+    out
+}
+
+fn md5(input: &[u8]) -> [u8; 16] {
+    const S: [u32; 64] = [
+        7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5,
+        9, 14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10,
+        15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+    ];
+    // K[i] = floor(abs(sin(i + 1)) * 2^32)
+    const K: [u32; 64] = [
+        0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613,
+        0xfd469501, 0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193,
+        0xa679438e, 0x49b40821, 0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d,
+        0x02441453, 0xd8a1e681, 0xe7d3fbc8, 0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed,
+        0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a, 0xfffa3942, 0x8771f681, 0x6d9d6122,
+        0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70, 0x289b7ec6, 0xeaa127fa,
+        0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665, 0xf4292244,
+        0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+        0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb,
+        0xeb86d391,
+    ];
+    let mut h: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+    for block in pad(input, false).chunks(64) {
+        let mut m = [0u32; 16];
+        for (i, word) in block.chunks(4).enumerate() {
+            m[i] = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        let [mut a, mut b, mut c, mut d] = h;
+        for i in 0..64 {
+            let (f, g) = match i {
+                0..=15 => ((b & c) | (!b & d), i),
+                16..=31 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                32..=47 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f.wrapping_add(a).wrapping_add(K[i]).wrapping_add(m[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(S[i]));
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+    let mut out = [0u8; 16];
+    for (chunk, v) in out.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&v.to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn md5_rfc_1321_vectors() {
+        for (input, digest) in [
+            ("", "d41d8cd98f00b204e9800998ecf8427e"),
+            ("a", "0cc175b9c0f1b6a831c399e269772661"),
+            ("abc", "900150983cd24fb0d6963f7d28e17f72"),
+            ("message digest", "f96b697d7cb7938d525a2f31aaf161d0"),
+            (
+                "abcdefghijklmnopqrstuvwxyz",
+                "c3fcd3d76192e4007dfb496cca67e13b",
+            ),
+            (
+                "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+                "d174ab98d277d9f5a5611c2c9f419d9f",
+            ),
+            (
+                "12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+                "57edf4a22be3c955ac49da2e2107b67a",
+            ),
+        ] {
+            assert_eq!(md5_hex(input), digest, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn sha1_rfc_3174_vectors() {
+        for (input, digest) in [
+            ("abc", "a9993e364706816aba3e25717850c26c9cd0d89d"),
+            (
+                "abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+                "84983e441c3bd26ebaae4aa1f95129e5e54670f1",
+            ),
+            ("", "da39a3ee5e6b4b0d3255bfef95601890afd80709"),
+        ] {
+            assert_eq!(sha1_hex(input), digest, "{:?}", input);
+        }
+        assert_eq!(
+            sha1_hex(&"a".repeat(1_000_000)),
+            "34aa973cd4c4daa4f61eeb2bdbad27316534016f"
+        );
+        assert_eq!(
+            sha1_hex(
+                &"0123456701234567012345670123456701234567012345670123456701234567".repeat(10)
+            ),
+            "dea356a2cddd90c7a7ecedc5ebb563934f460452"
+        );
+    }
+
+    #[test]
+    fn pads_across_block_boundaries() {
+        // 55 and 56 bytes: the length fits in the first block, or does not.
+        assert_eq!(md5_hex(&"a".repeat(55)), "ef1772b6dff9a122358552954ad0df65");
+        assert_eq!(md5_hex(&"a".repeat(56)), "3b0c8ac703f828b04c6c197006d17218");
+        assert_eq!(
+            sha1_hex(&"a".repeat(64)),
+            "0098ba824b5c16427bd7a1122a5a442a25ec644d"
+        );
+    }
+
+    #[test]
+    fn normalizes_raw_ids() {
+        assert_eq!(
+            normalize_imei("49-015420-323751-8").unwrap(),
+            "490154203237518"
+        );
+        assert_eq!(
+            normalize_imei("4901542032375"),
+            Err(InvalidDeviceId(DeviceIdKind::Imei))
+        );
+        assert_eq!(
+            normalize_android_id(" 9774D56D682E549C ").unwrap(),
+            "9774d56d682e549c"
+        );
+        assert!(normalize_android_id("9774d56d682e549cz").is_err());
+        assert_eq!(
+            normalize_mac("00-1A-2B-3C-4D-5E").unwrap(),
+            "00:1a:2b:3c:4d:5e"
+        );
+        assert_eq!(
+            normalize_mac("001a.2b3c.4d5e").unwrap(),
+            "00:1a:2b:3c:4d:5e"
+        );
+        assert!(normalize_mac("00:1a:2b:3c:4d").is_err());
+        assert_eq!(
+            normalize_ifa("6D92078A-8246-4BA4-AE5B-76104861E7DC").unwrap(),
+            "6d92078a-8246-4ba4-ae5b-76104861e7dc"
+        );
+        assert_eq!(
+            normalize_ifa("6D92078A82464BA4AE5B76104861E7DC").unwrap(),
+            "6d92078a-8246-4ba4-ae5b-76104861e7dc"
+        );
+        assert!(normalize_ifa("6D92078A8246-4BA4-AE5B-76104861E7DC").is_err());
+    }
+
+    #[test]
+    fn sets_hashed_ids() {
+        let mut device = Device {
+            ifa: Some(ZERO_IFA.into()),
+            didsha1: Some("zz".into()),
+            ..Device::default()
+        };
+        assert_eq!(device.ifa_status(), Some(IfaStatus::Zero));
+        assert_eq!(device.malformed_hashed_ids(), ["didsha1"]);
+        device.set_imei("490154203237518").unwrap();
+        assert_eq!(
+            device.didmd5.as_deref(),
+            Some(md5_hex("490154203237518").as_str())
+        );
+        assert!(device.malformed_hashed_ids().is_empty());
+        device.set_mac("00-1A-2B-3C-4D-5E").unwrap();
+        assert_eq!(device.macsha1, Some(sha1_hex("00:1a:2b:3c:4d:5e")));
+        device.ifa = Some("nope".into());
+        assert_eq!(device.ifa_status(), Some(IfaStatus::Malformed));
+    }
+}
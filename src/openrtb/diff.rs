@@ -0,0 +1,526 @@
+//! Structural diff and JSON Patch.
+//!
+//! `diff` compares two messages field by field through `Fields` and lists
+//! what was added, removed or changed, with the path of each change. The
+//! changes convert to an RFC 6902 JSON Patch, and `apply_patch` applies a
+//! patch to a typed message, so that
+//!
+//! ```text
+//! let patch = to_patch(&diff(&before, &after));
+//! apply_patch(&mut before, &patch)?; // before == after
+//! ```
+//!
+//! Arrays are compared by index: elements past the end of the shorter array
+//! are added or removed, and removals are listed from the last index so the
+//! patch applies in order.
+
+use super::fields::{Field, FieldPath, Fields, PathError, PathProblem};
+use super::{BidRequest, BidResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+
+/// A difference between two messages.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// The field is present only in the new message.
+    Added {
+        /// Path of the field.
+        path: FieldPath,
+        /// Value in the new message.
+        value: Value,
+    },
+    /// The field is present only in the old message.
+    Removed {
+        /// Path of the field.
+        path: FieldPath,
+        /// Value in the old message.
+        old: Value,
+    },
+    /// The field has a different value.
+    Changed {
+        /// Path of the field.
+        path: FieldPath,
+        /// Value in the old message.
+        old: Value,
+        /// Value in the new message.
+        new: Value,
+    },
+}
+
+impl Change {
+    /// Path of the changed field.
+    pub fn path(&self) -> &FieldPath {
+        match self {
+            Change::Added { path, .. }
+            | Change::Removed { path, .. }
+            | Change::Changed { path, .. } => path,
+        }
+    }
+
+    /// The JSON Patch operation making the change.
+    pub fn to_op(&self) -> PatchOp {
+        match self {
+            Change::Added { path, value } => PatchOp::Add {
+                path: path.to_pointer(),
+                value: value.clone(),
+            },
+            Change::Removed { path, .. } => PatchOp::Remove {
+                path: path.to_pointer(),
+            },
+            Change::Changed { path, new, .. } => PatchOp::Replace {
+                path: path.to_pointer(),
+                value: new.clone(),
+            },
+        }
+    }
+}
+
+/// One change per line: `+ path: value`, `- path: old` or
+/// `~ path: old -> new`, with dotted paths.
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added { path, value } => write!(f, "+ {}: {}", path.to_dotted(), value),
+            Change::Removed { path, old } => write!(f, "- {}: {}", path.to_dotted(), old),
+            Change::Changed { path, old, new } => {
+                write!(f, "~ {}: {} -> {}", path.to_dotted(), old, new)
+            }
+        }
+    }
+}
+
+/// The changes turning `old` into `new`.
+pub fn diff(old: &dyn Fields, new: &dyn Fields) -> Vec<Change> {
+    let mut changes = Vec::new();
+    walk(
+        Some(old),
+        Some(new),
+        &mut FieldPath::default(),
+        &mut changes,
+    );
+    changes
+}
+
+fn walk(
+    old: Option<&dyn Fields>,
+    new: Option<&dyn Fields>,
+    path: &mut FieldPath,
+    changes: &mut Vec<Change>,
+) {
+    let old = old.filter(|f| f.is_present());
+    let new = new.filter(|f| f.is_present());
+    let (old, new) = match (old, new) {
+        (None, None) => return,
+        (None, Some(new)) => {
+            changes.push(Change::Added {
+                path: path.clone(),
+                value: new.to_json(),
+            });
+            return;
+        }
+        (Some(old), None) => {
+            changes.push(Change::Removed {
+                path: path.clone(),
+                old: old.to_json(),
+            });
+            return;
+        }
+        (Some(old), Some(new)) => (old, new),
+    };
+    let child = |key: &str, path: &mut FieldPath, changes: &mut Vec<Change>| {
+        path.push(key);
+        walk(old.child(key), new.child(key), path, changes);
+        path.pop();
+    };
+    match (old.field(), new.field()) {
+        (Field::Object(_), Field::Object(_)) => {
+            let mut keys = old.keys();
+            for key in new.keys() {
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+            for key in &keys {
+                child(key, path, changes);
+            }
+        }
+        (Field::Array(_), Field::Array(_)) => {
+            let (old_len, new_len) = (old.keys().len(), new.keys().len());
+            for i in 0..new_len {
+                child(&i.to_string(), path, changes);
+            }
+            for i in (new_len..old_len).rev() {
+                child(&i.to_string(), path, changes);
+            }
+        }
+        (a, b) => {
+            let (old, new) = (a.to_json(), b.to_json());
+            if !json_eq(&old, &new) {
+                changes.push(Change::Changed {
+                    path: path.clone(),
+                    old,
+                    new,
+                });
+            }
+        }
+    }
+}
+
+/// JSON equality where numbers compare by value, so `1` equals `1.0`.
+fn json_eq(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => match (x.as_i64(), y.as_i64()) {
+            (Some(x), Some(y)) => x == y,
+            _ => x.as_f64() == y.as_f64(),
+        },
+        (Value::Array(x), Value::Array(y)) => {
+            x.len() == y.len() && x.iter().zip(y).all(|(x, y)| json_eq(x, y))
+        }
+        (Value::Object(x), Value::Object(y)) => {
+            x.len() == y.len()
+                && x.iter()
+                    .all(|(k, x)| y.get(k).is_some_and(|y| json_eq(x, y)))
+        }
+        _ => a == b,
+    }
+}
+
+/// An RFC 6902 operation. Paths are JSON Pointers.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOp {
+    /// Adds `value` at `path`, inserting it into an array.
+    Add {
+        /// Where to add the value.
+        path: String,
+        /// The value to add.
+        value: Value,
+    },
+    /// Removes the value at `path`.
+    Remove {
+        /// The value to remove.
+        path: String,
+    },
+    /// Replaces the existing value at `path` with `value`.
+    Replace {
+        /// The value to replace.
+        path: String,
+        /// The new value.
+        value: Value,
+    },
+    /// Removes the value at `from` and adds it at `path`.
+    Move {
+        /// The value to move.
+        from: String,
+        /// Where to add it.
+        path: String,
+    },
+    /// Adds a copy of the value at `from` at `path`.
+    Copy {
+        /// The value to copy.
+        from: String,
+        /// Where to add the copy.
+        path: String,
+    },
+    /// Checks that the value at `path` equals `value`.
+    Test {
+        /// The value to check.
+        path: String,
+        /// The expected value.
+        value: Value,
+    },
+}
+
+/// The JSON Patch making `changes`.
+pub fn to_patch(changes: &[Change]) -> Vec<PatchOp> {
+    changes.iter().map(Change::to_op).collect()
+}
+
+/// Why an operation failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PatchProblem {
+    /// A path is malformed, missing or cannot take the value.
+    Path(PathError),
+    /// A `test` operation found a different value.
+    TestFailed,
+    /// A `move` has `path` below `from`.
+    MoveIntoSelf,
+}
+
+/// Error returned for a patch that does not apply.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatchError {
+    /// Index of the failing operation in the patch.
+    pub op: usize,
+    /// Why the operation failed.
+    pub problem: PatchProblem,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.problem {
+            PatchProblem::Path(e) => write!(f, "patch operation {}: {}", self.op, e),
+            PatchProblem::TestFailed => write!(f, "patch operation {}: test failed", self.op),
+            PatchProblem::MoveIntoSelf => {
+                write!(
+                    f,
+                    "patch operation {}: move into a child of itself",
+                    self.op
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+impl From<PathError> for PatchProblem {
+    fn from(e: PathError) -> Self {
+        PatchProblem::Path(e)
+    }
+}
+
+/// Applies `patch` to `target`. The patch applies as a whole: if an
+/// operation fails, `target` is left unchanged.
+pub fn apply_patch<T: Fields + Clone>(target: &mut T, patch: &[PatchOp]) -> Result<(), PatchError> {
+    let mut patched = target.clone();
+    for (i, op) in patch.iter().enumerate() {
+        apply_op(&mut patched, op).map_err(|problem| PatchError { op: i, problem })?;
+    }
+    *target = patched;
+    Ok(())
+}
+
+fn apply_op(target: &mut dyn Fields, op: &PatchOp) -> Result<(), PatchProblem> {
+    match op {
+        PatchOp::Add { path, value } => add(target, &pointer(path)?, value.clone()),
+        PatchOp::Remove { path } => Ok(target.remove(&pointer(path)?)?),
+        PatchOp::Replace { path, value } => {
+            let path = pointer(path)?;
+            get(target, &path)?;
+            Ok(target.set(&path, value.clone())?)
+        }
+        PatchOp::Move { from, path } => {
+            let (from, path) = (pointer(from)?, pointer(path)?);
+            if from == path {
+                return Ok(());
+            }
+            if path.starts_with(&from) {
+                return Err(PatchProblem::MoveIntoSelf);
+            }
+            let value = get(target, &from)?;
+            target.remove(&from)?;
+            add(target, &path, value)
+        }
+        PatchOp::Copy { from, path } => {
+            let value = get(target, &pointer(from)?)?;
+            add(target, &pointer(path)?, value)
+        }
+        PatchOp::Test { path, value } => match json_eq(&get(target, &pointer(path)?)?, value) {
+            true => Ok(()),
+            false => Err(PatchProblem::TestFailed),
+        },
+    }
+}
+
+fn pointer(s: &str) -> Result<FieldPath, PathError> {
+    FieldPath::from_pointer(s)
+}
+
+fn get(target: &dyn Fields, path: &FieldPath) -> Result<Value, PathError> {
+    target.lookup(path).map(|f| f.to_json()).ok_or(PathError {
+        path: path.to_pointer(),
+        problem: PathProblem::NotFound,
+    })
+}
+
+/// `add`: the parent must exist; the root is replaced.
+fn add(target: &mut dyn Fields, path: &FieldPath, value: Value) -> Result<(), PatchProblem> {
+    let error = |problem| PathError {
+        path: path.to_pointer(),
+        problem,
+    };
+    let (last, parents) = match path.tokens().split_last() {
+        Some(split) => split,
+        None => return Ok(target.set(path, value)?),
+    };
+    let mut node = target;
+    for key in parents {
+        node = node
+            .child_mut(key)
+            .ok_or_else(|| error(PathProblem::NotFound))?;
+    }
+    if !node.is_present() {
+        return Err(error(PathProblem::NotFound).into());
+    }
+    Ok(node.insert_child(last, value).map_err(error)?)
+}
+
+impl BidRequest {
+    /// The changes turning this request into `other`.
+    pub fn diff(&self, other: &BidRequest) -> Vec<Change> {
+        diff(self, other)
+    }
+
+    /// Applies a JSON Patch; see `apply_patch`.
+    pub fn apply_patch(&mut self, patch: &[PatchOp]) -> Result<(), PatchError> {
+        apply_patch(self, patch)
+    }
+}
+
+impl BidResponse {
+    /// The changes turning this response into `other`.
+    pub fn diff(&self, other: &BidResponse) -> Vec<Change> {
+        diff(self, other)
+    }
+
+    /// Applies a JSON Patch; see `apply_patch`.
+    pub fn apply_patch(&mut self, patch: &[PatchOp]) -> Result<(), PatchError> {
+        apply_patch(self, patch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(json: &str) -> BidRequest {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn ops(value: Value) -> Vec<PatchOp> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn before() -> BidRequest {
+        request(
+            r#"{"id":"1","tmax":100,
+            "imp":[{"id":"a","banner":{"format":[{"w":300,"h":250},{"w":728,"h":90},{"w":1,"h":1}]}}],
+            "device":{"ip":"1.2.3.4","geo":{"country":"USA"},"ext":{"k":[1,2]}},
+            "user":{"id":"u"}}"#,
+        )
+    }
+
+    fn after() -> BidRequest {
+        request(
+            r#"{"id":"1","tmax":120,
+            "imp":[{"id":"a","banner":{"format":[{"w":320,"h":50}]}},{"id":"b"}],
+            "device":{"ip":"1.2.3.0","ext":{"k":[1,2,3],"n":true}},"regs":{"coppa":1}}"#,
+        )
+    }
+
+    #[test]
+    fn lists_changes_by_path() {
+        let lines: Vec<String> = before()
+            .diff(&after())
+            .iter()
+            .map(Change::to_string)
+            .collect();
+        for line in [
+            "~ tmax: 100 -> 120",
+            "- user: {\"id\":\"u\"}",
+            "+ regs: {\"coppa\":1}",
+            "~ device.ip: \"1.2.3.4\" -> \"1.2.3.0\"",
+            "- device.geo: {\"country\":\"USA\"}",
+            "+ device.ext.k.2: 3",
+            "+ imp.1: {\"id\":\"b\"}",
+            "- imp.0.banner.format.2: {\"h\":1,\"w\":1}",
+        ] {
+            assert!(
+                lines.iter().any(|l| l == line),
+                "{} not in {:#?}",
+                line,
+                lines
+            );
+        }
+        assert!(before().diff(&before()).is_empty());
+    }
+
+    #[test]
+    fn patch_round_trips() {
+        let (old, new) = (before(), after());
+        let patch = to_patch(&old.diff(&new));
+        let patch: Vec<PatchOp> =
+            serde_json::from_value(serde_json::to_value(&patch).unwrap()).unwrap();
+        let mut patched = old.clone();
+        patched.apply_patch(&patch).unwrap();
+        assert!(patched == new);
+        assert!(patched.diff(&new).is_empty());
+
+        // And back, growing the format array again.
+        let mut reverted = new.clone();
+        reverted.apply_patch(&to_patch(&new.diff(&old))).unwrap();
+        assert!(reverted == old);
+    }
+
+    #[test]
+    fn response_patch_round_trips() {
+        let old: BidResponse = serde_json::from_str(
+            r#"{"id":"1","seatbid":[{"seat":"a","bid":[{"id":"1","impid":"1","price":1.5},
+                {"id":"2","impid":"1","price":2}]},{"seat":"b","bid":[]}]}"#,
+        )
+        .unwrap();
+        let new: BidResponse = serde_json::from_str(
+            r#"{"id":"1","seatbid":[{"seat":"a","bid":[{"id":"1","impid":"1","price":1.25}]}]}"#,
+        )
+        .unwrap();
+        let mut patched = old.clone();
+        patched.apply_patch(&to_patch(&old.diff(&new))).unwrap();
+        assert!(patched == new);
+    }
+
+    #[test]
+    fn applies_every_operation() {
+        let mut request = before();
+        request
+            .apply_patch(&ops(json!([
+                {"op":"test","path":"/device/geo/country","value":"USA"},
+                {"op":"copy","from":"/device/geo","path":"/user/geo"},
+                {"op":"move","from":"/device/ip","path":"/device/ipv6"},
+                {"op":"add","path":"/imp/0/banner/format/0","value":{"w":1,"h":2}},
+                {"op":"test","path":"/tmax","value":100.0},
+                {"op":"remove","path":"/imp/0/banner/format/3"},
+            ])))
+            .unwrap();
+        let user_geo = request.user.as_ref().unwrap().geo.as_ref().unwrap();
+        assert_eq!(user_geo.country.as_deref(), Some("USA"));
+        let device = request.device.as_ref().unwrap();
+        assert_eq!(device.ipv6.as_deref(), Some("1.2.3.4"));
+        assert!(device.ip.is_none());
+        let format = request.imp[0]
+            .banner
+            .as_ref()
+            .unwrap()
+            .format
+            .as_ref()
+            .unwrap();
+        assert_eq!((format.len(), format[0].h), (3, Some(2)));
+    }
+
+    #[test]
+    fn failed_patch_leaves_target_unchanged() {
+        let mut request = before();
+        let err = request
+            .apply_patch(&ops(json!([
+                {"op":"replace","path":"/tmax","value":1},
+                {"op":"test","path":"/tmax","value":2},
+            ])))
+            .unwrap_err();
+        assert_eq!((err.op, err.problem), (1, PatchProblem::TestFailed));
+        assert!(request == before());
+
+        let err = request
+            .apply_patch(&ops(json!([{"op":"add","path":"/site/name","value":"x"}])))
+            .unwrap_err();
+        assert!(matches!(err.problem, PatchProblem::Path(_)));
+        let err = request
+            .apply_patch(&ops(
+                json!([{"op":"move","from":"/device","path":"/device/geo"}]),
+            ))
+            .unwrap_err();
+        assert_eq!(err.problem, PatchProblem::MoveIntoSelf);
+        assert!(request == before());
+    }
+}
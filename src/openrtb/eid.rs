@@ -0,0 +1,214 @@
+//! Extended identifiers (EIDs).
+//!
+//! Universal IDs such as UID2, ID5, RampID or SharedID are sent as one Eid
+//! object per identity source, each holding one or more Uids. OpenRTB 2.6
+//! carries them in `User.eids`; 2.5 carries them in `user.ext.eids`.
+
+use super::bid_request::User;
+use super::BidRequest;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// OpenRTB 2.6: Extended identifiers from a single source.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Eid {
+    /// Source or technology provider responsible for the set of included
+    /// IDs, expressed as a top-level domain, e.g. "uidapi.com".
+    pub source: String,
+
+    /// Array of extended ID Uid objects from the given source.
+    #[serde(default)]
+    pub uids: Vec<Uid>,
+
+    /// Extensions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ext: Option<Value>,
+}
+
+/// OpenRTB 2.6: A single user identifier provided as part of extended
+/// identifiers.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Uid {
+    /// The identifier for the user.
+    pub id: String,
+
+    /// Type of user agent the ID is from. See `AgentType`; values of 500
+    /// and above are vendor-specific.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub atype: Option<i32>,
+
+    /// Extensions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ext: Option<Value>,
+}
+
+/// AdCOM 1.0: Agent types, the kind of user agent an ID is tied to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(i32)]
+pub enum AgentType {
+    /// An ID tied to a specific web browser or device: cookie-based,
+    /// probabilistic or other.
+    BrowserOrDevice = 1,
+    /// An in-app ID, typically a privacy-compliant version of a device ID.
+    InApp = 2,
+    /// A person-based ID, the same across devices.
+    PersonBased = 3,
+}
+impl AgentType {
+    /// String value of the enum field names.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            AgentType::BrowserOrDevice => "BROWSER_OR_DEVICE",
+            AgentType::InApp => "IN_APP",
+            AgentType::PersonBased => "PERSON_BASED",
+        }
+    }
+
+    /// The agent type with value `v`, if it is not vendor-specific.
+    pub fn from_i32(v: i32) -> Option<AgentType> {
+        match v {
+            1 => Some(AgentType::BrowserOrDevice),
+            2 => Some(AgentType::InApp),
+            3 => Some(AgentType::PersonBased),
+            _ => None,
+        }
+    }
+}
+
+impl Uid {
+    /// The agent type, if set and not vendor-specific.
+    pub fn agent_type(&self) -> Option<AgentType> {
+        self.atype.and_then(AgentType::from_i32)
+    }
+}
+
+impl User {
+    /// The EIDs from `eids`, or from `ext.eids` when the field is absent.
+    pub fn eids_or_ext(&self) -> Option<serde_json::Result<Vec<Eid>>> {
+        if let Some(eids) = &self.eids {
+            return Some(Ok(eids.clone()));
+        }
+        let eids = self.ext.as_ref()?.get("eids")?;
+        Some(Vec::<Eid>::deserialize(eids))
+    }
+
+    /// The EID of `source`, compared case-insensitively. Malformed
+    /// `ext.eids` yields `None`.
+    pub fn eid(&self, source: &str) -> Option<Eid> {
+        let eids = self.eids_or_ext()?.ok()?;
+        eids.into_iter()
+            .find(|e| e.source.eq_ignore_ascii_case(source))
+    }
+
+    /// The EIDs reduced to the uids of agent type `atype`; sources left
+    /// without uids are dropped.
+    pub fn eids_by_atype(&self, atype: i32) -> Vec<Eid> {
+        let eids = match self.eids_or_ext() {
+            Some(Ok(eids)) => eids,
+            _ => return Vec::new(),
+        };
+        eids.into_iter()
+            .filter_map(|mut eid| {
+                eid.uids.retain(|uid| uid.atype == Some(atype));
+                (!eid.uids.is_empty()).then_some(eid)
+            })
+            .collect()
+    }
+
+    /// Removes the EIDs from both `eids` and `ext.eids`. Returns whether
+    /// any were present.
+    pub fn remove_eids(&mut self) -> bool {
+        let field = self.eids.take().is_some();
+        let ext = match &mut self.ext {
+            Some(Value::Object(ext)) => ext.remove("eids").is_some(),
+            _ => false,
+        };
+        field || ext
+    }
+}
+
+impl BidRequest {
+    /// Removes every EID unless the request may be used for personalized
+    /// advertising (see `BidRequest::can_personalize`). Returns whether any
+    /// were removed.
+    pub fn remove_eids_without_consent(&mut self) -> bool {
+        if self.can_personalize() {
+            return false;
+        }
+        self.user.as_mut().is_some_and(User::remove_eids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXT_EIDS: &str = r#"{"id":"1","imp":[],"regs":{"coppa":1},"user":{"ext":{"eids":[
+        {"source":"uidapi.com","uids":[{"id":"a","atype":3}]},
+        {"source":"id5-sync.com","uids":[{"id":"b","atype":1},{"id":"c","atype":3}]}],
+        "other":1}}}"#;
+
+    #[test]
+    fn looks_up_by_source_and_atype() {
+        let request: BidRequest = serde_json::from_str(EXT_EIDS).unwrap();
+        let user = request.user.as_ref().unwrap();
+        assert_eq!(user.eid("UIDAPI.com").unwrap().uids[0].id, "a");
+        assert!(user.eid("liveramp.com").is_none());
+
+        let person = user.eids_by_atype(AgentType::PersonBased as i32);
+        assert_eq!(person.len(), 2);
+        assert_eq!(person[1].source, "id5-sync.com");
+        assert_eq!(person[1].uids.len(), 1);
+        assert_eq!(person[1].uids[0].agent_type(), Some(AgentType::PersonBased));
+        assert_eq!(
+            user.eids_by_atype(AgentType::BrowserOrDevice as i32).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn decodes_an_eid_without_uids() {
+        let request: BidRequest =
+            serde_json::from_str(r#"{"id":"1","imp":[],"user":{"eids":[{"source":"x.com"}]}}"#)
+                .unwrap();
+        let user = request.user.as_ref().unwrap();
+        assert!(user.eid("x.com").unwrap().uids.is_empty());
+    }
+
+    #[test]
+    fn prefers_the_field_over_ext() {
+        let request: BidRequest = serde_json::from_str(
+            r#"{"id":"1","imp":[],"user":{
+                "eids":[{"source":"x.com","uids":[{"id":"a"}]}],
+                "ext":{"eids":[{"source":"y.com","uids":[{"id":"b"}]}]}}}"#,
+        )
+        .unwrap();
+        let user = request.user.unwrap();
+        assert!(user.eid("x.com").is_some());
+        assert!(user.eid("y.com").is_none());
+
+        let malformed = User {
+            ext: Some(serde_json::json!({ "eids": "x" })),
+            ..Default::default()
+        };
+        assert!(malformed.eids_or_ext().unwrap().is_err());
+        assert!(malformed.eid("x.com").is_none());
+    }
+
+    #[test]
+    fn removes_eids_without_consent() {
+        let mut request: BidRequest = serde_json::from_str(EXT_EIDS).unwrap();
+        assert!(request.remove_eids_without_consent());
+        assert_eq!(
+            serde_json::to_string(&request.user).unwrap(),
+            r#"{"ext":{"other":1}}"#
+        );
+
+        let mut request: BidRequest = serde_json::from_str(
+            r#"{"id":"1","imp":[],"user":{"eids":[{"source":"x.com","uids":[{"id":"a"}]}]}}"#,
+        )
+        .unwrap();
+        assert!(!request.remove_eids_without_consent());
+        assert!(request.user.unwrap().eids.is_some());
+    }
+}
@@ -0,0 +1,1547 @@
+//! Dynamic field access.
+//!
+//! Every message implements `Fields`, which walks the typed structs by JSON
+//! field name without converting them to a `serde_json::Value`. A
+//! `FieldPath` names a field either with dots (`device.geo.country`,
+//! `imp.0.banner.w` or `imp[0].banner.w`) or as an RFC 6901 JSON Pointer
+//! (`/imp/0/banner/w`); both continue into `ext` values.
+//!
+//! Reads return a `Field`, typed after the Rust field: strings, integers,
+//! floats, `Bool`s as booleans and enums with their wire value and name.
+//! Writes take the new value as JSON and deserialize it into the type of
+//! the target field only, so `"w": "300"` is rejected for `banner.w` as it
+//! would be when parsing a request. Missing objects on the way are created.
+
+use super::bid_request::data::Segment;
+use super::bid_request::imp::banner::Format;
+use super::bid_request::imp::pmp::Deal;
+use super::bid_request::imp::{Audio, Banner, Metric, Native, Pmp, Video};
+use super::bid_request::{
+    App, Content, Data, Device, Geo, Imp, Producer, Publisher, Regs, Site, Source, User,
+};
+use super::bid_response::seat_bid::Bid;
+use super::bid_response::SeatBid;
+use super::bool::Bool;
+use super::eid::{Eid, Uid};
+use super::schain::{SupplyChain, SupplyChainNode};
+use super::{native_request, native_response};
+use super::{
+    AdPosition, AdUnitId, ApiFramework, AuctionType, BannerAdType, BidRequest, BidResponse,
+    CompanionType, ConnectionType, ContentCategory, ContentContext, ContentDeliveryMethod,
+    ContextSubtype, ContextType, CreativeAttribute, DataAssetType, DeviceType, EventTrackingMethod,
+    EventType, ExpandableDirection, FeedType, ImageAssetType, LayoutId, LocationService,
+    LocationType, LossReason, NativeRequest, NativeResponse, NoBidReason, PlacementType,
+    PlaybackCessationMode, PlaybackMethod, ProductionQuality, Protocol, QagMediaRating, StartDelay,
+    VideoLinearity, VideoPlacementType, VolumeNormalizationMode,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fmt;
+use std::str::FromStr;
+
+/// The value of a field.
+#[derive(Clone, Copy)]
+pub enum Field<'a> {
+    /// A JSON `null` inside an extension.
+    Null,
+    /// A `Bool` field or a JSON boolean.
+    Bool(bool),
+    /// An integer field or a JSON number that fits in an `i64`.
+    Int(i64),
+    /// A floating point field or any other JSON number.
+    Float(f64),
+    /// A string field or a JSON string.
+    Str(&'a str),
+    /// An enum field: wire value and `as_str_name`.
+    Enum(i64, &'static str),
+    /// A message or a JSON object.
+    Object(&'a dyn Fields),
+    /// A repeated field or a JSON array.
+    Array(&'a dyn Fields),
+}
+
+impl<'a> Field<'a> {
+    /// Strings.
+    pub fn as_str(&self) -> Option<&'a str> {
+        match *self {
+            Field::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Integers and enum wire values.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Field::Int(n) | Field::Enum(n, _) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Floats and integers.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Field::Float(x) => Some(x),
+            Field::Int(n) => Some(n as f64),
+            _ => None,
+        }
+    }
+
+    /// `Bool` fields and JSON booleans.
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Field::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// The field as it would be serialized.
+    pub fn to_json(&self) -> Value {
+        match *self {
+            Field::Null => Value::Null,
+            Field::Bool(b) => Value::Bool(b),
+            Field::Int(n) | Field::Enum(n, _) => Value::from(n),
+            Field::Float(x) => Value::from(x),
+            Field::Str(s) => Value::from(s),
+            Field::Object(f) | Field::Array(f) => f.to_json(),
+        }
+    }
+}
+
+impl fmt::Debug for Field<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Field::Null => f.write_str("Null"),
+            Field::Bool(b) => write!(f, "Bool({})", b),
+            Field::Int(n) => write!(f, "Int({})", n),
+            Field::Float(x) => write!(f, "Float({})", x),
+            Field::Str(s) => write!(f, "Str({:?})", s),
+            Field::Enum(n, name) => write!(f, "Enum({}, {})", n, name),
+            Field::Object(o) => write!(f, "Object({})", o.to_json()),
+            Field::Array(a) => write!(f, "Array({})", a.to_json()),
+        }
+    }
+}
+
+/// What is wrong with a path or with the value written to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PathProblem {
+    /// The path is not a valid dotted path or JSON Pointer.
+    Syntax,
+    /// No such field or index, or the path continues below a scalar.
+    NotFound,
+    /// The field is not optional and cannot be removed.
+    Required,
+    /// The value does not deserialize into the field's type.
+    InvalidValue(String),
+}
+
+/// Error returned for a path that cannot be parsed, read or written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathError {
+    /// The path as given.
+    pub path: String,
+    /// What is wrong with it.
+    pub problem: PathProblem,
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.problem {
+            PathProblem::Syntax => write!(f, "invalid path {:?}", self.path),
+            PathProblem::NotFound => write!(f, "{}: no such field", self.path),
+            PathProblem::Required => write!(f, "{}: required field", self.path),
+            PathProblem::InvalidValue(e) => write!(f, "{}: {}", self.path, e),
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+/// A parsed path: field names and array indices from the root.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FieldPath {
+    tokens: Vec<String>,
+}
+
+impl FieldPath {
+    /// Parses a JSON Pointer if `s` is empty or starts with `/`, and a
+    /// dotted path otherwise.
+    pub fn parse(s: &str) -> Result<FieldPath, PathError> {
+        if s.is_empty() || s.starts_with('/') {
+            FieldPath::from_pointer(s)
+        } else {
+            FieldPath::from_dotted(s)
+        }
+    }
+
+    /// Parses an RFC 6901 JSON Pointer. `""` is the root.
+    pub fn from_pointer(s: &str) -> Result<FieldPath, PathError> {
+        let syntax = || PathError {
+            path: s.to_string(),
+            problem: PathProblem::Syntax,
+        };
+        let rest = match s {
+            "" => return Ok(FieldPath::default()),
+            _ => s.strip_prefix('/').ok_or_else(syntax)?,
+        };
+        let tokens = rest
+            .split('/')
+            .map(|t| {
+                let mut out = String::with_capacity(t.len());
+                let mut chars = t.chars();
+                while let Some(c) = chars.next() {
+                    if c != '~' {
+                        out.push(c);
+                        continue;
+                    }
+                    match chars.next() {
+                        Some('0') => out.push('~'),
+                        Some('1') => out.push('/'),
+                        _ => return Err(syntax()),
+                    }
+                }
+                Ok(out)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(FieldPath { tokens })
+    }
+
+    /// Parses `a.b.c`, where an index is either a segment of its own
+    /// (`imp.0`) or bracketed (`imp[0]`).
+    pub fn from_dotted(s: &str) -> Result<FieldPath, PathError> {
+        let syntax = || PathError {
+            path: s.to_string(),
+            problem: PathProblem::Syntax,
+        };
+        let mut tokens = Vec::new();
+        for segment in s.split('.') {
+            let (name, mut rest) = match segment.find('[') {
+                Some(i) => segment.split_at(i),
+                None => (segment, ""),
+            };
+            if name.is_empty() {
+                return Err(syntax());
+            }
+            tokens.push(name.to_string());
+            while !rest.is_empty() {
+                let (index, tail) = rest
+                    .strip_prefix('[')
+                    .and_then(|r| r.split_once(']'))
+                    .filter(|(i, _)| index(i).is_some())
+                    .ok_or_else(syntax)?;
+                tokens.push(index.to_string());
+                rest = tail;
+            }
+        }
+        Ok(FieldPath { tokens })
+    }
+
+    /// The field names and indices.
+    pub fn tokens(&self) -> &[String] {
+        &self.tokens
+    }
+
+    /// Appends a field name or index.
+    pub fn push(&mut self, token: impl Into<String>) {
+        self.tokens.push(token.into());
+    }
+
+    /// Removes the last field name or index.
+    pub fn pop(&mut self) -> Option<String> {
+        self.tokens.pop()
+    }
+
+    /// Whether `prefix` is this path or one of its ancestors.
+    pub fn starts_with(&self, prefix: &FieldPath) -> bool {
+        self.tokens.starts_with(&prefix.tokens)
+    }
+
+    /// The path one level up, or `None` for the root.
+    pub fn parent(&self) -> Option<FieldPath> {
+        let (_, parent) = self.tokens.split_last()?;
+        Some(FieldPath {
+            tokens: parent.to_vec(),
+        })
+    }
+
+    /// The path as a JSON Pointer.
+    pub fn to_pointer(&self) -> String {
+        self.tokens
+            .iter()
+            .map(|t| format!("/{}", t.replace('~', "~0").replace('/', "~1")))
+            .collect()
+    }
+
+    /// The path with dots; names containing `.` cannot be parsed back.
+    pub fn to_dotted(&self) -> String {
+        self.tokens.join(".")
+    }
+
+    fn error(&self, problem: PathProblem) -> PathError {
+        PathError {
+            path: self.to_pointer(),
+            problem,
+        }
+    }
+}
+
+/// Displays as a JSON Pointer.
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_pointer())
+    }
+}
+
+impl FromStr for FieldPath {
+    type Err = PathError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FieldPath::parse(s)
+    }
+}
+
+/// An array index as RFC 6901 allows it: digits without leading zeros.
+fn index(token: &str) -> Option<usize> {
+    if token.is_empty()
+        || !token.bytes().all(|b| b.is_ascii_digit())
+        || (token.len() > 1 && token.starts_with('0'))
+    {
+        return None;
+    }
+    token.parse().ok()
+}
+
+fn invalid(e: serde_json::Error) -> PathProblem {
+    PathProblem::InvalidValue(e.to_string())
+}
+
+fn deserialize<T: DeserializeOwned>(value: Value) -> Result<T, PathProblem> {
+    T::deserialize(value).map_err(invalid)
+}
+
+/// Field access by JSON name, implemented by every message and by the
+/// types of their fields.
+pub trait Fields {
+    /// This value. An absent optional field is `Field::Null`; see
+    /// `is_present`.
+    fn field(&self) -> Field<'_>;
+
+    /// Whether the value would be serialized, i.e. is not an absent
+    /// optional field.
+    fn is_present(&self) -> bool {
+        true
+    }
+
+    /// The child named by `key`: a field name, an `ext` key or an array
+    /// index. Absent optional fields are returned as well.
+    fn child(&self, _key: &str) -> Option<&dyn Fields> {
+        None
+    }
+
+    /// The child named by `key`, for writing in place.
+    fn child_mut(&mut self, _key: &str) -> Option<&mut dyn Fields> {
+        None
+    }
+
+    /// The keys of the present children.
+    fn keys(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Sets the value `keys` below this one, creating missing objects.
+    /// `-` as the last array index appends.
+    fn set_at(&mut self, keys: &[String], value: Value) -> Result<(), PathProblem>;
+
+    /// Adds the child named by `key`. Fields and `ext` keys are set;
+    /// array elements are inserted before index `key`, or appended for
+    /// `-` and the array length.
+    fn insert_child(&mut self, key: &str, value: Value) -> Result<(), PathProblem> {
+        self.child_mut(key)
+            .ok_or(PathProblem::NotFound)?
+            .set_at(&[], value)
+    }
+
+    /// Removes the child named by `key`.
+    fn remove_child(&mut self, _key: &str) -> Result<(), PathProblem> {
+        Err(PathProblem::NotFound)
+    }
+
+    /// Clears this value if it is optional.
+    fn clear(&mut self) -> Result<(), PathProblem> {
+        Err(PathProblem::Required)
+    }
+
+    /// Builds a value of this type from JSON.
+    fn from_json(value: Value) -> Result<Self, PathProblem>
+    where
+        Self: Sized;
+
+    /// An empty value to create missing objects with, if there is one.
+    fn empty() -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
+    /// The value as it would be serialized.
+    fn to_json(&self) -> Value;
+
+    /// The present value at `path`.
+    fn lookup(&self, path: &FieldPath) -> Option<Field<'_>> {
+        let mut node: &dyn Fields = self.as_fields();
+        for key in path.tokens() {
+            node = node.child(key)?;
+        }
+        node.is_present().then(|| node.field())
+    }
+
+    /// The present value at a dotted path or JSON Pointer. Malformed paths
+    /// yield `None`, as unknown fields do.
+    fn get_path(&self, path: &str) -> Option<Field<'_>> {
+        self.lookup(&FieldPath::parse(path).ok()?)
+    }
+
+    /// Sets the value at `path`; see `set_at`.
+    fn set(&mut self, path: &FieldPath, value: Value) -> Result<(), PathError> {
+        self.set_at(path.tokens(), value).map_err(|p| path.error(p))
+    }
+
+    /// Sets the value at a dotted path or JSON Pointer.
+    fn set_path(&mut self, path: &str, value: Value) -> Result<(), PathError> {
+        self.set(&FieldPath::parse(path)?, value)
+    }
+
+    /// Removes the value at `path`: clears an optional field, removes an
+    /// array element or an `ext` key.
+    fn remove(&mut self, path: &FieldPath) -> Result<(), PathError> {
+        let (last, parents) = path
+            .tokens()
+            .split_last()
+            .ok_or_else(|| path.error(PathProblem::Required))?;
+        let mut node: &mut dyn Fields = self.as_fields_mut();
+        for key in parents {
+            node = node
+                .child_mut(key)
+                .ok_or_else(|| path.error(PathProblem::NotFound))?;
+        }
+        node.remove_child(last).map_err(|p| path.error(p))
+    }
+
+    /// Removes the value at a dotted path or JSON Pointer.
+    fn remove_path(&mut self, path: &str) -> Result<(), PathError> {
+        self.remove(&FieldPath::parse(path)?)
+    }
+
+    #[doc(hidden)]
+    fn as_fields(&self) -> &dyn Fields;
+
+    #[doc(hidden)]
+    fn as_fields_mut(&mut self) -> &mut dyn Fields;
+}
+
+macro_rules! as_fields {
+    () => {
+        fn as_fields(&self) -> &dyn Fields {
+            self
+        }
+
+        fn as_fields_mut(&mut self) -> &mut dyn Fields {
+            self
+        }
+    };
+}
+
+impl<T: Fields + Serialize + DeserializeOwned> Fields for Option<T> {
+    fn field(&self) -> Field<'_> {
+        self.as_ref().map_or(Field::Null, T::field)
+    }
+
+    fn is_present(&self) -> bool {
+        self.is_some()
+    }
+
+    fn child(&self, key: &str) -> Option<&dyn Fields> {
+        self.as_ref()?.child(key)
+    }
+
+    fn child_mut(&mut self, key: &str) -> Option<&mut dyn Fields> {
+        self.as_mut()?.child_mut(key)
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.as_ref().map(T::keys).unwrap_or_default()
+    }
+
+    fn set_at(&mut self, keys: &[String], value: Value) -> Result<(), PathProblem> {
+        match self {
+            _ if keys.is_empty() => *self = Self::from_json(value)?,
+            Some(v) => v.set_at(keys, value)?,
+            None => {
+                let mut v = T::empty().ok_or(PathProblem::NotFound)?;
+                v.set_at(keys, value)?;
+                *self = Some(v);
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_child(&mut self, key: &str, value: Value) -> Result<(), PathProblem> {
+        self.as_mut()
+            .ok_or(PathProblem::NotFound)?
+            .insert_child(key, value)
+    }
+
+    fn remove_child(&mut self, key: &str) -> Result<(), PathProblem> {
+        self.as_mut()
+            .ok_or(PathProblem::NotFound)?
+            .remove_child(key)
+    }
+
+    fn clear(&mut self) -> Result<(), PathProblem> {
+        self.take().map(drop).ok_or(PathProblem::NotFound)
+    }
+
+    fn from_json(value: Value) -> Result<Self, PathProblem> {
+        match value {
+            Value::Null => Ok(None),
+            value => T::from_json(value).map(Some),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        self.as_ref().map_or(Value::Null, T::to_json)
+    }
+
+    as_fields!();
+}
+
+impl<T: Fields + Serialize + DeserializeOwned> Fields for Vec<T> {
+    fn field(&self) -> Field<'_> {
+        Field::Array(self)
+    }
+
+    fn child(&self, key: &str) -> Option<&dyn Fields> {
+        Some(self.get(index(key)?)?)
+    }
+
+    fn child_mut(&mut self, key: &str) -> Option<&mut dyn Fields> {
+        Some(self.get_mut(index(key)?)?)
+    }
+
+    fn keys(&self) -> Vec<String> {
+        (0..self.len()).map(|i| i.to_string()).collect()
+    }
+
+    fn set_at(&mut self, keys: &[String], value: Value) -> Result<(), PathProblem> {
+        let (key, rest) = match keys.split_first() {
+            None => {
+                *self = Self::from_json(value)?;
+                return Ok(());
+            }
+            Some(split) => split,
+        };
+        if key == "-" {
+            let item = match rest {
+                [] => T::from_json(value)?,
+                _ => {
+                    let mut item = T::empty().ok_or(PathProblem::NotFound)?;
+                    item.set_at(rest, value)?;
+                    item
+                }
+            };
+            self.push(item);
+            return Ok(());
+        }
+        self.get_mut(index(key).ok_or(PathProblem::NotFound)?)
+            .ok_or(PathProblem::NotFound)?
+            .set_at(rest, value)
+    }
+
+    fn insert_child(&mut self, key: &str, value: Value) -> Result<(), PathProblem> {
+        let i = match key {
+            "-" => self.len(),
+            _ => index(key)
+                .filter(|&i| i <= self.len())
+                .ok_or(PathProblem::NotFound)?,
+        };
+        self.insert(i, T::from_json(value)?);
+        Ok(())
+    }
+
+    fn remove_child(&mut self, key: &str) -> Result<(), PathProblem> {
+        match index(key) {
+            Some(i) if i < self.len() => {
+                self.remove(i);
+                Ok(())
+            }
+            _ => Err(PathProblem::NotFound),
+        }
+    }
+
+    fn from_json(value: Value) -> Result<Self, PathProblem> {
+        match value {
+            Value::Array(items) => items.into_iter().map(T::from_json).collect(),
+            value => deserialize(value),
+        }
+    }
+
+    fn empty() -> Option<Self> {
+        Some(Vec::new())
+    }
+
+    fn to_json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+
+    as_fields!();
+}
+
+/// Extensions: any JSON value.
+impl Fields for Value {
+    fn field(&self) -> Field<'_> {
+        match self {
+            Value::Null => Field::Null,
+            Value::Bool(b) => Field::Bool(*b),
+            Value::Number(n) => match n.as_i64() {
+                Some(n) => Field::Int(n),
+                None => Field::Float(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Value::String(s) => Field::Str(s),
+            Value::Array(_) => Field::Array(self),
+            Value::Object(_) => Field::Object(self),
+        }
+    }
+
+    fn child(&self, key: &str) -> Option<&dyn Fields> {
+        match self {
+            Value::Object(map) => Some(map.get(key)?),
+            Value::Array(items) => Some(items.get(index(key)?)?),
+            _ => None,
+        }
+    }
+
+    fn child_mut(&mut self, key: &str) -> Option<&mut dyn Fields> {
+        match self {
+            Value::Object(map) => Some(map.get_mut(key)?),
+            Value::Array(items) => Some(items.get_mut(index(key)?)?),
+            _ => None,
+        }
+    }
+
+    fn keys(&self) -> Vec<String> {
+        match self {
+            Value::Object(map) => map.keys().cloned().collect(),
+            Value::Array(items) => (0..items.len()).map(|i| i.to_string()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn set_at(&mut self, keys: &[String], value: Value) -> Result<(), PathProblem> {
+        let (key, rest) = match keys.split_first() {
+            None => {
+                *self = value;
+                return Ok(());
+            }
+            Some(split) => split,
+        };
+        if self.is_null() {
+            *self = Value::Object(Map::new());
+        }
+        match self {
+            Value::Object(map) => map
+                .entry(key.as_str())
+                .or_insert(Value::Null)
+                .set_at(rest, value),
+            Value::Array(items) if key == "-" => {
+                let mut item = Value::Null;
+                item.set_at(rest, value)?;
+                items.push(item);
+                Ok(())
+            }
+            Value::Array(items) => items
+                .get_mut(index(key).ok_or(PathProblem::NotFound)?)
+                .ok_or(PathProblem::NotFound)?
+                .set_at(rest, value),
+            _ => Err(PathProblem::NotFound),
+        }
+    }
+
+    fn insert_child(&mut self, key: &str, value: Value) -> Result<(), PathProblem> {
+        match self {
+            Value::Object(map) => {
+                map.insert(key.to_string(), value);
+                Ok(())
+            }
+            Value::Array(items) => {
+                let i = match key {
+                    "-" => items.len(),
+                    _ => index(key)
+                        .filter(|&i| i <= items.len())
+                        .ok_or(PathProblem::NotFound)?,
+                };
+                items.insert(i, value);
+                Ok(())
+            }
+            _ => Err(PathProblem::NotFound),
+        }
+    }
+
+    fn remove_child(&mut self, key: &str) -> Result<(), PathProblem> {
+        let removed = match self {
+            Value::Object(map) => map.remove(key).is_some(),
+            Value::Array(items) => match index(key) {
+                Some(i) if i < items.len() => {
+                    items.remove(i);
+                    true
+                }
+                _ => false,
+            },
+            _ => false,
+        };
+        removed.then_some(()).ok_or(PathProblem::NotFound)
+    }
+
+    fn from_json(value: Value) -> Result<Self, PathProblem> {
+        Ok(value)
+    }
+
+    fn empty() -> Option<Self> {
+        Some(Value::Object(Map::new()))
+    }
+
+    fn to_json(&self) -> Value {
+        self.clone()
+    }
+
+    as_fields!();
+}
+
+/// Scalars, which have no children and are written as a whole.
+macro_rules! scalar {
+    ($t:ty, |$v:ident| $field:expr) => {
+        impl Fields for $t {
+            fn field(&self) -> Field<'_> {
+                let $v = self;
+                $field
+            }
+
+            fn set_at(&mut self, keys: &[String], value: Value) -> Result<(), PathProblem> {
+                if !keys.is_empty() {
+                    return Err(PathProblem::NotFound);
+                }
+                *self = Self::from_json(value)?;
+                Ok(())
+            }
+
+            fn from_json(value: Value) -> Result<Self, PathProblem> {
+                deserialize(value)
+            }
+
+            fn to_json(&self) -> Value {
+                serde_json::to_value(self).unwrap_or(Value::Null)
+            }
+
+            as_fields!();
+        }
+    };
+}
+
+scalar!(String, |s| Field::Str(s));
+scalar!(i32, |n| Field::Int(i64::from(*n)));
+scalar!(f64, |x| Field::Float(*x));
+
+impl Fields for Bool {
+    fn field(&self) -> Field<'_> {
+        Field::Bool(*self == Bool::True)
+    }
+
+    fn set_at(&mut self, keys: &[String], value: Value) -> Result<(), PathProblem> {
+        if !keys.is_empty() {
+            return Err(PathProblem::NotFound);
+        }
+        *self = Self::from_json(value)?;
+        Ok(())
+    }
+
+    /// Takes a JSON boolean as well as 0 and 1.
+    fn from_json(value: Value) -> Result<Self, PathProblem> {
+        match value {
+            Value::Bool(true) => Ok(Bool::True),
+            Value::Bool(false) => Ok(Bool::False),
+            value => deserialize(value),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+
+    as_fields!();
+}
+
+scalar!(AuctionType, |a| match *a {
+    AuctionType::FirstPrice => Field::Enum(1, "FIRST_PRICE"),
+    AuctionType::SecondPrice => Field::Enum(2, "SECOND_PRICE"),
+    AuctionType::FixedPrice(v) => Field::Enum(v.into(), "FIXED_PRICE"),
+});
+
+macro_rules! enums {
+    ($($t:ty),* $(,)?) => {
+        $(scalar!($t, |e| Field::Enum(*e as i64, e.as_str_name()));)*
+    };
+}
+
+enums!(
+    AdPosition,
+    AdUnitId,
+    ApiFramework,
+    BannerAdType,
+    CompanionType,
+    ConnectionType,
+    ContentCategory,
+    ContentContext,
+    ContentDeliveryMethod,
+    ContextSubtype,
+    ContextType,
+    CreativeAttribute,
+    DataAssetType,
+    DeviceType,
+    EventTrackingMethod,
+    EventType,
+    ExpandableDirection,
+    FeedType,
+    ImageAssetType,
+    LayoutId,
+    LocationService,
+    LocationType,
+    LossReason,
+    NoBidReason,
+    PlacementType,
+    PlaybackCessationMode,
+    PlaybackMethod,
+    ProductionQuality,
+    Protocol,
+    QagMediaRating,
+    StartDelay,
+    VideoLinearity,
+    VideoPlacementType,
+    VolumeNormalizationMode,
+);
+
+/// JSON name of a field: the identifier without a raw prefix.
+const fn name(ident: &'static str) -> &'static str {
+    match ident.as_bytes() {
+        [b'r', b'#', rest @ ..] => match std::str::from_utf8(rest) {
+            Ok(name) => name,
+            Err(_) => ident,
+        },
+        _ => ident,
+    }
+}
+
+/// Messages: each field is a child under its JSON name. Messages without
+/// a `Default` are marked `@required` and cannot be created by a path.
+macro_rules! message {
+    ($t:ty { $($f:ident),* $(,)? }) => {
+        message!(@impl $t { $($f),* } Some(<$t>::default()));
+    };
+    (@required $t:ty { $($f:ident),* $(,)? }) => {
+        message!(@impl $t { $($f),* } None);
+    };
+    (@impl $t:ty { $($f:ident),* } $empty:expr) => {
+        #[allow(deprecated)]
+        impl Fields for $t {
+            fn field(&self) -> Field<'_> {
+                Field::Object(self)
+            }
+
+            fn child(&self, key: &str) -> Option<&dyn Fields> {
+                $(if key == name(stringify!($f)) {
+                    return Some(&self.$f);
+                })*
+                None
+            }
+
+            fn child_mut(&mut self, key: &str) -> Option<&mut dyn Fields> {
+                $(if key == name(stringify!($f)) {
+                    return Some(&mut self.$f);
+                })*
+                None
+            }
+
+            fn keys(&self) -> Vec<String> {
+                let mut keys = Vec::new();
+                $(if self.$f.is_present() {
+                    keys.push(name(stringify!($f)).to_string());
+                })*
+                keys
+            }
+
+            fn set_at(&mut self, keys: &[String], value: Value) -> Result<(), PathProblem> {
+                match keys.split_first() {
+                    None => {
+                        *self = Self::from_json(value)?;
+                        Ok(())
+                    }
+                    Some((key, rest)) => self
+                        .child_mut(key)
+                        .ok_or(PathProblem::NotFound)?
+                        .set_at(rest, value),
+                }
+            }
+
+            fn remove_child(&mut self, key: &str) -> Result<(), PathProblem> {
+                self.child_mut(key).ok_or(PathProblem::NotFound)?.clear()
+            }
+
+            fn from_json(value: Value) -> Result<Self, PathProblem> {
+                deserialize(value)
+            }
+
+            fn empty() -> Option<Self> {
+                $empty
+            }
+
+            fn to_json(&self) -> Value {
+                serde_json::to_value(self).unwrap_or(Value::Null)
+            }
+
+            as_fields!();
+        }
+    };
+}
+
+message!(BidRequest {
+    id,
+    imp,
+    site,
+    app,
+    device,
+    user,
+    test,
+    at,
+    tmax,
+    wseat,
+    bseat,
+    allimps,
+    cur,
+    wlang,
+    bcat,
+    badv,
+    bapp,
+    source,
+    regs,
+    ext,
+});
+message!(Source {
+    fd,
+    tid,
+    pchain,
+    schain,
+    ext
+});
+message!(SupplyChain {
+    complete,
+    nodes,
+    ver,
+    ext
+});
+message!(SupplyChainNode {
+    asi,
+    sid,
+    rid,
+    name,
+    domain,
+    hp,
+    ext
+});
+message!(Imp {
+    id,
+    metric,
+    banner,
+    video,
+    audio,
+    native,
+    pmp,
+    displaymanager,
+    displaymanagerver,
+    instl,
+    tagid,
+    bidfloor,
+    bidfloorcur,
+    clickbrowser,
+    secure,
+    iframebuster,
+    exp,
+    ext,
+});
+message!(Metric {
+    r#type,
+    value,
+    vendor,
+    ext
+});
+message!(Banner {
+    format,
+    w,
+    h,
+    wmax,
+    hmax,
+    wmin,
+    hmin,
+    btype,
+    battr,
+    pos,
+    mimes,
+    topframe,
+    expdir,
+    api,
+    id,
+    vcm,
+    ext,
+});
+message!(Format {
+    w,
+    h,
+    wratio,
+    hratio,
+    wmin,
+    ext
+});
+message!(Video {
+    mimes,
+    minduration,
+    maxduration,
+    startdelay,
+    protocols,
+    protocol,
+    w,
+    h,
+    placement,
+    linearity,
+    skip,
+    skipmin,
+    skipafter,
+    sequence,
+    battr,
+    maxextended,
+    minbitrate,
+    maxbitrate,
+    boxingallowed,
+    playbackmethod,
+    playbackend,
+    delivery,
+    pos,
+    companionad,
+    api,
+    companiontype,
+    ext,
+});
+message!(Audio {
+    mimes,
+    minduration,
+    maxduration,
+    protocols,
+    startdelay,
+    sequence,
+    battr,
+    maxextended,
+    minbitrate,
+    maxbitrate,
+    delivery,
+    companionad,
+    api,
+    companiontype,
+    maxseq,
+    feed,
+    stitched,
+    nvol,
+    ext,
+});
+message!(Native {
+    request,
+    request_native,
+    ver,
+    api,
+    battr,
+    ext
+});
+message!(Pmp {
+    private_auction,
+    deals,
+    ext
+});
+message!(Deal {
+    id,
+    bidfloor,
+    bidfloorcur,
+    at,
+    wseat,
+    wadomain,
+    ext
+});
+message!(Site {
+    id,
+    name,
+    domain,
+    cat,
+    sectioncat,
+    pagecat,
+    page,
+    r#ref,
+    search,
+    mobile,
+    privacypolicy,
+    publisher,
+    content,
+    keywords,
+    ext,
+});
+message!(App {
+    id,
+    name,
+    bundle,
+    domain,
+    storeurl,
+    cat,
+    sectioncat,
+    pagecat,
+    ver,
+    privacypolicy,
+    paid,
+    publisher,
+    content,
+    keywords,
+    ext,
+});
+message!(Publisher {
+    id,
+    name,
+    cat,
+    domain,
+    ext
+});
+message!(Content {
+    id,
+    episode,
+    title,
+    series,
+    season,
+    artist,
+    genre,
+    album,
+    isrc,
+    producer,
+    url,
+    cat,
+    prodq,
+    videoquality,
+    context,
+    contentrating,
+    userrating,
+    qagmediarating,
+    keywords,
+    livestream,
+    sourcerelationship,
+    len,
+    language,
+    embeddable,
+    data,
+    ext,
+});
+message!(Producer {
+    id,
+    name,
+    cat,
+    domain,
+    ext
+});
+message!(Device {
+    ua,
+    geo,
+    dnt,
+    lmt,
+    ip,
+    ipv6,
+    devicetype,
+    make,
+    model,
+    os,
+    osv,
+    hwv,
+    h,
+    w,
+    ppi,
+    pxratio,
+    js,
+    geofetch,
+    flashver,
+    language,
+    carrier,
+    mccmnc,
+    connectiontype,
+    ifa,
+    didsha1,
+    didmd5,
+    dpidsha1,
+    dpidmd5,
+    macsha1,
+    macmd5,
+    ext,
+});
+message!(Geo {
+    lat,
+    lon,
+    r#type,
+    accuracy,
+    lastfix,
+    ipservice,
+    country,
+    region,
+    regionfips104,
+    metro,
+    city,
+    zip,
+    utcoffset,
+    ext,
+});
+message!(User {
+    id,
+    buyeruid,
+    yob,
+    gender,
+    keywords,
+    customdata,
+    geo,
+    data,
+    eids,
+    ext
+});
+message!(Eid { source, uids, ext });
+message!(Uid { id, atype, ext });
+message!(Data {
+    id,
+    name,
+    segment,
+    ext
+});
+message!(Segment {
+    id,
+    name,
+    value,
+    ext
+});
+message!(Regs { coppa, ext });
+
+message!(BidResponse {
+    id,
+    seatbid,
+    bidid,
+    cur,
+    customdata,
+    nbr,
+    ext
+});
+message!(SeatBid {
+    bid,
+    seat,
+    group,
+    ext
+});
+message!(Bid {
+    id,
+    impid,
+    price,
+    adid,
+    nurl,
+    burl,
+    lurl,
+    adm,
+    adm_native,
+    adomain,
+    bundle,
+    iurl,
+    cid,
+    crid,
+    tactic,
+    cat,
+    attr,
+    api,
+    protocol,
+    qagmediarating,
+    language,
+    dealid,
+    w,
+    h,
+    wratio,
+    hratio,
+    exp,
+    ext,
+});
+
+message!(NativeRequest {
+    ver,
+    context,
+    contextsubtype,
+    plcmttype,
+    plcmtcnt,
+    seq,
+    assets,
+    aurlsupport,
+    durlsupport,
+    eventtrackers,
+    privacy,
+    layout,
+    adunit,
+    ext,
+});
+message!(native_request::Asset {
+    id,
+    required,
+    title,
+    img,
+    video,
+    data,
+    ext
+});
+message!(native_request::asset::Title { len, ext });
+message!(native_request::asset::Image {
+    r#type,
+    w,
+    h,
+    wmin,
+    hmin,
+    mimes,
+    ext
+});
+message!(@required native_request::asset::Data { r#type, len, ext });
+message!(@required native_request::EventTrackers { event, methods });
+message!(NativeResponse {
+    ver,
+    assets,
+    assetsurl,
+    dcourl,
+    link,
+    imptrackers,
+    jstracker,
+    eventtrackers,
+    privacy,
+    ext,
+});
+message!(native_response::Link {
+    url,
+    clicktrackers,
+    fallback,
+    ext
+});
+message!(native_response::Asset {
+    id,
+    required,
+    title,
+    img,
+    video,
+    data,
+    link,
+    ext
+});
+message!(native_response::asset::Title { text, len, ext });
+message!(native_response::asset::Image {
+    r#type,
+    url,
+    w,
+    h,
+    ext
+});
+message!(native_response::asset::Data {
+    r#type,
+    len,
+    label,
+    value,
+    ext
+});
+message!(native_response::asset::Video { vasttag, ext });
+message!(@required native_response::EventTracker { event, method, url, ext });
+
+#[cfg(test)]
+mod tests {
+    use super::super::{BidRequest, BidResponse};
+    use super::*;
+    use serde_json::json;
+
+    fn request() -> BidRequest {
+        serde_json::from_str(
+            r#"{"id":"1","at":1,"test":1,
+            "imp":[{"id":"a","banner":{"w":300,"format":[{"w":300,"h":250}]},
+                "ext":{"a/b":{"c~d":[1,2.5,"x",null]}}}],
+            "device":{"devicetype":3,"geo":{"country":"USA","type":2}},
+            "site":{"ref":"r"}}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_paths_and_pointers() {
+        let path = FieldPath::parse("imp[0].ext.a/b").unwrap();
+        assert_eq!(path.to_pointer(), "/imp/0/ext/a~1b");
+        assert_eq!(FieldPath::parse(&path.to_string()).unwrap(), path);
+        assert_eq!(FieldPath::parse("/imp/0/ext/a~1b").unwrap(), path);
+        assert_eq!(
+            FieldPath::parse("a..b").unwrap_err().problem,
+            PathProblem::Syntax
+        );
+    }
+
+    #[test]
+    fn reads_typed_fields() {
+        let request = request();
+        let get = |path: &str| request.get_path(path);
+        assert_eq!(get("/imp/0/banner/w").unwrap().as_i64(), Some(300));
+        assert_eq!(
+            get("imp[0].banner.format[0].h").unwrap().as_i64(),
+            Some(250)
+        );
+        assert_eq!(get("imp.0.banner.format.0.h").unwrap().as_i64(), Some(250));
+        assert_eq!(get("device.geo.country").unwrap().as_str(), Some("USA"));
+        assert!(matches!(
+            get("device.devicetype"),
+            Some(Field::Enum(3, "CONNECTED_TV"))
+        ));
+        assert!(matches!(get("device.geo.type"), Some(Field::Enum(2, _))));
+        assert!(matches!(get("at"), Some(Field::Enum(1, "FIRST_PRICE"))));
+        assert_eq!(get("test").unwrap().as_bool(), Some(true));
+        assert_eq!(get("site.ref").unwrap().as_str(), Some("r"));
+        assert_eq!(
+            get("imp.0.banner").unwrap().to_json(),
+            json!({"w":300,"format":[{"w":300,"h":250}]})
+        );
+        assert_eq!(
+            request.lookup(&FieldPath::default()).unwrap().to_json()["id"],
+            json!("1")
+        );
+
+        assert!(get("device.os").is_none());
+        assert!(get("device.nope").is_none());
+        assert!(get("/imp/01").is_none());
+    }
+
+    #[test]
+    fn reads_into_ext() {
+        let request = request();
+        assert_eq!(
+            request.get_path("/imp/0/ext/a~1b/c~0d/1").unwrap().as_f64(),
+            Some(2.5)
+        );
+        assert!(matches!(
+            request.get_path("/imp/0/ext/a~1b/c~0d/3"),
+            Some(Field::Null)
+        ));
+        assert!(request.get_path("/imp/0/ext/~2").is_none());
+    }
+
+    #[test]
+    fn writes_fields() {
+        let mut request = request();
+        request.set_path("user.geo.country", json!("CAN")).unwrap();
+        let user = request.user.as_ref().unwrap();
+        assert_eq!(user.geo.as_ref().unwrap().country.as_deref(), Some("CAN"));
+
+        request
+            .set_path("/imp/0/banner/format/-", json!({"w":728,"h":90}))
+            .unwrap();
+        let banner = request.imp[0].banner.as_ref().unwrap();
+        assert_eq!(banner.format.as_ref().unwrap().len(), 2);
+        request.set_path("imp.-.id", json!("b")).unwrap();
+        assert_eq!(request.imp[1].id, "b");
+
+        request.set_path("regs.coppa", json!(true)).unwrap();
+        assert_eq!(
+            request.get_path("regs.coppa").unwrap().as_bool(),
+            Some(true)
+        );
+        request.set_path("device.devicetype", json!(4)).unwrap();
+        assert!(matches!(
+            request.get_path("device.devicetype"),
+            Some(Field::Enum(4, _))
+        ));
+        request.set_path("device.ext.x.y", json!(5)).unwrap();
+        assert_eq!(
+            request.device.as_ref().unwrap().ext,
+            Some(json!({"x":{"y":5}}))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_writes() {
+        let mut request = request();
+        let err = request
+            .set_path("imp.0.banner.w", json!("300"))
+            .unwrap_err();
+        assert!(
+            matches!(err.problem, PathProblem::InvalidValue(_)),
+            "{}",
+            err
+        );
+        assert_eq!(
+            request
+                .set_path("device.nope", json!(1))
+                .unwrap_err()
+                .problem,
+            PathProblem::NotFound
+        );
+        assert_eq!(
+            request
+                .set_path("device.os.x", json!(1))
+                .unwrap_err()
+                .problem,
+            PathProblem::NotFound
+        );
+        assert!(request.device.as_ref().unwrap().os.is_none());
+    }
+
+    #[test]
+    fn removes_fields() {
+        let mut request = request();
+        request.remove_path("device.geo").unwrap();
+        assert!(request.device.as_ref().unwrap().geo.is_none());
+        assert_eq!(
+            request.remove_path("device.geo").unwrap_err().problem,
+            PathProblem::NotFound
+        );
+        assert_eq!(
+            request.remove_path("id").unwrap_err().problem,
+            PathProblem::Required
+        );
+        request.remove_path("/imp/0/ext/a~1b").unwrap();
+        assert_eq!(request.imp[0].ext, Some(json!({})));
+        request.remove_path("/imp/0").unwrap();
+        assert!(request.imp.is_empty());
+        assert_eq!(request.device.as_ref().unwrap().keys(), vec!["devicetype"]);
+    }
+
+    #[test]
+    fn writes_responses() {
+        let mut response = BidResponse::default();
+        response
+            .set_path("seatbid.-.bid.-.price", json!(1.25))
+            .unwrap();
+        assert_eq!(
+            response
+                .get_path("/seatbid/0/bid/0/price")
+                .unwrap()
+                .as_f64(),
+            Some(1.25)
+        );
+        response
+            .set_path("seatbid.0.bid.0.adm_native.link.url", json!("u"))
+            .unwrap();
+        assert_eq!(
+            response
+                .get_path("seatbid.0.bid.0.adm_native.link.url")
+                .unwrap()
+                .as_str(),
+            Some("u")
+        );
+    }
+}
@@ -0,0 +1,668 @@
+//! Offline IP-to-geo enrichment of `Device.geo`.
+//!
+//! `GeoLookup` resolves an address to a `GeoRecord`; `MmdbGeo` implements
+//! it over a local MaxMind DB file (GeoIP2/GeoLite2 City or Country
+//! layout), with the UTC offset of each record's time zone read from the
+//! local TZif files. `Device::enrich_geo` only fills missing fields, only
+//! when the existing location is empty or in the same country, and leaves a
+//! GPS-sourced `geo` untouched.
+
+use super::bid_request::{Device, Geo};
+use super::codes::alpha3_of;
+use super::tzif::Zone;
+use super::{LocationService, LocationType};
+use serde_json::{Map, Number, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+/// Geo data for an IP address.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GeoRecord {
+    /// ISO-3166-1 alpha-3 country code.
+    pub country: Option<String>,
+    /// ISO-3166-2 region code, e.g. "DE-BE", or the state code "CA" in the
+    /// USA.
+    pub region: Option<String>,
+    /// Nielsen DMA code.
+    pub metro: Option<String>,
+    /// City name, in the language the source was asked for.
+    pub city: Option<String>,
+    /// ZIP or postal code.
+    pub zip: Option<String>,
+    /// Offset from UTC in minutes.
+    pub utcoffset: Option<i32>,
+    /// Provider of the data.
+    pub ipservice: Option<LocationService>,
+}
+
+/// A source of IP-based geo data.
+pub trait GeoLookup {
+    /// The geo data for `addr`, or `None` if the address is unknown.
+    fn lookup(&self, addr: IpAddr) -> Option<GeoRecord>;
+}
+
+impl Device {
+    /// Fills missing `geo` fields from `lookup` using the device address
+    /// (`ipv6` preferred over `ip`). A `geo` of type `GpsLocation` is never
+    /// modified, and neither is one whose location is in another country
+    /// than the record (or in an unknown one). A `geo` without a location
+    /// gets it from the record, with type `Ip`. Returns whether a record was
+    /// found and applied.
+    pub fn enrich_geo<L: GeoLookup + ?Sized>(&mut self, lookup: &L) -> bool {
+        if let Some(Geo {
+            r#type: Some(LocationType::GpsLocation),
+            ..
+        }) = self.geo
+        {
+            return false;
+        }
+        let record = match self.addr().and_then(|addr| lookup.lookup(addr)) {
+            Some(record) => record,
+            None => return false,
+        };
+        let geo = self.geo.get_or_insert_with(Geo::default);
+        let located = geo.country.is_some()
+            || geo.region.is_some()
+            || geo.regionfips104.is_some()
+            || geo.metro.is_some()
+            || geo.city.is_some()
+            || geo.zip.is_some()
+            || geo.lat.is_some()
+            || geo.lon.is_some();
+        if !located {
+            geo.r#type = Some(LocationType::Ip);
+        } else if record.country.is_none() || geo.country != record.country {
+            return false;
+        } else if geo.r#type.is_none() {
+            geo.r#type = Some(LocationType::Ip);
+        }
+        if geo.ipservice.is_none() {
+            geo.ipservice = record.ipservice;
+        }
+        fill(&mut geo.country, record.country);
+        fill(&mut geo.region, record.region);
+        fill(&mut geo.metro, record.metro);
+        fill(&mut geo.city, record.city);
+        fill(&mut geo.zip, record.zip);
+        if geo.utcoffset.is_none() {
+            geo.utcoffset = record.utcoffset;
+        }
+        true
+    }
+}
+
+fn fill(field: &mut Option<String>, value: Option<String>) {
+    if field.is_none() {
+        *field = value;
+    }
+}
+
+/// Error returned when a MaxMind DB file cannot be read.
+#[derive(Debug)]
+pub enum MmdbError {
+    /// The file could not be read.
+    Io(std::io::Error),
+    /// The file is not a valid MaxMind DB.
+    Format(&'static str),
+}
+
+impl fmt::Display for MmdbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MmdbError::Io(e) => e.fmt(f),
+            MmdbError::Format(e) => write!(f, "invalid MaxMind DB: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MmdbError {}
+
+impl From<std::io::Error> for MmdbError {
+    fn from(e: std::io::Error) -> MmdbError {
+        MmdbError::Io(e)
+    }
+}
+
+const METADATA_MARKER: &[u8] = b"\xab\xcd\xefMaxMind.com";
+
+/// A MaxMind DB file held in memory.
+pub struct Mmdb {
+    buf: Vec<u8>,
+    node_count: usize,
+    record_size: usize,
+    ip_version: u64,
+    tree_size: usize,
+    /// Start of the data section.
+    data: usize,
+    /// Metadata map of the file (`database_type`, `build_epoch`, ...).
+    pub metadata: Value,
+}
+
+impl Mmdb {
+    /// Reads a database file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Mmdb, MmdbError> {
+        Mmdb::from_bytes(std::fs::read(path)?)
+    }
+
+    /// Parses a database from its bytes.
+    pub fn from_bytes(buf: Vec<u8>) -> Result<Mmdb, MmdbError> {
+        let start = buf
+            .windows(METADATA_MARKER.len())
+            .rposition(|w| w == METADATA_MARKER)
+            .ok_or(MmdbError::Format("metadata not found"))?
+            + METADATA_MARKER.len();
+        let (metadata, _) = Decoder {
+            buf: &buf,
+            base: start,
+        }
+        .decode(start, 0)?;
+        let field = |name| metadata.get(name).and_then(Value::as_u64);
+        let node_count = field("node_count").ok_or(MmdbError::Format("node_count"))? as usize;
+        let record_size = field("record_size").ok_or(MmdbError::Format("record_size"))? as usize;
+        let ip_version = field("ip_version").ok_or(MmdbError::Format("ip_version"))?;
+        if !matches!(record_size, 24 | 28 | 32) {
+            return Err(MmdbError::Format("unsupported record_size"));
+        }
+        let tree_size = node_count
+            .checked_mul(record_size / 4)
+            .ok_or(MmdbError::Format("search tree out of bounds"))?;
+        let data = tree_size
+            .checked_add(16)
+            .filter(|&data| data <= start)
+            .ok_or(MmdbError::Format("search tree out of bounds"))?;
+        Ok(Mmdb {
+            node_count,
+            record_size,
+            ip_version,
+            tree_size,
+            data,
+            metadata,
+            buf,
+        })
+    }
+
+    /// The data record for `addr`, if any.
+    pub fn lookup_value(&self, addr: IpAddr) -> Result<Option<Value>, MmdbError> {
+        let bits: u128 = match (addr, self.ip_version) {
+            (IpAddr::V4(v4), 4) => (u32::from(v4) as u128) << 96,
+            (IpAddr::V4(v4), _) => u32::from(v4) as u128,
+            (IpAddr::V6(v6), 4) => match v6.to_ipv4_mapped() {
+                Some(v4) => (u32::from(v4) as u128) << 96,
+                None => return Ok(None),
+            },
+            (IpAddr::V6(v6), _) => u128::from(v6),
+        };
+        let depth = if self.ip_version == 4 { 32 } else { 128 };
+        let mut node = 0;
+        for i in 0..depth {
+            if node >= self.node_count {
+                break;
+            }
+            node = self.record(node, (bits >> (127 - i)) & 1 == 1)?;
+        }
+        if node <= self.node_count {
+            return Ok(None);
+        }
+        let offset = node
+            .checked_sub(self.node_count + 16)
+            .ok_or(MmdbError::Format("data pointer out of bounds"))?
+            + self.data;
+        let decoder = Decoder {
+            buf: &self.buf,
+            base: self.data,
+        };
+        Ok(Some(decoder.decode(offset, 0)?.0))
+    }
+
+    /// The left (`right == false`) or right record of a search tree node.
+    fn record(&self, node: usize, right: bool) -> Result<usize, MmdbError> {
+        let size = self.record_size / 4;
+        let b = node
+            .checked_mul(size)
+            .and_then(|start| Some(start..start.checked_add(size)?))
+            .filter(|range| range.end <= self.tree_size)
+            .and_then(|range| self.buf.get(range))
+            .ok_or(MmdbError::Format("search tree out of bounds"))?;
+        let be = |bytes: &[u8]| bytes.iter().fold(0usize, |v, &b| (v << 8) | b as usize);
+        Ok(match (self.record_size, right) {
+            (24, false) => be(&b[0..3]),
+            (24, true) => be(&b[3..6]),
+            (28, false) => ((b[3] as usize & 0xf0) << 20) | be(&b[0..3]),
+            (28, true) => ((b[3] as usize & 0x0f) << 24) | be(&b[4..7]),
+            (_, false) => be(&b[0..4]),
+            (_, true) => be(&b[4..8]),
+        })
+    }
+}
+
+/// Decoder for the MaxMind DB data section format.
+struct Decoder<'a> {
+    buf: &'a [u8],
+    /// Offset that pointers are relative to.
+    base: usize,
+}
+
+/// Maximum nesting of maps, arrays and pointers.
+const MAX_DEPTH: usize = 64;
+
+impl Decoder<'_> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&[u8], MmdbError> {
+        self.buf
+            .get(offset..offset + len)
+            .ok_or(MmdbError::Format("data out of bounds"))
+    }
+
+    fn uint(&self, offset: usize, len: usize) -> Result<u128, MmdbError> {
+        Ok(self
+            .bytes(offset, len)?
+            .iter()
+            .fold(0u128, |v, &b| (v << 8) | b as u128))
+    }
+
+    /// Decodes the value at `offset`, returning it and the offset after it.
+    fn decode(&self, offset: usize, depth: usize) -> Result<(Value, usize), MmdbError> {
+        if depth > MAX_DEPTH {
+            return Err(MmdbError::Format("data nested too deeply"));
+        }
+        let ctrl = self.bytes(offset, 1)?[0];
+        let mut pos = offset + 1;
+        let mut kind = ctrl >> 5;
+        if kind == 0 {
+            kind = 7 + self.bytes(pos, 1)?[0];
+            pos += 1;
+        }
+        if kind == 1 {
+            let ss = (ctrl >> 3) & 0x3;
+            let vvv = (ctrl & 0x7) as usize;
+            let (len, bias) = match ss {
+                0 => (1, 0),
+                1 => (2, 2048),
+                2 => (3, 526336),
+                _ => (4, 0),
+            };
+            let raw = self.uint(pos, len)? as usize;
+            let target = match ss {
+                3 => raw,
+                _ => (vvv << (8 * len)) + raw + bias,
+            };
+            let (value, _) = self.decode(self.base + target, depth + 1)?;
+            return Ok((value, pos + len));
+        }
+        let mut size = (ctrl & 0x1f) as usize;
+        match size {
+            29 => {
+                size = 29 + self.uint(pos, 1)? as usize;
+                pos += 1;
+            }
+            30 => {
+                size = 285 + self.uint(pos, 2)? as usize;
+                pos += 2;
+            }
+            31 => {
+                size = 65821 + self.uint(pos, 3)? as usize;
+                pos += 3;
+            }
+            _ => {}
+        }
+        let value = match kind {
+            2 => {
+                let s = std::str::from_utf8(self.bytes(pos, size)?)
+                    .map_err(|_| MmdbError::Format("invalid UTF-8 string"))?;
+                pos += size;
+                Value::String(s.to_string())
+            }
+            3 => {
+                let v = f64::from_bits(self.uint(pos, 8)? as u64);
+                pos += 8;
+                Number::from_f64(v).map_or(Value::Null, Value::Number)
+            }
+            4 => {
+                let bytes = self.bytes(pos, size)?;
+                pos += size;
+                Value::Array(bytes.iter().map(|&b| Value::from(b)).collect())
+            }
+            5 | 6 | 9 | 10 => {
+                let v = self.uint(pos, size)?;
+                pos += size;
+                match u64::try_from(v) {
+                    Ok(v) => Value::from(v),
+                    Err(_) => Value::String(v.to_string()),
+                }
+            }
+            7 => {
+                let mut map = Map::new();
+                for _ in 0..size {
+                    let (key, next) = self.decode(pos, depth + 1)?;
+                    let (value, next) = self.decode(next, depth + 1)?;
+                    pos = next;
+                    match key {
+                        Value::String(key) => map.insert(key, value),
+                        _ => return Err(MmdbError::Format("map key is not a string")),
+                    };
+                }
+                Value::Object(map)
+            }
+            8 => {
+                let v = self.uint(pos, size)? as u32 as i32;
+                pos += size;
+                Value::from(v)
+            }
+            11 => {
+                let mut array = Vec::with_capacity(size.min(1024));
+                for _ in 0..size {
+                    let (value, next) = self.decode(pos, depth + 1)?;
+                    pos = next;
+                    array.push(value);
+                }
+                Value::Array(array)
+            }
+            14 => Value::Bool(size != 0),
+            15 => {
+                let v = f32::from_bits(self.uint(pos, 4)? as u32);
+                pos += 4;
+                Number::from_f64(v as f64).map_or(Value::Null, Value::Number)
+            }
+            _ => return Err(MmdbError::Format("unsupported data type")),
+        };
+        Ok((value, pos))
+    }
+}
+
+/// `GeoLookup` over a GeoIP2/GeoLite2 City or Country database.
+///
+/// The database only names the time zone of a record, so `utcoffset` is its
+/// offset at the time of the lookup, from the TZif file of that zone under
+/// `zoneinfo`. It is left unset if the zone has no readable file there.
+pub struct MmdbGeo {
+    /// The City or Country database.
+    pub db: Mmdb,
+    /// Language of the `names` map used for `city`.
+    pub language: String,
+    /// Directory of TZif time zone files, e.g. `/usr/share/zoneinfo`.
+    pub zoneinfo: PathBuf,
+    /// Time zones read so far, by name; `None` for unreadable files.
+    zones: RwLock<HashMap<String, Option<Arc<Zone>>>>,
+}
+
+impl MmdbGeo {
+    /// Wraps a database, using English city names and the system's time
+    /// zone files.
+    pub fn new(db: Mmdb) -> MmdbGeo {
+        MmdbGeo {
+            db,
+            language: "en".to_string(),
+            zoneinfo: PathBuf::from("/usr/share/zoneinfo"),
+            zones: RwLock::default(),
+        }
+    }
+
+    /// Opens a database file, using English city names and the system's
+    /// time zone files.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<MmdbGeo, MmdbError> {
+        Ok(MmdbGeo::new(Mmdb::open(path)?))
+    }
+
+    /// The current offset from UTC in minutes of the time zone `name`, such
+    /// as `America/Los_Angeles`.
+    fn utcoffset(&self, name: &str) -> Option<i32> {
+        let valid = name.split('/').all(|part| {
+            !part.is_empty()
+                && !part.starts_with('.')
+                && part
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-' | b'+' | b'.'))
+        });
+        if !valid {
+            return None;
+        }
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .ok()?
+            .as_secs() as i64;
+        let cached = self
+            .zones
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name)
+            .cloned();
+        // Read and parse a new zone without holding the lock; a racing
+        // lookup of the same zone only repeats the work.
+        let zone = match cached {
+            Some(zone) => zone,
+            None => {
+                let zone = std::fs::read(self.zoneinfo.join(name))
+                    .ok()
+                    .and_then(|tzif| Zone::parse(&tzif))
+                    .map(Arc::new);
+                self.zones
+                    .write()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .entry(name.to_string())
+                    .or_insert(zone)
+                    .clone()
+            }
+        };
+        zone?.utc_offset(now).map(|seconds| seconds / 60)
+    }
+}
+
+impl GeoLookup for MmdbGeo {
+    fn lookup(&self, addr: IpAddr) -> Option<GeoRecord> {
+        let v = self.db.lookup_value(addr).ok()??;
+        let str_at = |path: &str| v.pointer(path).and_then(Value::as_str);
+        let alpha2 = str_at("/country/iso_code");
+        let country = alpha2.and_then(alpha3_of);
+        // The database has the code within the country, e.g. "BE" for
+        // Berlin; OpenRTB wants the full ISO-3166-2 code outside the USA.
+        let region = match (alpha2, str_at("/subdivisions/0/iso_code")) {
+            (Some("US"), Some(code)) => Some(code.to_string()),
+            (Some(alpha2), Some(code)) => Some(format!("{}-{}", alpha2, code)),
+            _ => None,
+        };
+        let metro = v.pointer("/location/metro_code").and_then(Value::as_u64);
+        let city = str_at(&format!("/city/names/{}", self.language));
+        let zip = str_at("/postal/code");
+        Some(GeoRecord {
+            country: country.map(str::to_string),
+            region,
+            metro: metro.map(|m| m.to_string()),
+            city: city.map(str::to_string),
+            zip: zip.map(str::to_string),
+            utcoffset: str_at("/location/time_zone").and_then(|tz| self.utcoffset(tz)),
+            ipservice: Some(LocationService::Maxmind),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Written by testdata/geo/mmdb.py.
+    const CITY_24: &[u8] = include_bytes!("testdata/geo/city-24.mmdb");
+    const CITY_28: &[u8] = include_bytes!("testdata/geo/city-28.mmdb");
+    const CITY_32: &[u8] = include_bytes!("testdata/geo/city-32.mmdb");
+
+    const LOS_ANGELES: &[u8] = include_bytes!("testdata/geo/zoneinfo/America/Los_Angeles");
+
+    fn geo(bytes: &[u8]) -> MmdbGeo {
+        let geo = MmdbGeo {
+            zoneinfo: PathBuf::from("/nonexistent"),
+            ..MmdbGeo::new(Mmdb::from_bytes(bytes.to_vec()).unwrap())
+        };
+        geo.zones.write().unwrap().insert(
+            "America/Los_Angeles".to_string(),
+            Zone::parse(LOS_ANGELES).map(Arc::new),
+        );
+        geo
+    }
+
+    #[test]
+    fn reads_every_record_size() {
+        for (bytes, record_size, berlin) in [
+            (CITY_24, 24, "5.6.7.8"),
+            (CITY_28, 28, "2001:db8::1"),
+            (CITY_32, 32, "2001:db8::1"),
+        ] {
+            let geo = geo(bytes);
+            assert_eq!(geo.db.metadata["record_size"], record_size);
+            assert_eq!(geo.db.metadata["database_type"], "Test-City");
+            assert_eq!(geo.db.metadata["languages"][1], "en");
+
+            let us = geo.lookup("1.2.3.4".parse().unwrap()).unwrap();
+            assert_eq!(us.country.as_deref(), Some("USA"));
+            assert_eq!(us.region.as_deref(), Some("CA"));
+            assert_eq!(us.metro.as_deref(), Some("807"));
+            assert_eq!(us.city.as_deref(), Some("San Francisco"));
+            assert_eq!(us.zip.as_deref(), Some("94107"));
+            assert!(matches!(us.utcoffset, Some(-480 | -420)));
+            assert_eq!(us.ipservice, Some(LocationService::Maxmind));
+
+            let de = geo.lookup(berlin.parse().unwrap()).unwrap();
+            assert_eq!(de.country.as_deref(), Some("DEU"));
+            assert_eq!(de.city.as_deref(), Some("Berlin"));
+            assert_eq!(de.zip.as_deref(), Some("10115"));
+            assert_eq!(de.region.as_deref(), Some("DE-BE"));
+            assert_eq!(de.utcoffset, None);
+
+            assert_eq!(geo.utcoffset("Europe/Berlin"), None);
+            assert_eq!(geo.utcoffset("../zoneinfo/America/Los_Angeles"), None);
+            assert!(geo.lookup("1.2.4.1".parse().unwrap()).is_none());
+            assert!(geo.lookup("2001:db9::1".parse().unwrap()).is_none());
+        }
+    }
+
+    #[test]
+    fn decodes_data_types() {
+        let db = Mmdb::from_bytes(CITY_28.to_vec()).unwrap();
+        let v = db
+            .lookup_value("2001:db8:ffff::".parse().unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(v["location"]["latitude"], 52.52);
+        assert_eq!(v["location"]["accuracy_radius"], 20);
+        assert_eq!(v["is_eu"], true);
+        assert_eq!(v["country"]["iso_code"], "DE");
+        assert_eq!(db.metadata["build_epoch"], 1700000000u64);
+
+        // IPv4 addresses live under ::/96 of an IPv6 tree, and IPv4-mapped
+        // addresses resolve in an IPv4 tree.
+        assert!(db
+            .lookup_value("::1.2.3.4".parse().unwrap())
+            .unwrap()
+            .is_some());
+        let v4 = Mmdb::from_bytes(CITY_24.to_vec()).unwrap();
+        assert!(v4
+            .lookup_value("::ffff:1.2.3.4".parse().unwrap())
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        assert!(matches!(
+            Mmdb::from_bytes(b"not a database".to_vec()),
+            Err(MmdbError::Format("metadata not found"))
+        ));
+        // Keep the metadata but drop the search tree it describes.
+        let marker = CITY_32
+            .windows(METADATA_MARKER.len())
+            .position(|w| w == METADATA_MARKER)
+            .unwrap();
+        let truncated = CITY_32[marker - 16..].to_vec();
+        assert!(matches!(
+            Mmdb::from_bytes(truncated),
+            Err(MmdbError::Format("search tree out of bounds"))
+        ));
+
+        // A node_count whose tree size does not fit in usize.
+        let mut huge = METADATA_MARKER.to_vec();
+        huge.extend_from_slice(b"\xe3\x4anode_count\x08\x02");
+        huge.extend_from_slice(&[0xff; 8]);
+        huge.extend_from_slice(b"\x4brecord_size\xa1\x20\x4aip_version\xa1\x06");
+        assert!(matches!(
+            Mmdb::from_bytes(huge),
+            Err(MmdbError::Format("search tree out of bounds"))
+        ));
+    }
+
+    #[test]
+    fn enrich_fills_missing_fields_only() {
+        let geo = geo(CITY_32);
+        let mut device = Device {
+            ip: Some("1.2.3.4".to_string()),
+            geo: Some(Geo {
+                country: Some("USA".to_string()),
+                city: Some("Oakland".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(device.enrich_geo(&geo));
+        let g = device.geo.as_ref().unwrap();
+        assert_eq!(g.region.as_deref(), Some("CA"));
+        assert_eq!(g.city.as_deref(), Some("Oakland"));
+        assert_eq!(g.r#type, Some(LocationType::Ip));
+        assert_eq!(g.ipservice, Some(LocationService::Maxmind));
+
+        let mut gps = Device {
+            ip: Some("1.2.3.4".to_string()),
+            geo: Some(Geo {
+                r#type: Some(LocationType::GpsLocation),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(!gps.enrich_geo(&geo));
+        assert!(gps.geo.unwrap().country.is_none());
+
+        let mut unknown = Device {
+            ip: Some("9.9.9.9".to_string()),
+            ..Default::default()
+        };
+        assert!(!unknown.enrich_geo(&geo));
+        assert!(unknown.geo.is_none());
+    }
+
+    #[test]
+    fn enrich_keeps_locations_in_other_countries() {
+        let geo = geo(CITY_32);
+        for located in [
+            Geo {
+                country: Some("FRA".to_string()),
+                ..Default::default()
+            },
+            Geo {
+                city: Some("Paris".to_string()),
+                ..Default::default()
+            },
+        ] {
+            let mut device = Device {
+                ip: Some("1.2.3.4".to_string()),
+                geo: Some(located.clone()),
+                ..Default::default()
+            };
+            assert!(!device.enrich_geo(&geo));
+            assert!(device.geo == Some(located));
+        }
+
+        // An empty geo takes the whole location from the lookup.
+        let mut device = Device {
+            ip: Some("1.2.3.4".to_string()),
+            geo: Some(Geo {
+                r#type: Some(LocationType::UserProvided),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(device.enrich_geo(&geo));
+        let g = device.geo.unwrap();
+        assert_eq!(g.r#type, Some(LocationType::Ip));
+        assert_eq!(g.city.as_deref(), Some("San Francisco"));
+        assert!(matches!(g.utcoffset, Some(-480 | -420)));
+    }
+}
@@ -0,0 +1,659 @@
+//! IAB Global Privacy Platform (GPP) strings.
+//!
+//! OpenRTB 2.5 carries the string in `regs.ext.gpp` and the IDs of the
+//! sections applicable to the request in `regs.ext.gpp_sid`. A GPP string is
+//! a header listing section IDs followed by one `~`-separated encoded
+//! section per ID. The US national and original US state sections are
+//! decoded here; the embedded TCF EU and US Privacy sections reuse the
+//! `tcf` and `usp` decoders.
+
+use super::bid_request::Regs;
+use super::tcf::{decode_base64url, BitReader, TcString, TcfError};
+use super::usp::{ParseUsPrivacyError, UsPrivacy};
+use std::fmt;
+
+/// Section ID of the TCF EU v2 section.
+pub const TCF_EU_V2: u16 = 2;
+/// Section ID of the US Privacy (CCPA) section.
+pub const USP_V1: u16 = 6;
+/// Section ID of the US national section.
+pub const US_NAT: u16 = 7;
+/// Section ID of the California section.
+pub const US_CA: u16 = 8;
+/// Section ID of the Virginia section.
+pub const US_VA: u16 = 9;
+/// Section ID of the Colorado section.
+pub const US_CO: u16 = 10;
+/// Section ID of the Utah section.
+pub const US_UT: u16 = 11;
+/// Section ID of the Connecticut section.
+pub const US_CT: u16 = 12;
+
+/// Error returned when a GPP string or one of its sections is malformed.
+#[derive(Debug)]
+pub enum GppError {
+    /// The header is not a valid GPP v1 header.
+    InvalidHeader,
+    /// The number of sections does not match the header.
+    SectionCount { expected: usize, actual: usize },
+    /// A section could not be decoded.
+    Section(u16, String),
+}
+
+impl fmt::Display for GppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GppError::InvalidHeader => f.write_str("invalid GPP header"),
+            GppError::SectionCount { expected, actual } => write!(
+                f,
+                "GPP header lists {} sections but {} are present",
+                expected, actual
+            ),
+            GppError::Section(id, e) => write!(f, "invalid GPP section {}: {}", id, e),
+        }
+    }
+}
+
+impl std::error::Error for GppError {}
+
+/// A GPP string split into its sections, which are decoded on access.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GppString<'a> {
+    /// Section IDs in the order listed by the header.
+    pub section_ids: Vec<u16>,
+    sections: Vec<&'a str>,
+}
+
+impl<'a> GppString<'a> {
+    /// Decodes the header and splits the sections.
+    pub fn parse(s: &'a str) -> Result<GppString<'a>, GppError> {
+        let mut parts = s.split('~');
+        let header = decode_base64url(parts.next().unwrap_or_default())
+            .map_err(|_| GppError::InvalidHeader)?;
+        let section_ids = parse_header(&header).map_err(|_| GppError::InvalidHeader)?;
+        let sections: Vec<&str> = parts.collect();
+        if sections.len() != section_ids.len() {
+            return Err(GppError::SectionCount {
+                expected: section_ids.len(),
+                actual: sections.len(),
+            });
+        }
+        Ok(GppString {
+            section_ids,
+            sections,
+        })
+    }
+
+    /// The encoded section with the given ID, if present.
+    pub fn section(&self, id: u16) -> Option<&'a str> {
+        self.section_ids
+            .iter()
+            .position(|&s| s == id)
+            .map(|i| self.sections[i])
+    }
+
+    /// The TCF EU v2 section.
+    pub fn tcf_eu(&self) -> Option<Result<TcString, TcfError>> {
+        self.section(TCF_EU_V2).map(TcString::parse)
+    }
+
+    /// The US Privacy section.
+    pub fn us_privacy(&self) -> Option<Result<UsPrivacy, ParseUsPrivacyError>> {
+        self.section(USP_V1).map(str::parse)
+    }
+
+    /// The US national or state section with the given ID.
+    pub fn us_section(&self, id: u16) -> Option<Result<UsSection, GppError>> {
+        self.section(id).map(|s| UsSection::parse(id, s))
+    }
+
+    /// All US national and state sections present, in header order.
+    /// Sections with unknown IDs are skipped.
+    pub fn us_sections(&self) -> impl Iterator<Item = Result<UsSection, GppError>> + '_ {
+        self.section_ids
+            .iter()
+            .zip(&self.sections)
+            .filter(|(&id, _)| layout(id, 1).is_some())
+            .map(|(&id, s)| UsSection::parse(id, s))
+    }
+}
+
+/// Decodes the header: Type (3), Version (1) and a Fibonacci range of
+/// section IDs.
+fn parse_header(bytes: &[u8]) -> Result<Vec<u16>, TcfError> {
+    let mut r = BitReader::new(bytes);
+    if r.read(6)? != 3 || r.read(6)? != 1 {
+        return Err(TcfError::InvalidField("Type"));
+    }
+    let mut ids = Vec::new();
+    let mut last = 0u64;
+    for _ in 0..r.read(12)? {
+        let is_range = r.read_bool()?;
+        let start = last + read_fibonacci(&mut r)?;
+        let end = if is_range {
+            start + read_fibonacci(&mut r)?
+        } else {
+            start
+        };
+        if end > u16::MAX as u64 {
+            return Err(TcfError::InvalidField("SectionIds"));
+        }
+        ids.extend(start as u16..=end as u16);
+        last = end;
+    }
+    Ok(ids)
+}
+
+/// Reads a Fibonacci-coded integer, terminated by two consecutive 1 bits.
+fn read_fibonacci(r: &mut BitReader) -> Result<u64, TcfError> {
+    let (mut a, mut b) = (1u64, 2u64);
+    let mut value = 0u64;
+    let mut prev = false;
+    loop {
+        let bit = r.read_bool()?;
+        if bit && prev {
+            return Ok(value);
+        }
+        if bit {
+            value = value
+                .checked_add(a)
+                .ok_or(TcfError::InvalidField("Fibonacci"))?;
+        }
+        prev = bit;
+        (a, b) = (b, a.saturating_add(b));
+    }
+}
+
+/// A 2-bit GPP flag, used both for notices and for opt-outs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Flag {
+    /// Not applicable, or the field is not part of the section.
+    #[default]
+    NotApplicable,
+    /// Notice given, or opted out.
+    Yes,
+    /// Notice not given, or did not opt out.
+    No,
+}
+
+impl Flag {
+    fn read(r: &mut BitReader) -> Result<Flag, TcfError> {
+        match r.read(2)? {
+            0 => Ok(Flag::NotApplicable),
+            1 => Ok(Flag::Yes),
+            2 => Ok(Flag::No),
+            _ => Err(TcfError::InvalidField("Flag")),
+        }
+    }
+
+    fn read_n(r: &mut BitReader, n: usize) -> Result<Vec<Flag>, TcfError> {
+        (0..n).map(|_| Flag::read(r)).collect()
+    }
+}
+
+/// A US national or state section. Fields that a state does not define are
+/// `Flag::NotApplicable` (or empty).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UsSection {
+    /// GPP section ID, e.g. 7 for the US national section.
+    pub section_id: u16,
+    /// Version of the section encoding.
+    pub version: u8,
+    /// Whether the consumer was notified that their data is shared.
+    pub sharing_notice: Flag,
+    /// Whether the consumer was offered to opt out of the sale of their data.
+    pub sale_opt_out_notice: Flag,
+    /// Whether the consumer was offered to opt out of the sharing of their
+    /// data.
+    pub sharing_opt_out_notice: Flag,
+    /// Whether the consumer was offered to opt out of targeted advertising.
+    pub targeted_advertising_opt_out_notice: Flag,
+    /// Whether the consumer was offered to opt out of the processing of
+    /// sensitive data.
+    pub sensitive_data_processing_opt_out_notice: Flag,
+    /// Whether the consumer was offered to limit the use of sensitive data.
+    pub sensitive_data_limit_use_notice: Flag,
+    /// Whether the consumer opted out of the sale of their data.
+    pub sale_opt_out: Flag,
+    /// Whether the consumer opted out of the sharing of their data.
+    pub sharing_opt_out: Flag,
+    /// Whether the consumer opted out of targeted advertising.
+    pub targeted_advertising_opt_out: Flag,
+    /// Consent or opt-out per sensitive data category, in section order.
+    pub sensitive_data_processing: Vec<Flag>,
+    /// Consent to process the sensitive data of known children, per age band,
+    /// in section order.
+    pub known_child_sensitive_data_consents: Vec<Flag>,
+    /// Consent to collect personal data beyond what the disclosed purposes
+    /// need.
+    pub personal_data_consents: Flag,
+    /// Whether the transaction is covered by the Multi-State Privacy Agreement
+    /// (MSPA).
+    pub mspa_covered_transaction: Flag,
+    /// Whether the publisher operates in the MSPA Opt Out Option Mode.
+    pub mspa_opt_out_option_mode: Flag,
+    /// Whether the publisher operates in the MSPA Service Provider Mode.
+    pub mspa_service_provider_mode: Flag,
+    /// Global Privacy Control, if the section carries the GPC subsection.
+    pub gpc: Option<bool>,
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    SharingNotice,
+    SaleOptOutNotice,
+    SharingOptOutNotice,
+    TargetedAdvertisingOptOutNotice,
+    SensitiveDataProcessingOptOutNotice,
+    SensitiveDataLimitUseNotice,
+    SaleOptOut,
+    SharingOptOut,
+    TargetedAdvertisingOptOut,
+    SensitiveDataProcessing(usize),
+    KnownChildSensitiveDataConsents(usize),
+    PersonalDataConsents,
+    MspaCoveredTransaction,
+    MspaOptOutOptionMode,
+    MspaServiceProviderMode,
+}
+
+use Field::*;
+
+const MSPA: [Field; 3] = [
+    MspaCoveredTransaction,
+    MspaOptOutOptionMode,
+    MspaServiceProviderMode,
+];
+
+/// Core segment layout (after Version) of a section version, and whether
+/// the section may carry a GPC subsection.
+fn layout(id: u16, version: u8) -> Option<(Vec<Field>, bool)> {
+    let (fields, gpc): (&[Field], bool) = match (id, version) {
+        (US_NAT, 1) => (
+            &[
+                SharingNotice,
+                SaleOptOutNotice,
+                SharingOptOutNotice,
+                TargetedAdvertisingOptOutNotice,
+                SensitiveDataProcessingOptOutNotice,
+                SensitiveDataLimitUseNotice,
+                SaleOptOut,
+                SharingOptOut,
+                TargetedAdvertisingOptOut,
+                SensitiveDataProcessing(12),
+                KnownChildSensitiveDataConsents(2),
+                PersonalDataConsents,
+            ],
+            true,
+        ),
+        (US_NAT, _) => (
+            &[
+                SharingNotice,
+                SaleOptOutNotice,
+                SharingOptOutNotice,
+                TargetedAdvertisingOptOutNotice,
+                SensitiveDataProcessingOptOutNotice,
+                SensitiveDataLimitUseNotice,
+                SaleOptOut,
+                SharingOptOut,
+                TargetedAdvertisingOptOut,
+                SensitiveDataProcessing(16),
+                KnownChildSensitiveDataConsents(3),
+                PersonalDataConsents,
+            ],
+            true,
+        ),
+        (US_CA, _) => (
+            &[
+                SaleOptOutNotice,
+                SharingOptOutNotice,
+                SensitiveDataLimitUseNotice,
+                SaleOptOut,
+                SharingOptOut,
+                SensitiveDataProcessing(9),
+                KnownChildSensitiveDataConsents(2),
+                PersonalDataConsents,
+            ],
+            true,
+        ),
+        (US_VA, _) => (
+            &[
+                SharingNotice,
+                SaleOptOutNotice,
+                TargetedAdvertisingOptOutNotice,
+                SaleOptOut,
+                TargetedAdvertisingOptOut,
+                SensitiveDataProcessing(8),
+                KnownChildSensitiveDataConsents(1),
+            ],
+            false,
+        ),
+        (US_CO, _) => (
+            &[
+                SharingNotice,
+                SaleOptOutNotice,
+                TargetedAdvertisingOptOutNotice,
+                SaleOptOut,
+                TargetedAdvertisingOptOut,
+                SensitiveDataProcessing(7),
+                KnownChildSensitiveDataConsents(1),
+            ],
+            true,
+        ),
+        (US_UT, _) => (
+            &[
+                SharingNotice,
+                SaleOptOutNotice,
+                TargetedAdvertisingOptOutNotice,
+                SensitiveDataProcessingOptOutNotice,
+                SaleOptOut,
+                TargetedAdvertisingOptOut,
+                SensitiveDataProcessing(8),
+                KnownChildSensitiveDataConsents(1),
+            ],
+            false,
+        ),
+        (US_CT, _) => (
+            &[
+                SharingNotice,
+                SaleOptOutNotice,
+                TargetedAdvertisingOptOutNotice,
+                SaleOptOut,
+                TargetedAdvertisingOptOut,
+                SensitiveDataProcessing(8),
+                KnownChildSensitiveDataConsents(3),
+            ],
+            true,
+        ),
+        _ => return None,
+    };
+    Some((fields.iter().chain(&MSPA).copied().collect(), gpc))
+}
+
+impl UsSection {
+    /// Decodes the US national or state section `id` from its encoded form.
+    pub fn parse(id: u16, s: &str) -> Result<UsSection, GppError> {
+        UsSection::decode(id, s).map_err(|e| GppError::Section(id, e.to_string()))
+    }
+
+    fn decode(id: u16, s: &str) -> Result<UsSection, TcfError> {
+        let mut segments = s.split('.');
+        let core = decode_base64url(segments.next().unwrap_or_default())?;
+        let mut r = BitReader::new(&core);
+        let version = r.read(6)? as u8;
+        let (fields, has_gpc) = layout(id, version).ok_or(TcfError::InvalidField("SectionId"))?;
+
+        let mut section = UsSection {
+            section_id: id,
+            version,
+            ..UsSection::default()
+        };
+        for field in fields {
+            match field {
+                SensitiveDataProcessing(n) => {
+                    section.sensitive_data_processing = Flag::read_n(&mut r, n)?
+                }
+                KnownChildSensitiveDataConsents(n) => {
+                    section.known_child_sensitive_data_consents = Flag::read_n(&mut r, n)?
+                }
+                _ => *section.flag_mut(field) = Flag::read(&mut r)?,
+            }
+        }
+
+        if has_gpc {
+            for segment in segments {
+                let bytes = decode_base64url(segment)?;
+                let mut r = BitReader::new(&bytes);
+                if r.read(2)? == 1 {
+                    section.gpc = Some(r.read_bool()?);
+                }
+            }
+        }
+        Ok(section)
+    }
+
+    fn flag_mut(&mut self, field: Field) -> &mut Flag {
+        match field {
+            SharingNotice => &mut self.sharing_notice,
+            SaleOptOutNotice => &mut self.sale_opt_out_notice,
+            SharingOptOutNotice => &mut self.sharing_opt_out_notice,
+            TargetedAdvertisingOptOutNotice => &mut self.targeted_advertising_opt_out_notice,
+            SensitiveDataProcessingOptOutNotice => {
+                &mut self.sensitive_data_processing_opt_out_notice
+            }
+            SensitiveDataLimitUseNotice => &mut self.sensitive_data_limit_use_notice,
+            SaleOptOut => &mut self.sale_opt_out,
+            SharingOptOut => &mut self.sharing_opt_out,
+            TargetedAdvertisingOptOut => &mut self.targeted_advertising_opt_out,
+            PersonalDataConsents => &mut self.personal_data_consents,
+            MspaCoveredTransaction => &mut self.mspa_covered_transaction,
+            MspaOptOutOptionMode => &mut self.mspa_opt_out_option_mode,
+            MspaServiceProviderMode => &mut self.mspa_service_provider_mode,
+            SensitiveDataProcessing(_) | KnownChildSensitiveDataConsents(_) => {
+                unreachable!("flag lists are read by Flag::read_n")
+            }
+        }
+    }
+
+    /// Whether the user opted out of the sale of personal data.
+    pub fn opted_out_of_sale(&self) -> bool {
+        self.sale_opt_out == Flag::Yes
+    }
+
+    /// Whether the user opted out of sharing personal data.
+    pub fn opted_out_of_sharing(&self) -> bool {
+        self.sharing_opt_out == Flag::Yes
+    }
+
+    /// Whether the user opted out of targeted advertising.
+    pub fn opted_out_of_targeted_advertising(&self) -> bool {
+        self.targeted_advertising_opt_out == Flag::Yes
+    }
+
+    /// Whether any opt-out relevant to ad personalization is signalled,
+    /// including Global Privacy Control.
+    pub fn opted_out(&self) -> bool {
+        self.opted_out_of_sale()
+            || self.opted_out_of_sharing()
+            || self.opted_out_of_targeted_advertising()
+            || self.gpc == Some(true)
+    }
+}
+
+impl Regs {
+    /// The raw GPP string from `ext.gpp`.
+    pub fn gpp_str(&self) -> Option<&str> {
+        self.ext.as_ref()?.get("gpp")?.as_str()
+    }
+
+    /// The GPP string from `ext.gpp`, with its header decoded.
+    pub fn gpp(&self) -> Option<Result<GppString<'_>, GppError>> {
+        self.gpp_str().map(GppString::parse)
+    }
+
+    /// Section IDs applicable to the request, from `ext.gpp_sid`.
+    pub fn gpp_sid(&self) -> Option<Vec<u16>> {
+        self.ext
+            .as_ref()?
+            .get("gpp_sid")?
+            .as_array()?
+            .iter()
+            .map(|id| id.as_u64().and_then(|id| u16::try_from(id).ok()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tcf::tests::Bits;
+    use super::*;
+
+    /// The TCF EU section of the specification examples.
+    const TCF: &str = "CPXxRfAPXxRfAAfKABENB-CgAAAAAAAAAAYgAAAAAAAA";
+
+    /// Writes `n >= 1` in Fibonacci coding.
+    fn fibonacci(bits: &mut Bits, n: u64) {
+        let mut fib = vec![1u64, 2];
+        while fib[fib.len() - 1] <= n {
+            fib.push(fib[fib.len() - 1] + fib[fib.len() - 2]);
+        }
+        let mut set = vec![false; fib.len()];
+        let mut rest = n;
+        for i in (0..fib.len()).rev() {
+            if fib[i] <= rest {
+                set[i] = true;
+                rest -= fib[i];
+            }
+        }
+        let last = set.iter().rposition(|&b| b).unwrap();
+        for &bit in &set[..=last] {
+            bits.put(bit as u64, 1);
+        }
+        bits.put(1, 1);
+    }
+
+    /// A header listing `ranges` of section IDs.
+    fn header(ranges: &[(u64, u64)]) -> String {
+        let mut bits = Bits::default();
+        bits.put(3, 6).put(1, 6).put(ranges.len() as u64, 12);
+        let mut last = 0;
+        for &(start, end) in ranges {
+            bits.put((start != end) as u64, 1);
+            fibonacci(&mut bits, start - last);
+            if start != end {
+                fibonacci(&mut bits, end - start);
+            }
+            last = end;
+        }
+        bits.base64url()
+    }
+
+    #[test]
+    fn decodes_header() {
+        assert_eq!(header(&[(2, 2)]), "DBABMA");
+        assert_eq!(header(&[(2, 2), (6, 6)]), "DBACNYA");
+        assert_eq!(header(&[(7, 7)]), "DBABLA");
+        let s = format!("{}~a~b~c~d~e", header(&[(2, 2), (7, 10)]));
+        let gpp = GppString::parse(&s).unwrap();
+        assert_eq!(gpp.section_ids, [2, 7, 8, 9, 10]);
+        assert_eq!(gpp.section(9), Some("d"));
+        assert_eq!(gpp.section(6), None);
+    }
+
+    #[test]
+    fn rejects_malformed_strings() {
+        assert!(matches!(
+            GppString::parse("DBABMA"),
+            Err(GppError::SectionCount {
+                expected: 1,
+                actual: 0
+            })
+        ));
+        assert!(matches!(
+            GppString::parse("DBABMA~a~b"),
+            Err(GppError::SectionCount {
+                expected: 1,
+                actual: 2
+            })
+        ));
+        assert!(matches!(
+            GppString::parse("CBABMA~a"),
+            Err(GppError::InvalidHeader)
+        ));
+        assert!(matches!(
+            GppString::parse("D*"),
+            Err(GppError::InvalidHeader)
+        ));
+        assert!(matches!(
+            GppString::parse("DBAB"),
+            Err(GppError::InvalidHeader)
+        ));
+        let too_large = header(&[(65530, 65540)]);
+        assert!(matches!(
+            GppString::parse(&too_large),
+            Err(GppError::InvalidHeader)
+        ));
+    }
+
+    #[test]
+    fn decodes_embedded_tcf_and_usp_sections() {
+        let s = format!("DBACNYA~{}~1YNN", TCF);
+        let gpp = GppString::parse(&s).unwrap();
+        assert_eq!(gpp.section_ids, [TCF_EU_V2, USP_V1]);
+        let tc = gpp.tcf_eu().unwrap().unwrap();
+        assert_eq!(tc.version, 2);
+        let usp = gpp.us_privacy().unwrap().unwrap();
+        assert_eq!(usp.notice, Some(true));
+        assert_eq!(usp.opt_out_sale, Some(false));
+        assert_eq!(gpp.us_sections().count(), 0);
+    }
+
+    #[test]
+    fn decodes_us_national_section() {
+        let gpp = GppString::parse("DBABLA~BVQqAAAAAgA.QA").unwrap();
+        let section = gpp.us_section(US_NAT).unwrap().unwrap();
+        assert_eq!(section.version, 1);
+        assert_eq!(section.sharing_notice, Flag::Yes);
+        assert_eq!(section.sale_opt_out_notice, Flag::Yes);
+        assert_eq!(section.targeted_advertising_opt_out_notice, Flag::Yes);
+        assert_eq!(section.sale_opt_out, Flag::No);
+        assert_eq!(section.targeted_advertising_opt_out, Flag::No);
+        assert_eq!(section.sensitive_data_processing, [Flag::NotApplicable; 12]);
+        assert_eq!(section.known_child_sensitive_data_consents.len(), 2);
+        assert_eq!(section.mspa_covered_transaction, Flag::No);
+        assert_eq!(section.gpc, Some(false));
+        assert!(!section.opted_out());
+        let gpc = UsSection::parse(US_NAT, "BVQqAAAAAgA.YA").unwrap();
+        assert_eq!(gpc.gpc, Some(true));
+        assert!(gpc.opted_out());
+    }
+
+    #[test]
+    fn decodes_state_sections() {
+        // Virginia: notices, opt-outs, 8 sensitive categories, 1 child
+        // consent and the MSPA flags, without a GPC subsection.
+        let mut bits = Bits::default();
+        bits.put(1, 6)
+            .put(1, 2)
+            .put(1, 2)
+            .put(1, 2)
+            .put(1, 2)
+            .put(2, 2);
+        for _ in 0..8 {
+            bits.put(2, 2);
+        }
+        bits.put(0, 2).put(1, 2).put(2, 2).put(0, 2);
+        let section = UsSection::parse(US_VA, &format!("{}.YA", bits.base64url())).unwrap();
+        assert!(section.opted_out_of_sale());
+        assert!(!section.opted_out_of_targeted_advertising());
+        assert_eq!(section.sharing_opt_out, Flag::NotApplicable);
+        assert_eq!(section.sensitive_data_processing, [Flag::No; 8]);
+        assert_eq!(
+            section.known_child_sensitive_data_consents,
+            [Flag::NotApplicable]
+        );
+        assert_eq!(section.mspa_covered_transaction, Flag::Yes);
+        assert_eq!(section.gpc, None);
+
+        assert!(matches!(
+            UsSection::parse(US_VA, "BA"),
+            Err(GppError::Section(US_VA, _))
+        ));
+        assert!(matches!(
+            UsSection::parse(3, "BVQqAAAAAgA"),
+            Err(GppError::Section(3, _))
+        ));
+    }
+
+    #[test]
+    fn reads_regs_ext() {
+        let regs: Regs =
+            serde_json::from_str(r#"{"ext":{"gpp":"DBABLA~BVQqAAAAAgA.QA","gpp_sid":[7,8]}}"#)
+                .unwrap();
+        assert_eq!(regs.gpp_str(), Some("DBABLA~BVQqAAAAAgA.QA"));
+        assert_eq!(regs.gpp().unwrap().unwrap().section_ids, [US_NAT]);
+        assert_eq!(regs.gpp_sid(), Some(vec![7, 8]));
+        let regs: Regs = serde_json::from_str(r#"{"ext":{"gpp_sid":[7,-1]}}"#).unwrap();
+        assert_eq!(regs.gpp_sid(), None);
+    }
+}
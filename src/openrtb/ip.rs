@@ -0,0 +1,388 @@
+//! Typed IP addresses of a `Device`, anonymization and validation.
+//!
+//! `Device.ip` holds an IPv4 address and `Device.ipv6` an IPv6 address, both
+//! as strings. Senders mix them up, so either field is read as an address of
+//! either family; `Device::validate_ip` reports the mix-up. Addresses that
+//! cannot be public (private, loopback, reserved, ...) are a common sign of
+//! invalid traffic.
+
+use super::bid_request::Device;
+use std::net::{AddrParseError, IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Prefix lengths kept when anonymizing addresses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IpPrefix {
+    /// Bits kept of an IPv4 address, 24 by default.
+    pub v4: u8,
+    /// Bits kept of an IPv6 address, 48 by default (56 is also common).
+    pub v6: u8,
+}
+
+impl Default for IpPrefix {
+    fn default() -> IpPrefix {
+        IpPrefix { v4: 24, v6: 48 }
+    }
+}
+
+impl IpPrefix {
+    /// Zeroes the bits of `addr` beyond the prefix.
+    pub fn apply(self, addr: IpAddr) -> IpAddr {
+        match addr {
+            IpAddr::V4(v4) => IpAddr::V4(self.apply_v4(v4)),
+            IpAddr::V6(v6) => IpAddr::V6(self.apply_v6(v6)),
+        }
+    }
+
+    /// Truncates an address written as text, of either family. `None` when
+    /// it does not parse.
+    pub fn apply_str(self, addr: &str) -> Option<String> {
+        addr.parse::<IpAddr>()
+            .ok()
+            .map(|addr| self.apply(addr).to_string())
+    }
+
+    /// Truncates an IPv4 address to the `v4` prefix.
+    pub fn apply_v4(self, addr: Ipv4Addr) -> Ipv4Addr {
+        let mask = u32::MAX
+            .checked_shl(32 - self.v4.min(32) as u32)
+            .unwrap_or(0);
+        Ipv4Addr::from(u32::from(addr) & mask)
+    }
+
+    /// Truncates an IPv6 address to the `v6` prefix.
+    pub fn apply_v6(self, addr: Ipv6Addr) -> Ipv6Addr {
+        let mask = u128::MAX
+            .checked_shl(128 - self.v6.min(128) as u32)
+            .unwrap_or(0);
+        Ipv6Addr::from(u128::from(addr) & mask)
+    }
+
+    /// The stricter (shorter) of two prefixes.
+    pub fn min(self, other: IpPrefix) -> IpPrefix {
+        IpPrefix {
+            v4: self.v4.min(other.v4),
+            v6: self.v6.min(other.v6),
+        }
+    }
+}
+
+/// Kind of an address with respect to public routability.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IpClass {
+    /// A publicly routable address.
+    Public,
+    /// `0.0.0.0` or `::`.
+    Unspecified,
+    /// 127.0.0.0/8 or `::1`.
+    Loopback,
+    /// RFC 1918, shared address space (RFC 6598) or unique local (fc00::/7).
+    Private,
+    /// 169.254.0.0/16 or fe80::/10.
+    LinkLocal,
+    /// 224.0.0.0/4 or ff00::/8.
+    Multicast,
+    /// Reserved for documentation, e.g. 192.0.2.0/24 or 2001:db8::/32.
+    Documentation,
+    /// Any other special-purpose or unallocated range, e.g. the deprecated
+    /// 6to4 relay anycast 192.88.99.0/24.
+    Reserved,
+}
+
+impl IpClass {
+    /// Classifies an address. IPv6 addresses that embed an IPv4 address
+    /// (IPv4-mapped, NAT64 64:ff9b::/96 and 6to4 2002::/16) are classified
+    /// as the IPv4 address.
+    pub fn of(addr: IpAddr) -> IpClass {
+        match addr {
+            IpAddr::V4(v4) => IpClass::of_v4(v4),
+            IpAddr::V6(v6) => IpClass::of_v6(v6),
+        }
+    }
+
+    /// Classifies an IPv4 address.
+    pub fn of_v4(addr: Ipv4Addr) -> IpClass {
+        match addr.octets() {
+            [0, 0, 0, 0] => IpClass::Unspecified,
+            [127, ..] => IpClass::Loopback,
+            [10, ..] | [192, 168, ..] => IpClass::Private,
+            [172, b, ..] if b & 0xf0 == 16 => IpClass::Private,
+            [100, b, ..] if b & 0xc0 == 64 => IpClass::Private,
+            [169, 254, ..] => IpClass::LinkLocal,
+            [192, 0, 2, _] | [198, 51, 100, _] | [203, 0, 113, _] => IpClass::Documentation,
+            [224..=239, ..] => IpClass::Multicast,
+            [0, ..] | [192, 0, 0, _] | [192, 88, 99, _] | [240..=255, ..] => IpClass::Reserved,
+            [198, b, ..] if b & 0xfe == 18 => IpClass::Reserved,
+            _ => IpClass::Public,
+        }
+    }
+
+    /// Classifies an IPv6 address, as `of` does.
+    pub fn of_v6(addr: Ipv6Addr) -> IpClass {
+        if let Some(v4) = addr.to_ipv4_mapped() {
+            return IpClass::of_v4(v4);
+        }
+        let s = addr.segments();
+        let o = addr.octets();
+        if s[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+            return IpClass::of_v4(Ipv4Addr::new(o[12], o[13], o[14], o[15]));
+        }
+        if s[0] == 0x2002 {
+            return IpClass::of_v4(Ipv4Addr::new(o[2], o[3], o[4], o[5]));
+        }
+        if addr.is_unspecified() {
+            IpClass::Unspecified
+        } else if addr.is_loopback() {
+            IpClass::Loopback
+        } else if s[0] & 0xfe00 == 0xfc00 {
+            IpClass::Private
+        } else if s[0] & 0xffc0 == 0xfe80 {
+            IpClass::LinkLocal
+        } else if s[0] & 0xff00 == 0xff00 {
+            IpClass::Multicast
+        } else if s[0] == 0x2001 && s[1] == 0x0db8 {
+            IpClass::Documentation
+        } else if s[0] & 0xe000 != 0x2000 {
+            // Only 2000::/3 is allocated for global unicast.
+            IpClass::Reserved
+        } else {
+            IpClass::Public
+        }
+    }
+
+    /// Whether a device with this address is likely invalid traffic.
+    pub fn is_likely_ivt(self) -> bool {
+        self != IpClass::Public
+    }
+}
+
+/// Problem found by `Device::validate_ip`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IpProblem {
+    /// The field is not an IP address.
+    Invalid,
+    /// `ip` holds an IPv6 address or `ipv6` an IPv4 address.
+    WrongFamily,
+    /// The address is not public.
+    NotPublic(IpClass),
+}
+
+/// An IP address field with a problem.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IpIssue {
+    /// `device.ip` or `device.ipv6`.
+    pub field: &'static str,
+    /// What is wrong with the address.
+    pub problem: IpProblem,
+}
+
+impl Device {
+    /// `ip` as an address, of either family.
+    pub fn ip_addr(&self) -> Option<Result<IpAddr, AddrParseError>> {
+        self.ip.as_deref().map(str::parse)
+    }
+
+    /// `ipv6` as an address, of either family.
+    pub fn ipv6_addr(&self) -> Option<Result<IpAddr, AddrParseError>> {
+        self.ipv6.as_deref().map(str::parse)
+    }
+
+    /// The device address, preferring a valid `ipv6` over a valid `ip`.
+    pub fn addr(&self) -> Option<IpAddr> {
+        match self.ipv6_addr() {
+            Some(Ok(addr)) => Some(addr),
+            _ => self.ip_addr()?.ok(),
+        }
+    }
+
+    /// Stores `addr` in `ip` or `ipv6` depending on its family.
+    pub fn set_addr(&mut self, addr: IpAddr) {
+        match addr {
+            IpAddr::V4(v4) => self.ip = Some(v4.to_string()),
+            IpAddr::V6(v6) => self.ipv6 = Some(v6.to_string()),
+        }
+    }
+
+    /// Truncates `ip` and `ipv6` to `prefix`, each by the family of the
+    /// address it holds. Addresses that do not parse are removed.
+    pub fn anonymize_ip(&mut self, prefix: IpPrefix) {
+        for field in [&mut self.ip, &mut self.ipv6] {
+            *field = field.as_deref().and_then(|addr| prefix.apply_str(addr));
+        }
+    }
+
+    /// Checks `ip` and `ipv6`, reporting unparsable values, values of the
+    /// wrong family and addresses that are not public.
+    pub fn validate_ip(&self) -> Vec<IpIssue> {
+        let mut issues = Vec::new();
+        let fields = [
+            ("device.ip", &self.ip, false),
+            ("device.ipv6", &self.ipv6, true),
+        ];
+        for (field, value, v6) in fields {
+            let addr = match value.as_deref().map(str::parse::<IpAddr>) {
+                None => continue,
+                Some(Ok(addr)) => addr,
+                Some(Err(_)) => {
+                    issues.push(IpIssue {
+                        field,
+                        problem: IpProblem::Invalid,
+                    });
+                    continue;
+                }
+            };
+            if addr.is_ipv6() != v6 {
+                issues.push(IpIssue {
+                    field,
+                    problem: IpProblem::WrongFamily,
+                });
+            }
+            let class = IpClass::of(addr);
+            if class.is_likely_ivt() {
+                issues.push(IpIssue {
+                    field,
+                    problem: IpProblem::NotPublic(class),
+                });
+            }
+        }
+        issues
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(addr: &str) -> IpClass {
+        IpClass::of(addr.parse().unwrap())
+    }
+
+    #[test]
+    fn truncates_to_prefix() {
+        let prefix = IpPrefix { v4: 24, v6: 56 };
+        assert_eq!(
+            prefix.apply_str("198.51.100.77").as_deref(),
+            Some("198.51.100.0")
+        );
+        assert_eq!(
+            prefix.apply_str("2001:db8:1:2ff:3::1").as_deref(),
+            Some("2001:db8:1:200::")
+        );
+        assert_eq!(prefix.apply_str("not an ip"), None);
+        let none = IpPrefix { v4: 0, v6: 0 };
+        assert_eq!(none.apply_str("198.51.100.77").as_deref(), Some("0.0.0.0"));
+        assert_eq!(none.apply_str("2001:db8::1").as_deref(), Some("::"));
+        let all = IpPrefix { v4: 40, v6: 200 };
+        assert_eq!(
+            all.apply_str("198.51.100.77").as_deref(),
+            Some("198.51.100.77")
+        );
+        assert_eq!(prefix.min(IpPrefix::default()), IpPrefix { v4: 24, v6: 48 });
+    }
+
+    #[test]
+    fn classifies_ipv4() {
+        for (addr, expected) in [
+            ("8.8.8.8", IpClass::Public),
+            ("0.0.0.0", IpClass::Unspecified),
+            ("127.0.0.1", IpClass::Loopback),
+            ("10.1.1.1", IpClass::Private),
+            ("172.20.0.1", IpClass::Private),
+            ("172.32.0.1", IpClass::Public),
+            ("192.168.0.5", IpClass::Private),
+            ("100.64.0.1", IpClass::Private),
+            ("169.254.1.1", IpClass::LinkLocal),
+            ("224.0.0.1", IpClass::Multicast),
+            ("203.0.113.9", IpClass::Documentation),
+            ("192.0.0.8", IpClass::Reserved),
+            ("192.88.99.1", IpClass::Reserved),
+            ("192.88.98.1", IpClass::Public),
+            ("198.18.0.1", IpClass::Reserved),
+            ("255.255.255.255", IpClass::Reserved),
+        ] {
+            assert_eq!(class(addr), expected, "{}", addr);
+        }
+    }
+
+    #[test]
+    fn classifies_ipv6() {
+        for (addr, expected) in [
+            ("2a00:1450::1", IpClass::Public),
+            ("::", IpClass::Unspecified),
+            ("::1", IpClass::Loopback),
+            ("fd00::1", IpClass::Private),
+            ("fe80::1", IpClass::LinkLocal),
+            ("ff02::1", IpClass::Multicast),
+            ("2001:db8::1", IpClass::Documentation),
+            ("4000::1", IpClass::Reserved),
+            ("::ffff:127.0.0.1", IpClass::Loopback),
+            ("64:ff9b::8.8.8.8", IpClass::Public),
+            ("64:ff9b::10.0.0.1", IpClass::Private),
+            ("64:ff9b:1::8.8.8.8", IpClass::Reserved),
+            ("2002:0808:0808::1", IpClass::Public),
+            ("2002:c0a8:0001::1", IpClass::Private),
+            ("2002:7f00:0001::1", IpClass::Loopback),
+        ] {
+            assert_eq!(class(addr), expected, "{}", addr);
+        }
+        assert!(!IpClass::Public.is_likely_ivt());
+        assert!(IpClass::Documentation.is_likely_ivt());
+    }
+
+    #[test]
+    fn reads_either_family_from_either_field() {
+        let mut device = Device {
+            ip: Some("2a00:1450::1".into()),
+            ..Device::default()
+        };
+        assert_eq!(device.addr(), Some("2a00:1450::1".parse().unwrap()));
+        device.ipv6 = Some("8.8.8.8".into());
+        assert_eq!(device.addr(), Some("8.8.8.8".parse().unwrap()));
+        device.ipv6 = Some("bad".into());
+        assert_eq!(device.addr(), Some("2a00:1450::1".parse().unwrap()));
+        device.set_addr("8.8.4.4".parse().unwrap());
+        assert_eq!(device.ip.as_deref(), Some("8.8.4.4"));
+    }
+
+    #[test]
+    fn anonymizes_by_family_of_the_value() {
+        let mut device = Device {
+            ip: Some("2a00:1450:4001:81c::200e".into()),
+            ipv6: Some("8.8.8.8".into()),
+            ..Device::default()
+        };
+        device.anonymize_ip(IpPrefix::default());
+        assert_eq!(device.ip.as_deref(), Some("2a00:1450:4001::"));
+        assert_eq!(device.ipv6.as_deref(), Some("8.8.8.0"));
+        device.ip = Some("bad".into());
+        device.anonymize_ip(IpPrefix::default());
+        assert_eq!(device.ip, None);
+    }
+
+    #[test]
+    fn validates_addresses() {
+        let device = Device {
+            ip: Some("192.168.0.5".into()),
+            ipv6: Some("8.8.8.8".into()),
+            ..Device::default()
+        };
+        assert_eq!(
+            device.validate_ip(),
+            [
+                IpIssue {
+                    field: "device.ip",
+                    problem: IpProblem::NotPublic(IpClass::Private),
+                },
+                IpIssue {
+                    field: "device.ipv6",
+                    problem: IpProblem::WrongFamily,
+                },
+            ]
+        );
+        let device = Device {
+            ip: Some("1.2.3".into()),
+            ..Device::default()
+        };
+        assert_eq!(device.validate_ip()[0].problem, IpProblem::Invalid);
+        assert!(Device::default().validate_ip().is_empty());
+    }
+}
@@ -0,0 +1,286 @@
+//! JSON encoding and decoding of OpenRTB messages.
+//!
+//! By default these functions are thin wrappers around `serde_json`. With the
+//! `simd` cargo feature enabled, they go through the SIMD-accelerated
+//! `simd-json` parser instead. Both backends drive the same serde
+//! implementations, so the custom `Bool` and `AuctionType` handling and all
+//! `skip_serializing_if` rules are shared.
+//!
+//! The feature and its optional `simd-json` dependency are declared in the
+//! crate manifest, as listed in the [`openrtb` module docs](super).
+//!
+//! `differential_check` compares the two backends; the tests run it over the
+//! corpus in `testdata/json`.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+
+/// Error returned by the active JSON backend.
+#[derive(Debug)]
+pub enum Error {
+    /// Error from `serde_json`.
+    Json(serde_json::Error),
+    /// Error from `simd-json`.
+    #[cfg(feature = "simd")]
+    Simd(simd_json::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Json(e) => e.fmt(f),
+            #[cfg(feature = "simd")]
+            Error::Simd(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Error {
+        Error::Json(e)
+    }
+}
+
+#[cfg(feature = "simd")]
+impl From<simd_json::Error> for Error {
+    fn from(e: simd_json::Error) -> Error {
+        Error::Simd(e)
+    }
+}
+
+/// Decodes a message from a mutable buffer. The SIMD backend parses in place
+/// and leaves the buffer contents unspecified afterwards.
+pub fn from_slice<T>(buf: &mut [u8]) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    #[cfg(feature = "simd")]
+    return Ok(simd_json::serde::from_slice(buf)?);
+
+    #[cfg(not(feature = "simd"))]
+    return Ok(serde_json::from_slice(buf)?);
+}
+
+/// Decodes a message from a string. The SIMD backend copies the input into a
+/// scratch buffer first; prefer `from_slice` when the buffer can be reused.
+pub fn from_str<T>(s: &str) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    #[cfg(feature = "simd")]
+    return from_slice(&mut s.as_bytes().to_vec());
+
+    #[cfg(not(feature = "simd"))]
+    return Ok(serde_json::from_str(s)?);
+}
+
+/// Encodes a message as JSON bytes.
+pub fn to_vec<T>(value: &T) -> Result<Vec<u8>, Error>
+where
+    T: Serialize + ?Sized,
+{
+    #[cfg(feature = "simd")]
+    return Ok(simd_json::serde::to_vec(value)?);
+
+    #[cfg(not(feature = "simd"))]
+    return Ok(serde_json::to_vec(value)?);
+}
+
+/// Encodes a message as a JSON string.
+pub fn to_string<T>(value: &T) -> Result<String, Error>
+where
+    T: Serialize + ?Sized,
+{
+    #[cfg(feature = "simd")]
+    return Ok(simd_json::serde::to_string(value)?);
+
+    #[cfg(not(feature = "simd"))]
+    return Ok(serde_json::to_string(value)?);
+}
+
+/// Difference found between the `serde_json` and `simd-json` backends.
+#[cfg(feature = "simd")]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mismatch {
+    /// Only one of the backends accepted the input.
+    Acceptance {
+        /// The `serde_json` error, if it rejected the input.
+        serde_json: Option<String>,
+        /// The `simd-json` error, if it rejected the input.
+        simd_json: Option<String>,
+    },
+    /// Both backends decoded the input, into different values, given
+    /// re-encoded by `serde_json`.
+    Decoded {
+        /// The value decoded by `serde_json`.
+        serde_json: String,
+        /// The value decoded by `simd-json`.
+        simd_json: String,
+    },
+    /// Both backends encoded the decoded value, into different JSON.
+    Encoded {
+        /// The JSON written by `serde_json`.
+        serde_json: String,
+        /// The JSON written by `simd-json`.
+        simd_json: String,
+    },
+}
+
+/// Decodes `input` as `T` with both backends, then re-encodes the result with
+/// both, and reports the first difference. Inputs rejected by both backends
+/// are considered equivalent, and so are encodings that are the same JSON.
+#[cfg(feature = "simd")]
+pub fn differential_check<T>(input: &[u8]) -> Result<(), Mismatch>
+where
+    T: DeserializeOwned + Serialize + PartialEq,
+{
+    let by_json = serde_json::from_slice::<T>(input);
+    let by_simd = simd_json::serde::from_slice::<T>(&mut input.to_vec());
+    let value = match (by_json, by_simd) {
+        (Ok(a), Ok(b)) if a == b => a,
+        (Ok(a), Ok(b)) => {
+            let show = |v: &T| serde_json::to_string(v).unwrap_or_else(|e| e.to_string());
+            return Err(Mismatch::Decoded {
+                serde_json: show(&a),
+                simd_json: show(&b),
+            });
+        }
+        (Err(_), Err(_)) => return Ok(()),
+        (a, b) => {
+            return Err(Mismatch::Acceptance {
+                serde_json: a.err().map(|e| e.to_string()),
+                simd_json: b.err().map(|e| e.to_string()),
+            })
+        }
+    };
+
+    let by_json = serde_json::to_string(&value).map_err(|e| e.to_string());
+    let by_simd = simd_json::serde::to_string(&value).map_err(|e| e.to_string());
+    // Numbers may be spelled differently, as `1e308` and `1e+308`.
+    let same = match (&by_json, &by_simd) {
+        (Ok(a), Ok(b)) => {
+            a == b
+                || serde_json::from_str::<serde_json::Value>(a).ok()
+                    == serde_json::from_str::<serde_json::Value>(b).ok()
+        }
+        (a, b) => a == b,
+    };
+    if same {
+        return Ok(());
+    }
+    Err(Mismatch::Encoded {
+        serde_json: by_json.unwrap_or_else(|e| e),
+        simd_json: by_simd.unwrap_or_else(|e| e),
+    })
+}
+
+/// Runs `differential_check` over a corpus, returning the index and mismatch
+/// of every failing input.
+#[cfg(feature = "simd")]
+pub fn differential_check_corpus<'a, T, I>(corpus: I) -> Vec<(usize, Mismatch)>
+where
+    T: DeserializeOwned + Serialize + PartialEq,
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    corpus
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, input)| differential_check::<T>(input).err().map(|m| (i, m)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{BidRequest, BidResponse};
+    use super::*;
+
+    /// Inputs for both backends, by file name. Some are invalid on purpose.
+    const REQUESTS: &[(&str, &[u8])] = &[
+        (
+            "request-banner",
+            include_bytes!("testdata/json/request-banner.json"),
+        ),
+        (
+            "request-video",
+            include_bytes!("testdata/json/request-video.json"),
+        ),
+        (
+            "request-pmp",
+            include_bytes!("testdata/json/request-pmp.json"),
+        ),
+        (
+            "request-escapes",
+            include_bytes!("testdata/json/request-escapes.json"),
+        ),
+        (
+            "request-numbers",
+            include_bytes!("testdata/json/request-numbers.json"),
+        ),
+        (
+            "request-whitespace",
+            include_bytes!("testdata/json/request-whitespace.json"),
+        ),
+        (
+            "request-wrong-type",
+            include_bytes!("testdata/json/request-wrong-type.json"),
+        ),
+        (
+            "request-bool-range",
+            include_bytes!("testdata/json/request-bool-range.json"),
+        ),
+        (
+            "request-truncated",
+            include_bytes!("testdata/json/request-truncated.json"),
+        ),
+    ];
+
+    const RESPONSES: &[(&str, &[u8])] = &[
+        (
+            "response-banner",
+            include_bytes!("testdata/json/response-banner.json"),
+        ),
+        (
+            "response-nbr",
+            include_bytes!("testdata/json/response-nbr.json"),
+        ),
+        (
+            "response-group",
+            include_bytes!("testdata/json/response-group.json"),
+        ),
+    ];
+
+    const INVALID: &[&str] = &[
+        "request-wrong-type",
+        "request-bool-range",
+        "request-truncated",
+    ];
+
+    #[test]
+    fn corpus_round_trips() {
+        for (name, input) in REQUESTS {
+            let decoded = from_slice::<BidRequest>(&mut input.to_vec());
+            assert_eq!(decoded.is_err(), INVALID.contains(name), "{}", name);
+            if let Ok(req) = decoded {
+                let again: BidRequest = from_str(&to_string(&req).unwrap()).unwrap();
+                assert!(again == req, "{}", name);
+            }
+        }
+        for (name, input) in RESPONSES {
+            let resp: BidResponse = from_slice(&mut input.to_vec()).expect(name);
+            let again: BidResponse = from_slice(&mut to_vec(&resp).unwrap()).unwrap();
+            assert!(again == resp, "{}", name);
+        }
+    }
+
+    #[cfg(feature = "simd")]
+    #[test]
+    fn backends_agree_on_corpus() {
+        let mismatches = differential_check_corpus::<BidRequest, _>(REQUESTS.iter().map(|c| c.1));
+        assert_eq!(mismatches, vec![]);
+        let mismatches = differential_check_corpus::<BidResponse, _>(RESPONSES.iter().map(|c| c.1));
+        assert_eq!(mismatches, vec![]);
+    }
+}
@@ -0,0 +1,314 @@
+//! Partially decoded views of a `BidRequest`, backed by the raw JSON.
+//!
+//! Only the top-level structure (and the cheap scalar fields commonly used
+//! for pre-filtering) is decoded up front. Sub-objects such as `Device`,
+//! `User`, `Content` or `imp[].video` are kept as raw JSON slices and
+//! decoded on first access.
+//!
+//! `Lazy` borrows sub-objects as `serde_json::value::RawValue`, so this
+//! module needs serde_json's `raw_value` feature; see the
+//! [manifest requirements](super) of the `openrtb` module.
+
+use super::bid_request::imp::{Audio, Banner, Native, Pmp, Video};
+use super::bid_request::{Content, Device, Publisher, Regs, Source, User};
+use super::bool::Bool;
+use super::{AuctionType, BidRequest};
+use serde::{Deserialize, Deserializer};
+use serde_json::value::RawValue;
+use std::borrow::Cow;
+use std::cell::OnceCell;
+
+/// A JSON sub-object that is decoded on first access and cached afterwards.
+///
+/// An absent field and an explicit `null` are both treated as not present.
+pub struct Lazy<'a, T> {
+    raw: Option<&'a RawValue>,
+    cell: OnceCell<T>,
+}
+
+impl<'a, T> Default for Lazy<'a, T> {
+    fn default() -> Self {
+        Lazy {
+            raw: None,
+            cell: OnceCell::new(),
+        }
+    }
+}
+
+impl<'a, T> Lazy<'a, T> {
+    /// Whether the field was present (and not `null`) in the input.
+    pub fn is_present(&self) -> bool {
+        self.raw.is_some()
+    }
+
+    /// The raw JSON text of the field, if present.
+    pub fn raw(&self) -> Option<&'a str> {
+        self.raw.map(RawValue::get)
+    }
+
+    /// Decodes the field as another type without touching the cache.
+    pub fn decode_as<U>(&self) -> serde_json::Result<Option<U>>
+    where
+        U: Deserialize<'a>,
+    {
+        self.raw
+            .map(|raw| serde_json::from_str(raw.get()))
+            .transpose()
+    }
+}
+
+impl<'a, T> Lazy<'a, T>
+where
+    T: Deserialize<'a>,
+{
+    /// Decodes the field on first call and returns the cached value.
+    /// A decoding failure is not cached, so a later call will retry.
+    pub fn get(&self) -> serde_json::Result<Option<&T>> {
+        let raw = match self.raw {
+            Some(raw) => raw,
+            None => return Ok(None),
+        };
+        if let Some(v) = self.cell.get() {
+            return Ok(Some(v));
+        }
+        let v = serde_json::from_str(raw.get())?;
+        Ok(Some(self.cell.get_or_init(|| v)))
+    }
+}
+
+impl<'de: 'a, 'a, T> Deserialize<'de> for Lazy<'a, T> {
+    fn deserialize<D>(deserializer: D) -> Result<Lazy<'a, T>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Lazy {
+            raw: Option::<&'a RawValue>::deserialize(deserializer)?,
+            cell: OnceCell::new(),
+        })
+    }
+}
+
+/// Lazy view of a `BidRequest`. Scalars are decoded eagerly; everything else
+/// is decoded on first access. Use `to_bid_request` to get the full object.
+#[derive(Deserialize)]
+pub struct LazyBidRequest<'a> {
+    /// Unique ID of the bid request, provided by the exchange.
+    #[serde(borrow)]
+    pub id: Cow<'a, str>,
+
+    /// Impressions offered, see `LazyImp`.
+    #[serde(borrow, default)]
+    pub imp: Vec<LazyImp<'a>>,
+
+    /// Details about the user's device.
+    #[serde(borrow, default)]
+    pub device: Lazy<'a, Device>,
+
+    /// Regulations in force for this request.
+    #[serde(borrow, default)]
+    pub regs: Lazy<'a, Regs>,
+
+    /// Details about the human user of the device.
+    #[serde(borrow, default)]
+    pub user: Lazy<'a, User>,
+
+    /// Auction type.
+    #[serde(default)]
+    pub at: Option<AuctionType>,
+
+    /// Maximum time in milliseconds to submit a bid to avoid timeout.
+    #[serde(default)]
+    pub tmax: Option<i32>,
+
+    /// Indicator of test mode.
+    #[serde(default)]
+    pub test: Option<Bool>,
+
+    /// Data about the inventory source.
+    #[serde(borrow, default)]
+    pub source: Lazy<'a, Source>,
+
+    /// Details about the publisher's website.
+    #[serde(borrow, default)]
+    pub site: Lazy<'a, LazySite<'a>>,
+
+    /// Details about the publisher's app.
+    #[serde(borrow, default)]
+    pub app: Lazy<'a, LazyApp<'a>>,
+
+    #[serde(skip)]
+    raw: &'a str,
+}
+
+impl<'a> LazyBidRequest<'a> {
+    /// Parses the top-level structure of a JSON bid request.
+    pub fn parse(json: &'a str) -> serde_json::Result<LazyBidRequest<'a>> {
+        let mut req: LazyBidRequest = serde_json::from_str(json)?;
+        req.raw = json;
+        Ok(req)
+    }
+
+    /// The raw JSON this view was parsed from.
+    pub fn raw(&self) -> &'a str {
+        self.raw
+    }
+
+    /// Content of the site or app, whichever is present.
+    pub fn content(&self) -> serde_json::Result<Option<&Content>> {
+        if let Some(site) = self.site.get()? {
+            return site.content.get();
+        }
+        match self.app.get()? {
+            Some(app) => app.content.get(),
+            None => Ok(None),
+        }
+    }
+
+    /// Fully decodes the request from the raw JSON.
+    pub fn to_bid_request(&self) -> serde_json::Result<BidRequest> {
+        serde_json::from_str(self.raw)
+    }
+}
+
+/// Lazy view of an `Imp`.
+#[derive(Deserialize)]
+pub struct LazyImp<'a> {
+    /// A unique identifier for this impression within the bid request.
+    #[serde(borrow)]
+    pub id: Cow<'a, str>,
+
+    /// Identifier for specific ad placement or ad tag.
+    #[serde(borrow, default)]
+    pub tagid: Option<Cow<'a, str>>,
+
+    /// Minimum bid for this impression expressed in CPM.
+    #[serde(default)]
+    pub bidfloor: Option<f64>,
+
+    /// Currency of the bid floor using ISO-4217 alpha codes.
+    #[serde(borrow, default)]
+    pub bidfloorcur: Option<Cow<'a, str>>,
+
+    /// Banner object, if offered as a banner ad opportunity.
+    #[serde(borrow, default)]
+    pub banner: Lazy<'a, Banner>,
+
+    /// Video object, if offered as a video ad opportunity.
+    #[serde(borrow, default)]
+    pub video: Lazy<'a, Video>,
+
+    /// Audio object, if offered as an audio ad opportunity.
+    #[serde(borrow, default)]
+    pub audio: Lazy<'a, Audio>,
+
+    /// Native object, if offered as a native ad opportunity.
+    #[serde(borrow, default)]
+    pub native: Lazy<'a, Native>,
+
+    /// Private marketplace deals in effect for this impression.
+    #[serde(borrow, default)]
+    pub pmp: Lazy<'a, Pmp>,
+}
+
+/// Lazy view of a `Site`. Use `Lazy::decode_as::<Site>()` on the parent
+/// field to get the full object.
+#[derive(Deserialize)]
+pub struct LazySite<'a> {
+    /// Site ID on the exchange.
+    #[serde(borrow, default)]
+    pub id: Option<Cow<'a, str>>,
+
+    /// Domain of the site.
+    #[serde(borrow, default)]
+    pub domain: Option<Cow<'a, str>>,
+
+    /// URL of the page where the impression will be shown.
+    #[serde(borrow, default)]
+    pub page: Option<Cow<'a, str>>,
+
+    /// Details about the Publisher of the site.
+    #[serde(borrow, default)]
+    pub publisher: Lazy<'a, Publisher>,
+
+    /// Details about the Content within the site.
+    #[serde(borrow, default)]
+    pub content: Lazy<'a, Content>,
+}
+
+/// Lazy view of an `App`. Use `Lazy::decode_as::<App>()` on the parent
+/// field to get the full object.
+#[derive(Deserialize)]
+pub struct LazyApp<'a> {
+    /// Application ID on the exchange.
+    #[serde(borrow, default)]
+    pub id: Option<Cow<'a, str>>,
+
+    /// Platform-specific application identifier.
+    #[serde(borrow, default)]
+    pub bundle: Option<Cow<'a, str>>,
+
+    /// Domain of the application.
+    #[serde(borrow, default)]
+    pub domain: Option<Cow<'a, str>>,
+
+    /// App store URL for an installed app.
+    #[serde(borrow, default)]
+    pub storeurl: Option<Cow<'a, str>>,
+
+    /// Details about the Publisher of the app.
+    #[serde(borrow, default)]
+    pub publisher: Lazy<'a, Publisher>,
+
+    /// Details about the Content within the app.
+    #[serde(borrow, default)]
+    pub content: Lazy<'a, Content>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REQUEST: &str = r#"{"id":"r1","imp":[{"id":"1","bidfloor":1.5,"video":{"mimes":["video/mp4"],"w":640}}],"device":{"geo":{"country":"USA"},"ua":"x"},"app":{"bundle":"com.foo","content":{"title":"t"}},"regs":{"coppa":1},"user":null,"at":1}"#;
+
+    #[test]
+    fn decodes_on_access() {
+        let req = LazyBidRequest::parse(REQUEST).unwrap();
+        assert_eq!(req.id, "r1");
+        assert_eq!(req.imp[0].bidfloor, Some(1.5));
+        assert!(req.imp[0].video.is_present());
+        assert!(!req.imp[0].banner.is_present());
+        assert_eq!(req.imp[0].video.get().unwrap().unwrap().w, Some(640));
+        let device = req.device.get().unwrap().unwrap();
+        assert_eq!(device.geo.as_ref().unwrap().country.as_deref(), Some("USA"));
+        assert_eq!(
+            req.app.get().unwrap().unwrap().bundle.as_deref(),
+            Some("com.foo")
+        );
+        assert_eq!(req.content().unwrap().unwrap().title.as_deref(), Some("t"));
+    }
+
+    #[test]
+    fn null_is_absent() {
+        let req = LazyBidRequest::parse(REQUEST).unwrap();
+        assert!(!req.user.is_present());
+        assert!(req.user.get().unwrap().is_none());
+        assert!(!req.site.is_present());
+    }
+
+    #[test]
+    fn decode_errors_are_not_cached() {
+        let req = LazyBidRequest::parse(r#"{"id":"r","device":{"w":"wide"}}"#).unwrap();
+        assert!(req.device.get().is_err());
+        assert!(req.device.get().is_err());
+        assert_eq!(req.device.raw(), Some(r#"{"w":"wide"}"#));
+    }
+
+    #[test]
+    fn to_bid_request() {
+        let req = LazyBidRequest::parse(REQUEST).unwrap();
+        let full = req.to_bid_request().unwrap();
+        assert_eq!(full.id, "r1");
+        assert_eq!(full.imp.len(), 1);
+        assert_eq!(req.raw(), REQUEST);
+    }
+}
@@ -0,0 +1,361 @@
+//! Resource limits for decoding untrusted requests.
+//!
+//! The input is first scanned without being decoded (see `scan`), and rejected
+//! with the path of the offending field if any limit is exceeded, or with
+//! the offset of the error if it is not well-formed UTF-8 JSON. Only then
+//! is it handed to the JSON backend, so the memory used by the decoded
+//! message stays proportional to the bounded input.
+
+use super::json;
+use super::scan::{self, Event, ScanError, Segment};
+use serde::de::DeserializeOwned;
+use std::fmt;
+
+/// Configurable bounds enforced by `from_slice`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum size of the input in bytes.
+    pub max_input_len: usize,
+
+    /// Maximum nesting depth of objects and arrays.
+    pub max_depth: usize,
+
+    /// Maximum number of `imp` entries.
+    pub max_imp: usize,
+
+    /// Maximum length of `Banner.format`.
+    pub max_format: usize,
+
+    /// Maximum length of the top-level block and allow lists
+    /// (`bcat`, `badv`, `bapp`, `bseat`, `wseat`, `wlang`, `cur`).
+    pub max_block_list: usize,
+
+    /// Maximum length of any other array.
+    pub max_array_len: usize,
+
+    /// Maximum length in bytes of `Device.ua`.
+    pub max_ua_len: usize,
+
+    /// Maximum length in bytes of URL fields (`Site.page`, `Site.ref`,
+    /// `App.storeurl`, `Content.url`).
+    pub max_url_len: usize,
+
+    /// Maximum length in bytes of any other string or object key.
+    pub max_string_len: usize,
+
+    /// Maximum nesting depth inside an `ext` value; the `ext` object itself
+    /// has depth 1.
+    pub max_ext_depth: usize,
+
+    /// Maximum number of JSON values across all `ext` values of a message.
+    pub max_ext_values: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_input_len: 1 << 20,
+            max_depth: 32,
+            max_imp: 100,
+            max_format: 32,
+            max_block_list: 1024,
+            max_array_len: 256,
+            max_ua_len: 2048,
+            max_url_len: 8192,
+            max_string_len: 4096,
+            max_ext_depth: 8,
+            max_ext_values: 2048,
+        }
+    }
+}
+
+/// A limit that was exceeded, and where.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LimitExceeded {
+    /// Path of the offending field, e.g. `imp[0].banner.format`.
+    pub path: String,
+    /// Name of the `Limits` field that was exceeded.
+    pub limit: &'static str,
+    /// Configured maximum.
+    pub max: usize,
+    /// Observed value.
+    pub actual: usize,
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = if self.path.is_empty() {
+            "$"
+        } else {
+            &self.path
+        };
+        write!(
+            f,
+            "{}: {} exceeds {} ({})",
+            path, self.actual, self.limit, self.max
+        )
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+/// Error returned by `Limits::check` and `from_slice`.
+#[derive(Debug)]
+pub enum Error {
+    /// The input exceeds one of the limits.
+    Limit(LimitExceeded),
+    /// The input is not UTF-8 or not JSON, at the given byte offset.
+    Malformed(usize),
+    /// The input is within the limits but does not decode.
+    Json(json::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Limit(e) => e.fmt(f),
+            Error::Malformed(offset) => write!(f, "malformed JSON at byte {}", offset),
+            Error::Json(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<LimitExceeded> for Error {
+    fn from(e: LimitExceeded) -> Error {
+        Error::Limit(e)
+    }
+}
+
+impl From<json::Error> for Error {
+    fn from(e: json::Error) -> Error {
+        Error::Json(e)
+    }
+}
+
+/// Checks `buf` against `limits`, then decodes it with the active backend.
+pub fn from_slice<T>(buf: &mut [u8], limits: &Limits) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    limits.check(buf)?;
+    Ok(json::from_slice(buf)?)
+}
+
+impl Limits {
+    /// Checks `input` against the limits without decoding it. Input that is
+    /// not UTF-8 or not JSON is rejected, as the limits cannot be checked on
+    /// it.
+    pub fn check(&self, input: &[u8]) -> Result<(), Error> {
+        if input.len() > self.max_input_len {
+            return Err(exceeded(&[], "max_input_len", self.max_input_len, input.len()).into());
+        }
+        let input = std::str::from_utf8(input).map_err(|e| Error::Malformed(e.valid_up_to()))?;
+
+        let mut ext_values = 0;
+        let result = scan::scan(input, |path, _, event| {
+            let ext_depth = ext_depth_of(path);
+            if ext_depth.is_some() && !matches!(event, Event::Close { .. } | Event::Key { .. }) {
+                ext_values += 1;
+                if ext_values > self.max_ext_values {
+                    return Err(exceeded(
+                        path,
+                        "max_ext_values",
+                        self.max_ext_values,
+                        ext_values,
+                    ));
+                }
+            }
+            match event {
+                Event::Open { .. } => {
+                    let depth = path.len() + 1;
+                    if depth > self.max_depth {
+                        return Err(exceeded(path, "max_depth", self.max_depth, depth));
+                    }
+                    match ext_depth {
+                        Some(d) if d > self.max_ext_depth => {
+                            Err(exceeded(path, "max_ext_depth", self.max_ext_depth, d))
+                        }
+                        _ => Ok(()),
+                    }
+                }
+                Event::Close { array: true, len } => {
+                    let (limit, max) = self.array_limit(path);
+                    check(path, limit, max, len)
+                }
+                Event::Key { len } => check(path, "max_string_len", self.max_string_len, len),
+                Event::Str { len } => {
+                    let (limit, max) = self.string_limit(path);
+                    check(path, limit, max, len)
+                }
+                Event::Close { array: false, .. } | Event::Scalar => Ok(()),
+            }
+        });
+        match result {
+            Ok(()) => Ok(()),
+            Err(ScanError::Visit(e)) => Err(e.into()),
+            Err(ScanError::Syntax(offset)) => Err(Error::Malformed(offset)),
+        }
+    }
+
+    fn array_limit(&self, path: &[Segment]) -> (&'static str, usize) {
+        let mut keys = scan::keys(path);
+        match (keys.next_back(), keys.next_back()) {
+            (Some("imp"), None) => ("max_imp", self.max_imp),
+            (Some("format"), Some("banner" | "companionad")) => ("max_format", self.max_format),
+            (Some("bcat" | "badv" | "bapp" | "bseat" | "wseat" | "wlang" | "cur"), None) => {
+                ("max_block_list", self.max_block_list)
+            }
+            _ => ("max_array_len", self.max_array_len),
+        }
+    }
+
+    fn string_limit(&self, path: &[Segment]) -> (&'static str, usize) {
+        let mut keys = scan::keys(path);
+        match (keys.next(), keys.next(), keys.next(), keys.next()) {
+            (Some("device"), Some("ua"), None, _) => ("max_ua_len", self.max_ua_len),
+            (Some("site"), Some("page" | "ref"), None, _)
+            | (Some("app"), Some("storeurl"), None, _)
+            | (Some("site" | "app"), Some("content"), Some("url"), None) => {
+                ("max_url_len", self.max_url_len)
+            }
+            _ => ("max_string_len", self.max_string_len),
+        }
+    }
+}
+
+/// Depth of the current value below the outermost `ext` on the path,
+/// where the `ext` value itself has depth 1.
+fn ext_depth_of(path: &[Segment]) -> Option<usize> {
+    path.iter()
+        .position(|s| matches!(s, Segment::Key(k) if k == "ext"))
+        .map(|i| path.len() - i)
+}
+
+fn check(
+    path: &[Segment],
+    limit: &'static str,
+    max: usize,
+    actual: usize,
+) -> Result<(), LimitExceeded> {
+    if actual > max {
+        return Err(exceeded(path, limit, max, actual));
+    }
+    Ok(())
+}
+
+fn exceeded(path: &[Segment], limit: &'static str, max: usize, actual: usize) -> LimitExceeded {
+    LimitExceeded {
+        path: scan::format_path(path),
+        limit,
+        max,
+        actual,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::BidRequest;
+    use super::*;
+
+    fn limits() -> Limits {
+        Limits {
+            max_imp: 2,
+            max_ext_depth: 3,
+            max_ua_len: 5,
+            ..Limits::default()
+        }
+    }
+
+    fn exceeded(input: &str, limits: &Limits) -> LimitExceeded {
+        match limits.check(input.as_bytes()) {
+            Err(Error::Limit(e)) => e,
+            other => panic!("{}: {:?}", input, other),
+        }
+    }
+
+    const OK: &str = r#"{"id":"x","imp":[{"id":"1","banner":{"format":[{"w":1},{"w":2}]}},{"id":"2"}],"device":{"ua":"abcde"},"ext":{"a":{"b":1}}}"#;
+
+    #[test]
+    fn decodes_within_limits() {
+        let req: BidRequest = from_slice(&mut OK.as_bytes().to_vec(), &limits()).unwrap();
+        assert_eq!(req.imp.len(), 2);
+        assert!(limits().check(br#" { "a" : [ ] , "b":{ } } "#).is_ok());
+    }
+
+    #[test]
+    fn reports_path_of_exceeded_limit() {
+        let e = exceeded(
+            r#"{"id":"x","imp":[{"id":"1"},{"id":"2"},{"id":"3"}]}"#,
+            &limits(),
+        );
+        assert_eq!(e.to_string(), "imp: 3 exceeds max_imp (2)");
+        let e = exceeded(r#"{"id":"x","imp":[],"device":{"ua":"abcdef"}}"#, &limits());
+        assert_eq!(e.to_string(), "device.ua: 6 exceeds max_ua_len (5)");
+        let e = exceeded(
+            r#"{"imp":[{"id":"1","ext":{"a":{"b":{"c":[]}}}}]}"#,
+            &limits(),
+        );
+        assert_eq!(
+            e.to_string(),
+            "imp[0].ext.a.b.c: 4 exceeds max_ext_depth (3)"
+        );
+        let format = Limits {
+            max_format: 1,
+            ..Limits::default()
+        };
+        assert_eq!(exceeded(OK, &format).path, "imp[0].banner.format");
+        let values = Limits {
+            max_ext_values: 2,
+            ..Limits::default()
+        };
+        assert_eq!(
+            exceeded(r#"{"ext":[1,2,3]}"#, &values).limit,
+            "max_ext_values"
+        );
+        let input = Limits {
+            max_input_len: 4,
+            ..Limits::default()
+        };
+        assert_eq!(
+            exceeded("[1,2]", &input).to_string(),
+            "$: 5 exceeds max_input_len (4)"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for (input, offset) in [
+            (&b"{bad"[..], 1),
+            (b"{\"id\":\"\xff\"}", 7),
+            (b"[1,2", 4),
+            (b"{\"a\":1}x", 7),
+        ] {
+            match limits().check(input) {
+                Err(Error::Malformed(at)) => assert_eq!(at, offset),
+                other => panic!("{:?}: {:?}", input, other),
+            }
+        }
+        let mut bad = b"{\"imp\":[".to_vec();
+        let e = from_slice::<BidRequest>(&mut bad, &limits()).err().unwrap();
+        assert_eq!(e.to_string(), "malformed JSON at byte 8");
+    }
+
+    #[test]
+    fn matches_escaped_keys() {
+        let e = exceeded(
+            r#"{"id":"x","im\u0070":[{"id":"1"},{"id":"2"},{"id":"3"}]}"#,
+            &limits(),
+        );
+        assert_eq!(e.to_string(), "imp: 3 exceeds max_imp (2)");
+        let e = exceeded(
+            r#"{"imp":[{"id":"1","\u0065xt":{"a":{"b":{"c":[]}}}}]}"#,
+            &limits(),
+        );
+        assert_eq!(e.limit, "max_ext_depth");
+        assert_eq!(e.path, "imp[0].ext.a.b.c");
+        let e = exceeded(r#"{"device":{"\u0075a":"abcdef"}}"#, &limits());
+        assert_eq!(e.limit, "max_ua_len");
+    }
+}
@@ -0,0 +1,574 @@
+//! Mobile carrier codes.
+//!
+//! `Device.mccmnc` identifies the mobile network as an ITU E.212 mobile
+//! country code (MCC) and mobile network code (MNC), e.g. "310-005". The MNC
+//! is two or three digits and "01" and "001" are different networks, which is
+//! why OpenRTB asks for the dash. The tables here map an MCC to its country
+//! and the larger networks to a carrier name; they are not exhaustive.
+
+use super::bid_request::Device;
+use super::codes::Country;
+use std::fmt;
+use std::str::FromStr;
+
+/// MCC to ISO-3166-1 alpha-2 countries, sorted by MCC. An MCC shared by
+/// several territories lists all of them.
+const MCC_COUNTRIES: &[(u16, &[&str])] = &[
+    (202, &["GR"]),
+    (204, &["NL"]),
+    (206, &["BE"]),
+    (208, &["FR"]),
+    (212, &["MC"]),
+    (213, &["AD"]),
+    (214, &["ES"]),
+    (216, &["HU"]),
+    (218, &["BA"]),
+    (219, &["HR"]),
+    (220, &["RS"]),
+    (222, &["IT"]),
+    (225, &["VA"]),
+    (226, &["RO"]),
+    (228, &["CH"]),
+    (230, &["CZ"]),
+    (231, &["SK"]),
+    (232, &["AT"]),
+    (234, &["GB", "GG", "JE", "IM"]),
+    (235, &["GB"]),
+    (238, &["DK"]),
+    (240, &["SE"]),
+    (242, &["NO"]),
+    (244, &["FI", "AX"]),
+    (246, &["LT"]),
+    (247, &["LV"]),
+    (248, &["EE"]),
+    (250, &["RU"]),
+    (255, &["UA"]),
+    (257, &["BY"]),
+    (259, &["MD"]),
+    (260, &["PL"]),
+    (262, &["DE"]),
+    (266, &["GI"]),
+    (268, &["PT"]),
+    (270, &["LU"]),
+    (272, &["IE"]),
+    (274, &["IS"]),
+    (276, &["AL"]),
+    (278, &["MT"]),
+    (280, &["CY"]),
+    (282, &["GE"]),
+    (283, &["AM"]),
+    (284, &["BG"]),
+    (286, &["TR"]),
+    (288, &["FO"]),
+    (290, &["GL"]),
+    (292, &["SM"]),
+    (293, &["SI"]),
+    (294, &["MK"]),
+    (295, &["LI"]),
+    (297, &["ME"]),
+    (302, &["CA"]),
+    (308, &["PM"]),
+    (310, &["US", "GU", "MP"]),
+    (311, &["US", "GU"]),
+    (312, &["US"]),
+    (313, &["US"]),
+    (314, &["US"]),
+    (315, &["US"]),
+    (316, &["US"]),
+    (330, &["PR"]),
+    (332, &["VI"]),
+    (334, &["MX"]),
+    (338, &["JM"]),
+    (340, &["GP", "MQ", "GF", "BL", "MF"]),
+    (342, &["BB"]),
+    (344, &["AG"]),
+    (346, &["KY"]),
+    (348, &["VG"]),
+    (350, &["BM"]),
+    (352, &["GD"]),
+    (354, &["MS"]),
+    (356, &["KN"]),
+    (358, &["LC"]),
+    (360, &["VC"]),
+    (362, &["CW", "BQ", "SX"]),
+    (363, &["AW"]),
+    (364, &["BS"]),
+    (365, &["AI"]),
+    (366, &["DM"]),
+    (368, &["CU"]),
+    (370, &["DO"]),
+    (372, &["HT"]),
+    (374, &["TT"]),
+    (376, &["TC"]),
+    (400, &["AZ"]),
+    (401, &["KZ"]),
+    (402, &["BT"]),
+    (404, &["IN"]),
+    (405, &["IN"]),
+    (406, &["IN"]),
+    (410, &["PK"]),
+    (412, &["AF"]),
+    (413, &["LK"]),
+    (414, &["MM"]),
+    (415, &["LB"]),
+    (416, &["JO"]),
+    (417, &["SY"]),
+    (418, &["IQ"]),
+    (419, &["KW"]),
+    (420, &["SA"]),
+    (421, &["YE"]),
+    (422, &["OM"]),
+    (424, &["AE"]),
+    (425, &["IL", "PS"]),
+    (426, &["BH"]),
+    (427, &["QA"]),
+    (428, &["MN"]),
+    (429, &["NP"]),
+    (430, &["AE"]),
+    (431, &["AE"]),
+    (432, &["IR"]),
+    (434, &["UZ"]),
+    (436, &["TJ"]),
+    (437, &["KG"]),
+    (438, &["TM"]),
+    (440, &["JP"]),
+    (441, &["JP"]),
+    (450, &["KR"]),
+    (452, &["VN"]),
+    (454, &["HK"]),
+    (455, &["MO"]),
+    (456, &["KH"]),
+    (457, &["LA"]),
+    (460, &["CN"]),
+    (461, &["CN"]),
+    (466, &["TW"]),
+    (467, &["KP"]),
+    (470, &["BD"]),
+    (472, &["MV"]),
+    (502, &["MY"]),
+    (505, &["AU", "NF"]),
+    (510, &["ID"]),
+    (514, &["TL"]),
+    (515, &["PH"]),
+    (520, &["TH"]),
+    (525, &["SG"]),
+    (528, &["BN"]),
+    (530, &["NZ"]),
+    (536, &["NR"]),
+    (537, &["PG"]),
+    (539, &["TO"]),
+    (540, &["SB"]),
+    (541, &["VU"]),
+    (542, &["FJ"]),
+    (543, &["WF"]),
+    (544, &["AS"]),
+    (545, &["KI"]),
+    (546, &["NC"]),
+    (547, &["PF"]),
+    (548, &["CK"]),
+    (549, &["WS"]),
+    (550, &["FM"]),
+    (551, &["MH"]),
+    (552, &["PW"]),
+    (553, &["TV"]),
+    (554, &["TK"]),
+    (555, &["NU"]),
+    (602, &["EG"]),
+    (603, &["DZ"]),
+    (604, &["MA"]),
+    (605, &["TN"]),
+    (606, &["LY"]),
+    (607, &["GM"]),
+    (608, &["SN"]),
+    (609, &["MR"]),
+    (610, &["ML"]),
+    (611, &["GN"]),
+    (612, &["CI"]),
+    (613, &["BF"]),
+    (614, &["NE"]),
+    (615, &["TG"]),
+    (616, &["BJ"]),
+    (617, &["MU"]),
+    (618, &["LR"]),
+    (619, &["SL"]),
+    (620, &["GH"]),
+    (621, &["NG"]),
+    (622, &["TD"]),
+    (623, &["CF"]),
+    (624, &["CM"]),
+    (625, &["CV"]),
+    (626, &["ST"]),
+    (627, &["GQ"]),
+    (628, &["GA"]),
+    (629, &["CG"]),
+    (630, &["CD"]),
+    (631, &["AO"]),
+    (632, &["GW"]),
+    (633, &["SC"]),
+    (634, &["SD"]),
+    (635, &["RW"]),
+    (636, &["ET"]),
+    (637, &["SO"]),
+    (638, &["DJ"]),
+    (639, &["KE"]),
+    (640, &["TZ"]),
+    (641, &["UG"]),
+    (642, &["BI"]),
+    (643, &["MZ"]),
+    (645, &["ZM"]),
+    (646, &["MG"]),
+    (647, &["RE", "YT"]),
+    (648, &["ZW"]),
+    (649, &["NA"]),
+    (650, &["MW"]),
+    (651, &["LS"]),
+    (652, &["BW"]),
+    (653, &["SZ"]),
+    (654, &["KM"]),
+    (655, &["ZA"]),
+    (657, &["ER"]),
+    (658, &["SH"]),
+    (659, &["SS"]),
+    (702, &["BZ"]),
+    (704, &["GT"]),
+    (706, &["SV"]),
+    (708, &["HN"]),
+    (710, &["NI"]),
+    (712, &["CR"]),
+    (714, &["PA"]),
+    (716, &["PE"]),
+    (722, &["AR"]),
+    (724, &["BR"]),
+    (730, &["CL"]),
+    (732, &["CO"]),
+    (734, &["VE"]),
+    (736, &["BO"]),
+    (738, &["GY"]),
+    (740, &["EC"]),
+    (742, &["GF"]),
+    (744, &["PY"]),
+    (746, &["SR"]),
+    (748, &["UY"]),
+    (750, &["FK"]),
+];
+
+/// Carrier names of the larger networks, sorted by MCC-MNC.
+const CARRIERS: &[(&str, &str)] = &[
+    ("204-04", "Vodafone"),
+    ("204-08", "KPN"),
+    ("204-16", "Odido"),
+    ("204-20", "Odido"),
+    ("208-01", "Orange"),
+    ("208-10", "SFR"),
+    ("208-15", "Free Mobile"),
+    ("208-20", "Bouygues Telecom"),
+    ("214-01", "Vodafone"),
+    ("214-03", "Orange"),
+    ("214-04", "Yoigo"),
+    ("214-07", "Movistar"),
+    ("222-01", "TIM"),
+    ("222-10", "Vodafone"),
+    ("222-50", "Iliad"),
+    ("222-88", "WindTre"),
+    ("234-10", "O2"),
+    ("234-15", "Vodafone"),
+    ("234-20", "Three"),
+    ("234-30", "EE"),
+    ("234-33", "EE"),
+    ("240-01", "Telia"),
+    ("240-02", "Tre"),
+    ("240-07", "Tele2"),
+    ("250-01", "MTS"),
+    ("250-02", "MegaFon"),
+    ("250-20", "Tele2"),
+    ("250-99", "Beeline"),
+    ("260-01", "Plus"),
+    ("260-02", "T-Mobile"),
+    ("260-03", "Orange"),
+    ("260-06", "Play"),
+    ("262-01", "Telekom"),
+    ("262-02", "Vodafone"),
+    ("262-03", "O2"),
+    ("262-07", "O2"),
+    ("286-01", "Turkcell"),
+    ("286-02", "Vodafone"),
+    ("286-03", "Turk Telekom"),
+    ("302-220", "Telus"),
+    ("302-370", "Fido"),
+    ("302-490", "Freedom Mobile"),
+    ("302-500", "Videotron"),
+    ("302-610", "Bell"),
+    ("302-720", "Rogers"),
+    ("310-004", "Verizon Wireless"),
+    ("310-005", "Verizon Wireless"),
+    ("310-012", "Verizon Wireless"),
+    ("310-120", "Sprint"),
+    ("310-150", "AT&T"),
+    ("310-160", "T-Mobile"),
+    ("310-170", "AT&T"),
+    ("310-260", "T-Mobile"),
+    ("310-280", "AT&T"),
+    ("310-380", "AT&T"),
+    ("310-410", "AT&T"),
+    ("311-480", "Verizon Wireless"),
+    ("311-490", "T-Mobile"),
+    ("311-580", "U.S. Cellular"),
+    ("312-530", "Sprint"),
+    ("313-100", "AT&T"),
+    ("334-020", "Telcel"),
+    ("334-030", "Movistar"),
+    ("334-050", "AT&T"),
+    ("404-10", "Airtel"),
+    ("404-45", "Airtel"),
+    ("405-840", "Jio"),
+    ("440-10", "NTT Docomo"),
+    ("440-11", "Rakuten Mobile"),
+    ("440-20", "SoftBank"),
+    ("440-50", "au"),
+    ("450-05", "SK Telecom"),
+    ("450-06", "LG U+"),
+    ("450-08", "KT"),
+    ("460-00", "China Mobile"),
+    ("460-01", "China Unicom"),
+    ("460-02", "China Mobile"),
+    ("460-03", "China Telecom"),
+    ("460-07", "China Mobile"),
+    ("460-11", "China Telecom"),
+    ("505-01", "Telstra"),
+    ("505-02", "Optus"),
+    ("505-03", "Vodafone"),
+    ("510-01", "Indosat"),
+    ("510-10", "Telkomsel"),
+    ("510-11", "XL Axiata"),
+    ("515-02", "Globe"),
+    ("515-03", "Smart"),
+    ("525-01", "Singtel"),
+    ("525-03", "M1"),
+    ("525-05", "StarHub"),
+    ("530-01", "One NZ"),
+    ("530-05", "Spark"),
+    ("530-24", "2degrees"),
+    ("621-20", "Airtel"),
+    ("621-30", "MTN"),
+    ("621-50", "Glo"),
+    ("655-01", "Vodacom"),
+    ("655-07", "Cell C"),
+    ("655-10", "MTN"),
+    ("722-07", "Movistar"),
+    ("722-310", "Claro"),
+    ("722-34", "Personal"),
+    ("724-02", "TIM"),
+    ("724-03", "TIM"),
+    ("724-04", "TIM"),
+    ("724-05", "Claro"),
+    ("724-06", "Vivo"),
+    ("724-10", "Vivo"),
+    ("724-11", "Vivo"),
+    ("724-31", "Oi"),
+];
+
+/// Error returned when `mccmnc` is not an MCC followed by an MNC.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseMccMncError;
+
+impl fmt::Display for ParseMccMncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid MCC-MNC")
+    }
+}
+
+impl std::error::Error for ParseMccMncError {}
+
+/// A mobile network identified by its MCC and MNC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MccMnc {
+    mcc: u16,
+    mnc: u16,
+    mnc_digits: u8,
+}
+
+impl MccMnc {
+    /// Parses "310-005". The separator may also be a space or an underscore;
+    /// without one, the first three digits are the MCC and the rest the MNC.
+    pub fn parse(s: &str) -> Result<MccMnc, ParseMccMncError> {
+        let s = s.trim();
+        let (mcc, mnc) = match s.split_once(['-', ' ', '_']) {
+            Some(parts) => parts,
+            None if s.len() > 3 && s.is_char_boundary(3) => s.split_at(3),
+            None => return Err(ParseMccMncError),
+        };
+        let digits = |s: &str, len: std::ops::RangeInclusive<usize>| {
+            if len.contains(&s.len()) && s.bytes().all(|b| b.is_ascii_digit()) {
+                s.parse::<u16>().map_err(|_| ParseMccMncError)
+            } else {
+                Err(ParseMccMncError)
+            }
+        };
+        Ok(MccMnc {
+            mcc: digits(mcc, 3..=3)?,
+            mnc: digits(mnc, 2..=3)?,
+            mnc_digits: mnc.len() as u8,
+        })
+    }
+
+    /// The mobile country code.
+    pub fn mcc(&self) -> u16 {
+        self.mcc
+    }
+
+    /// The mobile network code.
+    pub fn mnc(&self) -> u16 {
+        self.mnc
+    }
+
+    /// The countries the MCC is assigned to, most often just one.
+    pub fn countries(&self) -> Vec<Country> {
+        MCC_COUNTRIES
+            .binary_search_by_key(&self.mcc, |(mcc, _)| *mcc)
+            .map(|i| {
+                MCC_COUNTRIES[i]
+                    .1
+                    .iter()
+                    .filter_map(|c| Country::from_alpha2(c).ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The main country of the MCC.
+    pub fn country(&self) -> Option<Country> {
+        self.countries().into_iter().next()
+    }
+
+    /// The carrier name, if the network is in the bundled table. The MNC
+    /// must have the number of digits the network was assigned: "234-10" is
+    /// O2 while "234-010" is not found.
+    pub fn carrier(&self) -> Option<&'static str> {
+        let key = self.to_string();
+        CARRIERS
+            .binary_search_by(|(k, _)| (*k).cmp(key.as_str()))
+            .ok()
+            .map(|i| CARRIERS[i].1)
+    }
+}
+
+impl fmt::Display for MccMnc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:03}-{:0width$}",
+            self.mcc,
+            self.mnc,
+            width = self.mnc_digits as usize
+        )
+    }
+}
+
+impl FromStr for MccMnc {
+    type Err = ParseMccMncError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MccMnc::parse(s)
+    }
+}
+
+/// Result of comparing the carrier's country with `Device.geo.country`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CountryCheck {
+    /// The geo country is one of the MCC's countries.
+    Match,
+    /// The countries differ. Roaming devices legitimately mismatch, so this
+    /// is a fraud signal rather than proof.
+    Mismatch {
+        /// Country of the carrier's MCC.
+        carrier: Country,
+        /// Country of `Device.geo`.
+        geo: Country,
+    },
+}
+
+impl Device {
+    /// The parsed `mccmnc`.
+    pub fn mccmnc_code(&self) -> Option<Result<MccMnc, ParseMccMncError>> {
+        self.mccmnc.as_deref().map(MccMnc::parse)
+    }
+
+    /// The carrier name from `mccmnc`, falling back to `carrier`.
+    pub fn carrier_name(&self) -> Option<&str> {
+        match self.mccmnc_code() {
+            Some(Ok(code)) => code.carrier().or(self.carrier.as_deref()),
+            _ => self.carrier.as_deref(),
+        }
+    }
+
+    /// Compares the country of `mccmnc` with `geo.country`. Returns `None`
+    /// unless both are present and valid.
+    pub fn carrier_country_check(&self) -> Option<CountryCheck> {
+        let code = self.mccmnc_code()?.ok()?;
+        let geo = self.geo.as_ref()?.country_code()?.ok()?;
+        let countries = code.countries();
+        let carrier = *countries.first()?;
+        Some(if countries.contains(&geo) {
+            CountryCheck::Match
+        } else {
+            CountryCheck::Mismatch { carrier, geo }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::BidRequest;
+    use super::*;
+
+    #[test]
+    fn parses_codes() {
+        let code = MccMnc::parse("310-005").unwrap();
+        assert_eq!((code.mcc(), code.mnc()), (310, 5));
+        assert_eq!(code.to_string(), "310-005");
+        assert_eq!(code.country().unwrap().alpha3(), "USA");
+        assert_eq!(MccMnc::parse("23410").unwrap().to_string(), "234-10");
+        assert_eq!(MccMnc::parse(" 234_10 ").unwrap().to_string(), "234-10");
+        assert!(MccMnc::parse("31-005").is_err());
+        assert!(MccMnc::parse("310-5").is_err());
+        assert!(MccMnc::parse("310-0005").is_err());
+        assert!(MccMnc::parse("310").is_err());
+        assert!(MCC_COUNTRIES.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(CARRIERS.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn carrier_needs_the_assigned_mnc_length() {
+        assert_eq!(
+            MccMnc::parse("310-005").unwrap().carrier(),
+            Some("Verizon Wireless")
+        );
+        assert_eq!(MccMnc::parse("234-10").unwrap().carrier(), Some("O2"));
+        assert_eq!(MccMnc::parse("234-010").unwrap().carrier(), None);
+        assert_eq!(MccMnc::parse("310-05").unwrap().carrier(), None);
+        assert_ne!(MccMnc::parse("234-10"), MccMnc::parse("234-010"));
+    }
+
+    #[test]
+    fn checks_carrier_country() {
+        let request: BidRequest = serde_json::from_str(
+            r#"{"id":"1","imp":[],
+                "device":{"mccmnc":"262-01","carrier":"x","geo":{"country":"FRA"}}}"#,
+        )
+        .unwrap();
+        let mut device = request.device.unwrap();
+        assert_eq!(device.carrier_name(), Some("Telekom"));
+        match device.carrier_country_check() {
+            Some(CountryCheck::Mismatch { carrier, geo }) => {
+                assert_eq!((carrier.alpha2(), geo.alpha2()), ("DE", "FR"))
+            }
+            _ => panic!("expected a mismatch"),
+        }
+
+        device.geo.as_mut().unwrap().country = Some("DEU".to_string());
+        assert_eq!(device.carrier_country_check(), Some(CountryCheck::Match));
+        device.mccmnc = Some("999-99".to_string());
+        assert_eq!(device.carrier_name(), Some("x"));
+        assert_eq!(device.carrier_country_check(), None);
+    }
+}
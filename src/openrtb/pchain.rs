@@ -0,0 +1,272 @@
+//! Payment ID chain (pchain).
+//!
+//! `Source.pchain` follows the TAG Payment ID Protocol v1.0: each
+//! participant that handles payment for the impression appends a Payment ID
+//! of the form `tagid:sellerid`, where `tagid` is its TAG-ID of 16
+//! hexadecimal digits and `sellerid` its own identifier for the seller.
+//! Entries are separated by `~`:
+//!
+//! ```text
+//! f08c47fec0942fa0:pub-123~a1b2c3d4e5f60718:acct-9
+//! ```
+
+use super::bid_request::Source;
+use std::fmt;
+use std::str::FromStr;
+
+/// Error returned for a malformed payment ID chain. Indices refer to the
+/// position of the entry in the chain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PchainError {
+    /// The chain is empty.
+    Empty,
+    /// The entry has no `:` between the TAG-ID and the seller ID.
+    MissingSeparator(usize),
+    /// The TAG-ID is not 16 hexadecimal digits.
+    InvalidTagId(usize),
+    /// The seller ID is empty or contains `:`, `~` or non-printable
+    /// characters.
+    InvalidSellerId(usize),
+}
+
+impl fmt::Display for PchainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PchainError::Empty => f.write_str("empty payment ID chain"),
+            PchainError::MissingSeparator(i) => write!(f, "payment ID {} has no ':'", i),
+            PchainError::InvalidTagId(i) => write!(f, "payment ID {} has an invalid TAG-ID", i),
+            PchainError::InvalidSellerId(i) => {
+                write!(f, "payment ID {} has an invalid seller ID", i)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PchainError {}
+
+/// Error returned by `PaymentId::new`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaymentIdError {
+    /// The TAG-ID is not 16 hexadecimal digits.
+    InvalidTagId,
+    /// The seller ID is empty or contains `:`, `~` or non-printable
+    /// characters.
+    InvalidSellerId,
+}
+
+impl PaymentIdError {
+    /// The chain error for this problem in entry `index`.
+    fn at(self, index: usize) -> PchainError {
+        match self {
+            PaymentIdError::InvalidTagId => PchainError::InvalidTagId(index),
+            PaymentIdError::InvalidSellerId => PchainError::InvalidSellerId(index),
+        }
+    }
+}
+
+impl fmt::Display for PaymentIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PaymentIdError::InvalidTagId => "invalid TAG-ID",
+            PaymentIdError::InvalidSellerId => "invalid seller ID",
+        })
+    }
+}
+
+impl std::error::Error for PaymentIdError {}
+
+/// One participant's entry in the chain.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PaymentId {
+    tag_id: String,
+    seller_id: String,
+}
+
+impl PaymentId {
+    /// Builds an entry, checking both parts. The TAG-ID is lowercased.
+    pub fn new(tag_id: &str, seller_id: &str) -> Result<PaymentId, PaymentIdError> {
+        if tag_id.len() != 16 || !tag_id.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(PaymentIdError::InvalidTagId);
+        }
+        if seller_id.is_empty()
+            || !seller_id
+                .bytes()
+                .all(|b| b.is_ascii_graphic() && b != b':' && b != b'~')
+        {
+            return Err(PaymentIdError::InvalidSellerId);
+        }
+        Ok(PaymentId {
+            tag_id: tag_id.to_ascii_lowercase(),
+            seller_id: seller_id.to_string(),
+        })
+    }
+
+    /// The participant's TAG-ID.
+    pub fn tag_id(&self) -> &str {
+        &self.tag_id
+    }
+
+    /// The participant's identifier for the seller.
+    pub fn seller_id(&self) -> &str {
+        &self.seller_id
+    }
+}
+
+impl fmt::Display for PaymentId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.tag_id, self.seller_id)
+    }
+}
+
+/// A parsed payment ID chain, in the order participants appended to it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PaymentChain {
+    /// The entries, first participant first.
+    pub entries: Vec<PaymentId>,
+}
+
+impl PaymentChain {
+    /// Parses a chain, rejecting empty entries and malformed parts.
+    pub fn parse(s: &str) -> Result<PaymentChain, PchainError> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(PchainError::Empty);
+        }
+        let entries = s
+            .split('~')
+            .enumerate()
+            .map(|(i, entry)| {
+                let (tag_id, seller_id) = entry
+                    .split_once(':')
+                    .ok_or(PchainError::MissingSeparator(i))?;
+                PaymentId::new(tag_id, seller_id).map_err(|e| e.at(i))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(PaymentChain { entries })
+    }
+
+    /// Appends the current participant's entry.
+    pub fn push(&mut self, id: PaymentId) {
+        self.entries.push(id);
+    }
+
+    /// The most recent participant.
+    pub fn last(&self) -> Option<&PaymentId> {
+        self.entries.last()
+    }
+}
+
+impl fmt::Display for PaymentChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i > 0 {
+                f.write_str("~")?;
+            }
+            write!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for PaymentChain {
+    type Err = PchainError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PaymentChain::parse(s)
+    }
+}
+
+impl Source {
+    /// The parsed `pchain`.
+    pub fn payment_chain(&self) -> Option<Result<PaymentChain, PchainError>> {
+        self.pchain.as_deref().map(PaymentChain::parse)
+    }
+
+    /// Appends `id` to `pchain`, starting a chain if there is none. The
+    /// upstream entries are kept as sent. A malformed chain is left
+    /// untouched and its error returned, so that a broken chain is not
+    /// passed on as if it were valid.
+    pub fn append_payment_id(&mut self, id: PaymentId) -> Result<(), PchainError> {
+        self.pchain = Some(match self.pchain.as_deref() {
+            Some(pchain) => {
+                PaymentChain::parse(pchain)?;
+                format!("{}~{}", pchain.trim(), id)
+            }
+            None => id.to_string(),
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_chains() {
+        let chain =
+            PaymentChain::parse("F08C47FEC0942FA0:pub-123~a1b2c3d4e5f60718:acct-9").unwrap();
+        assert_eq!(chain.entries.len(), 2);
+        assert_eq!(chain.last().unwrap().seller_id(), "acct-9");
+        assert_eq!(
+            chain.to_string(),
+            "f08c47fec0942fa0:pub-123~a1b2c3d4e5f60718:acct-9"
+        );
+        assert_eq!(PaymentChain::parse(" "), Err(PchainError::Empty));
+        assert_eq!(
+            PaymentChain::parse("f08c47fec0942fa0:a~~"),
+            Err(PchainError::MissingSeparator(1))
+        );
+        assert_eq!(
+            PaymentChain::parse("f08c47fec0942fa0:a~f08c:b"),
+            Err(PchainError::InvalidTagId(1))
+        );
+        assert_eq!(
+            PaymentChain::parse("f08c47fec0942fa0:"),
+            Err(PchainError::InvalidSellerId(0))
+        );
+    }
+
+    #[test]
+    fn checks_payment_ids() {
+        let id = PaymentId::new("A1B2C3D4E5F60718", "p1").unwrap();
+        assert_eq!(id.tag_id(), "a1b2c3d4e5f60718");
+        assert_eq!(
+            PaymentId::new("a1b2c3d4e5f6071", "p1"),
+            Err(PaymentIdError::InvalidTagId)
+        );
+        assert_eq!(
+            PaymentId::new("g1b2c3d4e5f60718", "p1"),
+            Err(PaymentIdError::InvalidTagId)
+        );
+        assert_eq!(
+            PaymentId::new("a1b2c3d4e5f60718", "p~1"),
+            Err(PaymentIdError::InvalidSellerId)
+        );
+    }
+
+    #[test]
+    fn appends_to_the_chain_as_sent() {
+        let mut source = Source::default();
+        source
+            .append_payment_id(PaymentId::new("f08c47fec0942fa0", "p1").unwrap())
+            .unwrap();
+        assert_eq!(source.pchain.as_deref(), Some("f08c47fec0942fa0:p1"));
+
+        source.pchain = Some("F08C47FEC0942FA0:Pub-1".to_string());
+        source
+            .append_payment_id(PaymentId::new("a1b2c3d4e5f60718", "p2").unwrap())
+            .unwrap();
+        assert_eq!(
+            source.pchain.as_deref(),
+            Some("F08C47FEC0942FA0:Pub-1~a1b2c3d4e5f60718:p2")
+        );
+
+        source.pchain = Some("bad".to_string());
+        assert_eq!(
+            source.append_payment_id(PaymentId::new("a1b2c3d4e5f60718", "p2").unwrap()),
+            Err(PchainError::MissingSeparator(0))
+        );
+        assert_eq!(source.pchain.as_deref(), Some("bad"));
+    }
+}
@@ -0,0 +1,613 @@
+//! Fixed-decimal prices.
+//!
+//! `Bid.price`, `Imp.bidfloor` and `Deal.bidfloor` are `f64` as in the spec.
+//! `Price` is an exact alternative for doing arithmetic on them: it stores an
+//! integer number of `10^-N` units, so it parses without accumulating binary
+//! rounding error, compares and adds exactly, and encodes with at most `N`
+//! decimals (e.g. `1.23` rather than `1.2300000000000002`).
+//!
+//! With `serde_json`, prices are decoded from their decimal text rather than
+//! through `f64`, using its `raw_value` feature. Other serde formats, such as
+//! the `simd` backend of `json`, decode through `f64`. Every format encodes
+//! through `f64`. Both are exact up to 15 significant digits, e.g. below a
+//! billion at six decimals.
+//!
+//! The arithmetic operators saturate at the ends of the range; the
+//! `checked_` methods report overflow instead.
+
+use super::bid_request::imp::pmp::Deal;
+use super::bid_request::Imp;
+use super::bid_response::seat_bid::Bid;
+use serde::de::{Error as _, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+/// A decimal price with `N` digits after the decimal point (micro-units by
+/// default). Values that do not fit are rounded half away from zero.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Price<const N: u32 = 6>(i64);
+
+/// Error returned when a string or float cannot be converted into a `Price`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParsePriceError {
+    /// Not a decimal number.
+    Invalid,
+    /// The value does not fit in the price range.
+    Overflow,
+}
+
+impl fmt::Display for ParsePriceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParsePriceError::Invalid => f.write_str("invalid decimal price"),
+            ParsePriceError::Overflow => f.write_str("decimal price out of range"),
+        }
+    }
+}
+
+impl std::error::Error for ParsePriceError {}
+
+impl<const N: u32> Price<N> {
+    /// Number of units in 1.0.
+    pub const SCALE: i64 = 10i64.pow(N);
+
+    /// The price 0.
+    pub const ZERO: Price<N> = Price(0);
+
+    /// Creates a price from a raw number of `10^-N` units.
+    pub const fn from_units(units: i64) -> Price<N> {
+        Price(units)
+    }
+
+    /// The raw number of `10^-N` units.
+    pub const fn units(self) -> i64 {
+        self.0
+    }
+
+    /// Converts a float using its shortest round-trip decimal representation,
+    /// so `1.23_f64` becomes exactly `1.23`. Returns `None` for NaN, infinities
+    /// and out of range values.
+    pub fn from_f64(v: f64) -> Option<Price<N>> {
+        if !v.is_finite() {
+            return None;
+        }
+        v.to_string().parse().ok()
+    }
+
+    /// The closest `f64`. For values with at most 15 significant digits this
+    /// formats back to exactly the same decimal.
+    pub fn to_f64(self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// Adds, returning `None` on overflow.
+    pub fn checked_add(self, rhs: Price<N>) -> Option<Price<N>> {
+        self.0.checked_add(rhs.0).map(Price)
+    }
+
+    /// Subtracts, returning `None` on overflow.
+    pub fn checked_sub(self, rhs: Price<N>) -> Option<Price<N>> {
+        self.0.checked_sub(rhs.0).map(Price)
+    }
+
+    /// Negates, returning `None` for the minimum price.
+    pub fn checked_neg(self) -> Option<Price<N>> {
+        self.0.checked_neg().map(Price)
+    }
+
+    /// Multiplies by a whole number, such as a quantity, returning `None` on
+    /// overflow.
+    pub fn checked_mul_int(self, rhs: i64) -> Option<Price<N>> {
+        self.0.checked_mul(rhs).map(Price)
+    }
+
+    /// Multiplies two prices (e.g. a price by a rate), rounding to `N`
+    /// decimals. Returns `None` on overflow.
+    pub fn checked_mul(self, rhs: Price<N>) -> Option<Price<N>> {
+        let product = self.0 as i128 * rhs.0 as i128;
+        i64::try_from(div_round(product, Self::SCALE as i128))
+            .ok()
+            .map(Price)
+    }
+
+    /// Divides two prices, rounding to `N` decimals. Returns `None` on
+    /// overflow or division by zero.
+    pub fn checked_div(self, rhs: Price<N>) -> Option<Price<N>> {
+        if rhs.0 == 0 {
+            return None;
+        }
+        let dividend = self.0 as i128 * Self::SCALE as i128;
+        i64::try_from(div_round(dividend, rhs.0 as i128))
+            .ok()
+            .map(Price)
+    }
+
+    /// Converts a CPM into the price of a single impression.
+    pub fn cpm_to_unit(self) -> Price<N> {
+        Price(div_round(self.0 as i128, 1000) as i64)
+    }
+
+    /// Rounds to `digits` decimals (no-op when `digits >= N`), saturating
+    /// when rounding away from zero leaves the range.
+    pub fn round_dp(self, digits: u32) -> Price<N> {
+        if digits >= N {
+            return self;
+        }
+        let step = 10i128.pow(N - digits);
+        let rounded = div_round(self.0 as i128, step) * step;
+        Price(rounded.clamp(i64::MIN as i128, i64::MAX as i128) as i64)
+    }
+}
+
+/// Integer division rounding half away from zero.
+fn div_round(n: i128, d: i128) -> i128 {
+    let q = n / d;
+    let r = n % d;
+    if r.abs() * 2 >= d.abs() {
+        q + (n.signum() * d.signum())
+    } else {
+        q
+    }
+}
+
+impl<const N: u32> FromStr for Price<N> {
+    type Err = ParsePriceError;
+
+    /// Parses a decimal number with optional sign, fraction and exponent.
+    /// Non-zero values whose exponent leaves the `i32` range, before or once
+    /// the digits are counted, are rejected as `Overflow`.
+    fn from_str(s: &str) -> Result<Price<N>, ParsePriceError> {
+        let (negative, s) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (s, exp) = match s.find(['e', 'E']) {
+            Some(i) => {
+                let exp = &s[i + 1..];
+                let digits = exp.strip_prefix(['+', '-']).unwrap_or(exp);
+                if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(ParsePriceError::Invalid);
+                }
+                // `None` when the exponent does not fit in an `i32`.
+                (&s[..i], exp.parse::<i32>().ok())
+            }
+            None => (s, Some(0)),
+        };
+        let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+        if int.is_empty() && frac.is_empty() {
+            return Err(ParsePriceError::Invalid);
+        }
+
+        let mut mantissa: i128 = 0;
+        let mut dropped = 0;
+        for c in int.bytes().chain(frac.bytes()) {
+            if !c.is_ascii_digit() {
+                return Err(ParsePriceError::Invalid);
+            }
+            if mantissa > i128::MAX / 100 {
+                // Digits beyond i128 precision cannot affect an i64 result.
+                dropped += 1;
+                continue;
+            }
+            mantissa = mantissa * 10 + (c - b'0') as i128;
+        }
+        if mantissa == 0 {
+            return Ok(Price::ZERO);
+        }
+        let exp = exp.ok_or(ParsePriceError::Overflow)?;
+
+        // The power of ten to divide the mantissa by to get units.
+        let shift = i32::try_from(frac.len())
+            .ok()
+            .and_then(|n| n.checked_sub(dropped))
+            .and_then(|n| n.checked_sub(exp))
+            .and_then(|n| n.checked_sub(N as i32))
+            .ok_or(ParsePriceError::Overflow)?;
+        let units = if shift > 0 {
+            if shift > 38 {
+                0
+            } else {
+                div_round(mantissa, 10i128.pow(shift as u32))
+            }
+        } else {
+            10i128
+                .checked_pow(shift.unsigned_abs())
+                .and_then(|m| mantissa.checked_mul(m))
+                .ok_or(ParsePriceError::Overflow)?
+        };
+        let units = if negative { -units } else { units };
+        i64::try_from(units)
+            .map(Price)
+            .map_err(|_| ParsePriceError::Overflow)
+    }
+}
+
+impl<const N: u32> fmt::Display for Price<N> {
+    /// Formats with at most `N` decimals, without trailing zeros.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let units = self.0.unsigned_abs();
+        let scale = Self::SCALE as u64;
+        if self.0 < 0 {
+            f.write_str("-")?;
+        }
+        write!(f, "{}", units / scale)?;
+        let frac = units % scale;
+        if frac != 0 {
+            let digits = format!("{:0width$}", frac, width = N as usize);
+            write!(f, ".{}", digits.trim_end_matches('0'))?;
+        }
+        Ok(())
+    }
+}
+
+impl<const N: u32> Add for Price<N> {
+    type Output = Price<N>;
+    fn add(self, rhs: Price<N>) -> Price<N> {
+        Price(self.0.saturating_add(rhs.0))
+    }
+}
+
+impl<const N: u32> AddAssign for Price<N> {
+    fn add_assign(&mut self, rhs: Price<N>) {
+        *self = *self + rhs;
+    }
+}
+
+impl<const N: u32> Sub for Price<N> {
+    type Output = Price<N>;
+    fn sub(self, rhs: Price<N>) -> Price<N> {
+        Price(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl<const N: u32> SubAssign for Price<N> {
+    fn sub_assign(&mut self, rhs: Price<N>) {
+        *self = *self - rhs;
+    }
+}
+
+impl<const N: u32> Neg for Price<N> {
+    type Output = Price<N>;
+    fn neg(self) -> Price<N> {
+        Price(self.0.saturating_neg())
+    }
+}
+
+impl<const N: u32> Mul<i64> for Price<N> {
+    type Output = Price<N>;
+    fn mul(self, rhs: i64) -> Price<N> {
+        Price(self.0.saturating_mul(rhs))
+    }
+}
+
+impl<const N: u32> Sum for Price<N> {
+    fn sum<I: Iterator<Item = Price<N>>>(iter: I) -> Price<N> {
+        iter.fold(Price::ZERO, Add::add)
+    }
+}
+
+/// Writes the price as the nearest `f64`, which formats such as `serde_json`
+/// write back as the decimal text for prices of up to 15 significant digits.
+impl<const N: u32> Serialize for Price<N> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_f64(self.to_f64())
+    }
+}
+
+/// The newtype name under which `serde_json` hands out the raw text of a
+/// value, as used by `Box<RawValue>`. Other deserializers treat it as a plain
+/// newtype.
+const RAW_VALUE_TOKEN: &str = "$serde_json::private::RawValue";
+
+/// Reads numbers and strings holding a number. `serde_json` numbers are read
+/// from their text, so `1.2345678901234567` is exact at `N = 16`; other
+/// formats are read through `f64`.
+impl<'de, const N: u32> Deserialize<'de> for Price<N> {
+    fn deserialize<D>(deserializer: D) -> Result<Price<N>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_newtype_struct(RAW_VALUE_TOKEN, PriceVisitor)
+    }
+}
+
+struct PriceVisitor<const N: u32>;
+
+impl<'de, const N: u32> Visitor<'de> for PriceVisitor<N> {
+    type Value = Price<N>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a decimal price")
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Price<N>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+
+    /// The raw text from `serde_json`, as the single entry of a map.
+    fn visit_map<A>(self, mut map: A) -> Result<Price<N>, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        if map.next_key::<String>()?.as_deref() != Some(RAW_VALUE_TOKEN) {
+            return Err(A::Error::custom("expected a decimal price"));
+        }
+        let text: String = map.next_value()?;
+        match text.starts_with('"') {
+            true => serde_json::from_str::<String>(&text)
+                .map_err(A::Error::custom)?
+                .parse(),
+            false => text.parse(),
+        }
+        .map_err(A::Error::custom)
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Price<N>, E> {
+        v.parse().map_err(E::custom)
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Price<N>, E> {
+        Price::<N>::SCALE
+            .checked_mul(v)
+            .map(Price::from_units)
+            .ok_or_else(|| E::custom(ParsePriceError::Overflow))
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Price<N>, E> {
+        match i64::try_from(v) {
+            Ok(v) => self.visit_i64(v),
+            Err(_) => Err(E::custom(ParsePriceError::Overflow)),
+        }
+    }
+
+    fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Price<N>, E> {
+        v.to_string().parse().map_err(E::custom)
+    }
+}
+
+impl Bid {
+    /// `price` as an exact decimal.
+    pub fn price_decimal<const N: u32>(&self) -> Option<Price<N>> {
+        Price::from_f64(self.price)
+    }
+
+    /// Sets `price` so that it encodes as exactly `price`.
+    pub fn set_price_decimal<const N: u32>(&mut self, price: Price<N>) {
+        self.price = price.to_f64();
+    }
+}
+
+impl Imp {
+    /// `bidfloor` as an exact decimal.
+    pub fn bidfloor_decimal<const N: u32>(&self) -> Option<Price<N>> {
+        self.bidfloor.and_then(Price::from_f64)
+    }
+
+    /// Sets `bidfloor` so that it encodes as exactly `bidfloor`.
+    pub fn set_bidfloor_decimal<const N: u32>(&mut self, bidfloor: Price<N>) {
+        self.bidfloor = Some(bidfloor.to_f64());
+    }
+}
+
+impl Deal {
+    /// `bidfloor` as an exact decimal.
+    pub fn bidfloor_decimal<const N: u32>(&self) -> Option<Price<N>> {
+        self.bidfloor.and_then(Price::from_f64)
+    }
+
+    /// Sets `bidfloor` so that it encodes as exactly `bidfloor`.
+    pub fn set_bidfloor_decimal<const N: u32>(&mut self, bidfloor: Price<N>) {
+        self.bidfloor = Some(bidfloor.to_f64());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(s: &str) -> Result<Price, ParsePriceError> {
+        s.parse()
+    }
+
+    #[test]
+    fn parses_decimals() {
+        let cases = [
+            ("0", 0),
+            ("1", 1_000_000),
+            ("1.23", 1_230_000),
+            ("+1.5", 1_500_000),
+            ("-0.25", -250_000),
+            (".5", 500_000),
+            ("2.", 2_000_000),
+            ("1.5e2", 150_000_000),
+            ("15E-1", 1_500_000),
+            ("0.0000005", 1),
+            ("-0.0000005", -1),
+            ("0.0000004999", 0),
+            ("1.2300000000000002", 1_230_000),
+            ("9223372036854.775807", i64::MAX),
+            ("0e2147483647", 0),
+            ("1e-2147483647", 0),
+        ];
+        for (input, units) in cases {
+            assert_eq!(price(input), Ok(Price::from_units(units)), "{}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_and_out_of_range() {
+        for input in [
+            "", "-", ".", "e5", "1e", "1.2.3", "1,5", "abc", "1e+", "1e--5", "1e5x",
+        ] {
+            assert_eq!(price(input), Err(ParsePriceError::Invalid), "{}", input);
+        }
+        for input in ["9223372036854.775808", "1e13", "1e38", "1e39"] {
+            assert_eq!(price(input), Err(ParsePriceError::Overflow), "{}", input);
+        }
+        // Exponents out of range before or once the digits are counted.
+        for input in [
+            "1e2147483647",
+            "1e99999999999",
+            "1e-2147483648",
+            "1e-99999999999",
+        ] {
+            assert_eq!(price(input), Err(ParsePriceError::Overflow), "{}", input);
+        }
+        assert_eq!(price("0e-2147483648"), Ok(Price::ZERO));
+        assert_eq!(price("0e99999999999"), Ok(Price::ZERO));
+        assert!(serde_json::from_str::<Price>(r#""1e2147483647""#).is_err());
+    }
+
+    #[test]
+    fn rounds_half_away_from_zero() {
+        assert_eq!("1.005".parse::<Price<2>>().unwrap().to_string(), "1.01");
+        assert_eq!("-1.005".parse::<Price<2>>().unwrap().to_string(), "-1.01");
+        assert_eq!(
+            Price::<6>::from_units(-1_500_000).round_dp(0).to_string(),
+            "-2"
+        );
+        assert_eq!(
+            Price::<6>::from_units(i64::MAX).round_dp(0),
+            Price::from_units(i64::MAX)
+        );
+    }
+
+    #[test]
+    fn formats_without_trailing_zeros() {
+        assert_eq!(Price::<6>::from_units(1_230_000).to_string(), "1.23");
+        assert_eq!(Price::<6>::from_units(-1).to_string(), "-0.000001");
+        assert_eq!(
+            Price::<6>::from_units(i64::MIN).to_string(),
+            "-9223372036854.775808"
+        );
+        assert_eq!(Price::<6>::from_f64(0.1 + 0.2).unwrap().to_string(), "0.3");
+    }
+
+    #[test]
+    fn decodes_json_numbers_exactly() {
+        let exact: Price<6> = serde_json::from_str("9007199254.740993").unwrap();
+        assert_eq!(exact.units(), 9_007_199_254_740_993);
+        let exact: Price<16> = serde_json::from_str("0.1234567890123457").unwrap();
+        assert_eq!(exact.units(), 1_234_567_890_123_457);
+        let quoted: Price = serde_json::from_str(r#""1.25""#).unwrap();
+        assert_eq!(quoted.to_string(), "1.25");
+        let int: Price = serde_json::from_str("-3").unwrap();
+        assert_eq!(int.to_string(), "-3");
+        assert!(serde_json::from_str::<Price>("true").is_err());
+        let from_value: Price = serde_json::from_value(serde_json::json!(1.5)).unwrap();
+        assert_eq!(from_value.to_string(), "1.5");
+        assert_eq!(
+            serde_json::to_string(&Price::<6>::from_units(1_230_000)).unwrap(),
+            "1.23"
+        );
+    }
+
+    #[test]
+    fn encodes_json_numbers_exactly() {
+        let long: Price = "123456789.012345".parse().unwrap();
+        assert_eq!(serde_json::to_string(&long).unwrap(), "123456789.012345");
+        let back: Price = serde_json::from_str("123456789.012345").unwrap();
+        assert_eq!(back, long);
+        assert_eq!(serde_json::to_string(&-long).unwrap(), "-123456789.012345");
+        // Past 15 significant digits, the nearest `f64`.
+        let longer: Price = "123456789012.345678".parse().unwrap();
+        assert_eq!(
+            serde_json::to_string(&longer).unwrap(),
+            "123456789012.34567"
+        );
+        assert_eq!(serde_json::to_string(&Price::<6>::ZERO).unwrap(), "0.0");
+        assert_eq!(
+            serde_json::to_value(Price::<6>::from_units(1_500_000)).unwrap(),
+            serde_json::json!(1.5)
+        );
+    }
+
+    /// Runs with the active `json` backend, so `--features simd` covers the
+    /// `simd-json` path.
+    #[test]
+    fn decodes_with_the_json_backend() {
+        use super::super::json;
+        for (input, expected) in [
+            ("1.23", "1.23"),
+            ("-3", "-3"),
+            ("9223372036854", "9223372036854"),
+            (r#""0.000001""#, "0.000001"),
+            ("2.5e-1", "0.25"),
+        ] {
+            let price: Price = json::from_str(input).unwrap();
+            assert_eq!(price.to_string(), expected, "{}", input);
+        }
+        assert!(json::from_str::<Price>("9223372036855").is_err());
+        assert!(json::from_str::<Price>("18446744073709551615").is_err());
+        assert!(json::from_str::<Price>("true").is_err());
+        assert!(json::from_str::<Price>(r#""x""#).is_err());
+        assert_eq!(
+            json::to_string(&Price::<6>::from_units(1_230_000)).unwrap(),
+            "1.23"
+        );
+        // Encoded as plain numbers that decode to the same price.
+        for input in ["-0.000001", "123456789.012345", "9007199254.740993"] {
+            let price: Price = input.parse().unwrap();
+            let text = json::to_string(&price).unwrap();
+            let value: serde_json::Value = serde_json::from_str(&text).unwrap();
+            assert!(value.is_number(), "{}", text);
+            let back: Price = json::from_str(&text).unwrap();
+            assert_eq!(back.to_f64(), price.to_f64(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn arithmetic_saturates() {
+        let max = Price::<6>::from_units(i64::MAX);
+        let min = Price::<6>::from_units(i64::MIN);
+        let one = price("1").unwrap();
+        assert_eq!(max + one, max);
+        assert_eq!(min - one, min);
+        assert_eq!(-min, max);
+        assert_eq!(max * 2, max);
+        assert_eq!(min * 2, min);
+        let mut sum = max;
+        sum += one;
+        assert_eq!(sum, max);
+        assert_eq!(max.checked_add(one), None);
+        assert_eq!(min.checked_neg(), None);
+        assert_eq!(max.checked_mul_int(2), None);
+        assert_eq!(one.checked_mul_int(3), Some(price("3").unwrap()));
+    }
+
+    #[test]
+    fn multiplies_and_divides() {
+        let a = price("1.23").unwrap();
+        assert_eq!(
+            a.checked_mul(price("2").unwrap()).unwrap().to_string(),
+            "2.46"
+        );
+        assert_eq!(
+            a.checked_div(price("3").unwrap()).unwrap().to_string(),
+            "0.41"
+        );
+        assert_eq!(a.checked_div(Price::ZERO), None);
+        assert_eq!(price("2.5").unwrap().cpm_to_unit().to_string(), "0.0025");
+    }
+
+    #[test]
+    fn sets_message_prices() {
+        let mut bid = Bid::default();
+        bid.set_price_decimal(price("1.23").unwrap() + Price::from_units(1));
+        assert_eq!(bid.price, 1.230001);
+        assert_eq!(bid.price_decimal::<6>(), Some(Price::from_units(1_230_001)));
+        let mut imp = Imp::default();
+        imp.set_bidfloor_decimal(price("0.3").unwrap());
+        assert_eq!(imp.bidfloor, Some(0.3));
+    }
+}
@@ -0,0 +1,734 @@
+//! Privacy signals of a request.
+//!
+//! `PrivacySignals` gathers COPPA, GDPR/TCF, US Privacy, GPP and the device
+//! `dnt`/`lmt` flags into one view, and answers whether the request may be
+//! used for personalized advertising. `scrub` enforces those signals by
+//! removing or coarsening personal data in place, according to a `Policy`.
+
+use super::bid_request::{Device, Geo, Regs, User};
+use super::bool::Bool;
+use super::gpp::{GppError, GppString, UsSection, USP_V1};
+use super::ip::IpPrefix;
+use super::tcf::{TcString, TcfError};
+use super::usp::{ParseUsPrivacyError, UsPrivacy};
+use super::BidRequest;
+use std::net::IpAddr;
+
+/// TCF purposes required for personalized advertising: store and access
+/// information, create a personalised ads profile, select personalised ads.
+const PERSONALIZATION_PURPOSES: [u8; 3] = [1, 3, 4];
+
+/// A view over the privacy-related fields of a request.
+#[derive(Clone, Copy, Default)]
+pub struct PrivacySignals<'a> {
+    /// The request's regulations: COPPA, GDPR and the privacy strings.
+    pub regs: Option<&'a Regs>,
+    /// The user, for the TCF consent string.
+    pub user: Option<&'a User>,
+    /// The device, for the Do Not Track and Limit Ad Tracking flags.
+    pub device: Option<&'a Device>,
+}
+
+impl<'a> PrivacySignals<'a> {
+    /// A view over the given objects of a request.
+    pub fn new(
+        regs: Option<&'a Regs>,
+        user: Option<&'a User>,
+        device: Option<&'a Device>,
+    ) -> PrivacySignals<'a> {
+        PrivacySignals { regs, user, device }
+    }
+
+    /// Whether the request is subject to COPPA.
+    pub fn coppa(&self) -> bool {
+        self.regs.and_then(|r| r.coppa.as_ref()) == Some(&Bool::True)
+    }
+
+    /// Whether the device signals Do Not Track.
+    pub fn dnt(&self) -> bool {
+        self.device.and_then(|d| d.dnt.as_ref()) == Some(&Bool::True)
+    }
+
+    /// Whether the device signals Limit Ad Tracking.
+    pub fn lmt(&self) -> bool {
+        self.device.and_then(|d| d.lmt.as_ref()) == Some(&Bool::True)
+    }
+
+    /// GDPR applicability; `None` when not signalled.
+    pub fn gdpr(&self) -> Option<bool> {
+        self.regs?.gdpr()
+    }
+
+    /// The TCF consent string from `user.ext.consent`, or else from the TCF
+    /// EU section of the GPP string.
+    pub fn tc_string(&self) -> Option<Result<TcString, TcfError>> {
+        match self.user.and_then(User::tc_string) {
+            Some(tc) => Some(tc),
+            None => self.gpp()?.ok()?.tcf_eu(),
+        }
+    }
+
+    /// The US Privacy string from `regs.ext.us_privacy`, or else from the
+    /// US Privacy section of the GPP string when it applies.
+    pub fn us_privacy(&self) -> Option<Result<UsPrivacy, ParseUsPrivacyError>> {
+        match self.regs?.us_privacy() {
+            Some(usp) => Some(usp),
+            None if self.gpp_applies(USP_V1) => self.gpp()?.ok()?.us_privacy(),
+            None => None,
+        }
+    }
+
+    /// The GPP string from `regs.ext.gpp`.
+    pub fn gpp(&self) -> Option<Result<GppString<'a>, GppError>> {
+        self.regs?.gpp()
+    }
+
+    /// The US national and state GPP sections that apply to the request:
+    /// those listed in `regs.ext.gpp_sid`, or all of them when it is absent.
+    pub fn us_sections(&self) -> Vec<Result<UsSection, GppError>> {
+        let gpp = match self.gpp() {
+            Some(Ok(gpp)) => gpp,
+            _ => return Vec::new(),
+        };
+        gpp.us_sections()
+            .filter(|section| match section {
+                Ok(section) => self.gpp_applies(section.section_id),
+                Err(_) => true,
+            })
+            .collect()
+    }
+
+    /// Whether the GPP section `id` applies: it is listed in
+    /// `regs.ext.gpp_sid`, or that is absent.
+    fn gpp_applies(&self, id: u16) -> bool {
+        match self.regs.and_then(Regs::gpp_sid) {
+            Some(sid) => sid.contains(&id),
+            None => true,
+        }
+    }
+
+    /// The signals that restrict the use of personal data, in the order
+    /// COPPA, `dnt`, `lmt`, GDPR, US opt-out. Empty when the request may be
+    /// personalized.
+    ///
+    /// GDPR restricts when it applies without consent to TCF purposes 1, 3
+    /// and 4. A US opt-out is an opt-out of sale in the US Privacy string
+    /// (`regs.ext.us_privacy` or the GPP section), or an opt-out of sale,
+    /// sharing or targeted advertising (or Global Privacy Control) in an
+    /// applicable GPP US section. Malformed strings count as missing consent
+    /// or as opt-outs.
+    pub fn restrictions(&self) -> Vec<Restriction> {
+        let mut restrictions = Vec::new();
+        if self.coppa() {
+            restrictions.push(Restriction::Coppa);
+        }
+        if self.dnt() {
+            restrictions.push(Restriction::Dnt);
+        }
+        if self.lmt() {
+            restrictions.push(Restriction::Lmt);
+        }
+        if self.gdpr() == Some(true) {
+            let consented = match self.tc_string() {
+                Some(Ok(tc)) => PERSONALIZATION_PURPOSES
+                    .iter()
+                    .all(|&p| tc.purpose_consent(p)),
+                _ => false,
+            };
+            if !consented {
+                restrictions.push(Restriction::Gdpr);
+            }
+        }
+        let usp_opt_out = matches!(
+            self.us_privacy(),
+            Some(Ok(UsPrivacy {
+                opt_out_sale: Some(true),
+                ..
+            })) | Some(Err(_))
+        );
+        let gpp_opt_out = matches!(self.gpp(), Some(Err(_)))
+            || !self
+                .us_sections()
+                .iter()
+                .all(|section| matches!(section, Ok(section) if !section.opted_out()));
+        if usp_opt_out || gpp_opt_out {
+            restrictions.push(Restriction::UsOptOut);
+        }
+        restrictions
+    }
+
+    /// Whether the request may be used for personalized advertising, i.e.
+    /// no signal restricts it; see `restrictions`.
+    pub fn can_personalize(&self) -> bool {
+        self.restrictions().is_empty()
+    }
+}
+
+/// A signal that restricts the use of personal data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Restriction {
+    /// `regs.coppa` is set.
+    Coppa,
+    /// `device.dnt` is set.
+    Dnt,
+    /// `device.lmt` is set.
+    Lmt,
+    /// GDPR applies and consent is missing.
+    Gdpr,
+    /// The user opted out under US privacy laws (CCPA and state laws).
+    UsOptOut,
+}
+
+/// How to treat the IP address fields.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IpScrub {
+    /// Leave the addresses as they are.
+    Keep,
+    /// Zero the bits beyond the prefix (/24 and /48 by default).
+    Truncate(IpPrefix),
+    /// Remove the addresses.
+    Remove,
+}
+
+impl Default for IpScrub {
+    fn default() -> IpScrub {
+        IpScrub::Truncate(IpPrefix::default())
+    }
+}
+
+/// What to scrub when a restriction applies.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScrubRules {
+    /// Remove `device.ifa` and the hashed device IDs (`didsha1`, `didmd5`,
+    /// `dpidsha1`, `dpidmd5`, `macsha1`, `macmd5`).
+    pub device_ids: bool,
+    /// Remove `user.id`, `user.buyeruid` and the EIDs (`user.eids` and
+    /// `user.ext.eids`).
+    pub user_ids: bool,
+    /// Remove `user.yob` and `user.gender`.
+    pub demographics: bool,
+    /// Remove `user.data`.
+    pub user_data: bool,
+    /// Treatment of `device.ip` and `device.ipv6`.
+    pub ip: IpScrub,
+    /// Round `lat`/`lon` of `device.geo` and `user.geo` to this many decimals.
+    pub geo_decimals: Option<u32>,
+    /// Remove `zip` and `city` of `device.geo` and `user.geo`.
+    pub geo_zip_city: bool,
+}
+
+impl ScrubRules {
+    /// Scrub everything this module knows about.
+    pub fn all() -> ScrubRules {
+        ScrubRules {
+            device_ids: true,
+            user_ids: true,
+            demographics: true,
+            user_data: true,
+            ip: IpScrub::default(),
+            geo_decimals: Some(2),
+            geo_zip_city: true,
+        }
+    }
+
+    /// Scrub nothing.
+    pub fn none() -> ScrubRules {
+        ScrubRules {
+            device_ids: false,
+            user_ids: false,
+            demographics: false,
+            user_data: false,
+            ip: IpScrub::Keep,
+            geo_decimals: None,
+            geo_zip_city: false,
+        }
+    }
+
+    /// The union of two rule sets: the stricter choice of each.
+    pub fn merge(&self, other: &ScrubRules) -> ScrubRules {
+        let ip = match (self.ip, other.ip) {
+            (IpScrub::Remove, _) | (_, IpScrub::Remove) => IpScrub::Remove,
+            (IpScrub::Truncate(a), IpScrub::Truncate(b)) => IpScrub::Truncate(a.min(b)),
+            (IpScrub::Truncate(p), _) | (_, IpScrub::Truncate(p)) => IpScrub::Truncate(p),
+            _ => IpScrub::Keep,
+        };
+        let geo_decimals = match (self.geo_decimals, other.geo_decimals) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        ScrubRules {
+            device_ids: self.device_ids || other.device_ids,
+            user_ids: self.user_ids || other.user_ids,
+            demographics: self.demographics || other.demographics,
+            user_data: self.user_data || other.user_data,
+            ip,
+            geo_decimals,
+            geo_zip_city: self.geo_zip_city || other.geo_zip_city,
+        }
+    }
+}
+
+/// Scrub rules per restriction. When several restrictions apply, their
+/// rules are merged. By default every restriction scrubs everything.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Policy {
+    /// Rules for `Restriction::Coppa`.
+    pub coppa: ScrubRules,
+    /// Rules for `Restriction::Dnt`.
+    pub dnt: ScrubRules,
+    /// Rules for `Restriction::Lmt`.
+    pub lmt: ScrubRules,
+    /// Rules for `Restriction::Gdpr`.
+    pub gdpr: ScrubRules,
+    /// Rules for `Restriction::UsOptOut`.
+    pub us_opt_out: ScrubRules,
+}
+
+impl Default for Policy {
+    fn default() -> Policy {
+        Policy {
+            coppa: ScrubRules::all(),
+            dnt: ScrubRules::all(),
+            lmt: ScrubRules::all(),
+            gdpr: ScrubRules::all(),
+            us_opt_out: ScrubRules::all(),
+        }
+    }
+}
+
+impl Policy {
+    /// The rules for a restriction.
+    pub fn rules(&self, restriction: Restriction) -> &ScrubRules {
+        match restriction {
+            Restriction::Coppa => &self.coppa,
+            Restriction::Dnt => &self.dnt,
+            Restriction::Lmt => &self.lmt,
+            Restriction::Gdpr => &self.gdpr,
+            Restriction::UsOptOut => &self.us_opt_out,
+        }
+    }
+}
+
+/// What was done to a field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// The field was cleared.
+    Removed,
+    /// An IP address was truncated to its prefix.
+    Truncated,
+    /// Coordinates were rounded to fewer decimals.
+    Rounded,
+}
+
+/// Audit record of a `scrub` call.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScrubReport {
+    /// Restrictions that applied.
+    pub restrictions: Vec<Restriction>,
+    /// Fields changed, by path (e.g. `device.ifa`), in the order changed.
+    /// Fields that were absent are not listed.
+    pub changes: Vec<(&'static str, Action)>,
+}
+
+impl ScrubReport {
+    /// Whether nothing was changed.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Scrubs `request` in place according to its own privacy signals and
+/// `policy`, and reports what was changed.
+pub fn scrub(request: &mut BidRequest, policy: &Policy) -> ScrubReport {
+    let restrictions = request.privacy_signals().restrictions();
+    let rules = restrictions
+        .iter()
+        .fold(ScrubRules::none(), |rules, &r| rules.merge(policy.rules(r)));
+    let mut report = ScrubReport {
+        restrictions,
+        changes: Vec::new(),
+    };
+    scrub_with(request, &rules, &mut report);
+    report
+}
+
+/// Scrubs `request` in place according to `rules`, regardless of its signals.
+pub fn scrub_with(request: &mut BidRequest, rules: &ScrubRules, report: &mut ScrubReport) {
+    if let Some(device) = &mut request.device {
+        if rules.device_ids {
+            remove(&mut device.ifa, "device.ifa", report);
+            remove(&mut device.didsha1, "device.didsha1", report);
+            remove(&mut device.didmd5, "device.didmd5", report);
+            remove(&mut device.dpidsha1, "device.dpidsha1", report);
+            remove(&mut device.dpidmd5, "device.dpidmd5", report);
+            remove(&mut device.macsha1, "device.macsha1", report);
+            remove(&mut device.macmd5, "device.macmd5", report);
+        }
+        match rules.ip {
+            IpScrub::Keep => {}
+            IpScrub::Truncate(prefix) => {
+                truncate_ip(&mut device.ip, prefix, false, "device.ip", report);
+                truncate_ip(&mut device.ipv6, prefix, true, "device.ipv6", report);
+            }
+            IpScrub::Remove => {
+                remove(&mut device.ip, "device.ip", report);
+                remove(&mut device.ipv6, "device.ipv6", report);
+            }
+        }
+        if let Some(geo) = &mut device.geo {
+            scrub_geo(
+                geo,
+                rules,
+                [
+                    "device.geo.lat",
+                    "device.geo.lon",
+                    "device.geo.zip",
+                    "device.geo.city",
+                ],
+                report,
+            );
+        }
+    }
+    if let Some(user) = &mut request.user {
+        if rules.user_ids {
+            remove(&mut user.id, "user.id", report);
+            remove(&mut user.buyeruid, "user.buyeruid", report);
+            if user.remove_eids() {
+                report.changes.push(("user.eids", Action::Removed));
+            }
+        }
+        if rules.demographics {
+            remove(&mut user.yob, "user.yob", report);
+            remove(&mut user.gender, "user.gender", report);
+        }
+        if rules.user_data {
+            remove(&mut user.data, "user.data", report);
+        }
+        if let Some(geo) = &mut user.geo {
+            scrub_geo(
+                geo,
+                rules,
+                [
+                    "user.geo.lat",
+                    "user.geo.lon",
+                    "user.geo.zip",
+                    "user.geo.city",
+                ],
+                report,
+            );
+        }
+    }
+}
+
+fn scrub_geo(
+    geo: &mut Geo,
+    rules: &ScrubRules,
+    paths: [&'static str; 4],
+    report: &mut ScrubReport,
+) {
+    if let Some(decimals) = rules.geo_decimals {
+        round(&mut geo.lat, decimals, paths[0], report);
+        round(&mut geo.lon, decimals, paths[1], report);
+    }
+    if rules.geo_zip_city {
+        remove(&mut geo.zip, paths[2], report);
+        remove(&mut geo.city, paths[3], report);
+    }
+}
+
+fn remove<T>(field: &mut Option<T>, path: &'static str, report: &mut ScrubReport) {
+    if field.take().is_some() {
+        report.changes.push((path, Action::Removed));
+    }
+}
+
+fn round(field: &mut Option<f64>, decimals: u32, path: &'static str, report: &mut ScrubReport) {
+    if let Some(v) = field {
+        let scale = 10f64.powi(decimals as i32);
+        let rounded = (*v * scale).round() / scale;
+        if rounded != *v {
+            *v = rounded;
+            report.changes.push((path, Action::Rounded));
+        }
+    }
+}
+
+/// Truncates an IP address of the field's family, IPv6 if `v6`. Anything
+/// else is removed: an IPv4 address in `device.ipv6` would otherwise be cut
+/// to the IPv6 prefix, which keeps all of it.
+fn truncate_ip(
+    field: &mut Option<String>,
+    prefix: IpPrefix,
+    v6: bool,
+    path: &'static str,
+    report: &mut ScrubReport,
+) {
+    let ip = match field.as_deref() {
+        Some(ip) => ip,
+        None => return,
+    };
+    let truncated = match ip.parse::<IpAddr>() {
+        Ok(addr) if addr.is_ipv6() == v6 => prefix.apply(addr).to_string(),
+        _ => return remove(field, path, report),
+    };
+    if truncated != ip {
+        *field = Some(truncated);
+        report.changes.push((path, Action::Truncated));
+    }
+}
+
+impl BidRequest {
+    /// The privacy signals of this request.
+    pub fn privacy_signals(&self) -> PrivacySignals<'_> {
+        PrivacySignals::new(self.regs.as_ref(), self.user.as_ref(), self.device.as_ref())
+    }
+
+    /// Whether the request may be used for personalized advertising; see
+    /// `PrivacySignals::can_personalize`.
+    pub fn can_personalize(&self) -> bool {
+        self.privacy_signals().can_personalize()
+    }
+
+    /// Scrubs this request in place; see `privacy::scrub`.
+    pub fn scrub(&mut self, policy: &Policy) -> ScrubReport {
+        scrub(self, policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The TCF EU section of the GPP specification examples, which consents
+    /// to no purpose.
+    const TCF: &str = "CPXxRfAPXxRfAAfKABENB-CgAAAAAAAAAAYgAAAAAAAA";
+
+    fn request(regs: &str) -> BidRequest {
+        serde_json::from_str(&format!(r#"{{"id":"1","imp":[],"regs":{}}}"#, regs)).unwrap()
+    }
+
+    fn restrictions(regs: &str) -> Vec<Restriction> {
+        request(regs).privacy_signals().restrictions()
+    }
+
+    #[test]
+    fn no_signals_allow_personalization() {
+        assert!(BidRequest::default().can_personalize());
+        assert_eq!(
+            restrictions(r#"{"ext":{"gdpr":0,"us_privacy":"1YNN"}}"#),
+            []
+        );
+    }
+
+    #[test]
+    fn lists_restrictions_in_order() {
+        let json = r#"{"id":"1","imp":[],"regs":{"coppa":1,"ext":{"gdpr":1,"us_privacy":"1YYN"}},
+            "device":{"dnt":1,"lmt":1}}"#;
+        let req: BidRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            req.privacy_signals().restrictions(),
+            [
+                Restriction::Coppa,
+                Restriction::Dnt,
+                Restriction::Lmt,
+                Restriction::Gdpr,
+                Restriction::UsOptOut
+            ]
+        );
+    }
+
+    #[test]
+    fn gdpr_needs_consent_from_user_or_gpp() {
+        assert_eq!(restrictions(r#"{"ext":{"gdpr":1}}"#), [Restriction::Gdpr]);
+        let gpp = format!(r#"{{"ext":{{"gdpr":1,"gpp":"DBABMA~{}"}}}}"#, TCF);
+        assert_eq!(restrictions(&gpp), [Restriction::Gdpr]);
+        assert!(request(&gpp).privacy_signals().tc_string().unwrap().is_ok());
+    }
+
+    #[test]
+    fn us_privacy_opt_out() {
+        assert_eq!(
+            restrictions(r#"{"ext":{"us_privacy":"1YYN"}}"#),
+            [Restriction::UsOptOut]
+        );
+        assert_eq!(
+            restrictions(r#"{"ext":{"us_privacy":"bad"}}"#),
+            [Restriction::UsOptOut]
+        );
+    }
+
+    #[test]
+    fn gpp_us_privacy_section() {
+        let opted_out = format!(r#"{{"ext":{{"gpp":"DBACNYA~{}~1YYN"}}}}"#, TCF);
+        assert_eq!(restrictions(&opted_out), [Restriction::UsOptOut]);
+        let not_opted_out = format!(r#"{{"ext":{{"gpp":"DBACNYA~{}~1YNN"}}}}"#, TCF);
+        assert_eq!(restrictions(&not_opted_out), []);
+        // Only sections listed in gpp_sid apply.
+        let not_listed = format!(
+            r#"{{"ext":{{"gpp":"DBACNYA~{}~1YYN","gpp_sid":[2]}}}}"#,
+            TCF
+        );
+        assert_eq!(restrictions(&not_listed), []);
+        // The string in regs.ext.us_privacy takes precedence.
+        let both = format!(
+            r#"{{"ext":{{"gpp":"DBACNYA~{}~1YYN","us_privacy":"1YNN"}}}}"#,
+            TCF
+        );
+        assert_eq!(restrictions(&both), []);
+    }
+
+    #[test]
+    fn gpp_us_national_section() {
+        assert_eq!(
+            restrictions(r#"{"ext":{"gpp":"DBABLA~BVQqAAAAAgA.QA"}}"#),
+            []
+        );
+        // Global Privacy Control set.
+        let gpc = r#"{"ext":{"gpp":"DBABLA~BVQqAAAAAgA.YA","gpp_sid":[7]}}"#;
+        assert_eq!(restrictions(gpc), [Restriction::UsOptOut]);
+        let not_listed = r#"{"ext":{"gpp":"DBABLA~BVQqAAAAAgA.YA","gpp_sid":[8]}}"#;
+        assert_eq!(restrictions(not_listed), []);
+        assert_eq!(
+            restrictions(r#"{"ext":{"gpp":"DBABLA"}}"#),
+            [Restriction::UsOptOut]
+        );
+    }
+
+    const PERSONAL: &str = r#"{"id":"1","imp":[],"regs":{"coppa":1},
+        "device":{"lmt":1,"ifa":"a","didmd5":"b","ip":"192.0.2.77","ipv6":"2001:db8:1:2:3::1",
+            "geo":{"lat":52.123456,"lon":13.4,"zip":"10115","city":"Berlin"}},
+        "user":{"id":"u","buyeruid":"b","yob":1990,"gender":"F","data":[{"id":"d"}],
+            "eids":[{"source":"x.com","uids":[{"id":"1"}]}]}}"#;
+
+    #[test]
+    fn scrubs_everything_by_default() {
+        let mut req: BidRequest = serde_json::from_str(PERSONAL).unwrap();
+        let report = req.scrub(&Policy::default());
+        assert_eq!(report.restrictions, [Restriction::Coppa, Restriction::Lmt]);
+        assert_eq!(
+            report.changes,
+            [
+                ("device.ifa", Action::Removed),
+                ("device.didmd5", Action::Removed),
+                ("device.ip", Action::Truncated),
+                ("device.ipv6", Action::Truncated),
+                ("device.geo.lat", Action::Rounded),
+                ("device.geo.zip", Action::Removed),
+                ("device.geo.city", Action::Removed),
+                ("user.id", Action::Removed),
+                ("user.buyeruid", Action::Removed),
+                ("user.eids", Action::Removed),
+                ("user.yob", Action::Removed),
+                ("user.gender", Action::Removed),
+                ("user.data", Action::Removed),
+            ]
+        );
+        let device = req.device.as_ref().unwrap();
+        assert_eq!(device.ip.as_deref(), Some("192.0.2.0"));
+        assert_eq!(device.ipv6.as_deref(), Some("2001:db8:1::"));
+        let geo = device.geo.as_ref().unwrap();
+        assert_eq!((geo.lat, geo.lon), (Some(52.12), Some(13.4)));
+        let user = req.user.as_ref().unwrap();
+        assert!(user.id.is_none() && user.eids.is_none() && user.data.is_none());
+    }
+
+    #[test]
+    fn merges_rules_of_each_restriction() {
+        let mut req: BidRequest = serde_json::from_str(PERSONAL).unwrap();
+        let policy = Policy {
+            coppa: ScrubRules {
+                ip: IpScrub::Remove,
+                ..ScrubRules::none()
+            },
+            lmt: ScrubRules {
+                device_ids: true,
+                geo_decimals: Some(1),
+                ..ScrubRules::none()
+            },
+            ..Policy::default()
+        };
+        let report = req.scrub(&policy);
+        assert_eq!(
+            report.changes,
+            [
+                ("device.ifa", Action::Removed),
+                ("device.didmd5", Action::Removed),
+                ("device.ip", Action::Removed),
+                ("device.ipv6", Action::Removed),
+                ("device.geo.lat", Action::Rounded),
+            ]
+        );
+        assert_eq!(req.device.unwrap().geo.unwrap().lat, Some(52.1));
+        assert!(req.user.unwrap().id.is_some());
+    }
+
+    #[test]
+    fn merge_takes_the_stricter_choice() {
+        let a = ScrubRules {
+            ip: IpScrub::Truncate(IpPrefix { v4: 16, v6: 64 }),
+            geo_decimals: Some(3),
+            ..ScrubRules::none()
+        };
+        let b = ScrubRules {
+            ip: IpScrub::Truncate(IpPrefix { v4: 24, v6: 32 }),
+            geo_decimals: None,
+            user_ids: true,
+            ..ScrubRules::none()
+        };
+        let merged = a.merge(&b);
+        assert_eq!(merged.ip, IpScrub::Truncate(IpPrefix { v4: 16, v6: 32 }));
+        assert_eq!(merged.geo_decimals, Some(3));
+        assert!(merged.user_ids && !merged.device_ids);
+        assert_eq!(
+            a.merge(&ScrubRules::all()).ip,
+            IpScrub::Truncate(IpPrefix { v4: 16, v6: 48 })
+        );
+        assert_eq!(
+            ScrubRules::none().merge(&ScrubRules::none()),
+            ScrubRules::none()
+        );
+    }
+
+    #[test]
+    fn leaves_unrestricted_requests_alone() {
+        let json = r#"{"id":"1","imp":[],"device":{"ip":"192.0.2.77","ifa":"a"}}"#;
+        let mut req: BidRequest = serde_json::from_str(json).unwrap();
+        let report = req.scrub(&Policy::default());
+        assert!(report.is_empty());
+        assert!(report.restrictions.is_empty());
+        assert_eq!(req.device.unwrap().ip.as_deref(), Some("192.0.2.77"));
+    }
+
+    #[test]
+    fn removes_unparsable_addresses() {
+        let json = r#"{"id":"1","imp":[],"device":{"ip":"not an ip","ipv6":"2001:db8::1"}}"#;
+        let mut req: BidRequest = serde_json::from_str(json).unwrap();
+        let mut report = ScrubReport::default();
+        scrub_with(&mut req, &ScrubRules::all(), &mut report);
+        assert_eq!(
+            report.changes,
+            [
+                ("device.ip", Action::Removed),
+                ("device.ipv6", Action::Truncated)
+            ]
+        );
+        assert_eq!(req.device.unwrap().ipv6.as_deref(), Some("2001:db8::"));
+    }
+
+    #[test]
+    fn removes_addresses_of_the_other_family() {
+        let json = r#"{"id":"1","imp":[],"device":{"ip":"2001:db8::1","ipv6":"192.0.2.1"}}"#;
+        let mut req: BidRequest = serde_json::from_str(json).unwrap();
+        let mut report = ScrubReport::default();
+        scrub_with(&mut req, &ScrubRules::all(), &mut report);
+        assert_eq!(
+            report.changes,
+            [
+                ("device.ip", Action::Removed),
+                ("device.ipv6", Action::Removed)
+            ]
+        );
+        let device = req.device.unwrap();
+        assert!(device.ip.is_none() && device.ipv6.is_none());
+    }
+}
@@ -0,0 +1,395 @@
+//! Structural scan of a JSON document.
+//!
+//! The scanner walks the input once, tracking the path of the current value,
+//! and reports each value to a visitor. It checks the structure and the
+//! shape of each token, but not the contents of strings or the exact number
+//! grammar; full validation is left to serde. Values are not decoded, except
+//! object keys, which are copied only if they contain escape sequences.
+
+use std::borrow::Cow;
+use std::fmt::Write;
+
+/// One step in the path from the document root to a value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Segment<'a> {
+    /// Object member, with its decoded key.
+    Key(Cow<'a, str>),
+    /// Array element.
+    Index(usize),
+}
+
+/// A value (or part of one) reported to the visitor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Event {
+    /// Start of an object or array.
+    Open { array: bool },
+    /// End of an object or array, with its number of members.
+    Close { array: bool, len: usize },
+    /// Object key; the path already ends with it.
+    Key { len: usize },
+    /// String value, with its length in bytes excluding the quotes.
+    Str { len: usize },
+    /// Number, boolean or null.
+    Scalar,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ScanError<E> {
+    /// Malformed JSON at the given byte offset.
+    Syntax(usize),
+    /// The visitor stopped the scan.
+    Visit(E),
+}
+
+struct Frame {
+    array: bool,
+    len: usize,
+}
+
+struct Scanner<'a> {
+    input: &'a str,
+    pos: usize,
+    path: Vec<Segment<'a>>,
+    stack: Vec<Frame>,
+}
+
+/// Scans `input`, calling `visit` with the current path, the byte offset of
+/// the value (or of the closing bracket for `Close`) and the event.
+pub(crate) fn scan<'a, E, F>(input: &'a str, visit: F) -> Result<(), ScanError<E>>
+where
+    F: FnMut(&[Segment<'a>], usize, Event) -> Result<(), E>,
+{
+    Scanner {
+        input,
+        pos: 0,
+        path: Vec::new(),
+        stack: Vec::new(),
+    }
+    .run(visit)
+}
+
+impl<'a> Scanner<'a> {
+    fn run<E, F>(mut self, mut visit: F) -> Result<(), ScanError<E>>
+    where
+        F: FnMut(&[Segment<'a>], usize, Event) -> Result<(), E>,
+    {
+        let mut expect_value = true;
+        loop {
+            self.skip_ws();
+            let start = self.pos;
+            if expect_value {
+                match self.peek() {
+                    Some(b @ (b'{' | b'[')) => {
+                        let array = b == b'[';
+                        visit(&self.path, start, Event::Open { array })
+                            .map_err(ScanError::Visit)?;
+                        self.pos += 1;
+                        self.stack.push(Frame { array, len: 0 });
+                        self.skip_ws();
+                        if self.peek() == Some(if array { b']' } else { b'}' }) {
+                            self.stack.pop();
+                            visit(&self.path, self.pos, Event::Close { array, len: 0 })
+                                .map_err(ScanError::Visit)?;
+                            self.pos += 1;
+                            expect_value = false;
+                        } else if array {
+                            self.path.push(Segment::Index(0));
+                        } else {
+                            self.key(&mut visit)?;
+                        }
+                    }
+                    Some(b'"') => {
+                        let end = self.string_end()?;
+                        visit(
+                            &self.path,
+                            start,
+                            Event::Str {
+                                len: end - start - 2,
+                            },
+                        )
+                        .map_err(ScanError::Visit)?;
+                        self.pos = end;
+                        expect_value = false;
+                    }
+                    Some(_) => {
+                        self.pos = scalar_end(self.input.as_bytes(), start);
+                        if !is_scalar(&self.input.as_bytes()[start..self.pos]) {
+                            return Err(ScanError::Syntax(start));
+                        }
+                        visit(&self.path, start, Event::Scalar).map_err(ScanError::Visit)?;
+                        expect_value = false;
+                    }
+                    None => return Err(ScanError::Syntax(start)),
+                }
+                continue;
+            }
+
+            let (array, len) = match self.stack.last_mut() {
+                Some(frame) => {
+                    frame.len += 1;
+                    (frame.array, frame.len)
+                }
+                None if self.pos == self.input.len() => return Ok(()),
+                None => return Err(ScanError::Syntax(self.pos)),
+            };
+            self.path.pop();
+            match (self.peek(), array) {
+                (Some(b','), true) => {
+                    self.pos += 1;
+                    self.path.push(Segment::Index(len));
+                    expect_value = true;
+                }
+                (Some(b','), false) => {
+                    self.pos += 1;
+                    self.key(&mut visit)?;
+                    expect_value = true;
+                }
+                (Some(b']'), true) | (Some(b'}'), false) => {
+                    self.stack.pop();
+                    visit(&self.path, self.pos, Event::Close { array, len })
+                        .map_err(ScanError::Visit)?;
+                    self.pos += 1;
+                }
+                _ => return Err(ScanError::Syntax(self.pos)),
+            }
+        }
+    }
+
+    /// Reads `"key":` and pushes the key onto the path.
+    fn key<E, F>(&mut self, visit: &mut F) -> Result<(), ScanError<E>>
+    where
+        F: FnMut(&[Segment<'a>], usize, Event) -> Result<(), E>,
+    {
+        self.skip_ws();
+        let start = self.pos;
+        if self.peek() != Some(b'"') {
+            return Err(ScanError::Syntax(start));
+        }
+        let end = self.string_end()?;
+        let raw = &self.input[start + 1..end - 1];
+        let key = if raw.contains('\\') {
+            let decoded = serde_json::from_str(&self.input[start..end])
+                .map_err(|_| ScanError::Syntax(start))?;
+            Cow::Owned(decoded)
+        } else {
+            Cow::Borrowed(raw)
+        };
+        self.path.push(Segment::Key(key));
+        visit(
+            &self.path,
+            start,
+            Event::Key {
+                len: end - start - 2,
+            },
+        )
+        .map_err(ScanError::Visit)?;
+        self.pos = end;
+        self.skip_ws();
+        if self.peek() != Some(b':') {
+            return Err(ScanError::Syntax(self.pos));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Offset just past the closing quote of the string starting at `pos`.
+    fn string_end<E>(&self) -> Result<usize, ScanError<E>> {
+        let bytes = self.input.as_bytes();
+        let mut i = self.pos + 1;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'"' => return Ok(i + 1),
+                b'\\' => i += 2,
+                _ => i += 1,
+            }
+        }
+        Err(ScanError::Syntax(self.pos))
+    }
+
+    fn skip_ws(&mut self) {
+        let bytes = self.input.as_bytes();
+        while matches!(bytes.get(self.pos), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+}
+
+/// Offset just past the number or literal starting at `start`.
+pub(crate) fn scalar_end(bytes: &[u8], start: usize) -> usize {
+    let mut i = start;
+    while i < bytes.len()
+        && (bytes[i].is_ascii_alphanumeric() || matches!(bytes[i], b'+' | b'-' | b'.'))
+    {
+        i += 1;
+    }
+    i
+}
+
+/// Whether `token` is a literal or looks like a number.
+fn is_scalar(token: &[u8]) -> bool {
+    match token {
+        b"true" | b"false" | b"null" => true,
+        [b'-' | b'0'..=b'9', rest @ ..] => rest
+            .iter()
+            .all(|b| b.is_ascii_digit() || matches!(b, b'.' | b'e' | b'E' | b'+' | b'-')),
+        _ => false,
+    }
+}
+
+/// Formats a path as `imp[2].video.protocols[1]`.
+pub(crate) fn format_path(path: &[Segment]) -> String {
+    let mut s = String::new();
+    for segment in path {
+        match segment {
+            Segment::Key(k) if s.is_empty() => s.push_str(k),
+            Segment::Key(k) => {
+                s.push('.');
+                s.push_str(k);
+            }
+            Segment::Index(i) => {
+                let _ = write!(s, "[{}]", i);
+            }
+        }
+    }
+    s
+}
+
+/// The object keys along a path, skipping array indices.
+pub(crate) fn keys<'p>(path: &'p [Segment]) -> impl DoubleEndedIterator<Item = &'p str> + 'p {
+    path.iter().filter_map(|segment| match segment {
+        Segment::Key(k) => Some(&**k),
+        Segment::Index(_) => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(input: &str) -> Result<Vec<(String, usize, Event)>, ScanError<()>> {
+        let mut events = Vec::new();
+        scan(input, |path, start, event| {
+            events.push((format_path(path), start, event));
+            Ok(())
+        })?;
+        Ok(events)
+    }
+
+    #[test]
+    fn reports_paths_and_offsets() {
+        let events = events(r#"{"a": [1, "xy", {}], "b\"c": null}"#).unwrap();
+        let expected = [
+            ("", 0, Event::Open { array: false }),
+            ("a", 1, Event::Key { len: 1 }),
+            ("a", 6, Event::Open { array: true }),
+            ("a[0]", 7, Event::Scalar),
+            ("a[1]", 10, Event::Str { len: 2 }),
+            ("a[2]", 16, Event::Open { array: false }),
+            (
+                "a[2]",
+                17,
+                Event::Close {
+                    array: false,
+                    len: 0,
+                },
+            ),
+            (
+                "a",
+                18,
+                Event::Close {
+                    array: true,
+                    len: 3,
+                },
+            ),
+            ("b\"c", 21, Event::Key { len: 4 }),
+            ("b\"c", 29, Event::Scalar),
+            (
+                "",
+                33,
+                Event::Close {
+                    array: false,
+                    len: 2,
+                },
+            ),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(p, s, e)| (p.to_string(), *s, *e))
+            .collect();
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for (input, offset) in [
+            ("", 0),
+            ("{", 1),
+            ("[1,]", 3),
+            ("[1 2]", 3),
+            (r#"{"a" 1}"#, 5),
+            (r#"{"a":1,}"#, 7),
+            (r#"{"a":tru}"#, 5),
+            (r#"{"a":x1}"#, 5),
+            (r#"["open]"#, 1),
+            (r#"{"a\x":1}"#, 1),
+            ("[1]]", 3),
+            ("{}{}", 2),
+            ("[}", 1),
+        ] {
+            assert_eq!(
+                events(input).err(),
+                Some(ScanError::Syntax(offset)),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn visitor_stops_scan() {
+        let result = scan("[1,2,3]", |path, _, _| match path {
+            [Segment::Index(1)] => Err("stop"),
+            _ => Ok(()),
+        });
+        assert_eq!(result, Err(ScanError::Visit("stop")));
+    }
+
+    #[test]
+    fn keys_skip_indices() {
+        let path = [
+            Segment::Key("imp".into()),
+            Segment::Index(2),
+            Segment::Key("video".into()),
+        ];
+        assert_eq!(keys(&path).collect::<Vec<_>>(), ["imp", "video"]);
+        assert_eq!(format_path(&path), "imp[2].video");
+    }
+
+    #[test]
+    fn decodes_escaped_keys() {
+        let mut keys = Vec::new();
+        scan(
+            r#"{"im\u0070":[], "a\/b": 1, "\ud83d\ude00": 2, "ext": 3}"#,
+            |path, _, event| {
+                if let (Event::Key { .. }, [Segment::Key(key)]) = (event, path) {
+                    keys.push((key.to_string(), matches!(key, Cow::Owned(_))));
+                }
+                Ok::<_, ()>(())
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            keys,
+            [
+                ("imp".to_string(), true),
+                ("a/b".to_string(), true),
+                ("\u{1f600}".to_string(), true),
+                ("ext".to_string(), false),
+            ]
+        );
+    }
+}
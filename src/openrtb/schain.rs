@@ -0,0 +1,363 @@
+//! Supply chain (schain).
+//!
+//! The SupplyChain object lists every entity that sold or resold a bid
+//! request, in order, starting with the originating seller. OpenRTB 2.6
+//! carries it in `Source.schain`; 2.5 carries it in `source.ext.schain`.
+//! `Source::schain_or_ext` reads either, and `Source::append_schain_node`
+//! adds our own hop when we pass a request on.
+
+use super::bid_request::Source;
+use super::bool::Bool;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The SupplyChain specification version supported here.
+pub const SCHAIN_VERSION: &str = "1.0";
+
+/// OpenRTB 2.6: The links in the supply chain and whether it is complete.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SupplyChain {
+    /// Flag indicating whether the chain contains all nodes involved in the
+    /// transaction leading back to the owner of the site, app or other
+    /// medium of the inventory, where 0 = no, 1 = yes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub complete: Option<Bool>,
+
+    /// Array of SupplyChainNode objects in the order of the chain. In a
+    /// complete supply chain, the first node represents the initial
+    /// advertising system and seller ID involved in the transaction.
+    #[serde(default)]
+    pub nodes: Vec<SupplyChainNode>,
+
+    /// Version of the supply chain specification in use, in the format of
+    /// "major.minor".
+    #[serde(default)]
+    pub ver: String,
+
+    /// Extensions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ext: Option<Value>,
+}
+
+/// OpenRTB 2.6: The identity of an entity participating in the supply chain.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SupplyChainNode {
+    /// The canonical domain name of the SSP, exchange, header wrapper, etc.
+    /// system that bidders connect to. This should be the same value as used
+    /// to identify sellers in an ads.txt file if one exists.
+    #[serde(default)]
+    pub asi: String,
+
+    /// The identifier associated with the seller or reseller account within
+    /// the advertising system. This must contain the same value used in
+    /// transactions (i.e. OpenRTB bid requests) in the field specified by
+    /// the SSP/exchange.
+    #[serde(default)]
+    pub sid: String,
+
+    /// The OpenRTB RequestId of the request as issued by this seller.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rid: Option<String>,
+
+    /// The name of the company (the legal entity) that is paid for inventory
+    /// transacted under the given seller_id.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// The business domain name of the entity represented by this node.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+
+    /// Indicates whether this node will be involved in the flow of payment
+    /// for the inventory, where 0 = no, 1 = yes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hp: Option<Bool>,
+
+    /// Extensions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ext: Option<Value>,
+}
+
+/// A problem found by `SupplyChain::validate`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SchainProblem {
+    /// `complete` is missing.
+    MissingComplete,
+    /// `ver` is not a supported version.
+    UnsupportedVersion(String),
+    /// `nodes` is empty.
+    NoNodes,
+    /// `asi` is empty.
+    MissingAsi,
+    /// `asi` is not a bare domain name, e.g. it has a scheme or a path.
+    InvalidAsi,
+    /// `sid` is empty.
+    MissingSid,
+    /// `hp` is missing.
+    MissingHp,
+    /// The same `asi` and `sid` appear at an earlier node.
+    DuplicateNode,
+}
+
+/// A problem with the chain (`node` is `None`) or with one of its nodes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchainIssue {
+    /// Index of the node in `nodes`.
+    pub node: Option<usize>,
+    /// What is wrong.
+    pub problem: SchainProblem,
+}
+
+impl SupplyChainNode {
+    /// A node for seller account `sid` on advertising system `asi` that takes
+    /// part in the flow of payment.
+    pub fn new(asi: &str, sid: &str) -> SupplyChainNode {
+        SupplyChainNode {
+            asi: asi.to_string(),
+            sid: sid.to_string(),
+            hp: Some(Bool::True),
+            ..SupplyChainNode::default()
+        }
+    }
+}
+
+impl SupplyChain {
+    /// An empty chain of the supported version.
+    pub fn new(complete: bool) -> SupplyChain {
+        SupplyChain {
+            complete: Some(if complete { Bool::True } else { Bool::False }),
+            nodes: Vec::new(),
+            ver: SCHAIN_VERSION.to_string(),
+            ext: None,
+        }
+    }
+
+    /// Whether `complete` is 1.
+    pub fn is_complete(&self) -> bool {
+        self.complete == Some(Bool::True)
+    }
+
+    /// Checks the chain against the SupplyChain specification. A valid
+    /// incomplete chain yields no issues; use `is_complete` to require a
+    /// complete one.
+    pub fn validate(&self) -> Vec<SchainIssue> {
+        let mut issues = Vec::new();
+        let mut chain = |problem| {
+            issues.push(SchainIssue {
+                node: None,
+                problem,
+            })
+        };
+        if self.complete.is_none() {
+            chain(SchainProblem::MissingComplete);
+        }
+        if self.ver != SCHAIN_VERSION {
+            chain(SchainProblem::UnsupportedVersion(self.ver.clone()));
+        }
+        if self.nodes.is_empty() {
+            chain(SchainProblem::NoNodes);
+        }
+        for (i, node) in self.nodes.iter().enumerate() {
+            let mut problems = Vec::new();
+            if node.asi.is_empty() {
+                problems.push(SchainProblem::MissingAsi);
+            } else if !is_domain(&node.asi) {
+                problems.push(SchainProblem::InvalidAsi);
+            }
+            if node.sid.is_empty() {
+                problems.push(SchainProblem::MissingSid);
+            }
+            if node.hp.is_none() {
+                problems.push(SchainProblem::MissingHp);
+            }
+            if self.nodes[..i]
+                .iter()
+                .any(|n| n.asi.eq_ignore_ascii_case(&node.asi) && n.sid == node.sid)
+            {
+                problems.push(SchainProblem::DuplicateNode);
+            }
+            issues.extend(problems.into_iter().map(|problem| SchainIssue {
+                node: Some(i),
+                problem,
+            }));
+        }
+        issues
+    }
+}
+
+/// Whether `s` is a bare domain name such as "exchange.com".
+fn is_domain(s: &str) -> bool {
+    s.contains('.')
+        && s.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
+}
+
+impl Source {
+    /// The supply chain from `schain`, or from `ext.schain` when the field
+    /// is absent.
+    pub fn schain_or_ext(&self) -> Option<serde_json::Result<SupplyChain>> {
+        if let Some(schain) = &self.schain {
+            return Some(Ok(schain.clone()));
+        }
+        let schain = self.ext.as_ref()?.get("schain")?;
+        Some(SupplyChain::deserialize(schain))
+    }
+
+    /// Stores `schain` in `ext.schain`, the OpenRTB 2.5 location.
+    pub fn set_ext_schain(&mut self, schain: &SupplyChain) -> serde_json::Result<()> {
+        let schain = serde_json::to_value(schain)?;
+        match self
+            .ext
+            .get_or_insert_with(|| Value::Object(Default::default()))
+        {
+            Value::Object(ext) => {
+                ext.insert("schain".to_string(), schain);
+            }
+            ext => *ext = serde_json::json!({ "schain": schain }),
+        }
+        Ok(())
+    }
+
+    /// Appends `node` to the supply chain, writing it back where it was
+    /// found. Without an upstream chain, a new incomplete chain holding only
+    /// `node` is stored in `ext.schain`, since a reseller cannot vouch for
+    /// the hops before it.
+    pub fn append_schain_node(&mut self, node: SupplyChainNode) -> serde_json::Result<()> {
+        if let Some(schain) = &mut self.schain {
+            schain.nodes.push(node);
+            return Ok(());
+        }
+        let mut schain = match self.schain_or_ext() {
+            Some(schain) => schain?,
+            None => SupplyChain::new(false),
+        };
+        schain.nodes.push(node);
+        self.set_ext_schain(&schain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::BidRequest;
+    use super::*;
+
+    fn issue(node: Option<usize>, problem: SchainProblem) -> SchainIssue {
+        SchainIssue { node, problem }
+    }
+
+    #[test]
+    fn validates_nodes() {
+        let request: BidRequest = serde_json::from_str(
+            r#"{"id":"1","imp":[],"source":{"ext":{"schain":{"complete":1,"ver":"1.0","nodes":[
+                {"asi":"ssp.com","sid":"42","hp":1},
+                {"asi":"http://x","sid":"","hp":1},
+                {"asi":"SSP.com","sid":"42"}]}}}}"#,
+        )
+        .unwrap();
+        let schain = request.source.unwrap().schain_or_ext().unwrap().unwrap();
+        assert!(schain.is_complete());
+        assert_eq!(
+            schain.validate(),
+            vec![
+                issue(Some(1), SchainProblem::InvalidAsi),
+                issue(Some(1), SchainProblem::MissingSid),
+                issue(Some(2), SchainProblem::MissingHp),
+                issue(Some(2), SchainProblem::DuplicateNode),
+            ]
+        );
+
+        let empty = SupplyChain {
+            complete: None,
+            ver: "2.0".to_string(),
+            ..SupplyChain::new(true)
+        };
+        assert_eq!(
+            empty.validate(),
+            vec![
+                issue(None, SchainProblem::MissingComplete),
+                issue(None, SchainProblem::UnsupportedVersion("2.0".to_string())),
+                issue(None, SchainProblem::NoNodes),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_missing_fields_instead_of_failing_to_decode() {
+        let request: BidRequest =
+            serde_json::from_str(r#"{"id":"1","imp":[],"source":{"schain":{"complete":1}}}"#)
+                .unwrap();
+        let schain = request.source.unwrap().schain_or_ext().unwrap().unwrap();
+        assert_eq!(
+            schain.validate(),
+            vec![
+                issue(None, SchainProblem::UnsupportedVersion(String::new())),
+                issue(None, SchainProblem::NoNodes),
+            ]
+        );
+
+        let request: BidRequest = serde_json::from_str(
+            r#"{"id":"1","imp":[],"source":{"schain":{"complete":1,"ver":"1.0","nodes":[
+                {"hp":1}]}}}"#,
+        )
+        .unwrap();
+        let schain = request.source.unwrap().schain_or_ext().unwrap().unwrap();
+        assert_eq!(
+            schain.validate(),
+            vec![
+                issue(Some(0), SchainProblem::MissingAsi),
+                issue(Some(0), SchainProblem::MissingSid),
+            ]
+        );
+    }
+
+    #[test]
+    fn appends_where_the_chain_was_found() {
+        let mut request: BidRequest = serde_json::from_str(
+            r#"{"id":"1","imp":[],"source":{"ext":{"schain":
+                {"complete":1,"ver":"1.0","nodes":[{"asi":"ssp.com","sid":"42","hp":1}]}}}}"#,
+        )
+        .unwrap();
+        let source = request.source.as_mut().unwrap();
+        source
+            .append_schain_node(SupplyChainNode::new("us.com", "7"))
+            .unwrap();
+        assert!(source.schain.is_none());
+        let schain = source.schain_or_ext().unwrap().unwrap();
+        assert_eq!(schain.nodes.len(), 2);
+        assert!(schain.is_complete());
+        let json = serde_json::to_string(&request).unwrap();
+        assert!(
+            json.contains(r#"{"asi":"us.com","hp":1,"sid":"7"}"#),
+            "{}",
+            json
+        );
+
+        let mut request: BidRequest = serde_json::from_str(
+            r#"{"id":"1","imp":[],"source":{"schain":{"complete":0,"ver":"1.0","nodes":[]}}}"#,
+        )
+        .unwrap();
+        let source = request.source.as_mut().unwrap();
+        source
+            .append_schain_node(SupplyChainNode::new("us.com", "7"))
+            .unwrap();
+        assert_eq!(source.schain.as_ref().unwrap().nodes.len(), 1);
+        assert!(source.ext.is_none());
+    }
+
+    #[test]
+    fn starts_an_incomplete_chain() {
+        let mut source = Source::default();
+        source
+            .append_schain_node(SupplyChainNode::new("us.com", "7"))
+            .unwrap();
+        let schain = source.schain_or_ext().unwrap().unwrap();
+        assert!(!schain.is_complete());
+        assert!(schain.validate().is_empty());
+    }
+}
@@ -0,0 +1,297 @@
+//! Audience and contextual segments.
+//!
+//! `User.data` and `Content.data` list data providers, each with segments.
+//! `SegmentIndex` indexes the segments of a request by provider and segment
+//! ID so that targeting rules of the form "provider X has segment Y" can be
+//! checked without walking the request each time. The `segtax` extension of
+//! a Data object names the taxonomy the segment IDs come from.
+
+use super::bid_request::data::Segment;
+use super::bid_request::Data;
+use super::BidRequest;
+use std::collections::HashMap;
+
+/// IAB Tech Lab taxonomies identified by `Data.ext.segtax`. Values of 500
+/// and above are vendor-specific.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(i32)]
+pub enum Taxonomy {
+    /// IAB Tech Lab Content Category Taxonomy 1.0.
+    ContentCategory1_0 = 1,
+    /// IAB Tech Lab Content Category Taxonomy 2.0.
+    ContentCategory2_0 = 2,
+    /// IAB Tech Lab Ad Product Taxonomy 1.0.
+    AdProduct1_0 = 3,
+    /// IAB Tech Lab Audience Taxonomy 1.1.
+    Audience1_1 = 4,
+    /// IAB Tech Lab Content Taxonomy 2.1.
+    Content2_1 = 5,
+    /// IAB Tech Lab Content Taxonomy 2.2.
+    Content2_2 = 6,
+    /// IAB Tech Lab Content Taxonomy 3.0.
+    Content3_0 = 7,
+}
+impl Taxonomy {
+    /// String value of the enum field names.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Taxonomy::ContentCategory1_0 => "CONTENT_CATEGORY_1_0",
+            Taxonomy::ContentCategory2_0 => "CONTENT_CATEGORY_2_0",
+            Taxonomy::AdProduct1_0 => "AD_PRODUCT_1_0",
+            Taxonomy::Audience1_1 => "AUDIENCE_1_1",
+            Taxonomy::Content2_1 => "CONTENT_2_1",
+            Taxonomy::Content2_2 => "CONTENT_2_2",
+            Taxonomy::Content3_0 => "CONTENT_3_0",
+        }
+    }
+
+    /// The taxonomy with `segtax` value `v`, if it is not vendor-specific.
+    pub fn from_i32(v: i32) -> Option<Taxonomy> {
+        match v {
+            1 => Some(Taxonomy::ContentCategory1_0),
+            2 => Some(Taxonomy::ContentCategory2_0),
+            3 => Some(Taxonomy::AdProduct1_0),
+            4 => Some(Taxonomy::Audience1_1),
+            5 => Some(Taxonomy::Content2_1),
+            6 => Some(Taxonomy::Content2_2),
+            7 => Some(Taxonomy::Content3_0),
+            _ => None,
+        }
+    }
+}
+
+impl Data {
+    /// The taxonomy of the segment IDs from `ext.segtax`.
+    pub fn segtax(&self) -> Option<i32> {
+        let segtax = self.ext.as_ref()?.get("segtax")?.as_i64()?;
+        i32::try_from(segtax).ok()
+    }
+
+    /// The provider key used by `SegmentIndex`: `id`, or `name` when there
+    /// is no ID.
+    pub fn provider(&self) -> Option<&str> {
+        self.id.as_deref().or(self.name.as_deref())
+    }
+}
+
+/// Where a Data object was found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DataOrigin {
+    /// `user.data`: audience segments.
+    User,
+    /// `site.content.data` or `app.content.data`: contextual segments.
+    Content,
+}
+
+/// A segment of the index with the Data object it belongs to.
+#[derive(Clone, Copy)]
+pub struct IndexedSegment<'a> {
+    /// Where `data` was found.
+    pub origin: DataOrigin,
+    /// The Data object holding the segment.
+    pub data: &'a Data,
+    /// The segment itself.
+    pub segment: &'a Segment,
+}
+
+/// Segments of a request by provider and segment ID. Segments without an
+/// ID are not indexed.
+#[derive(Clone, Default)]
+pub struct SegmentIndex<'a> {
+    providers: HashMap<&'a str, HashMap<&'a str, Vec<IndexedSegment<'a>>>>,
+}
+
+impl<'a> SegmentIndex<'a> {
+    /// Indexes `user.data` and the content data of the site or app.
+    pub fn new(req: &'a BidRequest) -> SegmentIndex<'a> {
+        let mut index = SegmentIndex::default();
+        let content = req
+            .site
+            .as_ref()
+            .and_then(|s| s.content.as_ref())
+            .or_else(|| req.app.as_ref().and_then(|a| a.content.as_ref()));
+        let sources = [
+            (
+                DataOrigin::User,
+                req.user.as_ref().and_then(|u| u.data.as_ref()),
+            ),
+            (DataOrigin::Content, content.and_then(|c| c.data.as_ref())),
+        ];
+        for (origin, data) in sources {
+            for data in data.into_iter().flatten() {
+                index.insert(origin, data);
+            }
+        }
+        index
+    }
+
+    /// Adds the segments of `data`.
+    pub fn insert(&mut self, origin: DataOrigin, data: &'a Data) {
+        let provider = data.provider().unwrap_or_default();
+        for segment in data.segment.iter().flatten() {
+            if let Some(id) = segment.id.as_deref() {
+                self.providers
+                    .entry(provider)
+                    .or_default()
+                    .entry(id)
+                    .or_default()
+                    .push(IndexedSegment {
+                        origin,
+                        data,
+                        segment,
+                    });
+            }
+        }
+    }
+
+    /// Whether `provider` sent segment `id`.
+    pub fn contains(&self, provider: &str, id: &str) -> bool {
+        !self.get(provider, id).is_empty()
+    }
+
+    /// Every occurrence of segment `id` from `provider`.
+    pub fn get(&self, provider: &str, id: &str) -> &[IndexedSegment<'a>] {
+        self.providers
+            .get(provider)
+            .and_then(|segments| segments.get(id))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// The distinct providers.
+    pub fn providers(&self) -> Vec<&'a str> {
+        let mut providers: Vec<&str> = self.providers.keys().copied().collect();
+        providers.sort_unstable();
+        providers
+    }
+
+    /// Every indexed segment whose Data object declares taxonomy `segtax`.
+    pub fn by_segtax(&self, segtax: i32) -> impl Iterator<Item = &IndexedSegment<'a>> + '_ {
+        self.providers
+            .values()
+            .flat_map(HashMap::values)
+            .flatten()
+            .filter(move |s| s.data.segtax() == Some(segtax))
+    }
+
+    /// Whether `rule` holds for the indexed request.
+    pub fn matches(&self, rule: &SegmentRule) -> bool {
+        let hits: Vec<&IndexedSegment> = match &rule.provider {
+            Some(provider) => self.get(provider, &rule.segment).iter().collect(),
+            None => self
+                .providers
+                .values()
+                .filter_map(|segments| segments.get(rule.segment.as_str()))
+                .flatten()
+                .collect(),
+        };
+        hits.into_iter().any(|s| {
+            (rule.segtax.is_none() || s.data.segtax() == rule.segtax)
+                && (rule.origin.is_none() || rule.origin == Some(s.origin))
+        })
+    }
+
+    /// Whether any of `rules` holds.
+    pub fn matches_any(&self, rules: &[SegmentRule]) -> bool {
+        rules.iter().any(|r| self.matches(r))
+    }
+
+    /// Whether all of `rules` hold.
+    pub fn matches_all(&self, rules: &[SegmentRule]) -> bool {
+        rules.iter().all(|r| self.matches(r))
+    }
+}
+
+/// "Provider X sent segment Y", optionally restricted to a taxonomy and to
+/// user or content data.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SegmentRule {
+    /// Provider key (`Data.id`, else `Data.name`); `None` matches any
+    /// provider, which suits rules on a shared taxonomy.
+    pub provider: Option<String>,
+    /// Segment ID.
+    pub segment: String,
+    /// Required `segtax` of the Data object.
+    pub segtax: Option<i32>,
+    /// Required origin of the Data object.
+    pub origin: Option<DataOrigin>,
+}
+
+impl SegmentRule {
+    /// A rule for segment `segment` of `provider`.
+    pub fn new(provider: &str, segment: &str) -> SegmentRule {
+        SegmentRule {
+            provider: Some(provider.to_string()),
+            segment: segment.to_string(),
+            ..SegmentRule::default()
+        }
+    }
+
+    /// A rule for segment `segment` of taxonomy `segtax` from any provider.
+    pub fn in_taxonomy(segtax: i32, segment: &str) -> SegmentRule {
+        SegmentRule {
+            segment: segment.to_string(),
+            segtax: Some(segtax),
+            ..SegmentRule::default()
+        }
+    }
+}
+
+impl BidRequest {
+    /// Indexes the segments of this request; see `SegmentIndex`.
+    pub fn segment_index(&self) -> SegmentIndex<'_> {
+        SegmentIndex::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> BidRequest {
+        serde_json::from_str(
+            r#"{"id":"1","imp":[],
+            "user":{"data":[
+                {"id":"p1","segment":[{"id":"s1"},{"id":"s2","value":"v"}]},
+                {"name":"p2","ext":{"segtax":4},"segment":[{"id":"100"},{"name":"noid"}]}]},
+            "site":{"content":{"data":[
+                {"id":"ctx","ext":{"segtax":7},"segment":[{"id":"100"}]}]}}}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn indexes_user_and_content_data() {
+        let request = request();
+        let index = request.segment_index();
+        assert!(index.contains("p1", "s2"));
+        assert!(!index.contains("p1", "s3"));
+        assert_eq!(index.get("p1", "s2")[0].segment.value.as_deref(), Some("v"));
+        assert_eq!(index.get("ctx", "100")[0].origin, DataOrigin::Content);
+        assert_eq!(index.providers(), vec!["ctx", "p1", "p2"]);
+        assert_eq!(index.by_segtax(4).count(), 1);
+        assert_eq!(index.by_segtax(5).count(), 0);
+    }
+
+    #[test]
+    fn matches_rules() {
+        let request = request();
+        let index = request.segment_index();
+        assert!(index.matches(&SegmentRule::in_taxonomy(
+            Taxonomy::Audience1_1 as i32,
+            "100"
+        )));
+        assert!(!index.matches(&SegmentRule::in_taxonomy(6, "100")));
+
+        let mut rule = SegmentRule::in_taxonomy(7, "100");
+        assert!(index.matches(&rule));
+        rule.origin = Some(DataOrigin::User);
+        assert!(!index.matches(&rule));
+        rule.origin = Some(DataOrigin::Content);
+        assert!(index.matches(&rule));
+
+        assert!(index.matches_all(&[SegmentRule::new("p1", "s1"), SegmentRule::new("ctx", "100")]));
+        assert!(!index.matches_all(&[SegmentRule::new("p1", "s1"), SegmentRule::new("p2", "s1")]));
+        assert!(index.matches_any(&[SegmentRule::new("p2", "s1"), SegmentRule::new("p2", "100")]));
+        assert!(!index.matches_any(&[SegmentRule::new("p2", "s1")]));
+    }
+}
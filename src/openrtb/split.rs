@@ -0,0 +1,355 @@
+//! Splitting requests by impression and merging the responses.
+//!
+//! `BidRequest::split_by_imp` turns a multi-imp request into one request
+//! per imp for bidders that take a single imp. Each keeps the shared
+//! context (`site`/`app`, `device`, `user`, `regs`, `source` and the
+//! auction settings) and the imp's own ID, so bids still name the imp of the
+//! original request; the request IDs are made unique by suffixing the imp's
+//! position. `BidRequest::merge_responses` combines the responses into one
+//! response to the original request.
+
+use super::bid_response::SeatBid;
+use super::bool::Bool;
+use super::{BidRequest, BidResponse};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Error returned when responses cannot be merged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MergeError {
+    /// The response ID is not one of the split request IDs.
+    UnknownResponse(String),
+    /// A second response has the split request ID of an earlier one.
+    DuplicateResponse(String),
+    /// A bid is for an imp other than the one of its split request.
+    UnknownImp(String),
+    /// Responses with bids are in different currencies.
+    CurrencyMismatch {
+        /// Currency of the earlier responses with bids.
+        expected: String,
+        /// Currency of the response that differs.
+        found: String,
+    },
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeError::UnknownResponse(id) => write!(f, "response {:?} is not for a split", id),
+            MergeError::DuplicateResponse(id) => write!(f, "second response {:?}", id),
+            MergeError::UnknownImp(id) => write!(f, "bid for imp {:?} of another split", id),
+            MergeError::CurrencyMismatch { expected, found } => {
+                write!(f, "bids in both {} and {}", expected, found)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+/// A bid ID that `merge_responses` changed because an earlier bid had it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RenamedBid {
+    /// ID of the split request the bid was for.
+    pub split_id: String,
+    /// The ID the bidder gave the bid.
+    pub original: String,
+    /// The ID of the bid in the merged response.
+    pub id: String,
+}
+
+impl BidRequest {
+    /// The ID of the split request for the imp at `index`.
+    pub fn split_id(&self, index: usize) -> String {
+        format!("{}-{}", self.id, index)
+    }
+
+    /// One request per imp, with ID `split_id`. `allimps` is dropped, as a
+    /// single imp no longer is every impression of the context.
+    pub fn split_by_imp(&self) -> Vec<BidRequest> {
+        let mut shared = self.clone();
+        let imps = std::mem::take(&mut shared.imp);
+        shared.allimps = None;
+        imps.into_iter()
+            .enumerate()
+            .map(|(i, imp)| BidRequest {
+                id: self.split_id(i),
+                imp: vec![imp],
+                ..shared.clone()
+            })
+            .collect()
+    }
+
+    /// Merges the responses to `split_by_imp` into one response to this
+    /// request.
+    ///
+    /// SeatBids of the same seat are merged unless they have `group` set:
+    /// those stay separate, so that no bids become all-or-nothing with bids
+    /// the bidder did not group them with. `bidid`, `customdata` and `ext`
+    /// are kept when every response agrees on them, and `nbr` only when
+    /// there are no bids.
+    ///
+    /// A bid whose ID an earlier bid already has gets the first `-n` suffix
+    /// that no bid has, and is listed in the returned renames. The bidder only knows the original
+    /// ID, so use it, not `bid.id`, to expand `${AUCTION_BID_ID}` in `nurl`,
+    /// `burl` and `lurl` and to report the bid back to the bidder.
+    pub fn merge_responses(
+        &self,
+        responses: impl IntoIterator<Item = BidResponse>,
+    ) -> Result<(BidResponse, Vec<RenamedBid>), MergeError> {
+        let imps: HashMap<String, &str> = self
+            .imp
+            .iter()
+            .enumerate()
+            .map(|(i, imp)| (self.split_id(i), imp.id.as_str()))
+            .collect();
+        let mut seen = HashSet::new();
+        let mut merged = BidResponse {
+            id: self.id.clone(),
+            ..BidResponse::default()
+        };
+        let mut currency: Option<String> = None;
+        let mut seatbids: Vec<SeatBid> = Vec::new();
+        let responses: Vec<BidResponse> = responses.into_iter().collect();
+        let originals: HashSet<String> = responses
+            .iter()
+            .flat_map(|r| r.seatbid.iter().flatten())
+            .flat_map(|s| s.bid.iter().map(|b| b.id.clone()))
+            .collect();
+        let mut bid_ids = HashSet::new();
+        let mut renamed = Vec::new();
+        for (i, resp) in responses.into_iter().enumerate() {
+            let Some(&impid) = imps.get(&resp.id) else {
+                return Err(MergeError::UnknownResponse(resp.id));
+            };
+            if !seen.insert(resp.id.clone()) {
+                return Err(MergeError::DuplicateResponse(resp.id));
+            }
+            agree(&mut merged.bidid, resp.bidid, i == 0);
+            agree(&mut merged.customdata, resp.customdata, i == 0);
+            agree(&mut merged.ext, resp.ext, i == 0);
+            let mut has_bids = false;
+            for mut seatbid in resp.seatbid.into_iter().flatten() {
+                if seatbid.bid.is_empty() {
+                    continue;
+                }
+                has_bids = true;
+                for bid in &mut seatbid.bid {
+                    if bid.impid != impid {
+                        return Err(MergeError::UnknownImp(bid.impid.clone()));
+                    }
+                    if bid_ids.insert(bid.id.clone()) {
+                        continue;
+                    }
+                    let original = bid.id.clone();
+                    let mut n = 1;
+                    loop {
+                        bid.id = format!("{}-{}", original, n);
+                        if !originals.contains(&bid.id) && bid_ids.insert(bid.id.clone()) {
+                            break;
+                        }
+                        n += 1;
+                    }
+                    renamed.push(RenamedBid {
+                        split_id: resp.id.clone(),
+                        original,
+                        id: bid.id.clone(),
+                    });
+                }
+                let seat = seatbids
+                    .iter_mut()
+                    .find(|s| s.seat == seatbid.seat && s.group != Some(Bool::True));
+                match seat {
+                    Some(seat) if seatbid.group != Some(Bool::True) => {
+                        seat.bid.append(&mut seatbid.bid)
+                    }
+                    _ => seatbids.push(seatbid),
+                }
+            }
+            if !has_bids {
+                merged.nbr = merged.nbr.or(resp.nbr);
+                continue;
+            }
+            let cur = resp.cur.as_deref().unwrap_or("USD").to_ascii_uppercase();
+            match &currency {
+                Some(expected) if *expected != cur => {
+                    return Err(MergeError::CurrencyMismatch {
+                        expected: expected.clone(),
+                        found: cur,
+                    })
+                }
+                Some(_) => {}
+                None => currency = Some(cur),
+            }
+            merged.cur = merged.cur.or(resp.cur);
+        }
+        if !seatbids.is_empty() {
+            merged.seatbid = Some(seatbids);
+            merged.nbr = None;
+        }
+        Ok((merged, renamed))
+    }
+}
+
+/// Keeps `merged` while every response has the same value.
+fn agree<T: PartialEq>(merged: &mut Option<T>, value: Option<T>, first: bool) {
+    if first {
+        *merged = value;
+    } else if *merged != value {
+        *merged = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> BidRequest {
+        serde_json::from_str(
+            r#"{"id":"r","allimps":1,"tmax":100,
+            "imp":[{"id":"a"},{"id":"b"},{"id":"c"}],"site":{"page":"p"},"device":{"ua":"u"},
+            "user":{"id":"x"},"regs":{"coppa":1},"source":{"tid":"t"}}"#,
+        )
+        .unwrap()
+    }
+
+    fn response(json: &str) -> BidResponse {
+        serde_json::from_str(json).unwrap()
+    }
+
+    /// Responses to the three parts: duplicate bid IDs across parts, a
+    /// grouped seat in two parts, and a no-bid.
+    fn responses() -> Vec<BidResponse> {
+        vec![
+            response(
+                r#"{"id":"r-0","cur":"usd","bidid":"z","seatbid":[
+                {"seat":"s1","bid":[{"id":"1","impid":"a","price":1}]},
+                {"seat":"s2","group":1,"bid":[{"id":"1","impid":"a","price":2}]}]}"#,
+            ),
+            response(
+                r#"{"id":"r-1","bidid":"z","seatbid":[
+                {"seat":"s1","bid":[{"id":"1","impid":"b","price":3}]},
+                {"seat":"s2","group":1,"bid":[{"id":"9","impid":"b","price":4}]}]}"#,
+            ),
+            response(r#"{"id":"r-2","nbr":2}"#),
+        ]
+    }
+
+    #[test]
+    fn splits_by_imp() {
+        let request = request();
+        let parts = request.split_by_imp();
+        assert_eq!(parts.len(), 3);
+        for (i, part) in parts.iter().enumerate() {
+            assert_eq!(part.id, request.split_id(i));
+            assert_eq!(part.imp.len(), 1);
+            assert_eq!(part.imp[0].id, request.imp[i].id);
+            assert!(part.allimps.is_none());
+            assert_eq!(part.tmax, Some(100));
+            assert!(part.site == request.site && part.device == request.device);
+            assert!(part.user == request.user && part.regs == request.regs);
+            assert!(part.source == request.source);
+        }
+        assert_eq!(parts[1].id, "r-1");
+    }
+
+    #[test]
+    fn merges_responses() {
+        let (merged, renamed) = request().merge_responses(responses()).unwrap();
+        assert_eq!(merged.id, "r");
+        assert_eq!(merged.cur.as_deref(), Some("usd"));
+        assert!(merged.nbr.is_none());
+        assert!(merged.bidid.is_none());
+
+        let seatbids = merged.seatbid.as_ref().unwrap();
+        let seats: Vec<_> = seatbids
+            .iter()
+            .map(|s| (s.seat.as_deref(), s.bid.len()))
+            .collect();
+        assert_eq!(
+            seats,
+            vec![(Some("s1"), 2), (Some("s2"), 1), (Some("s2"), 1)]
+        );
+        let ids: Vec<&str> = seatbids
+            .iter()
+            .flat_map(|s| s.bid.iter().map(|b| b.id.as_str()))
+            .collect();
+        assert_eq!(ids, vec!["1", "1-2", "1-1", "9"]);
+        let renamed: Vec<_> = renamed
+            .iter()
+            .map(|r| (r.split_id.as_str(), r.original.as_str(), r.id.as_str()))
+            .collect();
+        assert_eq!(renamed, vec![("r-0", "1", "1-1"), ("r-1", "1", "1-2")]);
+        let imps: Vec<&str> = seatbids[0].bid.iter().map(|b| b.impid.as_str()).collect();
+        assert_eq!(imps, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn keeps_ids_that_later_bids_have() {
+        let mut responses = responses();
+        responses[1].seatbid.as_mut().unwrap()[1].bid[0].id = "1-1".to_string();
+        let (merged, renamed) = request().merge_responses(responses).unwrap();
+        let ids: Vec<&str> = merged
+            .seatbid
+            .iter()
+            .flatten()
+            .flat_map(|s| s.bid.iter().map(|b| b.id.as_str()))
+            .collect();
+        assert_eq!(ids, vec!["1", "1-3", "1-2", "1-1"]);
+        assert!(renamed.iter().all(|r| r.original == "1"));
+        assert_eq!(renamed.len(), 2);
+    }
+
+    #[test]
+    fn keeps_the_no_bid_reason_without_bids() {
+        let (merged, renamed) = request()
+            .merge_responses(vec![response(r#"{"id":"r-2","nbr":2}"#)])
+            .unwrap();
+        assert!(renamed.is_empty());
+        assert!(merged.seatbid.is_none());
+        assert!(merged.nbr.is_some());
+    }
+
+    #[test]
+    fn rejects_inconsistent_responses() {
+        let request = request();
+        let mut bad = responses();
+        bad[1].cur = Some("EUR".to_string());
+        assert_eq!(
+            request.merge_responses(bad).err().unwrap(),
+            MergeError::CurrencyMismatch {
+                expected: "USD".to_string(),
+                found: "EUR".to_string()
+            }
+        );
+
+        let mut bad = responses();
+        bad[1].id = "q".to_string();
+        assert_eq!(
+            request.merge_responses(bad).err().unwrap(),
+            MergeError::UnknownResponse("q".to_string())
+        );
+
+        let mut bad = responses();
+        bad[1].id = "r-0".to_string();
+        assert_eq!(
+            request.merge_responses(bad).err().unwrap(),
+            MergeError::DuplicateResponse("r-0".to_string())
+        );
+
+        let mut bad = responses();
+        bad[0].seatbid.as_mut().unwrap()[0].bid[0].impid = "zz".to_string();
+        assert_eq!(
+            request.merge_responses(bad).err().unwrap(),
+            MergeError::UnknownImp("zz".to_string())
+        );
+
+        // "b" is in the request, but not the imp of split "r-0".
+        let mut bad = responses();
+        bad[0].seatbid.as_mut().unwrap()[0].bid[0].impid = "b".to_string();
+        assert_eq!(
+            request.merge_responses(bad).err().unwrap(),
+            MergeError::UnknownImp("b".to_string())
+        );
+    }
+}
@@ -0,0 +1,468 @@
+//! Structured user agent (SUA).
+//!
+//! OpenRTB 2.6 models User-Agent Client Hints as `Device.sua`; for 2.5 the
+//! same object is carried in `device.ext.sua`. `StructuredUserAgent` can be
+//! built from the raw `Sec-CH-UA-*` request headers, and
+//! `Device::enrich` fills `Device` fields from it in preference to parsing
+//! `Device.ua`, which Chrome has reduced.
+
+use super::bid_request::Device;
+use super::bool::Bool;
+use super::ua::{fill_browser, fill_dependent, make_of_model};
+use super::DeviceType;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_repr::{Deserialize_repr, Serialize_repr};
+
+/// OpenRTB 2.6: Structured user agent information, from User-Agent Client
+/// Hints or parsed from the user-agent string.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StructuredUserAgent {
+    /// Each BrandVersion object identifies a browser or similar software
+    /// component, in the order of the Sec-CH-UA-Full-Version-List header.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub browsers: Option<Vec<BrandVersion>>,
+
+    /// The user agent's execution platform / OS, from Sec-CH-UA-Platform and
+    /// Sec-CH-UA-Platform-Version.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<BrandVersion>,
+
+    /// 1 if the agent prefers a "mobile" version of the content, from
+    /// Sec-CH-UA-Mobile.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mobile: Option<Bool>,
+
+    /// Device's major binary architecture, e.g. "x86" or "arm", from
+    /// Sec-CH-UA-Arch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub architecture: Option<String>,
+
+    /// Device's bitness, e.g. "64", from Sec-CH-UA-Bitness.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bitness: Option<String>,
+
+    /// Device model, from Sec-CH-UA-Model.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+
+    /// The source of data used to create this object.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<UserAgentSource>,
+
+    /// Extensions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ext: Option<Value>,
+}
+
+/// OpenRTB 2.6: Identifies a browser or platform and its version.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BrandVersion {
+    /// A brand identifier, e.g. "Chrome" or "Windows".
+    pub brand: String,
+
+    /// Version components, from major to minor, e.g. ["116", "0", "5845"].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<Vec<String>>,
+
+    /// Extensions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ext: Option<Value>,
+}
+
+/// OpenRTB 2.6: The source of data used to create the structured user agent.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize_repr, Deserialize_repr,
+)]
+#[repr(i32)]
+pub enum UserAgentSource {
+    /// Unspecified or unknown source.
+    Unknown = 0,
+    /// User-Agent Client Hints, only low-entropy headers available.
+    LowEntropyClientHints = 1,
+    /// User-Agent Client Hints, with high-entropy headers available.
+    HighEntropyClientHints = 2,
+    /// Parsed from the User-Agent header, the same as in `Device.ua`.
+    ParsedUserAgent = 3,
+}
+impl UserAgentSource {
+    /// String value of the enum field names.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            UserAgentSource::Unknown => "UNKNOWN",
+            UserAgentSource::LowEntropyClientHints => "LOW_ENTROPY_CLIENT_HINTS",
+            UserAgentSource::HighEntropyClientHints => "HIGH_ENTROPY_CLIENT_HINTS",
+            UserAgentSource::ParsedUserAgent => "PARSED_USER_AGENT",
+        }
+    }
+}
+
+impl BrandVersion {
+    /// The version components joined with dots, e.g. "116.0.5845".
+    pub fn version_string(&self) -> Option<String> {
+        match &self.version {
+            Some(v) if !v.is_empty() => Some(v.join(".")),
+            _ => None,
+        }
+    }
+
+    /// Whether the brand is a GREASE entry such as "Not)A;Brand", which
+    /// browsers add to client hints to keep parsers tolerant.
+    pub fn is_grease(&self) -> bool {
+        self.brand.contains("Not") && self.brand.contains("Brand")
+    }
+}
+
+impl StructuredUserAgent {
+    /// Builds the object from `Sec-CH-UA-*` headers. Header names are
+    /// case-insensitive; unknown headers are ignored. Returns `None` when
+    /// no client hint is present.
+    pub fn from_client_hints<'a, I>(headers: I) -> Option<StructuredUserAgent>
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        let mut sua = StructuredUserAgent::default();
+        let mut brands = None;
+        let mut full_versions = None;
+        let mut platform_version = None;
+        let mut high_entropy = false;
+        let mut any = false;
+        for (name, value) in headers {
+            let value = value.trim();
+            match name.to_ascii_lowercase().as_str() {
+                "sec-ch-ua" => brands = Some(parse_brand_list(value)),
+                "sec-ch-ua-full-version-list" => {
+                    full_versions = Some(parse_brand_list(value));
+                    high_entropy = true;
+                }
+                "sec-ch-ua-platform" => {
+                    sua.platform = Some(BrandVersion {
+                        brand: unquote(value),
+                        ..BrandVersion::default()
+                    })
+                }
+                "sec-ch-ua-platform-version" => {
+                    platform_version = Some(split_version(&unquote(value)));
+                    high_entropy = true;
+                }
+                "sec-ch-ua-mobile" => {
+                    sua.mobile = match value {
+                        "?1" => Some(Bool::True),
+                        "?0" => Some(Bool::False),
+                        _ => None,
+                    }
+                }
+                "sec-ch-ua-arch" => {
+                    sua.architecture = non_empty(unquote(value));
+                    high_entropy = true;
+                }
+                "sec-ch-ua-bitness" => {
+                    sua.bitness = non_empty(unquote(value));
+                    high_entropy = true;
+                }
+                "sec-ch-ua-model" => {
+                    sua.model = non_empty(unquote(value));
+                    high_entropy = true;
+                }
+                _ => continue,
+            }
+            any = true;
+        }
+        if !any {
+            return None;
+        }
+        if let (Some(platform), Some(version)) = (&mut sua.platform, platform_version) {
+            platform.version = Some(version);
+        }
+        sua.browsers = full_versions.or(brands);
+        sua.source = Some(if high_entropy {
+            UserAgentSource::HighEntropyClientHints
+        } else {
+            UserAgentSource::LowEntropyClientHints
+        });
+        Some(sua)
+    }
+
+    /// The most specific browser: the first non-GREASE brand other than
+    /// "Chromium", or "Chromium" itself.
+    pub fn browser(&self) -> Option<&BrandVersion> {
+        let browsers = self.browsers.as_deref().unwrap_or_default();
+        browsers
+            .iter()
+            .find(|b| !b.is_grease() && b.brand != "Chromium")
+            .or_else(|| browsers.iter().find(|b| !b.is_grease()))
+    }
+
+    /// The operating system version in the conventional form. Windows
+    /// reports a platform version rather than a marketing version, which is
+    /// mapped to "11" (13 and above) or "10" (1 to 10).
+    pub fn osv(&self) -> Option<String> {
+        let platform = self.platform.as_ref()?;
+        let version = platform.version_string()?;
+        if platform.brand != "Windows" {
+            return Some(version);
+        }
+        let major: u32 = version.split('.').next()?.parse().ok()?;
+        match major {
+            13.. => Some("11".to_string()),
+            1..=10 => Some("10".to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a structured header list of brands such as
+/// `"Chromium";v="116", "Not)A;Brand";v="24"`.
+fn parse_brand_list(value: &str) -> Vec<BrandVersion> {
+    split_outside_quotes(value, ',')
+        .into_iter()
+        .filter_map(|item| {
+            let mut params = split_outside_quotes(item, ';').into_iter();
+            let brand = unquote(params.next()?.trim());
+            let version = params
+                .filter_map(|p| p.trim().strip_prefix("v="))
+                .map(|v| split_version(&unquote(v)))
+                .next();
+            Some(BrandVersion {
+                brand,
+                version,
+                ext: None,
+            })
+        })
+        .collect()
+}
+
+fn split_outside_quotes(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c == sep && !quoted => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// Removes the quotes and escapes of a structured header string.
+fn unquote(s: &str) -> String {
+    let s = s.trim();
+    match s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        Some(inner) => {
+            let mut out = String::with_capacity(inner.len());
+            let mut chars = inner.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => out.extend(chars.next()),
+                    c => out.push(c),
+                }
+            }
+            out
+        }
+        None => s.to_string(),
+    }
+}
+
+fn split_version(v: &str) -> Vec<String> {
+    v.split('.').map(str::to_string).collect()
+}
+
+fn non_empty(s: String) -> Option<String> {
+    if s.is_empty() {
+        None
+    } else {
+        Some(s)
+    }
+}
+
+impl Device {
+    /// The structured user agent from `ext.sua`.
+    pub fn sua(&self) -> Option<serde_json::Result<StructuredUserAgent>> {
+        let sua = self.ext.as_ref()?.get("sua")?;
+        Some(StructuredUserAgent::deserialize(sua))
+    }
+
+    /// Stores `sua` in `ext.sua`.
+    pub fn set_sua(&mut self, sua: &StructuredUserAgent) -> serde_json::Result<()> {
+        let sua = serde_json::to_value(sua)?;
+        match self
+            .ext
+            .get_or_insert_with(|| Value::Object(Default::default()))
+        {
+            Value::Object(ext) => {
+                ext.insert("sua".to_string(), sua);
+            }
+            ext => *ext = serde_json::json!({ "sua": sua }),
+        }
+        Ok(())
+    }
+
+    /// Fills `make`, `model`, `os`, `osv`, `devicetype` and the browser
+    /// from `ext.sua` where they are missing. As with `enrich_from_ua`,
+    /// `model` and `osv` are only filled when `make` and `os` are missing or
+    /// name the maker and platform of the hints.
+    pub fn enrich_from_sua(&mut self) -> Option<StructuredUserAgent> {
+        let sua = self.sua()?.ok()?;
+        if let Some(model) = &sua.model {
+            let make = make_of_model(model);
+            fill_dependent(&mut self.make, &mut self.model, &make, &sua.model);
+        }
+        if let Some(platform) = &sua.platform {
+            let os = Some(platform.brand.clone());
+            fill_dependent(&mut self.os, &mut self.osv, &os, &sua.osv());
+        }
+        if self.devicetype.is_none() {
+            let desktop = sua.platform.as_ref().is_some_and(|p| {
+                matches!(
+                    p.brand.as_str(),
+                    "Windows" | "macOS" | "Chrome OS" | "Linux"
+                )
+            });
+            // `mobile` cannot tell a phone from a tablet, so a tablet user
+            // agent keeps its more specific type.
+            let ua_type = self.parse_ua().and_then(|ua| ua.devicetype);
+            self.devicetype = match sua.mobile {
+                Some(Bool::True) if ua_type == Some(DeviceType::Tablet) => ua_type,
+                Some(Bool::True) => Some(DeviceType::HighendPhone),
+                Some(Bool::False) if desktop => Some(DeviceType::PersonalComputer),
+                _ => None,
+            };
+        }
+        if let Some(browser) = sua.browser() {
+            fill_browser(self, &browser.brand, browser.version_string().as_deref());
+        }
+        Some(sua)
+    }
+
+    /// Fills missing device fields from `ext.sua` first and then from `ua`,
+    /// so that client hints take precedence over the reduced user agent.
+    /// `osv` and `model` are never taken from `ua` when the hints supplied
+    /// `os` and `make`: the reduced user agent freezes them, e.g. at
+    /// Windows 10 on Windows 11.
+    pub fn enrich(&mut self) {
+        let (os, make) = (self.os.is_none(), self.make.is_none());
+        self.enrich_from_sua();
+        let sua_os = os && self.os.is_some();
+        let sua_make = make && self.make.is_some();
+        let (osv, model) = (self.osv.clone(), self.model.clone());
+        self.enrich_from_ua();
+        if sua_os {
+            self.osv = osv;
+        }
+        if sua_make {
+            self.model = model;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chrome_on_windows_11() -> StructuredUserAgent {
+        StructuredUserAgent::from_client_hints([
+            (
+                "Sec-CH-UA",
+                r#""Chromium";v="116", "Not)A;Brand";v="24", "Google Chrome";v="116""#,
+            ),
+            (
+                "sec-ch-ua-full-version-list",
+                r#""Chromium";v="116.0.5845.96", "Not)A;Brand";v="24.0.0.0", "Google Chrome";v="116.0.5845.96""#,
+            ),
+            ("Sec-CH-UA-Platform", "\"Windows\""),
+            ("Sec-CH-UA-Platform-Version", "\"15.0.0\""),
+            ("Sec-CH-UA-Mobile", "?0"),
+            ("Sec-CH-UA-Model", "\"\""),
+        ])
+        .unwrap()
+    }
+
+    fn mobile_device(ua: &str) -> Device {
+        let sua = StructuredUserAgent::from_client_hints([
+            ("Sec-CH-UA-Platform", "\"Android\""),
+            ("Sec-CH-UA-Mobile", "?1"),
+        ])
+        .unwrap();
+        let mut device = Device {
+            ua: Some(ua.into()),
+            ..Default::default()
+        };
+        device.set_sua(&sua).unwrap();
+        device
+    }
+
+    #[test]
+    fn parses_client_hints() {
+        let sua = chrome_on_windows_11();
+        assert!(sua.model.is_none());
+        assert_eq!(sua.osv().as_deref(), Some("11"));
+        assert_eq!(sua.browser().unwrap().brand, "Google Chrome");
+        assert!(sua.source == Some(UserAgentSource::HighEntropyClientHints));
+        assert!(StructuredUserAgent::from_client_hints([("Accept", "x")]).is_none());
+    }
+
+    #[test]
+    fn enrich_prefers_client_hints() {
+        let mut device = Device {
+            ua: Some("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/116.0.0.0 Safari/537.36".into()),
+            ..Default::default()
+        };
+        device.set_sua(&chrome_on_windows_11()).unwrap();
+        device.enrich();
+        assert_eq!(device.os.as_deref(), Some("Windows"));
+        assert_eq!(device.osv.as_deref(), Some("11"));
+        assert_eq!(device.devicetype, Some(DeviceType::PersonalComputer));
+        let ext = device.ext.unwrap();
+        assert_eq!(ext["browserv"], "116.0.5845.96");
+        assert_eq!(ext["sua"]["platform"]["version"][0], "15");
+    }
+
+    #[test]
+    fn enrich_takes_osv_from_the_source_of_os() {
+        const WINDOWS_UA: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/116.0.0.0 Safari/537.36";
+
+        // Low-entropy hints name the platform but not its version; the
+        // frozen "10" of the user agent must not fill in for it.
+        let mut device = Device {
+            ua: Some(WINDOWS_UA.into()),
+            ..Default::default()
+        };
+        let sua = StructuredUserAgent::from_client_hints([("Sec-CH-UA-Platform", "\"Windows\"")])
+            .unwrap();
+        device.set_sua(&sua).unwrap();
+        device.enrich();
+        assert_eq!(device.os.as_deref(), Some("Windows"));
+        assert_eq!(device.osv, None);
+
+        // A supplied os of another platform takes no version from the hints.
+        let mut device = Device {
+            ua: Some(WINDOWS_UA.into()),
+            os: Some("Android".into()),
+            ..Default::default()
+        };
+        device.set_sua(&chrome_on_windows_11()).unwrap();
+        device.enrich();
+        assert_eq!(device.os.as_deref(), Some("Android"));
+        assert_eq!(device.osv, None);
+    }
+
+    #[test]
+    fn mobile_hint_keeps_tablet_user_agent() {
+        let mut phone = mobile_device("Mozilla/5.0 (Linux; Android 13; SM-S918B) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/116.0.0.0 Mobile Safari/537.36");
+        phone.enrich();
+        assert_eq!(phone.devicetype, Some(DeviceType::HighendPhone));
+
+        let mut tablet = mobile_device("Mozilla/5.0 (iPad; CPU OS 17_1 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.1 Mobile/15E148 Safari/604.1");
+        tablet.enrich();
+        assert_eq!(tablet.devicetype, Some(DeviceType::Tablet));
+    }
+}
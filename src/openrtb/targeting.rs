@@ -0,0 +1,1285 @@
+//! Targeting rules.
+//!
+//! A small expression language evaluated against a `BidRequest` and one of
+//! its `Imp`s:
+//!
+//! ```text
+//! device.geo.country in ["USA", "CAN"]
+//!     and imp.banner.format contains 300x250
+//!     and not site.cat intersects ["IAB25"]
+//! ```
+//!
+//! A predicate is a field path, optionally followed by an operator:
+//!
+//! * `==`, `!=`, `<`, `<=`, `>`, `>=` compare with a literal;
+//! * `in [..]` and `intersects [..]` test membership in a list;
+//! * `contains` tests a list field for one literal;
+//! * `exists` tests presence; a bare path tests for a truthy value.
+//!
+//! Predicates combine with `and`, `or`, `not` and parentheses. Literals are
+//! strings (`"USA"`), numbers, `true`/`false`, sizes (`300x250`) and bare
+//! enum names (`CONNECTED_TV`), which match the `as_str_name` of enum
+//! fields; enum fields also compare equal to their numeric value.
+//!
+//! Fields with several values, such as lists, match when any value does,
+//! and `!=` is the negation of `==`. `<object>.ext.<key>...` reads into the
+//! extensions of the request, `imp`, `source`, `regs`, `site`, `app`,
+//! `device`, `user` and their geo, content and publisher objects.
+//! `content.` and `publisher.` are shorthands for the site's or app's.
+//!
+//! `Rule::parse` resolves every path once, so evaluating a compiled rule
+//! does no string matching on field names.
+
+use super::bid_request::imp::banner::Format;
+use super::bid_request::imp::{Audio, Banner, Native, Pmp, Video};
+use super::bid_request::{App, Content, Device, Geo, Imp, Publisher, Regs, Site, Source, User};
+use super::bool::Bool;
+use super::{
+    AdPosition, ApiFramework, AuctionType, BannerAdType, BidRequest, CompanionType, ConnectionType,
+    ContentContext, ContentDeliveryMethod, CreativeAttribute, DeviceType, ExpandableDirection,
+    FeedType, LocationService, LocationType, PlaybackCessationMode, PlaybackMethod,
+    ProductionQuality, Protocol, QagMediaRating, VideoLinearity, VideoPlacementType,
+    VolumeNormalizationMode,
+};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+/// Error returned for a rule that does not parse or names an unknown field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseRuleError {
+    /// Byte offset in the rule source.
+    pub offset: usize,
+    /// What is wrong, e.g. "unknown field 'device.foo'".
+    pub message: String,
+}
+
+impl fmt::Display for ParseRuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for ParseRuleError {}
+
+/// A compiled targeting rule.
+pub struct Rule {
+    source: String,
+    expr: Expr,
+}
+
+impl Rule {
+    /// Parses and compiles a rule.
+    pub fn parse(source: &str) -> Result<Rule, ParseRuleError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            end: source.len(),
+            depth: 0,
+        };
+        let expr = parser.or()?;
+        if let Some((offset, token)) = parser.tokens.get(parser.pos) {
+            return Err(error(*offset, format!("unexpected {}", token)));
+        }
+        Ok(Rule {
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    /// Whether the rule holds for `imp` of `req`.
+    pub fn eval(&self, req: &BidRequest, imp: &Imp) -> bool {
+        self.expr.eval(req, imp)
+    }
+
+    /// The rule source.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl fmt::Debug for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Rule").field(&self.source).finish()
+    }
+}
+
+impl FromStr for Rule {
+    type Err = ParseRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Rule::parse(s)
+    }
+}
+
+impl BidRequest {
+    /// The impressions for which `rule` holds.
+    pub fn matching_imps<'a>(&'a self, rule: &'a Rule) -> impl Iterator<Item = &'a Imp> + 'a {
+        self.imp.iter().filter(move |imp| rule.eval(self, imp))
+    }
+}
+
+/// A value of a field, borrowed from the request.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Val<'r> {
+    Str(&'r str),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    /// An enum field: its numeric value and its name.
+    Enum(i64, &'static str),
+    Size(i32, i32),
+    /// An object, for `exists` and truthiness.
+    Present,
+}
+
+impl Val<'_> {
+    fn number(self) -> Option<f64> {
+        match self {
+            Val::Int(n) | Val::Enum(n, _) => Some(n as f64),
+            Val::Float(x) => Some(x),
+            Val::Bool(b) => Some(b as i64 as f64),
+            Val::Str(s) => s.trim().parse().ok(),
+            Val::Size(..) | Val::Present => None,
+        }
+    }
+
+    fn truthy(self) -> bool {
+        match self {
+            Val::Str(s) => !s.is_empty(),
+            Val::Int(n) => n != 0,
+            Val::Float(x) => x != 0.0,
+            Val::Bool(b) => b,
+            Val::Enum(..) | Val::Size(..) | Val::Present => true,
+        }
+    }
+
+    fn matches(self, lit: &Lit) -> bool {
+        match (self, lit) {
+            (Val::Str(a), Lit::Str(b)) => a == b,
+            (Val::Enum(_, name), Lit::Str(b)) => name.eq_ignore_ascii_case(b),
+            (Val::Bool(a), Lit::Bool(b)) => a == *b,
+            (Val::Size(w, h), Lit::Size(lw, lh)) => w == *lw && h == *lh,
+            (v, Lit::Int(n)) => v.number() == Some(*n as f64),
+            (v, Lit::Float(x)) => v.number() == Some(*x),
+            _ => false,
+        }
+    }
+
+    fn compare(self, op: CmpOp, lit: &Lit) -> bool {
+        let ordering = match (self, lit) {
+            (Val::Str(a), Lit::Str(b)) => Some(a.cmp(b.as_str())),
+            (v, Lit::Int(n)) => v.number().and_then(|x| x.partial_cmp(&(*n as f64))),
+            (v, Lit::Float(y)) => v.number().and_then(|x| x.partial_cmp(y)),
+            _ => None,
+        };
+        ordering.is_some_and(|o| match op {
+            CmpOp::Lt => o.is_lt(),
+            CmpOp::Le => o.is_le(),
+            CmpOp::Gt => o.is_gt(),
+            CmpOp::Ge => o.is_ge(),
+        })
+    }
+}
+
+/// A literal of the rule source.
+#[derive(Clone, Debug, PartialEq)]
+enum Lit {
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Size(i32, i32),
+}
+
+/// A list literal, with string members hashed.
+struct LitSet {
+    strs: HashSet<String>,
+    /// Upper-cased string members, for enum names.
+    names: HashSet<String>,
+    others: Vec<Lit>,
+}
+
+impl LitSet {
+    fn new(lits: Vec<Lit>) -> LitSet {
+        let mut set = LitSet {
+            strs: HashSet::new(),
+            names: HashSet::new(),
+            others: Vec::new(),
+        };
+        for lit in lits {
+            match lit {
+                Lit::Str(s) => {
+                    set.names.insert(s.to_ascii_uppercase());
+                    set.strs.insert(s);
+                }
+                lit => set.others.push(lit),
+            }
+        }
+        set
+    }
+
+    fn contains(&self, v: Val) -> bool {
+        let hashed = match v {
+            Val::Str(s) => self.strs.contains(s),
+            Val::Enum(_, name) => self.names.contains(name),
+            _ => false,
+        };
+        hashed || self.others.iter().any(|lit| v.matches(lit))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CmpOp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+enum Test {
+    Truthy,
+    Exists,
+    Eq(Lit),
+    Cmp(CmpOp, Lit),
+    In(LitSet),
+}
+
+impl Test {
+    fn test(&self, v: Val) -> bool {
+        match self {
+            Test::Truthy => v.truthy(),
+            Test::Exists => true,
+            Test::Eq(lit) => v.matches(lit),
+            Test::Cmp(op, lit) => v.compare(*op, lit),
+            Test::In(set) => set.contains(v),
+        }
+    }
+}
+
+enum Expr {
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    Pred(Accessor, Test),
+}
+
+impl Expr {
+    fn eval(&self, req: &BidRequest, imp: &Imp) -> bool {
+        match self {
+            Expr::And(exprs) => exprs.iter().all(|e| e.eval(req, imp)),
+            Expr::Or(exprs) => exprs.iter().any(|e| e.eval(req, imp)),
+            Expr::Not(expr) => !expr.eval(req, imp),
+            Expr::Pred(field, test) => field(req, imp, &mut |v| test.test(v)),
+        }
+    }
+}
+
+fn error(offset: usize, message: String) -> ParseRuleError {
+    ParseRuleError { offset, message }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Lit(Lit),
+    Op(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(w) => write!(f, "'{}'", w),
+            Token::Lit(lit) => write!(f, "literal {:?}", lit),
+            Token::Op(op) => write!(f, "'{}'", op),
+        }
+    }
+}
+
+fn tokenize(src: &str) -> Result<Vec<(usize, Token)>, ParseRuleError> {
+    let bytes = src.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let b = bytes[i];
+        let token = match b {
+            b' ' | b'\t' | b'\r' | b'\n' => {
+                i += 1;
+                continue;
+            }
+            b'"' => {
+                let mut s = String::new();
+                let mut chars = src[i + 1..].char_indices();
+                loop {
+                    match chars.next() {
+                        Some((j, '"')) => {
+                            i += j + 2;
+                            break;
+                        }
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) => s.push(c),
+                            None => return Err(error(start, "unterminated string".into())),
+                        },
+                        Some((_, c)) => s.push(c),
+                        None => return Err(error(start, "unterminated string".into())),
+                    }
+                }
+                Token::Lit(Lit::Str(s))
+            }
+            b'(' | b')' | b'[' | b']' | b',' => {
+                i += 1;
+                Token::Op(match b {
+                    b'(' => "(",
+                    b')' => ")",
+                    b'[' => "[",
+                    b']' => "]",
+                    _ => ",",
+                })
+            }
+            b'=' | b'!' | b'<' | b'>' => {
+                let two = bytes.get(i + 1) == Some(&b'=');
+                let op = match (b, two) {
+                    (b'=', true) => "==",
+                    (b'!', true) => "!=",
+                    (b'<', true) => "<=",
+                    (b'>', true) => ">=",
+                    (b'<', false) => "<",
+                    (b'>', false) => ">",
+                    _ => return Err(error(start, format!("unexpected '{}'", b as char))),
+                };
+                i += op.len();
+                Token::Op(op)
+            }
+            b'-' | b'0'..=b'9' => {
+                i += 1;
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'.') {
+                    i += 1;
+                }
+                Token::Lit(
+                    number(&src[start..i]).ok_or_else(|| {
+                        error(start, format!("invalid number '{}'", &src[start..i]))
+                    })?,
+                )
+            }
+            b if b.is_ascii_alphabetic() || b == b'_' => {
+                while i < bytes.len()
+                    && (bytes[i].is_ascii_alphanumeric() || matches!(bytes[i], b'_' | b'.'))
+                {
+                    i += 1;
+                }
+                Token::Word(src[start..i].to_string())
+            }
+            _ => {
+                let c = src[i..].chars().next().unwrap_or_default();
+                return Err(error(start, format!("unexpected '{}'", c)));
+            }
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+/// Parses an integer, a float or a size such as `300x250`.
+fn number(s: &str) -> Option<Lit> {
+    if let Some((w, h)) = s.split_once('x') {
+        return Some(Lit::Size(w.parse().ok()?, h.parse().ok()?));
+    }
+    if let Ok(n) = s.parse() {
+        return Some(Lit::Int(n));
+    }
+    s.parse().ok().map(Lit::Float)
+}
+
+/// How deeply `not` and parentheses may nest, so that parsing and
+/// evaluating a rule cannot overflow the stack.
+const MAX_NESTING: usize = 64;
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    end: usize,
+    /// Number of enclosing `not`s and parentheses.
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |(o, _)| *o)
+    }
+
+    fn next(&mut self) -> Result<(usize, Token), ParseRuleError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| error(self.end, "unexpected end of rule".into()))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn op(&mut self, op: &'static str) -> bool {
+        if self.peek() == Some(&Token::Op(op)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, op: &'static str) -> Result<(), ParseRuleError> {
+        let (offset, token) = self.next()?;
+        if token == Token::Op(op) {
+            Ok(())
+        } else {
+            Err(error(offset, format!("expected '{}', found {}", op, token)))
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseRuleError> {
+        let mut exprs = vec![self.and()?];
+        while self.keyword("or") {
+            exprs.push(self.and()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            Expr::Or(exprs)
+        })
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseRuleError> {
+        let mut exprs = vec![self.unary()?];
+        while self.keyword("and") {
+            exprs.push(self.unary()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            Expr::And(exprs)
+        })
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseRuleError> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.nested(Parser::unary)?)));
+        }
+        if self.op("(") {
+            let expr = self.nested(Parser::or)?;
+            self.expect(")")?;
+            return Ok(expr);
+        }
+        self.predicate()
+    }
+
+    /// Parses with `parse` one level deeper, after a `not` or `(`.
+    fn nested(
+        &mut self,
+        parse: fn(&mut Parser) -> Result<Expr, ParseRuleError>,
+    ) -> Result<Expr, ParseRuleError> {
+        if self.depth == MAX_NESTING {
+            let offset = self.tokens[self.pos - 1].0;
+            return Err(error(offset, "rule nested too deeply".into()));
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    fn predicate(&mut self) -> Result<Expr, ParseRuleError> {
+        let (offset, token) = self.next()?;
+        let path = match token {
+            Token::Word(w) if !is_keyword(&w) => w,
+            token => return Err(error(offset, format!("expected a field, found {}", token))),
+        };
+        let field =
+            resolve(&path).ok_or_else(|| error(offset, format!("unknown field '{}'", path)))?;
+        let pred = |test| Ok(Expr::Pred(field, test));
+        let cmp = |op: &str| match op {
+            "<" => CmpOp::Lt,
+            "<=" => CmpOp::Le,
+            ">" => CmpOp::Gt,
+            _ => CmpOp::Ge,
+        };
+        match self.peek().cloned() {
+            Some(Token::Op(op @ ("==" | "!=" | "<" | "<=" | ">" | ">="))) => {
+                self.pos += 1;
+                let lit = self.literal()?;
+                match op {
+                    "==" => pred(Test::Eq(lit)),
+                    "!=" => Ok(Expr::Not(Box::new(pred(Test::Eq(lit))?))),
+                    op => pred(Test::Cmp(cmp(op), lit)),
+                }
+            }
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("in") => {
+                self.pos += 1;
+                pred(Test::In(LitSet::new(self.list()?)))
+            }
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("intersects") => {
+                self.pos += 1;
+                pred(Test::In(LitSet::new(self.list()?)))
+            }
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("contains") => {
+                self.pos += 1;
+                pred(Test::Eq(self.literal()?))
+            }
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("exists") => {
+                self.pos += 1;
+                pred(Test::Exists)
+            }
+            _ => pred(Test::Truthy),
+        }
+    }
+
+    fn literal(&mut self) -> Result<Lit, ParseRuleError> {
+        match self.next()? {
+            (_, Token::Lit(lit)) => Ok(lit),
+            (_, Token::Word(w)) if w == "true" => Ok(Lit::Bool(true)),
+            (_, Token::Word(w)) if w == "false" => Ok(Lit::Bool(false)),
+            (_, Token::Word(w)) if !is_keyword(&w) => Ok(Lit::Str(w)),
+            (offset, token) => Err(error(
+                offset,
+                format!("expected a literal, found {}", token),
+            )),
+        }
+    }
+
+    fn list(&mut self) -> Result<Vec<Lit>, ParseRuleError> {
+        let offset = self.offset();
+        if !self.op("[") {
+            return Err(error(offset, "expected '['".into()));
+        }
+        let mut lits = Vec::new();
+        if self.op("]") {
+            return Ok(lits);
+        }
+        loop {
+            lits.push(self.literal()?);
+            if self.op("]") {
+                return Ok(lits);
+            }
+            self.expect(",")?;
+        }
+    }
+}
+
+fn is_keyword(w: &str) -> bool {
+    ["and", "or", "not", "in", "intersects", "contains", "exists"]
+        .iter()
+        .any(|k| w.eq_ignore_ascii_case(k))
+}
+
+/// A compiled field path: visits each value of the field until the sink
+/// returns true, and returns whether it did.
+type Accessor = Box<
+    dyn for<'r> Fn(&'r BidRequest, &'r Imp, &mut dyn FnMut(Val<'r>) -> bool) -> bool + Send + Sync,
+>;
+
+/// Visits the values of one field of a `T`.
+type Getter<T> = for<'r> fn(&'r T, &mut dyn FnMut(Val<'r>) -> bool) -> bool;
+
+/// Finds the object holding a field.
+type Root<T> = for<'r> fn(&'r BidRequest, &'r Imp) -> Option<&'r T>;
+
+/// The `ext` of a `T`.
+type Ext<T> = for<'r> fn(&'r T) -> Option<&'r Value>;
+
+fn accessor<F>(f: F) -> Accessor
+where
+    F: for<'r> Fn(&'r BidRequest, &'r Imp, &mut dyn FnMut(Val<'r>) -> bool) -> bool
+        + Send
+        + Sync
+        + 'static,
+{
+    Box::new(f)
+}
+
+/// Resolves `path` against the object at `prefix`: a field of its table,
+/// the object itself (for `exists`), or its `ext`.
+fn lookup<T: 'static>(
+    path: &str,
+    prefix: &str,
+    root: Root<T>,
+    fields: &'static [(&'static str, Getter<T>)],
+    ext: Ext<T>,
+) -> Option<Accessor> {
+    if !prefix.is_empty() && path == prefix {
+        return Some(accessor(move |r, i, f| {
+            root(r, i).is_some() && f(Val::Present)
+        }));
+    }
+    let name = if prefix.is_empty() {
+        path
+    } else {
+        path.strip_prefix(prefix)?.strip_prefix('.')?
+    };
+    if name == "ext" || name.starts_with("ext.") {
+        let keys: Vec<String> = name.split('.').skip(1).map(str::to_string).collect();
+        return Some(accessor(move |r, i, f| {
+            root(r, i)
+                .and_then(ext)
+                .is_some_and(|v| visit_ext(v, &keys, f))
+        }));
+    }
+    let (_, getter) = fields.iter().find(|(n, _)| *n == name)?;
+    let getter = *getter;
+    Some(accessor(move |r, i, f| {
+        root(r, i).is_some_and(|o| getter(o, f))
+    }))
+}
+
+fn resolve(path: &str) -> Option<Accessor> {
+    fn content(r: &BidRequest) -> Option<&Content> {
+        match &r.site {
+            Some(site) => site.content.as_ref(),
+            None => r.app.as_ref()?.content.as_ref(),
+        }
+    }
+    fn publisher(r: &BidRequest) -> Option<&Publisher> {
+        match &r.site {
+            Some(site) => site.publisher.as_ref(),
+            None => r.app.as_ref()?.publisher.as_ref(),
+        }
+    }
+    let geo = |p, root| lookup::<Geo>(path, p, root, GEO, |g| g.ext.as_ref());
+    let content_at = |p, root| lookup::<Content>(path, p, root, CONTENT, |c| c.ext.as_ref());
+    let publisher_at = |p, root| lookup::<Publisher>(path, p, root, PUBLISHER, |p| p.ext.as_ref());
+    geo("device.geo", |r, _| r.device.as_ref()?.geo.as_ref())
+        .or_else(|| geo("user.geo", |r, _| r.user.as_ref()?.geo.as_ref()))
+        .or_else(|| content_at("site.content", |r, _| r.site.as_ref()?.content.as_ref()))
+        .or_else(|| content_at("app.content", |r, _| r.app.as_ref()?.content.as_ref()))
+        .or_else(|| content_at("content", |r, _| content(r)))
+        .or_else(|| publisher_at("site.publisher", |r, _| r.site.as_ref()?.publisher.as_ref()))
+        .or_else(|| publisher_at("app.publisher", |r, _| r.app.as_ref()?.publisher.as_ref()))
+        .or_else(|| publisher_at("publisher", |r, _| publisher(r)))
+        .or_else(|| {
+            lookup::<Banner>(
+                path,
+                "imp.banner",
+                |_, i| i.banner.as_ref(),
+                BANNER,
+                |b| b.ext.as_ref(),
+            )
+        })
+        .or_else(|| {
+            lookup::<Video>(
+                path,
+                "imp.video",
+                |_, i| i.video.as_ref(),
+                VIDEO,
+                |v| v.ext.as_ref(),
+            )
+        })
+        .or_else(|| {
+            lookup::<Audio>(
+                path,
+                "imp.audio",
+                |_, i| i.audio.as_ref(),
+                AUDIO,
+                |a| a.ext.as_ref(),
+            )
+        })
+        .or_else(|| {
+            lookup::<Native>(
+                path,
+                "imp.native",
+                |_, i| i.native.as_ref(),
+                NATIVE,
+                |n| n.ext.as_ref(),
+            )
+        })
+        .or_else(|| {
+            lookup::<Pmp>(
+                path,
+                "imp.pmp",
+                |_, i| i.pmp.as_ref(),
+                PMP,
+                |p| p.ext.as_ref(),
+            )
+        })
+        .or_else(|| lookup::<Imp>(path, "imp", |_, i| Some(i), IMP, |i| i.ext.as_ref()))
+        .or_else(|| {
+            lookup::<Site>(
+                path,
+                "site",
+                |r, _| r.site.as_ref(),
+                SITE,
+                |s| s.ext.as_ref(),
+            )
+        })
+        .or_else(|| lookup::<App>(path, "app", |r, _| r.app.as_ref(), APP, |a| a.ext.as_ref()))
+        .or_else(|| {
+            lookup::<Device>(
+                path,
+                "device",
+                |r, _| r.device.as_ref(),
+                DEVICE,
+                |d| d.ext.as_ref(),
+            )
+        })
+        .or_else(|| {
+            lookup::<User>(
+                path,
+                "user",
+                |r, _| r.user.as_ref(),
+                USER,
+                |u| u.ext.as_ref(),
+            )
+        })
+        .or_else(|| {
+            lookup::<Regs>(
+                path,
+                "regs",
+                |r, _| r.regs.as_ref(),
+                REGS,
+                |g| g.ext.as_ref(),
+            )
+        })
+        .or_else(|| {
+            lookup::<Source>(
+                path,
+                "source",
+                |r, _| r.source.as_ref(),
+                SOURCE,
+                |s| s.ext.as_ref(),
+            )
+        })
+        .or_else(|| lookup::<BidRequest>(path, "", |r, _| Some(r), REQUEST, |r| r.ext.as_ref()))
+}
+
+/// Walks `keys` into an `ext` value and visits the values found. Arrays
+/// are indexed by a numeric key and otherwise searched element by element.
+fn visit_ext<'r>(v: &'r Value, keys: &[String], f: &mut dyn FnMut(Val<'r>) -> bool) -> bool {
+    match (keys.split_first(), v) {
+        (Some((key, rest)), Value::Array(items)) if key.parse::<usize>().is_ok() => key
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| items.get(i))
+            .is_some_and(|item| visit_ext(item, rest, f)),
+        (_, Value::Array(items)) => items.iter().any(|item| visit_ext(item, keys, f)),
+        (Some((key, rest)), Value::Object(map)) => {
+            map.get(key).is_some_and(|v| visit_ext(v, rest, f))
+        }
+        (Some(_), _) | (None, Value::Null) => false,
+        (None, Value::Bool(b)) => f(Val::Bool(*b)),
+        (None, Value::Number(n)) => match n.as_i64() {
+            Some(n) => f(Val::Int(n)),
+            None => n.as_f64().is_some_and(|x| f(Val::Float(x))),
+        },
+        (None, Value::String(s)) => f(Val::Str(s)),
+        (None, Value::Object(_)) => f(Val::Present),
+    }
+}
+
+fn s<'r>(v: &'r Option<String>, f: &mut dyn FnMut(Val<'r>) -> bool) -> bool {
+    v.as_deref().is_some_and(|v| f(Val::Str(v)))
+}
+
+fn strs<'r>(v: &'r Option<Vec<String>>, f: &mut dyn FnMut(Val<'r>) -> bool) -> bool {
+    v.iter().flatten().any(|v| f(Val::Str(v)))
+}
+
+fn int<'r>(v: Option<i32>, f: &mut dyn FnMut(Val<'r>) -> bool) -> bool {
+    v.is_some_and(|v| f(Val::Int(v as i64)))
+}
+
+fn float<'r>(v: Option<f64>, f: &mut dyn FnMut(Val<'r>) -> bool) -> bool {
+    v.is_some_and(|v| f(Val::Float(v)))
+}
+
+fn flag<'r>(v: &Option<Bool>, f: &mut dyn FnMut(Val<'r>) -> bool) -> bool {
+    v.as_ref().is_some_and(|v| f(Val::Bool(*v == Bool::True)))
+}
+
+fn present<'r, T>(v: &Option<T>, f: &mut dyn FnMut(Val<'r>) -> bool) -> bool {
+    v.is_some() && f(Val::Present)
+}
+
+/// Enums with a numeric value and a name.
+trait Coded: Copy {
+    fn val(self) -> Val<'static>;
+}
+
+impl Coded for AuctionType {
+    fn val(self) -> Val<'static> {
+        match self {
+            AuctionType::FirstPrice => Val::Enum(1, "FIRST_PRICE"),
+            AuctionType::SecondPrice => Val::Enum(2, "SECOND_PRICE"),
+            AuctionType::FixedPrice(v) => Val::Enum(v.into(), "FIXED_PRICE"),
+        }
+    }
+}
+
+macro_rules! coded {
+    ($($t:ty),*) => {
+        $(impl Coded for $t {
+            fn val(self) -> Val<'static> {
+                Val::Enum(self as i64, self.as_str_name())
+            }
+        })*
+    };
+}
+
+coded!(
+    AdPosition,
+    ApiFramework,
+    BannerAdType,
+    CompanionType,
+    ConnectionType,
+    ContentContext,
+    ContentDeliveryMethod,
+    CreativeAttribute,
+    DeviceType,
+    ExpandableDirection,
+    FeedType,
+    LocationService,
+    LocationType,
+    PlaybackCessationMode,
+    PlaybackMethod,
+    ProductionQuality,
+    Protocol,
+    QagMediaRating,
+    VideoLinearity,
+    VideoPlacementType,
+    VolumeNormalizationMode
+);
+
+fn en<'r, E: Coded>(v: Option<E>, f: &mut dyn FnMut(Val<'r>) -> bool) -> bool {
+    v.is_some_and(|v| f(v.val()))
+}
+
+fn ens<'r, E: Coded>(v: &Option<Vec<E>>, f: &mut dyn FnMut(Val<'r>) -> bool) -> bool {
+    v.iter().flatten().any(|v| f(v.val()))
+}
+
+/// Banner sizes: each format with a width and height, then `w`×`h`.
+fn sizes<'r>(b: &'r Banner, f: &mut dyn FnMut(Val<'r>) -> bool) -> bool {
+    let formats = b
+        .format
+        .iter()
+        .flatten()
+        .filter_map(|Format { w, h, .. }| Some(((*w)?, (*h)?)));
+    formats.chain(b.w.zip(b.h)).any(|(w, h)| f(Val::Size(w, h)))
+}
+
+const REQUEST: &[(&str, Getter<BidRequest>)] = &[
+    ("id", |r, f| f(Val::Str(&r.id))),
+    ("at", |r, f| en(r.at, f)),
+    ("tmax", |r, f| int(r.tmax, f)),
+    ("wseat", |r, f| strs(&r.wseat, f)),
+    ("bseat", |r, f| strs(&r.bseat, f)),
+    ("allimps", |r, f| flag(&r.allimps, f)),
+    ("cur", |r, f| strs(&r.cur, f)),
+    ("wlang", |r, f| strs(&r.wlang, f)),
+    ("bcat", |r, f| strs(&r.bcat, f)),
+    ("badv", |r, f| strs(&r.badv, f)),
+    ("bapp", |r, f| strs(&r.bapp, f)),
+    ("test", |r, f| flag(&r.test, f)),
+];
+
+const SOURCE: &[(&str, Getter<Source>)] = &[
+    ("fd", |s, f| flag(&s.fd, f)),
+    ("tid", |s, f| self::s(&s.tid, f)),
+    ("pchain", |s, f| self::s(&s.pchain, f)),
+    ("schain", |s, f| present(&s.schain, f)),
+];
+
+const REGS: &[(&str, Getter<Regs>)] = &[
+    ("coppa", |r, f| flag(&r.coppa, f)),
+    ("gdpr", |r, f| r.gdpr().is_some_and(|g| f(Val::Bool(g)))),
+    ("us_privacy", |r, f| {
+        r.us_privacy_str().is_some_and(|u| f(Val::Str(u)))
+    }),
+];
+
+const IMP: &[(&str, Getter<Imp>)] = &[
+    ("id", |i, f| f(Val::Str(&i.id))),
+    ("displaymanager", |i, f| s(&i.displaymanager, f)),
+    ("displaymanagerver", |i, f| s(&i.displaymanagerver, f)),
+    ("instl", |i, f| flag(&i.instl, f)),
+    ("tagid", |i, f| s(&i.tagid, f)),
+    ("bidfloor", |i, f| float(i.bidfloor, f)),
+    ("bidfloorcur", |i, f| s(&i.bidfloorcur, f)),
+    ("clickbrowser", |i, f| flag(&i.clickbrowser, f)),
+    ("secure", |i, f| flag(&i.secure, f)),
+    ("iframebuster", |i, f| strs(&i.iframebuster, f)),
+    ("exp", |i, f| int(i.exp, f)),
+];
+
+const BANNER: &[(&str, Getter<Banner>)] = &[
+    ("w", |b, f| int(b.w, f)),
+    ("h", |b, f| int(b.h, f)),
+    ("format", sizes),
+    ("id", |b, f| s(&b.id, f)),
+    ("pos", |b, f| en(b.pos, f)),
+    ("btype", |b, f| ens(&b.btype, f)),
+    ("battr", |b, f| ens(&b.battr, f)),
+    ("mimes", |b, f| strs(&b.mimes, f)),
+    ("topframe", |b, f| flag(&b.topframe, f)),
+    ("expdir", |b, f| ens(&b.expdir, f)),
+    ("api", |b, f| ens(&b.api, f)),
+    ("vcm", |b, f| flag(&b.vcm, f)),
+];
+
+const VIDEO: &[(&str, Getter<Video>)] = &[
+    ("mimes", |v, f| strs(&v.mimes, f)),
+    ("minduration", |v, f| int(v.minduration, f)),
+    ("maxduration", |v, f| int(v.maxduration, f)),
+    ("startdelay", |v, f| int(v.startdelay, f)),
+    ("protocols", |v, f| ens(&v.protocols, f)),
+    ("w", |v, f| int(v.w, f)),
+    ("h", |v, f| int(v.h, f)),
+    ("placement", |v, f| en(v.placement, f)),
+    ("linearity", |v, f| en(v.linearity, f)),
+    ("skip", |v, f| flag(&v.skip, f)),
+    ("skipmin", |v, f| int(v.skipmin, f)),
+    ("skipafter", |v, f| int(v.skipafter, f)),
+    ("sequence", |v, f| int(v.sequence, f)),
+    ("battr", |v, f| ens(&v.battr, f)),
+    ("maxextended", |v, f| int(v.maxextended, f)),
+    ("minbitrate", |v, f| int(v.minbitrate, f)),
+    ("maxbitrate", |v, f| int(v.maxbitrate, f)),
+    ("boxingallowed", |v, f| flag(&v.boxingallowed, f)),
+    ("playbackmethod", |v, f| ens(&v.playbackmethod, f)),
+    ("playbackend", |v, f| en(v.playbackend, f)),
+    ("delivery", |v, f| ens(&v.delivery, f)),
+    ("pos", |v, f| en(v.pos, f)),
+    ("api", |v, f| ens(&v.api, f)),
+    ("companiontype", |v, f| ens(&v.companiontype, f)),
+];
+
+const AUDIO: &[(&str, Getter<Audio>)] = &[
+    ("mimes", |a, f| strs(&a.mimes, f)),
+    ("minduration", |a, f| int(a.minduration, f)),
+    ("maxduration", |a, f| int(a.maxduration, f)),
+    ("protocols", |a, f| ens(&a.protocols, f)),
+    ("startdelay", |a, f| int(a.startdelay, f)),
+    ("sequence", |a, f| int(a.sequence, f)),
+    ("battr", |a, f| ens(&a.battr, f)),
+    ("maxextended", |a, f| int(a.maxextended, f)),
+    ("minbitrate", |a, f| int(a.minbitrate, f)),
+    ("maxbitrate", |a, f| int(a.maxbitrate, f)),
+    ("delivery", |a, f| ens(&a.delivery, f)),
+    ("api", |a, f| ens(&a.api, f)),
+    ("companiontype", |a, f| ens(&a.companiontype, f)),
+    ("maxseq", |a, f| int(a.maxseq, f)),
+    ("feed", |a, f| en(a.feed, f)),
+    ("stitched", |a, f| flag(&a.stitched, f)),
+    ("nvol", |a, f| en(a.nvol, f)),
+];
+
+const NATIVE: &[(&str, Getter<Native>)] = &[
+    ("ver", |n, f| s(&n.ver, f)),
+    ("api", |n, f| ens(&n.api, f)),
+    ("battr", |n, f| ens(&n.battr, f)),
+];
+
+const PMP: &[(&str, Getter<Pmp>)] = &[
+    ("private_auction", |p, f| flag(&p.private_auction, f)),
+    ("deals.id", |p, f| {
+        p.deals.iter().flatten().any(|d| f(Val::Str(&d.id)))
+    }),
+    ("deals.bidfloor", |p, f| {
+        p.deals.iter().flatten().any(|d| float(d.bidfloor, f))
+    }),
+    ("deals.at", |p, f| {
+        p.deals.iter().flatten().any(|d| en(d.at, f))
+    }),
+    ("deals.wseat", |p, f| {
+        p.deals.iter().flatten().any(|d| strs(&d.wseat, f))
+    }),
+    ("deals.wadomain", |p, f| {
+        p.deals.iter().flatten().any(|d| strs(&d.wadomain, f))
+    }),
+];
+
+const SITE: &[(&str, Getter<Site>)] = &[
+    ("id", |x, f| s(&x.id, f)),
+    ("name", |x, f| s(&x.name, f)),
+    ("domain", |x, f| s(&x.domain, f)),
+    ("cat", |x, f| strs(&x.cat, f)),
+    ("sectioncat", |x, f| strs(&x.sectioncat, f)),
+    ("pagecat", |x, f| strs(&x.pagecat, f)),
+    ("page", |x, f| s(&x.page, f)),
+    ("ref", |x, f| s(&x.r#ref, f)),
+    ("search", |x, f| s(&x.search, f)),
+    ("privacypolicy", |x, f| flag(&x.privacypolicy, f)),
+    ("keywords", |x, f| s(&x.keywords, f)),
+    ("mobile", |x, f| flag(&x.mobile, f)),
+];
+
+const APP: &[(&str, Getter<App>)] = &[
+    ("id", |x, f| s(&x.id, f)),
+    ("name", |x, f| s(&x.name, f)),
+    ("domain", |x, f| s(&x.domain, f)),
+    ("cat", |x, f| strs(&x.cat, f)),
+    ("sectioncat", |x, f| strs(&x.sectioncat, f)),
+    ("pagecat", |x, f| strs(&x.pagecat, f)),
+    ("ver", |x, f| s(&x.ver, f)),
+    ("bundle", |x, f| s(&x.bundle, f)),
+    ("privacypolicy", |x, f| flag(&x.privacypolicy, f)),
+    ("paid", |x, f| flag(&x.paid, f)),
+    ("keywords", |x, f| s(&x.keywords, f)),
+    ("storeurl", |x, f| s(&x.storeurl, f)),
+];
+
+const PUBLISHER: &[(&str, Getter<Publisher>)] = &[
+    ("id", |p, f| s(&p.id, f)),
+    ("name", |p, f| s(&p.name, f)),
+    ("cat", |p, f| strs(&p.cat, f)),
+    ("domain", |p, f| s(&p.domain, f)),
+];
+
+const CONTENT: &[(&str, Getter<Content>)] = &[
+    ("id", |c, f| s(&c.id, f)),
+    ("episode", |c, f| int(c.episode, f)),
+    ("title", |c, f| s(&c.title, f)),
+    ("series", |c, f| s(&c.series, f)),
+    ("season", |c, f| s(&c.season, f)),
+    ("artist", |c, f| s(&c.artist, f)),
+    ("genre", |c, f| s(&c.genre, f)),
+    ("album", |c, f| s(&c.album, f)),
+    ("isrc", |c, f| s(&c.isrc, f)),
+    ("producer.id", |c, f| {
+        c.producer.as_ref().is_some_and(|p| s(&p.id, f))
+    }),
+    ("producer.name", |c, f| {
+        c.producer.as_ref().is_some_and(|p| s(&p.name, f))
+    }),
+    ("producer.domain", |c, f| {
+        c.producer.as_ref().is_some_and(|p| s(&p.domain, f))
+    }),
+    ("producer.cat", |c, f| {
+        c.producer.as_ref().is_some_and(|p| strs(&p.cat, f))
+    }),
+    ("url", |c, f| s(&c.url, f)),
+    ("cat", |c, f| strs(&c.cat, f)),
+    ("prodq", |c, f| en(c.prodq, f)),
+    ("context", |c, f| en(c.context, f)),
+    ("contentrating", |c, f| s(&c.contentrating, f)),
+    ("userrating", |c, f| s(&c.userrating, f)),
+    ("qagmediarating", |c, f| en(c.qagmediarating, f)),
+    ("keywords", |c, f| s(&c.keywords, f)),
+    ("livestream", |c, f| flag(&c.livestream, f)),
+    ("sourcerelationship", |c, f| flag(&c.sourcerelationship, f)),
+    ("len", |c, f| int(c.len, f)),
+    ("language", |c, f| s(&c.language, f)),
+    ("embeddable", |c, f| flag(&c.embeddable, f)),
+    ("data.id", |c, f| {
+        c.data.iter().flatten().any(|d| s(&d.id, f))
+    }),
+    ("data.segment.id", |c, f| {
+        c.data
+            .iter()
+            .flatten()
+            .flat_map(|d| d.segment.iter().flatten())
+            .any(|seg| s(&seg.id, f))
+    }),
+];
+
+const DEVICE: &[(&str, Getter<Device>)] = &[
+    ("dnt", |d, f| flag(&d.dnt, f)),
+    ("lmt", |d, f| flag(&d.lmt, f)),
+    ("ua", |d, f| s(&d.ua, f)),
+    ("ip", |d, f| s(&d.ip, f)),
+    ("ipv6", |d, f| s(&d.ipv6, f)),
+    ("devicetype", |d, f| en(d.devicetype, f)),
+    ("make", |d, f| s(&d.make, f)),
+    ("model", |d, f| s(&d.model, f)),
+    ("os", |d, f| s(&d.os, f)),
+    ("osv", |d, f| s(&d.osv, f)),
+    ("hwv", |d, f| s(&d.hwv, f)),
+    ("w", |d, f| int(d.w, f)),
+    ("h", |d, f| int(d.h, f)),
+    ("ppi", |d, f| int(d.ppi, f)),
+    ("pxratio", |d, f| float(d.pxratio, f)),
+    ("js", |d, f| flag(&d.js, f)),
+    ("geofetch", |d, f| flag(&d.geofetch, f)),
+    ("flashver", |d, f| s(&d.flashver, f)),
+    ("language", |d, f| s(&d.language, f)),
+    ("carrier", |d, f| s(&d.carrier, f)),
+    ("mccmnc", |d, f| s(&d.mccmnc, f)),
+    ("connectiontype", |d, f| en(d.connectiontype, f)),
+    ("ifa", |d, f| s(&d.ifa, f)),
+    ("didsha1", |d, f| s(&d.didsha1, f)),
+    ("didmd5", |d, f| s(&d.didmd5, f)),
+    ("dpidsha1", |d, f| s(&d.dpidsha1, f)),
+    ("dpidmd5", |d, f| s(&d.dpidmd5, f)),
+    ("macsha1", |d, f| s(&d.macsha1, f)),
+    ("macmd5", |d, f| s(&d.macmd5, f)),
+];
+
+const GEO: &[(&str, Getter<Geo>)] = &[
+    ("lat", |g, f| float(g.lat, f)),
+    ("lon", |g, f| float(g.lon, f)),
+    ("country", |g, f| s(&g.country, f)),
+    ("region", |g, f| s(&g.region, f)),
+    ("regionfips104", |g, f| s(&g.regionfips104, f)),
+    ("metro", |g, f| s(&g.metro, f)),
+    ("city", |g, f| s(&g.city, f)),
+    ("zip", |g, f| s(&g.zip, f)),
+    ("type", |g, f| en(g.r#type, f)),
+    ("accuracy", |g, f| int(g.accuracy, f)),
+    ("lastfix", |g, f| int(g.lastfix, f)),
+    ("ipservice", |g, f| en(g.ipservice, f)),
+    ("utcoffset", |g, f| int(g.utcoffset, f)),
+];
+
+const USER: &[(&str, Getter<User>)] = &[
+    ("id", |u, f| s(&u.id, f)),
+    ("buyeruid", |u, f| s(&u.buyeruid, f)),
+    ("yob", |u, f| int(u.yob, f)),
+    ("gender", |u, f| s(&u.gender, f)),
+    ("keywords", |u, f| s(&u.keywords, f)),
+    ("customdata", |u, f| s(&u.customdata, f)),
+    ("data.id", |u, f| {
+        u.data.iter().flatten().any(|d| s(&d.id, f))
+    }),
+    ("data.segment.id", |u, f| {
+        u.data
+            .iter()
+            .flatten()
+            .flat_map(|d| d.segment.iter().flatten())
+            .any(|seg| s(&seg.id, f))
+    }),
+    // Like `User::eids_or_ext`, read `ext.eids` when the field is absent.
+    ("eids.source", |u, f| match &u.eids {
+        Some(eids) => eids.iter().any(|e| f(Val::Str(&e.source))),
+        None => u
+            .ext
+            .as_ref()
+            .and_then(|ext| ext.get("eids"))
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.get("source")?.as_str())
+            .any(|source| f(Val::Str(source))),
+    }),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = r#"device.geo.country in ["USA","CAN"] and imp.banner.format contains 300x250 and not site.cat intersects ["IAB25"]"#;
+
+    fn request() -> BidRequest {
+        serde_json::from_str(
+            r#"{"id":"1","at":1,
+            "imp":[
+                {"id":"a","banner":{"format":[{"w":300,"h":250}]},"bidfloor":1.5,"ext":{"gpid":"/x"}},
+                {"id":"b","video":{"mimes":["video/mp4"],"w":640,"h":480}}],
+            "site":{"cat":["IAB1"],"publisher":{"id":"p"}},
+            "device":{"devicetype":3,"geo":{"country":"USA"},"ext":{"ifa_type":"aaid"}},
+            "regs":{"coppa":0}}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn evaluates_the_example_rule() {
+        let mut request = request();
+        let rule = Rule::parse(EXAMPLE).unwrap();
+        let ids: Vec<&str> = request
+            .matching_imps(&rule)
+            .map(|i| i.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["a"]);
+        assert_eq!(rule.to_string(), EXAMPLE);
+
+        request.site.as_mut().unwrap().cat = Some(vec!["IAB25".to_string()]);
+        assert_eq!(request.matching_imps(&rule).count(), 0);
+        request.site.as_mut().unwrap().cat = None;
+        request
+            .device
+            .as_mut()
+            .unwrap()
+            .geo
+            .as_mut()
+            .unwrap()
+            .country = Some("MEX".to_string());
+        assert_eq!(request.matching_imps(&rule).count(), 0);
+    }
+
+    #[test]
+    fn evaluates_predicates() {
+        let request = request();
+        let eval =
+            |rule: &str, imp: usize| Rule::parse(rule).unwrap().eval(&request, &request.imp[imp]);
+        assert!(eval("device.devicetype == CONNECTED_TV", 0));
+        assert!(eval("device.devicetype == 3 and at == FIRST_PRICE", 0));
+        assert!(eval("imp.bidfloor >= 1.5 and imp.bidfloor < 2", 0));
+        assert!(!eval("imp.bidfloor > 1.5", 0));
+        assert!(eval("imp.video and imp.video.w >= 640", 1));
+        assert!(!eval("imp.video", 0));
+        assert!(eval("imp.video.mimes contains \"video/mp4\"", 1));
+        assert!(eval("imp.ext.gpid == \"/x\"", 0));
+        assert!(eval("device.ext.ifa_type exists", 0));
+        assert!(!eval("user.ext.eids exists", 0));
+        assert!(!eval("user.eids.source == \"id5-sync.com\"", 0));
+        assert!(eval("publisher.id == \"p\" and not regs.coppa", 0));
+        assert!(eval(
+            "(site.cat contains \"IAB1\" or app) and user.id != \"u\"",
+            0
+        ));
+    }
+
+    #[test]
+    fn reads_eids_from_the_field_or_ext() {
+        let mut request = request();
+        let rule = Rule::parse("user.eids.source in [\"id5-sync.com\"]").unwrap();
+        request.user = Some(
+            serde_json::from_str(r#"{"ext":{"eids":[{"source":"id5-sync.com","uids":[]}]}}"#)
+                .unwrap(),
+        );
+        assert!(rule.eval(&request, &request.imp[0]));
+
+        let user = request.user.as_mut().unwrap();
+        user.eids = serde_json::from_str(r#"[{"source":"liveramp.com","uids":[]}]"#).unwrap();
+        assert!(!rule.eval(&request, &request.imp[0]));
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        let err = Rule::parse("device.nope == 1").unwrap_err();
+        assert_eq!(err.offset, 0);
+        assert!(Rule::parse("device.os ==").is_err());
+        assert!(Rule::parse("device.os == \"x").is_err());
+        assert!(Rule::parse("device.os and (").is_err());
+        assert!(Rule::parse("device.os in \"x\"").is_err());
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let nested = |n| format!("{}device.os == \"x\"{}", "(".repeat(n), ")".repeat(n));
+        assert!(Rule::parse(&nested(MAX_NESTING)).is_ok());
+        let err = Rule::parse(&nested(MAX_NESTING + 1)).unwrap_err();
+        assert_eq!(err.offset, MAX_NESTING);
+        assert_eq!(err.message, "rule nested too deeply");
+        assert!(Rule::parse(&"(".repeat(100_000)).is_err());
+        assert!(Rule::parse(&"not ".repeat(100_000)).is_err());
+    }
+}
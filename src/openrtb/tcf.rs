@@ -0,0 +1,806 @@
+//! GDPR signals and IAB TCF v2 consent strings.
+//!
+//! OpenRTB 2.5 carries GDPR applicability in `regs.ext.gdpr` and the TCF
+//! consent string in `user.ext.consent`. This module exposes both as typed
+//! values, decodes the consent string, and answers whether a vendor may
+//! process data for a purpose, using a locally supplied Global Vendor List.
+
+use super::bid_request::{Regs, User};
+use super::BidRequest;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Error returned when a TC string or vendor list cannot be decoded.
+#[derive(Debug)]
+pub enum TcfError {
+    /// A segment is not valid base64url.
+    InvalidBase64,
+    /// The core segment version is not 2.
+    UnsupportedVersion(u8),
+    /// A segment ended before all of its fields were read.
+    Truncated,
+    /// A field holds a value that is not allowed, e.g. a reversed range.
+    InvalidField(&'static str),
+    /// The Global Vendor List could not be read or parsed.
+    VendorList(String),
+}
+
+impl fmt::Display for TcfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TcfError::InvalidBase64 => f.write_str("TC string is not valid base64url"),
+            TcfError::UnsupportedVersion(v) => write!(f, "unsupported TC string version {}", v),
+            TcfError::Truncated => f.write_str("TC string is truncated"),
+            TcfError::InvalidField(name) => write!(f, "invalid TC string field {}", name),
+            TcfError::VendorList(e) => write!(f, "invalid Global Vendor List: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TcfError {}
+
+/// A set of vendor IDs.
+#[derive(Clone, Debug, Default)]
+pub struct VendorSet {
+    bits: Vec<u64>,
+}
+
+impl VendorSet {
+    /// Whether the set holds `vendor_id`.
+    pub fn contains(&self, vendor_id: u16) -> bool {
+        let i = vendor_id as usize;
+        self.bits
+            .get(i / 64)
+            .is_some_and(|word| word & (1 << (i % 64)) != 0)
+    }
+
+    /// Adds `vendor_id` to the set.
+    pub fn insert(&mut self, vendor_id: u16) {
+        self.insert_range(vendor_id, vendor_id);
+    }
+
+    /// Inserts the IDs from `start` to `end` inclusive, a word at a time.
+    pub fn insert_range(&mut self, start: u16, end: u16) {
+        if end < start {
+            return;
+        }
+        let (start, end) = (start as usize, end as usize);
+        if self.bits.len() <= end / 64 {
+            self.bits.resize(end / 64 + 1, 0);
+        }
+        for word in start / 64..=end / 64 {
+            let low = if word == start / 64 { start % 64 } else { 0 };
+            let high = if word == end / 64 { end % 64 } else { 63 };
+            self.bits[word] |= (u64::MAX << low) & (u64::MAX >> (63 - high));
+        }
+    }
+
+    /// Vendor IDs in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = u16> + '_ {
+        (0..self.bits.len() * 64)
+            .filter(move |&i| self.bits[i / 64] & (1 << (i % 64)) != 0)
+            .map(|i| i as u16)
+    }
+
+    /// Whether the set holds no IDs.
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|&word| word == 0)
+    }
+}
+
+/// Sets are equal when they hold the same IDs, however many trailing empty
+/// words either has.
+impl PartialEq for VendorSet {
+    fn eq(&self, other: &VendorSet) -> bool {
+        fn used(bits: &[u64]) -> &[u64] {
+            let len = bits
+                .iter()
+                .rposition(|&word| word != 0)
+                .map_or(0, |i| i + 1);
+            &bits[..len]
+        }
+        used(&self.bits) == used(&other.bits)
+    }
+}
+
+impl Eq for VendorSet {}
+
+/// Publisher restriction types.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RestrictionType {
+    /// Purpose flatly not allowed by the publisher.
+    NotAllowed,
+    /// Vendors must rely on consent for the purpose.
+    RequireConsent,
+    /// Vendors must rely on legitimate interest for the purpose.
+    RequireLegitimateInterest,
+}
+
+/// A publisher restriction on a purpose for a set of vendors.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PublisherRestriction {
+    /// The restricted purpose (1-based).
+    pub purpose_id: u8,
+    /// What the restriction requires of the vendors.
+    pub restriction_type: RestrictionType,
+    /// Vendors the restriction applies to.
+    pub vendors: VendorSet,
+}
+
+/// The optional Publisher Purposes Transparency and Consent segment.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PublisherTc {
+    /// Purposes the user consented to for the publisher, as a bitmask where
+    /// bit `n - 1` is purpose `n`.
+    pub purpose_consents: u32,
+    /// Purposes established for the publisher on legitimate interest.
+    pub purpose_legitimate_interests: u32,
+    /// Number of custom purposes defined by the publisher.
+    pub num_custom_purposes: u8,
+    /// Custom purposes the user consented to, as a bitmask like
+    /// `purpose_consents`.
+    pub custom_purpose_consents: u64,
+    /// Custom purposes established on legitimate interest.
+    pub custom_purpose_legitimate_interests: u64,
+}
+
+/// A decoded TCF v2 consent string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TcString {
+    /// Version of the core segment, always 2.
+    pub version: u8,
+    /// When the string was first created, to the decisecond.
+    pub created: SystemTime,
+    /// When the string was last updated, to the decisecond.
+    pub last_updated: SystemTime,
+    /// ID of the Consent Management Platform that wrote the string.
+    pub cmp_id: u16,
+    /// Version of the CMP that wrote the string, as assigned by the CMP.
+    pub cmp_version: u16,
+    /// The CMP screen on which consent was given.
+    pub consent_screen: u8,
+    /// ISO 639-1 language of the consent UI, e.g. "EN".
+    pub consent_language: String,
+    /// Version of the Global Vendor List the CMP used.
+    pub vendor_list_version: u16,
+    /// Version of the TCF policies the string was created under.
+    pub tcf_policy_version: u8,
+    /// Whether the consent applies to one service only, rather than being
+    /// shared across services.
+    pub is_service_specific: bool,
+    /// Whether the CMP used texts other than the standard ones.
+    pub use_non_standard_texts: bool,
+    /// Special features opted in, as a bitmask where bit `n - 1` is feature `n`.
+    pub special_feature_opt_ins: u16,
+    /// Purposes consented, as a bitmask where bit `n - 1` is purpose `n`.
+    pub purpose_consents: u32,
+    /// Purposes whose legitimate interest was disclosed and not objected to.
+    pub purpose_legitimate_interests: u32,
+    /// Whether purpose 1 was not disclosed, as allowed in some countries.
+    pub purpose_one_treatment: bool,
+    /// ISO 3166-1 alpha-2 country of the publisher, e.g. "DE".
+    pub publisher_cc: String,
+    /// Vendors the user consented to.
+    pub vendor_consents: VendorSet,
+    /// Vendors whose legitimate interest was disclosed and not objected to.
+    pub vendor_legitimate_interests: VendorSet,
+    /// Restrictions the publisher placed on purposes for given vendors.
+    pub publisher_restrictions: Vec<PublisherRestriction>,
+    /// Vendors disclosed to the user, if the segment is present.
+    pub disclosed_vendors: Option<VendorSet>,
+    /// Vendors allowed by the publisher, if the segment is present; other
+    /// vendors may not process personal data at all.
+    pub allowed_vendors: Option<VendorSet>,
+    /// The publisher's own purposes, if the segment is present.
+    pub publisher_tc: Option<PublisherTc>,
+}
+
+impl TcString {
+    /// Decodes a TCF v2 consent string, including its optional segments.
+    pub fn parse(s: &str) -> Result<TcString, TcfError> {
+        let mut segments = s.split('.');
+        let core = decode_base64url(segments.next().unwrap_or_default())?;
+        let mut r = BitReader::new(&core);
+
+        let version = r.read(6)? as u8;
+        if version != 2 {
+            return Err(TcfError::UnsupportedVersion(version));
+        }
+        let mut tc = TcString {
+            version,
+            created: r.read_time()?,
+            last_updated: r.read_time()?,
+            cmp_id: r.read(12)? as u16,
+            cmp_version: r.read(12)? as u16,
+            consent_screen: r.read(6)? as u8,
+            consent_language: r.read_letters()?,
+            vendor_list_version: r.read(12)? as u16,
+            tcf_policy_version: r.read(6)? as u8,
+            is_service_specific: r.read_bool()?,
+            use_non_standard_texts: r.read_bool()?,
+            special_feature_opt_ins: r.read_flags(12)? as u16,
+            purpose_consents: r.read_flags(24)? as u32,
+            purpose_legitimate_interests: r.read_flags(24)? as u32,
+            purpose_one_treatment: r.read_bool()?,
+            publisher_cc: r.read_letters()?,
+            vendor_consents: r.read_vendor_section()?,
+            vendor_legitimate_interests: r.read_vendor_section()?,
+            publisher_restrictions: Vec::new(),
+            disclosed_vendors: None,
+            allowed_vendors: None,
+            publisher_tc: None,
+        };
+        for _ in 0..r.read(12)? {
+            let purpose_id = r.read(6)? as u8;
+            let restriction_type = match r.read(2)? {
+                0 => RestrictionType::NotAllowed,
+                1 => RestrictionType::RequireConsent,
+                2 => RestrictionType::RequireLegitimateInterest,
+                _ => return Err(TcfError::InvalidField("RestrictionType")),
+            };
+            let mut vendors = VendorSet::default();
+            r.read_ranges(&mut vendors)?;
+            tc.publisher_restrictions.push(PublisherRestriction {
+                purpose_id,
+                restriction_type,
+                vendors,
+            });
+        }
+
+        for segment in segments {
+            let bytes = decode_base64url(segment)?;
+            let mut r = BitReader::new(&bytes);
+            match r.read(3)? {
+                1 => tc.disclosed_vendors = Some(r.read_vendor_section()?),
+                2 => tc.allowed_vendors = Some(r.read_vendor_section()?),
+                3 => {
+                    let purpose_consents = r.read_flags(24)? as u32;
+                    let purpose_legitimate_interests = r.read_flags(24)? as u32;
+                    let n = r.read(6)? as usize;
+                    tc.publisher_tc = Some(PublisherTc {
+                        purpose_consents,
+                        purpose_legitimate_interests,
+                        num_custom_purposes: n as u8,
+                        custom_purpose_consents: r.read_flags(n)?,
+                        custom_purpose_legitimate_interests: r.read_flags(n)?,
+                    });
+                }
+                _ => return Err(TcfError::InvalidField("SegmentType")),
+            }
+        }
+        Ok(tc)
+    }
+
+    /// Whether the user consented to `purpose` (1-based).
+    pub fn purpose_consent(&self, purpose: u8) -> bool {
+        has_flag(self.purpose_consents as u64, purpose)
+    }
+
+    /// Whether legitimate interest was established for `purpose` (1-based).
+    pub fn purpose_legitimate_interest(&self, purpose: u8) -> bool {
+        has_flag(self.purpose_legitimate_interests as u64, purpose)
+    }
+
+    /// Whether the user opted in to special feature `feature` (1-based).
+    pub fn special_feature_opt_in(&self, feature: u8) -> bool {
+        has_flag(self.special_feature_opt_ins as u64, feature)
+    }
+
+    /// Whether the user consented to vendor `vendor_id`.
+    pub fn vendor_consent(&self, vendor_id: u16) -> bool {
+        self.vendor_consents.contains(vendor_id)
+    }
+
+    /// Whether legitimate interest was established for vendor `vendor_id`.
+    pub fn vendor_legitimate_interest(&self, vendor_id: u16) -> bool {
+        self.vendor_legitimate_interests.contains(vendor_id)
+    }
+
+    /// The publisher restriction on `purpose` for `vendor_id`, if any.
+    pub fn restriction(&self, purpose: u8, vendor_id: u16) -> Option<RestrictionType> {
+        self.publisher_restrictions
+            .iter()
+            .find(|r| r.purpose_id == purpose && r.vendors.contains(vendor_id))
+            .map(|r| r.restriction_type)
+    }
+
+    /// Whether `vendor_id` may process personal data for `purpose` under
+    /// this consent string. The vendor's declared legal bases come from
+    /// `gvl`; flexible purposes follow the publisher restrictions, and
+    /// purpose 1 can never rely on legitimate interest. When the string has
+    /// an Allowed Vendors segment, vendors missing from it are refused.
+    pub fn may_process(&self, gvl: &GlobalVendorList, vendor_id: u16, purpose: u8) -> bool {
+        if let Some(allowed) = &self.allowed_vendors {
+            if !allowed.contains(vendor_id) {
+                return false;
+            }
+        }
+        let vendor = match gvl.vendors.get(&vendor_id) {
+            Some(vendor) if vendor.deleted_date.is_none() => vendor,
+            _ => return false,
+        };
+        let restriction = self.restriction(purpose, vendor_id);
+        let flexible = vendor.flexible_purposes.contains(&purpose);
+        let consent_basis = match restriction {
+            Some(RestrictionType::NotAllowed) => return false,
+            _ if vendor.purposes.contains(&purpose) => {
+                !(flexible && restriction == Some(RestrictionType::RequireLegitimateInterest))
+            }
+            _ if vendor.leg_int_purposes.contains(&purpose) => {
+                flexible && restriction == Some(RestrictionType::RequireConsent)
+            }
+            _ => return false,
+        };
+        if consent_basis {
+            self.purpose_consent(purpose) && self.vendor_consent(vendor_id)
+        } else {
+            purpose != 1
+                && self.purpose_legitimate_interest(purpose)
+                && self.vendor_legitimate_interest(vendor_id)
+        }
+    }
+}
+
+fn has_flag(flags: u64, id: u8) -> bool {
+    (1..=64).contains(&id) && flags & (1 << (id - 1)) != 0
+}
+
+/// A vendor entry of the Global Vendor List.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GvlVendor {
+    /// The vendor ID, as used in TC strings.
+    pub id: u16,
+    /// Display name of the vendor.
+    #[serde(default)]
+    pub name: String,
+    /// Purposes the vendor relies on consent for.
+    #[serde(default)]
+    pub purposes: Vec<u8>,
+    /// Purposes the vendor relies on legitimate interest for.
+    #[serde(default)]
+    pub leg_int_purposes: Vec<u8>,
+    /// Purposes whose legal basis the publisher may change.
+    #[serde(default)]
+    pub flexible_purposes: Vec<u8>,
+    /// Special purposes, which need no legal basis from the user.
+    #[serde(default)]
+    pub special_purposes: Vec<u8>,
+    /// Features the vendor uses.
+    #[serde(default)]
+    pub features: Vec<u8>,
+    /// Special features, which need the user's opt-in.
+    #[serde(default)]
+    pub special_features: Vec<u8>,
+    /// Set when the vendor was removed from the list.
+    #[serde(default)]
+    pub deleted_date: Option<String>,
+}
+
+/// The IAB Global Vendor List (`vendor-list.json`), loaded locally.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GlobalVendorList {
+    /// Version of the list, compared with `TcString::vendor_list_version`.
+    #[serde(default)]
+    pub vendor_list_version: u32,
+    /// Version of the TCF policies the list was published under.
+    #[serde(default)]
+    pub tcf_policy_version: u32,
+    /// Vendors keyed by vendor ID.
+    #[serde(default)]
+    pub vendors: HashMap<u16, GvlVendor>,
+}
+
+impl GlobalVendorList {
+    /// Parses a Global Vendor List from its JSON text.
+    pub fn from_json(json: &str) -> Result<GlobalVendorList, TcfError> {
+        serde_json::from_str(json).map_err(|e| TcfError::VendorList(e.to_string()))
+    }
+
+    /// Reads a Global Vendor List from a local file.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<GlobalVendorList, TcfError> {
+        let json =
+            std::fs::read_to_string(path).map_err(|e| TcfError::VendorList(e.to_string()))?;
+        GlobalVendorList::from_json(&json)
+    }
+}
+
+impl Regs {
+    /// GDPR applicability from `ext.gdpr`, where 0 = no and 1 = yes.
+    pub fn gdpr(&self) -> Option<bool> {
+        match self.ext.as_ref()?.get("gdpr")?.as_u64()? {
+            0 => Some(false),
+            _ => Some(true),
+        }
+    }
+}
+
+impl User {
+    /// The TCF consent string from `ext.consent`.
+    pub fn consent(&self) -> Option<&str> {
+        self.ext.as_ref()?.get("consent")?.as_str()
+    }
+
+    /// The decoded TCF consent string from `ext.consent`.
+    pub fn tc_string(&self) -> Option<Result<TcString, TcfError>> {
+        self.consent().map(TcString::parse)
+    }
+}
+
+impl BidRequest {
+    /// GDPR applicability from `regs.ext.gdpr`; `None` when unknown.
+    pub fn gdpr_applies(&self) -> Option<bool> {
+        self.regs.as_ref()?.gdpr()
+    }
+
+    /// Whether `vendor_id` may process personal data for `purpose` for this
+    /// request. Requests where GDPR does not apply (or is not signalled) are
+    /// allowed; otherwise a valid consent string is required.
+    pub fn vendor_may_process(&self, gvl: &GlobalVendorList, vendor_id: u16, purpose: u8) -> bool {
+        if self.gdpr_applies() != Some(true) {
+            return true;
+        }
+        match self.user.as_ref().and_then(User::tc_string) {
+            Some(Ok(tc)) => tc.may_process(gvl, vendor_id, purpose),
+            _ => false,
+        }
+    }
+}
+
+/// Decodes unpadded base64url (standard base64 and padding are accepted too).
+pub(crate) fn decode_base64url(s: &str) -> Result<Vec<u8>, TcfError> {
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in s.trim_end_matches('=').bytes() {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'-' | b'+' => 62,
+            b'_' | b'/' => 63,
+            _ => return Err(TcfError::InvalidBase64),
+        };
+        acc = (acc << 6) | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+/// Reads big-endian bit fields.
+pub(crate) struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> BitReader<'a> {
+        BitReader { bytes, pos: 0 }
+    }
+
+    /// Reads an `n`-bit unsigned integer, `n <= 64`.
+    pub(crate) fn read(&mut self, n: usize) -> Result<u64, TcfError> {
+        if self.pos + n > self.bytes.len() * 8 {
+            return Err(TcfError::Truncated);
+        }
+        let mut v = 0u64;
+        for _ in 0..n {
+            let bit = self.bytes[self.pos / 8] >> (7 - self.pos % 8) & 1;
+            v = (v << 1) | bit as u64;
+            self.pos += 1;
+        }
+        Ok(v)
+    }
+
+    pub(crate) fn read_bool(&mut self) -> Result<bool, TcfError> {
+        Ok(self.read(1)? == 1)
+    }
+
+    /// Reads `n` flags where the first bit read is id 1, returned as a
+    /// bitmask where bit `id - 1` is set.
+    pub(crate) fn read_flags(&mut self, n: usize) -> Result<u64, TcfError> {
+        let mut flags = 0;
+        for i in 0..n {
+            if self.read_bool()? {
+                flags |= 1 << i;
+            }
+        }
+        Ok(flags)
+    }
+
+    /// Reads a 36-bit deciseconds timestamp.
+    fn read_time(&mut self) -> Result<SystemTime, TcfError> {
+        Ok(UNIX_EPOCH + Duration::from_millis(self.read(36)? * 100))
+    }
+
+    /// Reads two 6-bit letters where 0 = 'A'.
+    fn read_letters(&mut self) -> Result<String, TcfError> {
+        let mut s = String::with_capacity(2);
+        for _ in 0..2 {
+            let v = self.read(6)? as u8;
+            if v > 25 {
+                return Err(TcfError::InvalidField("Letter"));
+            }
+            s.push((b'A' + v) as char);
+        }
+        Ok(s)
+    }
+
+    /// Reads a vendor section: MaxVendorId, then a bit field or ranges.
+    fn read_vendor_section(&mut self) -> Result<VendorSet, TcfError> {
+        let max_vendor_id = self.read(16)? as u16;
+        let mut vendors = VendorSet::default();
+        if self.read_bool()? {
+            self.read_ranges(&mut vendors)?;
+        } else {
+            for id in 1..=max_vendor_id {
+                if self.read_bool()? {
+                    vendors.insert(id);
+                }
+            }
+        }
+        Ok(vendors)
+    }
+
+    /// Reads NumEntries range entries into `vendors`.
+    fn read_ranges(&mut self, vendors: &mut VendorSet) -> Result<(), TcfError> {
+        for _ in 0..self.read(12)? {
+            let is_range = self.read_bool()?;
+            let start = self.read(16)? as u16;
+            let end = if is_range {
+                self.read(16)? as u16
+            } else {
+                start
+            };
+            if end < start {
+                return Err(TcfError::InvalidField("EndVendorId"));
+            }
+            vendors.insert_range(start, end);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Writes big-endian bit fields and encodes them, padded to whole bytes
+    /// as in real strings, as unpadded base64url.
+    #[derive(Default)]
+    pub(crate) struct Bits(Vec<bool>);
+
+    impl Bits {
+        pub(crate) fn put(&mut self, value: u64, n: usize) -> &mut Bits {
+            self.0.extend((0..n).rev().map(|i| value >> i & 1 == 1));
+            self
+        }
+
+        pub(crate) fn base64url(&self) -> String {
+            const ALPHABET: &[u8] =
+                b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+            let mut bits = self.0.clone();
+            bits.resize(bits.len().div_ceil(8) * 8, false);
+            bits.chunks(6)
+                .map(|chunk| {
+                    let v = chunk
+                        .iter()
+                        .enumerate()
+                        .fold(0, |v, (i, &bit)| v | (bit as usize) << (5 - i));
+                    ALPHABET[v] as char
+                })
+                .collect()
+        }
+    }
+
+    /// The core segment up to and including PublisherCC.
+    fn core_header(bits: &mut Bits) {
+        bits.put(2, 6) // Version
+            .put(16_000_000_000, 36) // Created
+            .put(16_000_000_001, 36) // LastUpdated
+            .put(7, 12) // CmpId
+            .put(1, 12) // CmpVersion
+            .put(1, 6) // ConsentScreen
+            .put(4, 6) // "E"
+            .put(13, 6) // "N"
+            .put(100, 12) // VendorListVersion
+            .put(2, 6) // TcfPolicyVersion
+            .put(0, 1) // IsServiceSpecific
+            .put(0, 1) // UseNonStandardTexts
+            .put(0b1000_0000_0000, 12) // SpecialFeatureOptIns: 1
+            .put(0b1100_0000_0000_0000_0000_0000, 24) // PurposesConsent: 1, 2
+            .put(0b0010_0000_0000_0000_0000_0000, 24) // PurposesLITransparency: 3
+            .put(0, 1) // PurposeOneTreatment
+            .put(3, 6) // "D"
+            .put(4, 6); // "E"
+    }
+
+    fn example() -> String {
+        let mut bits = Bits::default();
+        core_header(&mut bits);
+        // Vendor consents as a bit field up to vendor 10: vendors 2 and 10.
+        bits.put(10, 16).put(0, 1).put(0b01_0000_0001, 10);
+        // Vendor legitimate interests as one range: 5 to 8.
+        bits.put(8, 16).put(1, 1).put(1, 12);
+        bits.put(1, 1).put(5, 16).put(8, 16);
+        // Purpose 2 requires legitimate interest for vendor 10.
+        bits.put(1, 12)
+            .put(2, 6)
+            .put(2, 2)
+            .put(1, 12)
+            .put(0, 1)
+            .put(10, 16);
+        let core = bits.base64url();
+        // Disclosed vendors: 1 and 3.
+        let mut disclosed = Bits::default();
+        disclosed.put(1, 3).put(3, 16).put(0, 1).put(0b101, 3);
+        format!("{}.{}", core, disclosed.base64url())
+    }
+
+    #[test]
+    fn decodes_core_and_disclosed_segments() {
+        let tc = TcString::parse(&example()).unwrap();
+        assert_eq!(tc.version, 2);
+        assert_eq!(tc.created, UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+        assert_eq!(
+            tc.last_updated,
+            UNIX_EPOCH + Duration::from_millis(1_600_000_000_100)
+        );
+        assert_eq!((tc.cmp_id, tc.cmp_version, tc.consent_screen), (7, 1, 1));
+        assert_eq!(tc.consent_language, "EN");
+        assert_eq!(tc.vendor_list_version, 100);
+        assert_eq!(tc.tcf_policy_version, 2);
+        assert_eq!(tc.publisher_cc, "DE");
+        assert!(tc.special_feature_opt_in(1) && !tc.special_feature_opt_in(2));
+        assert!(tc.purpose_consent(1) && tc.purpose_consent(2) && !tc.purpose_consent(3));
+        assert!(tc.purpose_legitimate_interest(3) && !tc.purpose_legitimate_interest(1));
+        assert_eq!(tc.vendor_consents.iter().collect::<Vec<_>>(), [2, 10]);
+        assert_eq!(
+            tc.vendor_legitimate_interests.iter().collect::<Vec<_>>(),
+            [5, 6, 7, 8]
+        );
+        assert_eq!(
+            tc.restriction(2, 10),
+            Some(RestrictionType::RequireLegitimateInterest)
+        );
+        assert_eq!(tc.restriction(2, 9), None);
+        let disclosed = tc.disclosed_vendors.unwrap();
+        assert_eq!(disclosed.iter().collect::<Vec<_>>(), [1, 3]);
+        assert_eq!(tc.allowed_vendors, None);
+    }
+
+    #[test]
+    fn rejects_malformed_strings() {
+        let full = example();
+        let core = full.split('.').next().unwrap();
+        assert!(matches!(
+            TcString::parse(&core[..20]),
+            Err(TcfError::Truncated)
+        ));
+        assert!(matches!(
+            TcString::parse("C*"),
+            Err(TcfError::InvalidBase64)
+        ));
+        let mut v1 = Bits::default();
+        v1.put(1, 6).put(0, 36);
+        assert!(matches!(
+            TcString::parse(&v1.base64url()),
+            Err(TcfError::UnsupportedVersion(1))
+        ));
+
+        let mut reversed = Bits::default();
+        core_header(&mut reversed);
+        reversed
+            .put(9, 16)
+            .put(1, 1)
+            .put(1, 12)
+            .put(1, 1)
+            .put(9, 16)
+            .put(3, 16);
+        assert!(matches!(
+            TcString::parse(&reversed.base64url()),
+            Err(TcfError::InvalidField("EndVendorId"))
+        ));
+    }
+
+    #[test]
+    fn decodes_wide_ranges_quickly() {
+        // 4095 entries each covering every vendor ID.
+        let mut bits = Bits::default();
+        core_header(&mut bits);
+        bits.put(65535, 16).put(1, 1).put(4095, 12);
+        for _ in 0..4095 {
+            bits.put(1, 1).put(1, 16).put(65535, 16);
+        }
+        bits.put(0, 16).put(0, 1).put(0, 12);
+        let tc = TcString::parse(&bits.base64url()).unwrap();
+        assert!(tc.vendor_consent(1) && tc.vendor_consent(65535));
+        assert!(!tc.vendor_consent(0));
+        assert_eq!(tc.vendor_consents.iter().count(), 65535);
+    }
+
+    #[test]
+    fn vendor_sets_ignore_trailing_zero_words() {
+        let mut a = VendorSet::default();
+        a.insert(3);
+        let b = VendorSet {
+            bits: vec![1 << 3, 0, 0],
+        };
+        assert_eq!(a, b);
+        assert_eq!(VendorSet::default(), VendorSet { bits: vec![0] });
+        assert_ne!(a, VendorSet::default());
+    }
+
+    #[test]
+    fn inserts_ranges_across_words() {
+        let mut set = VendorSet::default();
+        set.insert_range(60, 130);
+        set.insert_range(200, 199);
+        assert_eq!(
+            set.iter().collect::<Vec<_>>(),
+            (60..=130).collect::<Vec<_>>()
+        );
+        assert!(!set.contains(59) && !set.contains(131) && !set.contains(200));
+        set.insert_range(u16::MAX, u16::MAX);
+        assert!(set.contains(u16::MAX));
+    }
+
+    #[test]
+    fn applies_vendor_list_and_restrictions() {
+        let tc = TcString::parse(&example()).unwrap();
+        let gvl = GlobalVendorList::from_json(
+            r#"{"vendorListVersion":100,"vendors":{
+                "2":{"id":2,"purposes":[1,2]},
+                "10":{"id":10,"purposes":[2],"flexiblePurposes":[2]},
+                "6":{"id":6,"legIntPurposes":[3]},
+                "7":{"id":7,"legIntPurposes":[1]},
+                "8":{"id":8,"purposes":[1],"deletedDate":"2020-01-01T00:00:00Z"}}}"#,
+        )
+        .unwrap();
+        assert!(tc.may_process(&gvl, 2, 1));
+        assert!(tc.may_process(&gvl, 2, 2));
+        // Restricted to legitimate interest, which purpose 2 lacks.
+        assert!(!tc.may_process(&gvl, 10, 2));
+        assert!(tc.may_process(&gvl, 6, 3));
+        // Purpose 1 never relies on legitimate interest.
+        assert!(!tc.may_process(&gvl, 7, 1));
+        assert!(!tc.may_process(&gvl, 8, 1));
+        assert!(!tc.may_process(&gvl, 3, 1));
+
+        let mut allowed = VendorSet::default();
+        allowed.insert(6);
+        let tc = TcString {
+            allowed_vendors: Some(allowed),
+            ..tc
+        };
+        assert!(!tc.may_process(&gvl, 2, 1));
+        assert!(tc.may_process(&gvl, 6, 3));
+    }
+
+    #[test]
+    fn reads_signals_from_request() {
+        let gvl =
+            GlobalVendorList::from_json(r#"{"vendors":{"2":{"id":2,"purposes":[1]}}}"#).unwrap();
+        let json = format!(
+            r#"{{"id":"1","imp":[],"regs":{{"ext":{{"gdpr":1}}}},"user":{{"ext":{{"consent":"{}"}}}}}}"#,
+            example()
+        );
+        let mut req: BidRequest = serde_json::from_str(&json).unwrap();
+        assert_eq!(req.gdpr_applies(), Some(true));
+        assert!(req.vendor_may_process(&gvl, 2, 1));
+        assert!(!req.vendor_may_process(&gvl, 3, 1));
+        req.user = None;
+        assert!(!req.vendor_may_process(&gvl, 2, 1));
+        req.regs = None;
+        assert_eq!(req.gdpr_applies(), None);
+        assert!(req.vendor_may_process(&gvl, 3, 1));
+    }
+}
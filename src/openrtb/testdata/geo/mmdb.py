@@ -0,0 +1,161 @@
+#!/usr/bin/env python3
+"""Writes the MaxMind DB fixtures used by the geo tests.
+
+city-24.mmdb is an IPv4 tree, city-28.mmdb and city-32.mmdb are IPv6 trees
+with IPv4 under ::/96. Each maps 1.2.3.0/24 to a US record and
+2001:db8::/32 (or 5.6.0.0/16 in the IPv4 tree) to a DE record whose values
+are reached through pointers of every size class but the 19-bit one.
+"""
+
+import ipaddress
+import struct
+from pathlib import Path
+
+
+def ctrl(kind, size):
+    if kind > 7:
+        head, ext = 0, bytes([kind - 7])
+    else:
+        head, ext = kind << 5, b""
+    if size < 29:
+        return bytes([head | size]) + ext
+    if size < 285:
+        return bytes([head | 29]) + ext + bytes([size - 29])
+    return bytes([head | 30]) + ext + (size - 285).to_bytes(2, "big")
+
+
+def enc(v):
+    if isinstance(v, bytes):  # already encoded, e.g. a pointer
+        return v
+    if isinstance(v, str):
+        b = v.encode()
+        return ctrl(2, len(b)) + b
+    if isinstance(v, float):
+        return ctrl(3, 8) + struct.pack(">d", v)
+    if isinstance(v, bool):
+        return ctrl(14, int(v))
+    if isinstance(v, int):
+        b = v.to_bytes(max(1, (v.bit_length() + 7) // 8), "big") if v else b""
+        return ctrl(5 if v < 1 << 16 else 6 if v < 1 << 32 else 9, len(b)) + b
+    if isinstance(v, dict):
+        return ctrl(7, len(v)) + b"".join(enc(k) + enc(x) for k, x in v.items())
+    if isinstance(v, list):
+        return ctrl(11, len(v)) + b"".join(enc(x) for x in v)
+    raise TypeError(v)
+
+
+def pointer(target, ss):
+    if ss == 0:
+        assert target < 1 << 11
+        return bytes([0x20 | (target >> 8), target & 0xFF])
+    if ss == 1:
+        t = target - 2048
+        assert 0 <= t < 1 << 19
+        return bytes([0x28 | (t >> 16)]) + (t & 0xFFFF).to_bytes(2, "big")
+    return bytes([0x38]) + target.to_bytes(4, "big")
+
+
+def data_section():
+    data = bytearray()
+    de = len(data)
+    data += enc({"iso_code": "DE"})
+    us = len(data)
+    data += enc(
+        {
+            "country": {"iso_code": "US"},
+            "subdivisions": [{"iso_code": "CA"}],
+            "location": {"metro_code": 807, "time_zone": "America/Los_Angeles"},
+            "city": {"names": {"en": "San Francisco", "de": "San Francisco"}},
+            "postal": {"code": "94107"},
+        }
+    )
+    data += enc("x" * 2100)
+    berlin = len(data)
+    data += enc("Berlin")
+    zip_code = len(data)
+    data += enc("10115")
+    berlin_record = len(data)
+    data += enc(
+        {
+            "country": pointer(de, 0),
+            "subdivisions": [{"iso_code": "BE"}],
+            "city": {"names": {"en": pointer(berlin, 1)}},
+            "postal": {"code": pointer(zip_code, 3)},
+            "location": {"latitude": 52.52, "accuracy_radius": 20},
+            "is_eu": True,
+        }
+    )
+    return bytes(data), us, berlin_record
+
+
+def tree(networks, ip_version):
+    nodes = [[None, None]]
+    for net, value in networks:
+        bits = int(net.network_address)
+        width = 32 if ip_version == 4 else 128
+        prefix = net.prefixlen + (96 if net.version == 4 and ip_version == 6 else 0)
+        node = 0
+        for i in range(prefix):
+            bit = (bits >> (width - 1 - i)) & 1
+            if i == prefix - 1:
+                nodes[node][bit] = ("data", value)
+            else:
+                if nodes[node][bit] is None:
+                    nodes.append([None, None])
+                    nodes[node][bit] = len(nodes) - 1
+                node = nodes[node][bit]
+    return nodes
+
+
+def record_value(r, node_count):
+    if r is None:
+        return node_count
+    if isinstance(r, tuple):
+        return node_count + 16 + r[1]
+    return r
+
+
+def write(path, record_size, ip_version):
+    data, us, de = data_section()
+    networks = [(ipaddress.ip_network("1.2.3.0/24"), us)]
+    if ip_version == 4:
+        networks.append((ipaddress.ip_network("5.6.0.0/16"), de))
+    else:
+        networks.append((ipaddress.ip_network("2001:db8::/32"), de))
+    nodes = tree(networks, ip_version)
+    n = len(nodes)
+    out = bytearray()
+    for left, right in nodes:
+        l, r = record_value(left, n), record_value(right, n)
+        if record_size == 24:
+            out += l.to_bytes(3, "big") + r.to_bytes(3, "big")
+        elif record_size == 28:
+            out += (l & 0xFFFFFF).to_bytes(3, "big")
+            out.append(((l >> 24) << 4) | (r >> 24))
+            out += (r & 0xFFFFFF).to_bytes(3, "big")
+        else:
+            out += l.to_bytes(4, "big") + r.to_bytes(4, "big")
+    out += bytes(16)
+    out += data
+    out += b"\xab\xcd\xefMaxMind.com"
+    out += enc(
+        {
+            "binary_format_major_version": 2,
+            "binary_format_minor_version": 0,
+            "build_epoch": 1700000000,
+            "database_type": "Test-City",
+            "description": {"en": "geo test fixture"},
+            "ip_version": ip_version,
+            "languages": ["de", "en"],
+            "node_count": n,
+            "record_size": record_size,
+        }
+    )
+    Path(path).write_bytes(out)
+
+
+if __name__ == "__main__":
+    here = Path(__file__).parent
+    write(here / "city-24.mmdb", 24, 4)
+    write(here / "city-28.mmdb", 28, 6)
+    write(here / "city-32.mmdb", 32, 6)
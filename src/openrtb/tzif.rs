@@ -0,0 +1,359 @@
+//! UTC offsets from TZif time zone files (RFC 8536), as installed under
+//! `/usr/share/zoneinfo`.
+//!
+//! Instants past the last transition of a file follow the POSIX TZ string in
+//! its footer, so files written by `zic -b slim` work as well as fat ones.
+
+const HEADER_LEN: usize = 44;
+
+/// A parsed TZif file.
+#[derive(Debug)]
+pub(crate) struct Zone {
+    /// Transition times in seconds since the Unix epoch, with the offset from
+    /// UTC in seconds that starts at each.
+    transitions: Vec<(i64, i32)>,
+    /// Offset before the first transition.
+    initial: i32,
+    /// POSIX TZ string for instants past the last transition.
+    footer: Option<String>,
+}
+
+impl Zone {
+    /// Parses the TZif file `tzif`, or returns `None` if it is malformed.
+    pub(crate) fn parse(tzif: &[u8]) -> Option<Zone> {
+        let v1 = Block::parse(tzif, 4)?;
+        let (block, footer) = if tzif[4] == 0 {
+            (v1, None)
+        } else {
+            let rest = tzif.get(v1.end..)?;
+            let v2 = Block::parse(rest, 8)?;
+            let footer = rest.get(v2.end..)?.strip_prefix(b"\n")?;
+            let footer = &footer[..footer.iter().position(|&b| b == b'\n')?];
+            (v2, Some(std::str::from_utf8(footer).ok()?))
+        };
+
+        let offset = |index: usize| {
+            let info = block.types.get(index * 6..index * 6 + 4)?;
+            Some(i32::from_be_bytes(info.try_into().ok()?))
+        };
+        let transitions = block
+            .times
+            .chunks(block.time_size)
+            .zip(block.indices)
+            .map(|(t, &index)| {
+                let time = t
+                    .iter()
+                    .fold(if t[0] & 0x80 != 0 { -1i64 } else { 0 }, |v, &b| {
+                        (v << 8) | b as i64
+                    });
+                Some((time, offset(index as usize)?))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Zone {
+            transitions,
+            initial: offset(0)?,
+            footer: footer.filter(|tz| !tz.is_empty()).map(str::to_string),
+        })
+    }
+
+    /// The offset from UTC in seconds at `at` (seconds since the Unix
+    /// epoch), or `None` if the footer is malformed.
+    pub(crate) fn utc_offset(&self, at: i64) -> Option<i32> {
+        let index = self.transitions.iter().rposition(|&(t, _)| t <= at);
+        let last = match index {
+            Some(i) => i + 1 == self.transitions.len(),
+            None => self.transitions.is_empty(),
+        };
+        match &self.footer {
+            Some(tz) if last => posix_offset(tz, at),
+            _ => Some(index.map_or(self.initial, |i| self.transitions[i].1)),
+        }
+    }
+}
+
+/// The data block of a TZif file following its header.
+struct Block<'a> {
+    time_size: usize,
+    times: &'a [u8],
+    indices: &'a [u8],
+    types: &'a [u8],
+    /// Offset just past the block.
+    end: usize,
+}
+
+impl<'a> Block<'a> {
+    fn parse(buf: &'a [u8], time_size: usize) -> Option<Block<'a>> {
+        if buf.get(..4)? != b"TZif" {
+            return None;
+        }
+        let count = |i: usize| {
+            let b = buf.get(20 + 4 * i..24 + 4 * i)?;
+            Some(u32::from_be_bytes(b.try_into().ok()?) as usize)
+        };
+        let (isutcnt, isstdcnt, leapcnt) = (count(0)?, count(1)?, count(2)?);
+        let (timecnt, typecnt, charcnt) = (count(3)?, count(4)?, count(5)?);
+        let times = HEADER_LEN;
+        let indices = times + timecnt * time_size;
+        let types = indices + timecnt;
+        let end = types + typecnt * 6 + charcnt + leapcnt * (time_size + 4) + isstdcnt + isutcnt;
+        if typecnt == 0 || buf.len() < end {
+            return None;
+        }
+        Some(Block {
+            time_size,
+            times: &buf[times..indices],
+            indices: &buf[indices..types],
+            types: &buf[types..types + typecnt * 6],
+            end,
+        })
+    }
+}
+
+/// The offset from UTC in seconds at `at` under a POSIX TZ string such as
+/// `PST8PDT,M3.2.0,M11.1.0`.
+pub(crate) fn posix_offset(tz: &str, at: i64) -> Option<i32> {
+    let mut p = Posix(tz.as_bytes());
+    p.name()?;
+    let std = -p.time()?;
+    if p.0.is_empty() {
+        return Some(std as i32);
+    }
+    p.name()?;
+    let dst = match p.0.first() {
+        Some(b',') | None => std + 3600,
+        _ => -p.time()?,
+    };
+    if !p.eat(b',') {
+        return Some(std as i32);
+    }
+    let (start, start_time) = p.rule()?;
+    if !p.eat(b',') {
+        return None;
+    }
+    let (end, end_time) = p.rule()?;
+    if !p.0.is_empty() {
+        return None;
+    }
+
+    let year = civil_from_days((at + std).div_euclid(86400)).0;
+    let start = start.day(year) * 86400 + start_time - std;
+    let end = end.day(year) * 86400 + end_time - dst;
+    let in_dst = if start < end {
+        start <= at && at < end
+    } else {
+        !(end <= at && at < start)
+    };
+    let offset = if in_dst { dst } else { std };
+    Some(offset as i32)
+}
+
+/// The day of the year on which DST starts or ends.
+enum Rule {
+    /// `Jn`: day `n` from 1 to 365, not counting February 29.
+    Julian(i64),
+    /// `n`: day `n` from 0 to 365, counting February 29.
+    Day(i64),
+    /// `Mm.w.d`: weekday `d` (0 is Sunday) of week `w` (5 is the last) of
+    /// month `m`.
+    Month(i64, i64, i64),
+}
+
+impl Rule {
+    /// Days from the Unix epoch to the day in `year`.
+    fn day(&self, year: i64) -> i64 {
+        let jan1 = days_from_civil(year, 1, 1);
+        match *self {
+            Rule::Julian(n) => jan1 + n - 1 + (is_leap(year) && n >= 60) as i64,
+            Rule::Day(n) => jan1 + n,
+            Rule::Month(m, w, d) => {
+                let first = days_from_civil(year, m, 1);
+                // 1970-01-01 was a Thursday.
+                let mut day = first + (d - (first + 4)).rem_euclid(7) + (w - 1) * 7;
+                let next_month = if m == 12 {
+                    days_from_civil(year + 1, 1, 1)
+                } else {
+                    days_from_civil(year, m + 1, 1)
+                };
+                while day >= next_month {
+                    day -= 7;
+                }
+                day
+            }
+        }
+    }
+}
+
+/// Cursor over a POSIX TZ string.
+struct Posix<'a>(&'a [u8]);
+
+impl Posix<'_> {
+    fn eat(&mut self, b: u8) -> bool {
+        match self.0.split_first() {
+            Some((&c, rest)) if c == b => {
+                self.0 = rest;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// A zone abbreviation, either alphabetic or quoted in `<>`.
+    fn name(&mut self) -> Option<()> {
+        let len = if self.eat(b'<') {
+            self.0.iter().position(|&b| b == b'>')? + 1
+        } else {
+            self.0
+                .iter()
+                .take_while(|b| b.is_ascii_alphabetic())
+                .count()
+        };
+        if len < 3 {
+            return None;
+        }
+        self.0 = &self.0[len..];
+        Some(())
+    }
+
+    fn number(&mut self) -> Option<i64> {
+        let len = self.0.iter().take_while(|b| b.is_ascii_digit()).count();
+        if len == 0 || len > 3 {
+            return None;
+        }
+        let n = std::str::from_utf8(&self.0[..len]).ok()?.parse().ok()?;
+        self.0 = &self.0[len..];
+        Some(n)
+    }
+
+    /// `[+-]hh[:mm[:ss]]`, in seconds.
+    fn time(&mut self) -> Option<i64> {
+        let sign = if self.eat(b'-') {
+            -1
+        } else {
+            self.eat(b'+');
+            1
+        };
+        let mut seconds = self.number()? * 3600;
+        if self.eat(b':') {
+            seconds += self.number()? * 60;
+            if self.eat(b':') {
+                seconds += self.number()?;
+            }
+        }
+        Some(sign * seconds)
+    }
+
+    /// A date and optional time of day, which defaults to 02:00.
+    fn rule(&mut self) -> Option<(Rule, i64)> {
+        let rule = if self.eat(b'J') {
+            Rule::Julian(self.number().filter(|n| (1..=365).contains(n))?)
+        } else if self.eat(b'M') {
+            let m = self.number().filter(|m| (1..=12).contains(m))?;
+            self.eat(b'.').then_some(())?;
+            let w = self.number().filter(|w| (1..=5).contains(w))?;
+            self.eat(b'.').then_some(())?;
+            let d = self.number().filter(|d| (0..=6).contains(d))?;
+            Rule::Month(m, w, d)
+        } else {
+            Rule::Day(self.number().filter(|n| (0..=365).contains(n))?)
+        };
+        let time = if self.eat(b'/') { self.time()? } else { 7200 };
+        Some((rule, time))
+    }
+}
+
+fn is_leap(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+/// Days from the Unix epoch to a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// The year, month and day of a day counted from the Unix epoch.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + (month <= 2) as i64, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A copy of America/Los_Angeles from the IANA time zone database.
+    const LOS_ANGELES: &[u8] = include_bytes!("testdata/geo/zoneinfo/America/Los_Angeles");
+
+    fn utc_offset(tzif: &[u8], at: i64) -> Option<i32> {
+        Zone::parse(tzif)?.utc_offset(at)
+    }
+
+    fn at(year: i64, month: i64, day: i64, hour: i64) -> i64 {
+        days_from_civil(year, month, day) * 86400 + hour * 3600
+    }
+
+    #[test]
+    fn reads_transitions_and_footer() {
+        assert_eq!(utc_offset(LOS_ANGELES, at(2023, 1, 15, 0)), Some(-8 * 3600));
+        assert_eq!(utc_offset(LOS_ANGELES, at(2023, 7, 1, 0)), Some(-7 * 3600));
+        // Past the last transition, from `PST8PDT,M3.2.0,M11.1.0`.
+        assert_eq!(utc_offset(LOS_ANGELES, at(2100, 7, 1, 0)), Some(-7 * 3600));
+        assert_eq!(utc_offset(LOS_ANGELES, at(2100, 12, 1, 0)), Some(-8 * 3600));
+        // Local mean time before the first transition.
+        assert_eq!(utc_offset(LOS_ANGELES, at(1800, 1, 1, 0)), Some(-28378));
+
+        assert_eq!(utc_offset(b"TZif2", 0), None);
+        assert_eq!(utc_offset(&LOS_ANGELES[..100], 0), None);
+    }
+
+    #[test]
+    fn applies_posix_rules() {
+        let pst = "PST8PDT,M3.2.0,M11.1.0";
+        // DST starts on 2024-03-10 at 02:00 PST, 10:00 UTC.
+        assert_eq!(posix_offset(pst, at(2024, 3, 10, 10) - 1), Some(-8 * 3600));
+        assert_eq!(posix_offset(pst, at(2024, 3, 10, 10)), Some(-7 * 3600));
+        // And ends on 2024-11-03 at 02:00 PDT, 09:00 UTC.
+        assert_eq!(posix_offset(pst, at(2024, 11, 3, 9) - 1), Some(-7 * 3600));
+        assert_eq!(posix_offset(pst, at(2024, 11, 3, 9)), Some(-8 * 3600));
+
+        // Southern hemisphere, with DST across the new year.
+        let sydney = "AEST-10AEDT,M10.1.0,M4.1.0/3";
+        assert_eq!(posix_offset(sydney, at(2024, 1, 1, 0)), Some(11 * 3600));
+        assert_eq!(posix_offset(sydney, at(2024, 6, 1, 0)), Some(10 * 3600));
+
+        // Last Sunday of the month, and quoted names without DST.
+        let berlin = "CET-1CEST,M3.5.0,M10.5.0/3";
+        assert_eq!(posix_offset(berlin, at(2024, 3, 31, 0)), Some(3600));
+        assert_eq!(posix_offset(berlin, at(2024, 3, 31, 1)), Some(7200));
+        assert_eq!(posix_offset("<+0530>-5:30", 0), Some(19800));
+        assert_eq!(
+            posix_offset("EST5EDT,J60/1,300", at(2023, 3, 1, 6)),
+            Some(-4 * 3600)
+        );
+
+        for bad in ["", "P8", "PST", "PST8PDT,M13.1.0,M11.1.0", "PST8PDT,M3.2.0"] {
+            assert_eq!(posix_offset(bad, 0), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn converts_dates() {
+        for days in [-719468, -1, 0, 19723, 2932896] {
+            let (y, m, d) = civil_from_days(days);
+            assert_eq!(days_from_civil(y, m, d), days);
+        }
+        assert_eq!(civil_from_days(19723), (2024, 1, 1));
+    }
+}
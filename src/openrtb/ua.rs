@@ -0,0 +1,520 @@
+//! User-agent parsing with a bundled rule set.
+//!
+//! The rules are plain substring and prefix matches covering the common
+//! operating systems, browsers, device makers and crawlers; no network or
+//! external data is needed. `Device::enrich_from_ua` fills the `Device`
+//! fields that a supply source left empty.
+
+use super::bid_request::Device;
+use super::bool::Bool;
+use super::{BidRequest, DeviceType, NoBidReason};
+use serde_json::{json, Value};
+
+/// Information parsed from a user-agent string.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UserAgent {
+    /// Device maker, e.g. "Samsung", "Apple".
+    pub make: Option<String>,
+    /// Device model, e.g. "SM-S918B", "iPhone".
+    pub model: Option<String>,
+    /// Operating system, e.g. "Android", "iOS", "Windows".
+    pub os: Option<String>,
+    /// Operating system version, e.g. "17.1.2".
+    pub osv: Option<String>,
+    /// Device type, as in `Device.devicetype`.
+    pub devicetype: Option<DeviceType>,
+    /// Browser name, e.g. "Chrome", "Safari".
+    pub browser: Option<String>,
+    /// Browser version, e.g. "120.0.2210.91".
+    pub browser_version: Option<String>,
+    /// Whether the user agent is a known bot, crawler or automation tool.
+    pub bot: bool,
+}
+
+/// Lowercase tokens identifying bots, crawlers and automation tools.
+const BOT_TOKENS: &[&str] = &[
+    "googlebot",
+    "adsbot-google",
+    "mediapartners-google",
+    "bingbot",
+    "bingpreview",
+    "yandexbot",
+    "baiduspider",
+    "duckduckbot",
+    "slurp",
+    "applebot",
+    "facebookexternalhit",
+    "twitterbot",
+    "linkedinbot",
+    "ahrefsbot",
+    "semrushbot",
+    "mj12bot",
+    "petalbot",
+    "ia_archiver",
+    "headlesschrome",
+    "phantomjs",
+    "lighthouse",
+    "pingdom",
+    "uptimerobot",
+    "python-requests",
+    "python-urllib",
+    "go-http-client",
+    "apache-httpclient",
+    "scrapy",
+    "curl/",
+    "wget/",
+    "crawler",
+    "spider",
+    "bot/",
+    "bot;",
+    "+http",
+];
+
+/// Model number formats of Android devices, with their maker. In a format,
+/// `#` is an ASCII digit, `@` an uppercase ASCII letter, `*` any number of
+/// uppercase ASCII letters and `$` the end of the model or of a word in it;
+/// other characters match themselves, and a format without `$` only has to
+/// match the start of the model.
+const MAKERS: &[(&str, &str)] = &[
+    ("SM-@###", "Samsung"),
+    ("GT-@####", "Samsung"),
+    ("SAMSUNG$", "Samsung"),
+    ("Pixel$", "Google"),
+    ("Nexus$", "Google"),
+    ("Redmi$", "Xiaomi"),
+    ("Mi$", "Xiaomi"),
+    ("POCO$", "Xiaomi"),
+    ("ONEPLUS$", "OnePlus"),
+    ("HUAWEI$", "Huawei"),
+    ("HONOR$", "Honor"),
+    ("LG-@###", "LG"),
+    ("LM-@###", "LG"),
+    ("moto$", "Motorola"),
+    ("Moto$", "Motorola"),
+    ("motorola$", "Motorola"),
+    ("XT####$", "Motorola"),
+    ("CPH####$", "OPPO"),
+    ("RMX####$", "realme"),
+    ("vivo$", "vivo"),
+    ("Nokia$", "Nokia"),
+    ("KF@@*$", "Amazon"),
+    ("AFT@*$", "Amazon"),
+];
+
+impl UserAgent {
+    /// Parses a user-agent string. Fields that cannot be determined are
+    /// left empty.
+    pub fn parse(ua: &str) -> UserAgent {
+        let lower = ua.to_ascii_lowercase();
+        let mut parsed = UserAgent {
+            bot: BOT_TOKENS.iter().any(|token| lower.contains(token)),
+            ..UserAgent::default()
+        };
+        parsed.parse_platform(ua);
+        parsed.parse_browser(ua);
+        parsed
+    }
+
+    fn parse_platform(&mut self, ua: &str) {
+        let set = |p: &mut UserAgent, os: &str, osv: Option<String>, t: DeviceType| {
+            p.os = Some(os.to_string());
+            p.osv = osv;
+            p.devicetype = Some(t);
+        };
+        if ua.contains("Windows Phone") {
+            set(
+                self,
+                "Windows Phone",
+                version_after(ua, "Windows Phone OS ")
+                    .or_else(|| version_after(ua, "Windows Phone ")),
+                DeviceType::HighendPhone,
+            );
+        } else if ua.contains("Xbox") {
+            set(self, "Windows", None, DeviceType::ConnectedDevice);
+            self.make = Some("Microsoft".to_string());
+            self.model = Some("Xbox".to_string());
+        } else if let Some(nt) = version_after(ua, "Windows NT ") {
+            let osv = match nt.as_str() {
+                "10.0" => "10",
+                "6.3" => "8.1",
+                "6.2" => "8",
+                "6.1" => "7",
+                "6.0" => "Vista",
+                "5.1" | "5.2" => "XP",
+                other => other,
+            };
+            set(
+                self,
+                "Windows",
+                Some(osv.to_string()),
+                DeviceType::PersonalComputer,
+            );
+        } else if let Some(model) = ["iPhone", "iPad", "iPod"].iter().find(|m| ua.contains(*m)) {
+            let t = match *model {
+                "iPad" => DeviceType::Tablet,
+                _ => DeviceType::HighendPhone,
+            };
+            let osv = version_after(ua, "iPhone OS ").or_else(|| version_after(ua, "CPU OS "));
+            set(self, "iOS", osv, t);
+            self.make = Some("Apple".to_string());
+            self.model = Some(model.to_string());
+        } else if ua.contains("AppleTV") || ua.contains("tvOS") {
+            set(
+                self,
+                "tvOS",
+                version_after(ua, "tvOS/").or_else(|| version_after(ua, "OS ")),
+                DeviceType::SetTopBox,
+            );
+            self.make = Some("Apple".to_string());
+            self.model = Some("Apple TV".to_string());
+        } else if ua.contains("Roku") {
+            set(
+                self,
+                "Roku OS",
+                version_after(ua, "Roku/DVP-"),
+                DeviceType::ConnectedTv,
+            );
+            self.make = Some("Roku".to_string());
+        } else if ua.contains("Tizen") {
+            let t = if ua.contains("TV") {
+                DeviceType::ConnectedTv
+            } else {
+                DeviceType::ConnectedDevice
+            };
+            set(self, "Tizen", version_after(ua, "Tizen "), t);
+            self.make = Some("Samsung".to_string());
+        } else if ua.contains("Web0S") || ua.contains("webOS") {
+            set(self, "webOS", None, DeviceType::ConnectedTv);
+            self.make = Some("LG".to_string());
+        } else if ua.contains("CrKey") {
+            set(
+                self,
+                "Cast OS",
+                version_after(ua, "CrKey/"),
+                DeviceType::ConnectedTv,
+            );
+            self.make = Some("Google".to_string());
+            self.model = Some("Chromecast".to_string());
+        } else if ua.contains("PlayStation") {
+            set(self, "PlayStation", None, DeviceType::ConnectedDevice);
+            self.make = Some("Sony".to_string());
+        } else if let Some(osv) = version_after(ua, "Android ") {
+            self.model = android_model(ua);
+            self.make = self.model.as_deref().and_then(make_of_model);
+            let t = if self.make.as_deref() == Some("Amazon") && ua.contains("AFT") {
+                DeviceType::ConnectedTv
+            } else if ua.contains("Mobile") {
+                DeviceType::HighendPhone
+            } else if ua.contains(" TV") || ua.contains("SMART-TV") {
+                DeviceType::ConnectedTv
+            } else {
+                DeviceType::Tablet
+            };
+            set(self, "Android", Some(osv), t);
+        } else if ua.contains("CrOS") {
+            set(self, "Chrome OS", None, DeviceType::PersonalComputer);
+        } else if let Some(osv) = version_after(ua, "Mac OS X ") {
+            set(self, "macOS", Some(osv), DeviceType::PersonalComputer);
+            self.make = Some("Apple".to_string());
+        } else if ua.contains("Macintosh") {
+            set(self, "macOS", None, DeviceType::PersonalComputer);
+            self.make = Some("Apple".to_string());
+        } else if ua.contains("SMART-TV") || ua.contains("SmartTV") {
+            set(self, "Linux", None, DeviceType::ConnectedTv);
+        } else if ua.contains("Linux") && !ua.contains("Android") {
+            set(self, "Linux", None, DeviceType::PersonalComputer);
+        }
+    }
+
+    fn parse_browser(&mut self, ua: &str) {
+        let browsers: &[(&str, &str)] = &[
+            ("Edg/", "Edge"),
+            ("EdgA/", "Edge"),
+            ("EdgiOS/", "Edge"),
+            ("Edge/", "Edge"),
+            ("OPR/", "Opera"),
+            ("SamsungBrowser/", "Samsung Internet"),
+            ("YaBrowser/", "Yandex Browser"),
+            ("UCBrowser/", "UC Browser"),
+            ("FxiOS/", "Firefox"),
+            ("Firefox/", "Firefox"),
+            ("CriOS/", "Chrome"),
+            ("Chrome/", "Chrome"),
+        ];
+        let found = browsers
+            .iter()
+            .find(|(token, _)| ua.contains(token))
+            .map(|(token, name)| (name.to_string(), version_after(ua, token)));
+        let found = found.or_else(|| {
+            if ua.contains("Safari/") && ua.contains("Version/") {
+                Some(("Safari".to_string(), version_after(ua, "Version/")))
+            } else if ua.contains("MSIE ") {
+                Some(("Internet Explorer".to_string(), version_after(ua, "MSIE ")))
+            } else if ua.contains("Trident/") {
+                Some(("Internet Explorer".to_string(), version_after(ua, "rv:")))
+            } else {
+                None
+            }
+        });
+        if let Some((name, version)) = found {
+            self.browser = Some(name);
+            self.browser_version = version;
+        }
+    }
+}
+
+/// The maker of an Android device model, e.g. `Samsung` for `SM-S918B`.
+pub(crate) fn make_of_model(model: &str) -> Option<String> {
+    MAKERS
+        .iter()
+        .find(|(format, _)| matches_format(format.as_bytes(), model.as_bytes()))
+        .map(|(_, make)| make.to_string())
+}
+
+/// Whether `model` starts with the `MAKERS` model number `format`.
+fn matches_format(format: &[u8], model: &[u8]) -> bool {
+    match (format.split_first(), model.split_first()) {
+        (None, _) => true,
+        (Some((b'$', _)), Some((c, _))) => !c.is_ascii_alphanumeric(),
+        (Some((b'$', _)), None) => true,
+        (Some((b'*', rest)), _) => {
+            let letters = model.iter().take_while(|c| c.is_ascii_uppercase()).count();
+            matches_format(rest, &model[letters..])
+        }
+        (Some((&f, rest)), Some((&c, model))) => {
+            let ok = match f {
+                b'#' => c.is_ascii_digit(),
+                b'@' => c.is_ascii_uppercase(),
+                _ => f == c,
+            };
+            ok && matches_format(rest, model)
+        }
+        (Some(_), None) => false,
+    }
+}
+
+/// The version following `token`: digits, dots and underscores, with
+/// underscores turned into dots (`OS 17_1_2` gives `17.1.2`).
+fn version_after(ua: &str, token: &str) -> Option<String> {
+    let start = ua.find(token)? + token.len();
+    let version: String = ua[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.' || *c == '_')
+        .map(|c| if c == '_' { '.' } else { c })
+        .collect();
+    let version = version.trim_end_matches('.');
+    if version.is_empty() {
+        None
+    } else {
+        Some(version.to_string())
+    }
+}
+
+/// The model in the platform part of an Android user agent, e.g.
+/// `SM-S918B` in `(Linux; Android 13; SM-S918B)`. The reduced user agent
+/// model `K` is ignored.
+fn android_model(ua: &str) -> Option<String> {
+    let start = ua.find('(')? + 1;
+    let end = start + ua[start..].find(')')?;
+    let mut segments = ua[start..end].split(';').map(str::trim);
+    segments.find(|s| s.starts_with("Android"))?;
+    segments
+        .map(|s| s.split(" Build/").next().unwrap_or(s).trim())
+        .find(|s| !s.is_empty() && *s != "wv" && *s != "K" && !is_locale(s))
+        .map(str::to_string)
+}
+
+/// Whether `s` looks like a locale such as `en-us` or `de`.
+fn is_locale(s: &str) -> bool {
+    let mut parts = s.split(['-', '_']);
+    let lang = parts.next().unwrap_or_default();
+    lang.len() == 2
+        && lang.bytes().all(|b| b.is_ascii_alphabetic())
+        && parts.all(|p| p.len() == 2 && p.bytes().all(|b| b.is_ascii_alphabetic()))
+}
+
+impl Device {
+    /// Parses `ua`, if present.
+    pub fn parse_ua(&self) -> Option<UserAgent> {
+        self.ua.as_deref().map(UserAgent::parse)
+    }
+
+    /// Fills `make`, `model`, `os`, `osv`, `devicetype` and `js`, and the
+    /// browser name and version in `ext.browser`/`ext.browserv`, from `ua`
+    /// where they are missing. Existing values are never overwritten, and
+    /// `model` and `osv` are only filled when `make` and `os` are missing or
+    /// name the parsed maker and OS. Returns the parsed user agent.
+    pub fn enrich_from_ua(&mut self) -> Option<UserAgent> {
+        let parsed = self.parse_ua()?;
+        fill_dependent(&mut self.make, &mut self.model, &parsed.make, &parsed.model);
+        fill_dependent(&mut self.os, &mut self.osv, &parsed.os, &parsed.osv);
+        if self.devicetype.is_none() {
+            self.devicetype = parsed.devicetype;
+        }
+        if self.js.is_none() && parsed.browser.is_some() && !parsed.bot {
+            self.js = Some(Bool::True);
+        }
+        if let Some(browser) = &parsed.browser {
+            fill_browser(self, browser, parsed.browser_version.as_deref());
+        }
+        Some(parsed)
+    }
+
+    /// Whether `ua` identifies a known bot or crawler.
+    pub fn is_known_spider(&self) -> bool {
+        self.parse_ua().is_some_and(|ua| ua.bot)
+    }
+}
+
+fn fill(field: &mut Option<String>, value: &Option<String>) {
+    if field.is_none() {
+        field.clone_from(value);
+    }
+}
+
+/// Fills `parent` and `child` where missing, but `child` only when `parent`
+/// is missing or names `value` (ignoring case), so that e.g. an iOS version
+/// is never written next to a supplied Android `os`.
+pub(crate) fn fill_dependent(
+    parent: &mut Option<String>,
+    child: &mut Option<String>,
+    value: &Option<String>,
+    child_value: &Option<String>,
+) {
+    let same = match (parent.as_deref(), value.as_deref()) {
+        (None, _) => true,
+        (Some(parent), Some(value)) => parent.eq_ignore_ascii_case(value),
+        (Some(_), None) => false,
+    };
+    if same {
+        fill(child, child_value);
+    }
+    fill(parent, value);
+}
+
+/// Sets `ext.browser` and `ext.browserv` where missing.
+pub(crate) fn fill_browser(device: &mut Device, browser: &str, version: Option<&str>) {
+    if let Value::Object(ext) = device.ext.get_or_insert_with(|| json!({})) {
+        ext.entry("browser").or_insert_with(|| json!(browser));
+        if let Some(version) = version {
+            ext.entry("browserv").or_insert_with(|| json!(version));
+        }
+    }
+}
+
+impl BidRequest {
+    /// `NoBidReason::KnownWebSpider` if the device user agent is a known bot
+    /// or crawler.
+    pub fn spider_no_bid_reason(&self) -> Option<NoBidReason> {
+        match &self.device {
+            Some(device) if device.is_known_spider() => Some(NoBidReason::KnownWebSpider),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GALAXY_S23: &str = "Mozilla/5.0 (Linux; Android 13; SM-S918B) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/116.0.0.0 Mobile Safari/537.36";
+    const GOOGLEBOT: &str =
+        "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)";
+
+    #[test]
+    fn parses_android_phone() {
+        let ua = UserAgent::parse(GALAXY_S23);
+        assert_eq!(ua.make.as_deref(), Some("Samsung"));
+        assert_eq!(ua.model.as_deref(), Some("SM-S918B"));
+        assert_eq!(ua.osv.as_deref(), Some("13"));
+        assert_eq!(ua.devicetype, Some(DeviceType::HighendPhone));
+        assert_eq!(ua.browser.as_deref(), Some("Chrome"));
+        assert!(!ua.bot);
+
+        let ua = UserAgent::parse("Mozilla/5.0 (Linux; U; Android 4.4.2; en-us; GT-I9505 Build/KOT49H) AppleWebKit/534.30 Version/4.0 Mobile Safari/534.30");
+        assert_eq!(ua.make.as_deref(), Some("Samsung"));
+        assert_eq!(ua.model.as_deref(), Some("GT-I9505"));
+    }
+
+    #[test]
+    fn matches_full_model_numbers() {
+        for (model, make) in [
+            ("SM-A515F", Some("Samsung")),
+            ("SAMSUNG-SM-G900A", Some("Samsung")),
+            ("XT2041-4", Some("Motorola")),
+            ("moto g(8)", Some("Motorola")),
+            ("KFTT", Some("Amazon")),
+            ("KFMUWI", Some("Amazon")),
+            ("AFTMM", Some("Amazon")),
+            ("CPH2207", Some("OPPO")),
+            ("Mi 9T", Some("Xiaomi")),
+            ("XTREME 5", None),
+            ("XT12", None),
+            ("KFC Phone", None),
+            ("KF2", None),
+            ("Mix 4", None),
+            ("SM-X", None),
+            ("Pixelbook", None),
+        ] {
+            assert_eq!(make_of_model(model).as_deref(), make, "{}", model);
+        }
+    }
+
+    #[test]
+    fn parses_ios_and_windows() {
+        let ua = UserAgent::parse("Mozilla/5.0 (iPhone; CPU iPhone OS 17_1_2 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.1.2 Mobile/15E148 Safari/604.1");
+        assert_eq!(ua.os.as_deref(), Some("iOS"));
+        assert_eq!(ua.osv.as_deref(), Some("17.1.2"));
+        assert_eq!(ua.browser.as_deref(), Some("Safari"));
+
+        let ua = UserAgent::parse("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36 Edg/120.0.2210.91");
+        assert_eq!(ua.osv.as_deref(), Some("10"));
+        assert_eq!(ua.browser.as_deref(), Some("Edge"));
+        assert_eq!(ua.browser_version.as_deref(), Some("120.0.2210.91"));
+    }
+
+    #[test]
+    fn enrich_keeps_supplied_fields() {
+        let mut device = Device {
+            ua: Some("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.0 Safari/605.1.15".into()),
+            os: Some("MacOS".into()),
+            ..Default::default()
+        };
+        device.enrich_from_ua();
+        assert_eq!(device.os.as_deref(), Some("MacOS"));
+        assert_eq!(device.osv.as_deref(), Some("10.15.7"));
+        assert_eq!(device.ext.unwrap()["browser"], "Safari");
+    }
+
+    #[test]
+    fn enrich_skips_versions_and_models_of_other_devices() {
+        let mut device = Device {
+            ua: Some("Mozilla/5.0 (iPhone; CPU iPhone OS 17_1 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.1 Mobile/15E148 Safari/604.1".into()),
+            os: Some("Android".into()),
+            make: Some("Samsung".into()),
+            ..Default::default()
+        };
+        device.enrich_from_ua();
+        assert_eq!(device.os.as_deref(), Some("Android"));
+        assert_eq!(device.osv, None);
+        assert_eq!(device.make.as_deref(), Some("Samsung"));
+        assert_eq!(device.model, None);
+        assert_eq!(device.devicetype, Some(DeviceType::HighendPhone));
+    }
+
+    #[test]
+    fn flags_known_spiders() {
+        assert!(UserAgent::parse(GOOGLEBOT).bot);
+
+        let mut request = BidRequest {
+            device: Some(Device {
+                ua: Some(GOOGLEBOT.into()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(request.spider_no_bid_reason() == Some(NoBidReason::KnownWebSpider));
+        request.device.as_mut().unwrap().ua = Some(GALAXY_S23.into());
+        assert!(request.spider_no_bid_reason().is_none());
+    }
+}
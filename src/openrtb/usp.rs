@@ -0,0 +1,121 @@
+//! IAB US Privacy (CCPA) strings.
+//!
+//! OpenRTB 2.5 carries the string in `regs.ext.us_privacy`, e.g. `1YNN`:
+//! the specification version followed by three `Y`, `N` or `-` flags.
+
+use super::bid_request::Regs;
+use std::fmt;
+use std::str::FromStr;
+
+/// A parsed US Privacy string. Each flag is `None` when not applicable (`-`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct UsPrivacy {
+    /// Specification version, currently 1.
+    pub version: u8,
+    /// Explicit notice and opportunity to opt out was given.
+    pub notice: Option<bool>,
+    /// The user opted out of the sale of personal information.
+    pub opt_out_sale: Option<bool>,
+    /// The publisher is a signatory to the IAB Limited Service Provider
+    /// Agreement.
+    pub lspa_covered: Option<bool>,
+}
+
+/// Error returned when a US Privacy string is malformed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseUsPrivacyError;
+
+impl fmt::Display for ParseUsPrivacyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid US Privacy string")
+    }
+}
+
+impl std::error::Error for ParseUsPrivacyError {}
+
+impl UsPrivacy {
+    /// Whether CCPA applies at all, i.e. any flag is set.
+    pub fn applies(&self) -> bool {
+        self.notice.is_some() || self.opt_out_sale.is_some() || self.lspa_covered.is_some()
+    }
+}
+
+impl FromStr for UsPrivacy {
+    type Err = ParseUsPrivacyError;
+
+    /// Parses a 4-character US Privacy string; flags are case-insensitive.
+    fn from_str(s: &str) -> Result<UsPrivacy, ParseUsPrivacyError> {
+        let b = s.as_bytes();
+        if b.len() != 4 || b[0] != b'1' {
+            return Err(ParseUsPrivacyError);
+        }
+        let flag = |c: u8| match c.to_ascii_uppercase() {
+            b'Y' => Ok(Some(true)),
+            b'N' => Ok(Some(false)),
+            b'-' => Ok(None),
+            _ => Err(ParseUsPrivacyError),
+        };
+        Ok(UsPrivacy {
+            version: 1,
+            notice: flag(b[1])?,
+            opt_out_sale: flag(b[2])?,
+            lspa_covered: flag(b[3])?,
+        })
+    }
+}
+
+impl fmt::Display for UsPrivacy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flag = |v: Option<bool>| match v {
+            Some(true) => 'Y',
+            Some(false) => 'N',
+            None => '-',
+        };
+        write!(
+            f,
+            "{}{}{}{}",
+            self.version,
+            flag(self.notice),
+            flag(self.opt_out_sale),
+            flag(self.lspa_covered)
+        )
+    }
+}
+
+impl Regs {
+    /// The raw US Privacy string from `ext.us_privacy`.
+    pub fn us_privacy_str(&self) -> Option<&str> {
+        self.ext.as_ref()?.get("us_privacy")?.as_str()
+    }
+
+    /// The parsed US Privacy string from `ext.us_privacy`.
+    pub fn us_privacy(&self) -> Option<Result<UsPrivacy, ParseUsPrivacyError>> {
+        self.us_privacy_str().map(str::parse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_flags() {
+        let usp: UsPrivacy = "1YnN".parse().unwrap();
+        assert_eq!(usp.version, 1);
+        assert_eq!(usp.notice, Some(true));
+        assert_eq!(usp.opt_out_sale, Some(false));
+        assert_eq!(usp.lspa_covered, Some(false));
+        assert!(usp.applies());
+        assert_eq!(usp.to_string(), "1YNN");
+        let usp: UsPrivacy = "1---".parse().unwrap();
+        assert!(!usp.applies());
+        assert_eq!(usp.to_string(), "1---");
+    }
+
+    #[test]
+    fn rejects_malformed_strings() {
+        for s in ["", "1YN", "1YNNN", "2YNN", "1YXN", "1ÿN"] {
+            assert_eq!(s.parse::<UsPrivacy>(), Err(ParseUsPrivacyError), "{}", s);
+        }
+    }
+}